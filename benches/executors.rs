@@ -0,0 +1,31 @@
+// The same programs run by walking the tree and by compiling it into closures first:
+// `cargo bench --bench executors`.
+
+use std::time::Instant;
+
+use odo::exec::interpreter::{closures::ClosureCompiler, Interpreter};
+
+const WORKLOADS: &[(&str, &str, &str)] = &[
+    ("calls", "func fib(n: int) -> int {\n    if n < 2 {\n        return n\n    }\n    return fib(n - 1) + fib(n - 2)\n}", "fib(22)"),
+    ("arithmetic", "func count(n: int, total: int) -> int {\n    if n == 0 {\n        return total\n    }\n    var a = total * 3 + 1\n    var b = a - total * 2 - 1\n    return count(n - 1, b + 1)\n}", "count(50000, 0)"),
+];
+
+fn main() -> anyhow::Result<()> {
+    for (name, setup, call) in WORKLOADS {
+        for compiled in [false, true] {
+            let mut interpreter = Interpreter::new();
+            if compiled {
+                interpreter.set_executor(ClosureCompiler::new());
+            }
+            interpreter.eval(setup.to_string())?;
+
+            let started = Instant::now();
+            let result = interpreter.eval(call.to_string())?;
+
+            let executor = if compiled { "closures" } else { "tree" };
+            println!("{:<12} {:<9} {:>8}  {:>8.1?}", name, executor, result.as_int()?, started.elapsed());
+        }
+    }
+
+    Ok(())
+}
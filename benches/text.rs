@@ -0,0 +1,30 @@
+// String building in odo, at a few sizes, to see how joining scales: `cargo bench --bench text`.
+// Each run should take about ten times as long as the one before it, not a hundred.
+
+use std::time::Instant;
+
+use odo::exec::interpreter::Interpreter;
+
+// Through recursion, since for loops over lists copy what's left of the list on every step
+const WORKLOADS: &[(&str, &str)] = &[
+    ("appending", "func build(n: int, built: string) -> string {\n    if n == 0 {\n        return built\n    }\n    return build(n - 1, built + \"piece \")\n}"),
+    ("prepending", "func build(n: int, built: string) -> string {\n    if n == 0 {\n        return built\n    }\n    return build(n - 1, \"piece \" + built)\n}"),
+    ("numbering", "func build(n: int, built: string) -> string {\n    if n == 0 {\n        return built\n    }\n    return build(n - 1, built + withSeparators(n) + \", \")\n}"),
+];
+
+fn main() -> anyhow::Result<()> {
+    for (name, code) in WORKLOADS {
+        for size in [1_000, 10_000, 100_000] {
+            let mut interpreter = Interpreter::new();
+            interpreter.eval(code.to_string())?;
+
+            let started = Instant::now();
+            let built = interpreter.eval(format!("build({}, \"\")", size))?;
+            let length = built.as_text()?.len();
+
+            println!("{:<12} {:>7} joins  {:>10} bytes  {:>8.1?}", name, size, length, started.elapsed());
+        }
+    }
+
+    Ok(())
+}
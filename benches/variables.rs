@@ -0,0 +1,49 @@
+// Reading variables in a tight loop, where most of the time goes to finding them:
+// `cargo bench --bench variables`.
+
+use std::time::Instant;
+
+use odo::exec::interpreter::Interpreter;
+
+const SETUP: &str = "var step = 1
+func count(n: int, total: int) -> int {
+    if n == 0 {
+        return total
+    }
+    var a = total
+    var b = a + step
+    var c = b + a - a
+    return count(n - step, c)
+}
+func counting(limit: int) -> func(int, int) -> int {
+    func inner(n: int, total: int) -> int {
+        if n == limit {
+            return total
+        }
+        var a = total + step
+        return inner(n + 1, a + limit - limit)
+    }
+    return inner
+}";
+
+// Through recursion, since for loops over lists copy what's left of the list on every step
+const WORKLOADS: &[(&str, &str)] = &[
+    ("locals", "count({n}, 0)"),
+    ("captured", "counting({n})(0, 0)"),
+];
+
+fn main() -> anyhow::Result<()> {
+    for (name, call) in WORKLOADS {
+        for size in [10_000, 100_000] {
+            let mut interpreter = Interpreter::new();
+            interpreter.eval(SETUP.to_string())?;
+
+            let started = Instant::now();
+            let total = interpreter.eval(call.replace("{n}", &size.to_string()))?;
+
+            println!("{:<10} {:>7} calls  total {:>7}  {:>8.1?}", name, size, total.as_int()?, started.elapsed());
+        }
+    }
+
+    Ok(())
+}
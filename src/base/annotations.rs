@@ -0,0 +1,74 @@
+use serde::{Serialize, Deserialize};
+
+use crate::base::{lexer::Token, parser::Ast, suggest::{self, NotFound}};
+
+// `@name` or `@name(arguments)` before a declaration. They're kept on the symbol it declares, for
+// whatever cares about them: lints, the test runner, the module system. `@cfg` is the exception,
+// since it decides whether there's a symbol at all.
+
+/// An annotation as it was written, with its arguments in order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Annotation {
+    pub name: Token,
+    pub arguments: Vec<Token>, // Names, numbers or text
+}
+
+impl Annotation {
+    pub fn kind(&self) -> Option<Kind> {
+        Kind::from_name(&self.name.value).ok()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Deprecated, // Using the symbol gives a warning, with the reason if there's one
+    Test, // A function the test runner calls
+    Export, // Other modules can import the symbol
+    Cfg, // Only declared when the feature it names is defined, like with `--define debug`
+}
+
+impl Kind {
+    pub const ALL: [Kind; 4] = [Kind::Deprecated, Kind::Test, Kind::Export, Kind::Cfg];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Deprecated => "deprecated",
+            Kind::Test => "test",
+            Kind::Export => "export",
+            Kind::Cfg => "cfg",
+        }
+    }
+
+    // How many arguments it takes, at most
+    pub fn max_arguments(&self) -> usize {
+        match self {
+            Kind::Deprecated | Kind::Cfg => 1,
+            Kind::Test | Kind::Export => 0,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Kind, NotFound> {
+        Kind::ALL.into_iter()
+            .find(|kind| kind.name() == name)
+            .ok_or_else(|| suggest::with_suggestions(
+                format!("There's no annotation called @{}", name),
+                name,
+                Kind::ALL.iter().map(Kind::name)
+            ))
+    }
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "@{}", self.name())
+    }
+}
+
+// What a statement declares, if annotations can go on it
+pub(crate) fn declared_name(ast: &Ast) -> Option<&Token> {
+    match ast {
+        Ast::Declaration(name, _) | Ast::TypedDeclaration(name, ..) | Ast::FunctionDeclaration(name, ..) |
+        Ast::TypeDeclaration(name, ..) | Ast::ContractDeclaration(name, _) => Some(name),
+        _ => None
+    }
+}
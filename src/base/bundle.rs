@@ -0,0 +1,202 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}, sync::{Arc, Mutex}};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::base::modules::ModuleLoader;
+
+// A program and every module it imports, packed in one file to hand to someone else. It keeps the
+// sources, so unlike a compiled program it runs with any version of odo and any prelude that
+// can analyze it.
+//
+// Which files a program imports is only known by analyzing it, so bundles are made by recording
+// what the loader is asked for while the program is compiled.
+
+pub const EXTENSION: &str = "odob";
+
+// Bumped whenever the saved representation changes
+const FORMAT: u32 = 1;
+
+/// Sources of a program and of the modules it imports, by their path inside the bundle. Running
+/// it imports the same files the program imported when it was bundled.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bundle {
+    format: u32,
+    pub entry: String,
+    files: BTreeMap<String, String>,
+    // Where each import went: the importing file, the module's name and the imported file
+    imports: Vec<(String, String, String)>,
+}
+
+impl Bundle {
+    /// The code of the file the bundle runs.
+    pub fn entry_source(&self) -> anyhow::Result<&str> {
+        self.files.get(&self.entry).map(String::as_str)
+            .ok_or_else(|| anyhow::anyhow!("The bundle doesn't have its entry file, {}", self.entry))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let text = serde_json::to_string(self)?;
+
+        std::fs::write(path, text)
+            .with_context(|| format!("Could not write bundle {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Bundle> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read bundle {}", path.display()))?;
+
+        let bundle: Bundle = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid bundle {}", path.display()))?;
+
+        if bundle.format != FORMAT {
+            return Err(anyhow::anyhow!("{} was bundled by a different version of odo. Bundle it again", path.display()));
+        }
+
+        Ok(bundle)
+    }
+}
+
+impl ModuleLoader for Bundle {
+    fn resolve(&self, name: &str, importer: Option<&Path>) -> anyhow::Result<PathBuf> {
+        let importer = importer.map(|path| path.to_string_lossy()).unwrap_or_default();
+
+        self.imports.iter()
+            .find(|(from, module, _)| *from == importer && module == name)
+            .map(|(_, _, file)| PathBuf::from(file))
+            .ok_or_else(|| anyhow::anyhow!("Module {} isn't in the bundle", name))
+    }
+
+    fn read(&self, path: &Path) -> anyhow::Result<String> {
+        self.files.get(path.to_string_lossy().as_ref()).cloned()
+            .ok_or_else(|| anyhow::anyhow!("The bundle doesn't have {}", path.display()))
+    }
+}
+
+/// A loader that remembers every module `loader` finds, to bundle them. Clones share what
+/// they've seen.
+pub struct Recording<L> {
+    loader: Arc<L>,
+    seen: Arc<Mutex<Seen>>,
+}
+
+// Not derived, since the loader itself doesn't need to be Clone
+impl<L> Clone for Recording<L> {
+    fn clone(&self) -> Self {
+        Recording { loader: self.loader.clone(), seen: self.seen.clone() }
+    }
+}
+
+#[derive(Default)]
+struct Seen {
+    files: BTreeMap<PathBuf, String>,
+    imports: Vec<(Option<PathBuf>, String, PathBuf)>,
+}
+
+impl<L: ModuleLoader> Recording<L> {
+    pub fn new(loader: L) -> Recording<L> {
+        Recording { loader: Arc::new(loader), seen: Arc::default() }
+    }
+
+    /// Bundles `code`, from the file at `entry`, with the modules seen while it was analyzed.
+    /// Files are kept by their path from the closest directory they're all in.
+    pub fn bundle(&self, entry: &Path, code: String) -> anyhow::Result<Bundle> {
+        let seen = self.seen.lock().map_err(|_| anyhow::anyhow!("The recorded modules were poisoned"))?;
+
+        let mut base = entry.parent().unwrap_or(Path::new(""));
+        while !seen.files.keys().all(|path| path.starts_with(base)) {
+            base = base.parent().unwrap_or(Path::new(""));
+        }
+        let key = |path: &Path| path.strip_prefix(base).unwrap_or(path).components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let mut files: BTreeMap<_, _> = seen.files.iter()
+            .map(|(path, source)| (key(path), source.clone()))
+            .collect();
+        files.insert(key(entry), code);
+
+        // Names that were only looked up, like for suggestions, were never imported
+        let imports = seen.imports.iter()
+            .filter(|(_, _, file)| seen.files.contains_key(file))
+            .map(|(importer, name, file)| (importer.as_deref().map(key).unwrap_or_default(), name.clone(), key(file)))
+            .collect();
+
+        Ok(Bundle { format: FORMAT, entry: key(entry), files, imports })
+    }
+}
+
+impl<L: ModuleLoader> ModuleLoader for Recording<L> {
+    fn resolve(&self, name: &str, importer: Option<&Path>) -> anyhow::Result<PathBuf> {
+        let path = self.loader.resolve(name, importer)?;
+        if let Ok(mut seen) = self.seen.lock() {
+            seen.imports.push((importer.map(Path::to_path_buf), name.to_string(), path.clone()));
+        }
+
+        Ok(path)
+    }
+
+    fn read(&self, path: &Path) -> anyhow::Result<String> {
+        let source = self.loader.read(path)?;
+        if let Ok(mut seen) = self.seen.lock() {
+            seen.files.insert(path.to_path_buf(), source.clone());
+        }
+
+        Ok(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{Bundle, Recording};
+    use crate::{base::modules::ModuleLoader, exec::interpreter::Interpreter};
+
+    // Files in memory, found only beside the module importing them
+    struct Files(Vec<(&'static str, &'static str)>);
+
+    impl ModuleLoader for Files {
+        fn resolve(&self, name: &str, importer: Option<&Path>) -> anyhow::Result<PathBuf> {
+            let path = importer.and_then(Path::parent).unwrap_or(Path::new("/")).join(format!("{}.odo", name));
+            self.0.iter().find(|(file, _)| Path::new(file) == path)
+                .map(|(file, _)| PathBuf::from(file))
+                .ok_or_else(|| anyhow::anyhow!("Module {} not found", name))
+        }
+
+        fn read(&self, path: &Path) -> anyhow::Result<String> {
+            self.0.iter().find(|(file, _)| Path::new(file) == path)
+                .map(|(_, source)| source.to_string())
+                .ok_or_else(|| anyhow::anyhow!("Could not read {}", path.display()))
+        }
+    }
+
+    #[test]
+    fn test_bundle() {
+        let files = Files(vec![
+            ("/project/lib/shapes.odo", "import units\n@export\nfunc area(side: int) -> int {\n    return side * side * units.scale\n}"),
+            ("/project/lib/units.odo", "@export\nvar scale = 10"),
+            ("/project/lib/unused.odo", "var never = 0"),
+        ]);
+        let code = "import shapes\nshapes.area(3)";
+
+        let recording = Recording::new(files);
+        let mut interpreter = Interpreter::new();
+        interpreter.set_module_loader(recording.clone());
+        interpreter.set_main_module("/project/lib/main.odo");
+        interpreter.compile(code.to_string()).unwrap();
+
+        let bundle = recording.bundle(Path::new("/project/lib/main.odo"), code.to_string()).unwrap();
+        assert_eq!(bundle.entry, "main.odo");
+        assert_eq!(bundle.files.keys().collect::<Vec<_>>(), ["main.odo", "shapes.odo", "units.odo"]);
+
+        // It runs without the files it was made from
+        let bundle: Bundle = serde_json::from_str(&serde_json::to_string(&bundle).unwrap()).unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_main_module(&bundle.entry);
+        let code = bundle.entry_source().unwrap().to_string();
+        interpreter.set_module_loader(bundle);
+        assert_eq!(interpreter.eval(code).unwrap().as_int().unwrap(), 90);
+    }
+}
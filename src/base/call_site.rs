@@ -0,0 +1,59 @@
+use std::fmt;
+use std::sync::Mutex;
+
+use super::semantic_analyzer::SymbolId;
+
+// Method calls are resolved when they run, from the type the receiver has then. Finding a method
+// by name means going through the type's symbols, so each call site remembers what it found for
+// the types it has seen. Most sites only ever see one or two, so a few are enough. A site that
+// sees more than that keeps the first ones it saw, and looks the rest up every time.
+//
+// The site only remembers method symbols, not the functions bound to them. Those are looked up by
+// symbol on every call, so a method that's bound again is seen right away, and one that's gone
+// (after an undo, say) is a miss.
+
+const TYPES_REMEMBERED: usize = 4;
+
+/// What a method call resolved to before, by receiver type.
+#[derive(Default)]
+pub struct CallSite(Mutex<Vec<(SymbolId, SymbolId)>>);
+
+impl CallSite {
+    fn targets(&self) -> std::sync::MutexGuard<'_, Vec<(SymbolId, SymbolId)>> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The method this site called for receivers of `type_id`, if it has called one.
+    pub fn target(&self, type_id: SymbolId) -> Option<SymbolId> {
+        self.targets().iter()
+            .find(|(seen, _)| *seen == type_id)
+            .map(|(_, method_id)| *method_id)
+    }
+
+    pub fn remember(&self, type_id: SymbolId, method_id: SymbolId) {
+        let mut targets = self.targets();
+        targets.retain(|(seen, _)| *seen != type_id);
+        if targets.len() < TYPES_REMEMBERED {
+            targets.push((type_id, method_id));
+        }
+    }
+
+    /// Drops what the site remembers for `type_id`, once the method isn't bound anymore.
+    pub fn forget(&self, type_id: SymbolId) {
+        self.targets().retain(|(seen, _)| *seen != type_id);
+    }
+}
+
+// Copies start out knowing what the original knows
+impl Clone for CallSite {
+    fn clone(&self) -> CallSite {
+        CallSite(Mutex::new(self.targets().clone()))
+    }
+}
+
+impl fmt::Debug for CallSite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CallSite({} types)", self.targets().len())
+    }
+}
+
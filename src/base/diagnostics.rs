@@ -0,0 +1,168 @@
+use serde::Serialize;
+
+use crate::base::{lexer, modules::{CircularImport, ModuleError}, parser, semantic_analyzer::Warning, suggest::NotFound, xref::Span};
+
+// Errors and warnings in a shape tools can read, like editors and CI.
+// Everything that reports problems with `--error-format=json` goes through here.
+
+/// An error found before the code ran: while lexing, parsing or analyzing it.
+/// It reads just like the error it wraps.
+#[derive(Debug)]
+pub struct CompileError(pub anyhow::Error);
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for CompileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
+/// Whether the code `error` came from failed to compile, instead of failing while it ran.
+pub fn is_compile_error(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.is::<CompileError>())
+}
+
+// Every error in the chain, with the ones CompileErrors and ModuleErrors wrap after them
+fn causes(error: &anyhow::Error) -> Vec<&(dyn std::error::Error + 'static)> {
+    let mut causes = vec![];
+    for cause in error.chain() {
+        if let Some(compile_error) = cause.downcast_ref::<CompileError>() {
+            causes.extend(self::causes(&compile_error.0));
+            break;
+        }
+
+        causes.push(cause);
+        if let Some(module_error) = cause.downcast_ref::<ModuleError>() {
+            causes.extend(self::causes(&module_error.error));
+            break;
+        }
+    }
+
+    causes
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    // What kind of problem it is: syntax, unknown-name, semantic or runtime for errors,
+    // and the name of the lint for warnings
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub file: Option<String>,
+    // None when the problem isn't about a specific place, like most runtime errors
+    pub span: Option<Span>,
+    // Names that might have been meant instead
+    pub suggestions: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Describes an error from lexing, parsing, analyzing or running code in `file`.
+    pub fn from_error(error: &anyhow::Error, file: Option<&str>) -> Diagnostic {
+        let mut diagnostic = Diagnostic {
+            code: if is_compile_error(error) { "semantic" } else { "runtime" },
+            severity: Severity::Error,
+            message: error.to_string(),
+            file: file.map(str::to_string),
+            span: None,
+            suggestions: vec![],
+        };
+
+        // Context is added on top of the errors that know where they happened, so any of them could
+        for cause in causes(error) {
+            // Spans in an imported module are in its file
+            if let Some(module_error) = cause.downcast_ref::<ModuleError>() {
+                diagnostic.file = Some(module_error.path.display().to_string());
+                continue;
+            }
+
+            if let Some(lexer_diagnostic) = cause.downcast_ref::<lexer::Diagnostic>() {
+                diagnostic.code = "syntax";
+                diagnostic.message = lexer_diagnostic.error.to_string();
+                diagnostic.span = Some(Span::at(lexer_diagnostic.line, lexer_diagnostic.column));
+            } else if let Some(parser_error) = cause.downcast_ref::<parser::Error>() {
+                diagnostic.code = "syntax";
+                diagnostic.span = parser_error.token().map(Span::of);
+            } else if let Some(cycle) = cause.downcast_ref::<CircularImport>() {
+                diagnostic.file = Some(cycle.path().display().to_string());
+                diagnostic.span = cycle.imports.last().map(Span::of);
+            } else if let Some(not_found) = cause.downcast_ref::<NotFound>() {
+                diagnostic.code = "unknown-name";
+                diagnostic.message = not_found.message.clone();
+                diagnostic.suggestions = not_found.suggestions.clone();
+                diagnostic.span = not_found.span;
+            } else {
+                continue;
+            }
+
+            break;
+        }
+
+        diagnostic
+    }
+
+    pub fn from_warning(warning: &Warning, file: Option<&str>) -> Diagnostic {
+        Diagnostic {
+            code: warning.lint.name(),
+            severity: Severity::Warning,
+            message: warning.message.clone(),
+            file: file.map(str::to_string),
+            span: Some(warning.span),
+            suggestions: vec![],
+        }
+    }
+
+    /// One line of JSON, without the line break.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Diagnostics should always serialize")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Diagnostic, Severity};
+    use crate::base::xref::Span;
+    use crate::exec::interpreter::Interpreter;
+
+    fn diagnostic(code: &str) -> Diagnostic {
+        let error = Interpreter::new().eval(code.to_string()).err().expect("The code should fail");
+        Diagnostic::from_error(&error, Some("main.odo"))
+    }
+
+    #[test]
+    fn test_error_diagnostics() {
+        let unknown = diagnostic("var total = 1\ntotl + 1");
+        assert_eq!(unknown.code, "unknown-name");
+        assert_eq!(unknown.severity, Severity::Error);
+        assert_eq!(unknown.message, "Variable totl not found");
+        assert_eq!(unknown.suggestions, ["total"]);
+        assert_eq!(unknown.span, Some(Span { line: 2, column: 0, length: 4 }));
+
+        let unterminated = diagnostic("var x = \"abc");
+        assert_eq!(unterminated.code, "syntax");
+        assert_eq!(unterminated.span, Some(Span::at(1, 8)));
+
+        assert_eq!(diagnostic("var x = 1\nx = \"text\"").code, "semantic");
+        assert_eq!(diagnostic("var x = 1 / (1 - 1)").code, "runtime");
+
+        let reserved = diagnostic("var if = 1");
+        assert_eq!(reserved.code, "syntax");
+        assert_eq!(reserved.span.map(|span| span.column), Some(4));
+
+        let json: serde_json::Value = serde_json::from_str(&unknown.to_json()).unwrap();
+        assert_eq!(json["severity"], "error");
+        assert_eq!(json["file"], "main.odo");
+        assert_eq!(json["span"]["line"], 2);
+    }
+}
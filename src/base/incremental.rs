@@ -0,0 +1,171 @@
+use crate::base::{
+    lexer::{Lexer, DEFAULT_TAB_WIDTH},
+    parser::Parser,
+    semantic_analyzer::{Checkpoint, SemanticAnalyzer, TableId},
+};
+
+// Analysis of a buffer that's edited over and over, like in an editor.
+// Only the top-level statements from the first one that changed are lexed and analyzed again,
+// the ones before keep their symbols.
+
+pub struct Buffer {
+    // Where the buffer's top-level declarations live
+    scope_id: TableId,
+    statements: Vec<Statement>,
+    tab_width: usize,
+}
+
+struct Statement {
+    first_line: usize,
+    text: String,
+    // The analyzer before this statement, to undo it and everything after it
+    checkpoint: Checkpoint,
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BufferError {
+    // First line of the statement that failed
+    pub line: usize,
+    pub message: String,
+}
+
+impl Buffer {
+    /// An empty buffer, analyzed in a scope nested in the analyzer's current one.
+    pub fn new(analyzer: &mut SemanticAnalyzer) -> Buffer {
+        Buffer {
+            scope_id: analyzer.create_scope("buffer", analyzer.current_scope_id),
+            statements: Vec::new(),
+            tab_width: DEFAULT_TAB_WIDTH,
+        }
+    }
+
+    pub fn tab_width(mut self, width: usize) -> Buffer {
+        self.tab_width = width;
+        self
+    }
+
+    /// Brings the analysis up to date with `code`. Returns how many statements had to be analyzed again.
+    pub fn update(&mut self, analyzer: &mut SemanticAnalyzer, code: &str) -> anyhow::Result<usize> {
+        let chunks = split_statements(code);
+
+        let unchanged = self.statements.iter()
+            .zip(&chunks)
+            .take_while(|(statement, (first_line, text))| statement.first_line == *first_line && statement.text == *text)
+            .count();
+
+        if let Some(first_changed) = self.statements.get(unchanged) {
+            analyzer.rollback(&first_changed.checkpoint)?;
+        }
+        self.statements.truncate(unchanged);
+
+        let analyzed = chunks.len() - unchanged;
+
+        for (first_line, text) in chunks.into_iter().skip(unchanged) {
+            let checkpoint = analyzer.checkpoint(self.scope_id)?;
+
+            let error = analyzer.in_scope(self.scope_id, |analyzer| self.analyze(analyzer, first_line, &text))
+                .err()
+                .map(|e| format!("{:#}", e));
+
+            self.statements.push(Statement { first_line, text, checkpoint, error });
+        }
+
+        Ok(analyzed)
+    }
+
+    pub fn errors(&self) -> Vec<BufferError> {
+        self.statements.iter()
+            .filter_map(|statement| statement.error.as_ref().map(|message| BufferError {
+                line: statement.first_line,
+                message: message.clone(),
+            }))
+            .collect()
+    }
+
+    fn analyze(&self, analyzer: &mut SemanticAnalyzer, first_line: usize, text: &str) -> anyhow::Result<()> {
+        let tokens = Lexer::new(text.to_string())
+            .first_line(first_line)
+            .tab_width(self.tab_width)
+            .tokenize()?;
+
+        for node in Parser::new(tokens).statement_list()? {
+            analyzer.analyze_node(node)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Top-level statements and the line each starts at. A statement goes on until the end of a
+// line where every bracket it opened is closed. Blank lines don't belong to any.
+fn split_statements(code: &str) -> Vec<(usize, String)> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut first_line = 1;
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (index, line) in code.split_inclusive('\n').enumerate() {
+        if current.is_empty() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            first_line = index + 1;
+        }
+
+        for c in line.chars() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = !in_string,
+                '(' | '[' | '{' if !in_string => depth += 1,
+                ')' | ']' | '}' if !in_string => depth -= 1,
+                _ => {}
+            }
+        }
+        // Strings end with the line, closed or not
+        in_string = false;
+        escaped = false;
+
+        current.push_str(line);
+
+        if depth <= 0 {
+            statements.push((first_line, std::mem::take(&mut current)));
+            depth = 0;
+        }
+    }
+
+    if !current.is_empty() {
+        statements.push((first_line, current));
+    }
+
+    statements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Buffer;
+    use crate::base::semantic_analyzer::SemanticAnalyzer;
+
+    #[test]
+    fn test_only_changes_are_analyzed() {
+        let mut analyzer = SemanticAnalyzer::new();
+        let mut buffer = Buffer::new(&mut analyzer);
+
+        let code = "var a = 1\nfunc f(x: int) -> int {\n    return x + a\n}\n\nvar b = f(a)\n";
+        assert_eq!(buffer.update(&mut analyzer, code).unwrap(), 3);
+        assert!(buffer.errors().is_empty());
+
+        let code = "var a = 1\nfunc f(x: int) -> int {\n    return x + a\n}\n\nvar b = f(a) + \"no\"\n";
+        assert_eq!(buffer.update(&mut analyzer, code).unwrap(), 1);
+        assert_eq!(buffer.errors()[0].line, 6);
+
+        // Without `a`, everything that uses it fails
+        let code = "var c = 1\nfunc f(x: int) -> int {\n    return x + a\n}\n\nvar b = f(a)\n";
+        assert_eq!(buffer.update(&mut analyzer, code).unwrap(), 3);
+        let lines: Vec<_> = buffer.errors().iter().map(|error| error.line).collect();
+        assert_eq!(lines, [2, 6]);
+    }
+}
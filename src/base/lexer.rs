@@ -1,16 +1,16 @@
-use std::collections::HashMap;
+use std::cell::Cell;
 
-use lazy_static::lazy_static;
+use serde::{Serialize, Deserialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Token {
     pub token_type: TokenType,
     pub value: String,
-    line: usize,
-    column: usize,
+    pub line: usize,
+    pub column: usize,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TokenType {
     // for now, just a variable assignment and number type
     Var, // var a = 10.0
@@ -20,52 +20,272 @@ pub enum TokenType {
     Text,
 
     Assign, // =
+    Arrow, // ->
+
+    // Comparison
+    Equal, // ==
+    NotEqual, // !=
+    Less, // <
+    LessEqual, // <=
+    Greater, // >
+    GreaterEqual, // >=
+
+    // Logic
+    And,
+    Or,
+    In,
+
+    // Arithmetic
+    Plus, // +
+    Minus, // -
+    Star, // *
+    Slash, // /
+    Percent, // %
+
+    // Compound assignment
+    PlusAssign, // +=
+    MinusAssign, // -=
+    StarAssign, // *=
+    SlashAssign, // /=
+    PercentAssign, // %=
+
+    // Not supported, but recognized to suggest `+= 1` and `-= 1`
+    Increment, // ++
+    Decrement, // --
 
     NewLine, // \n
+    Pragma, // #allow(dead-code), a line of its own. The value is everything after the #
 
     SemiColon, // ;
     Comma, // ,
+    Dot, // .
+    Ellipsis, // ...
+    Underscore, // _, in patterns
+    At, // @, before an annotation
 
     LeftCurly, // {
     RightCurly, // }
     LeftParen, // (
     RightParen, // )
+    LeftBracket, // [
+    RightBracket, // ]
 
     // Control flow
     If,
+    With,
+    Match,
+    Defer,
+    For,
+
+    // Functions
+    Func,
+    Return,
+    Yield,
+
+    // User types
+    Type,
+    Contract,
+    Extend,
 
-    DebugPrint // ':' - Temporary
+    // Modules
+    Import,
+
+    Colon // ':' - Also DebugPrint at the start of a statement. Temporary
+}
+
+impl TokenType {
+    // `+=` to `+`, and so on
+    pub fn compound_operator(&self) -> Option<TokenType> {
+        match self {
+            TokenType::PlusAssign => Some(TokenType::Plus),
+            TokenType::MinusAssign => Some(TokenType::Minus),
+            TokenType::StarAssign => Some(TokenType::Star),
+            TokenType::SlashAssign => Some(TokenType::Slash),
+            TokenType::PercentAssign => Some(TokenType::Percent),
+            _ => None
+        }
+    }
+
+    pub fn is_comparison(&self) -> bool {
+        matches!(self,
+            TokenType::Equal | TokenType::NotEqual |
+            TokenType::Less | TokenType::LessEqual |
+            TokenType::Greater | TokenType::GreaterEqual |
+            TokenType::In
+        )
+    }
 }
 
 pub struct Lexer {
     code: String,
+    // Byte offset into `code`
     position: usize,
-    current_line: usize,
-    current_column: usize,
+    // Byte offset where each line starts. Lines and columns are computed from it, instead of being tracked while advancing.
+    line_starts: Vec<usize>,
+    // Columns a tab takes up when reporting positions
+    tab_width: usize,
+    // Byte offset and column of the last position computed. Tokens are located in order, so each
+    // one continues from the last instead of counting from the start of a possibly very long line.
+    last_location: Cell<(usize, usize)>,
+    // Line reported for the start of `code`, when it's a piece of a bigger file
+    first_line: usize,
+    // Problems found while lexing. The lexer keeps going, so the first one is usually the one to report.
+    diagnostics: Vec<Diagnostic>,
+    // Limits for untrusted code. Past `max_tokens` the lexer stops.
+    max_tokens: Option<usize>,
+    max_text_length: Option<usize>,
+    token_count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    UnknownEscape(char),
+    InvalidEscape(String), // What's wrong with it
+    UnterminatedString,
+    TooManyTokens(usize), // The limit
+    TextTooLong(usize), // The limit, in characters
+    MisplacedPragma,
+    UnexpectedCharacter(char),
+}
+
+impl Error {
+    fn description(&self) -> String {
+        match self {
+            Error::UnknownEscape(c) => format!("Unknown escape sequence \\{}", c),
+            Error::InvalidEscape(reason) => format!("Invalid escape sequence: {}", reason),
+            Error::UnterminatedString => "Unterminated string literal".to_string(),
+            Error::TooManyTokens(limit) => format!("The code has more than {} tokens", limit),
+            Error::TextTooLong(limit) => format!("String literal longer than {} characters", limit),
+            Error::MisplacedPragma => "Pragmas go on a line of their own".to_string(),
+            Error::UnexpectedCharacter(c) => format!("Unexpected character {:?}", c),
+        }
+    }
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub error: Error,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} (line {}, column {})", self.error, self.line, self.column)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
 impl Lexer {
     pub fn new(code: String) -> Lexer {
+        let line_starts = std::iter::once(0)
+            .chain(code.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
         Lexer {
             code,
             position: 0,
-            current_line: 1,
-            current_column: 0,
+            line_starts,
+            tab_width: DEFAULT_TAB_WIDTH,
+            last_location: Cell::new((0, 0)),
+            first_line: 1,
+            diagnostics: Vec::new(),
+            max_tokens: None,
+            max_text_length: None,
+            token_count: 0,
         }
     }
 
+    pub fn tab_width(mut self, width: usize) -> Lexer {
+        self.tab_width = width.max(1);
+        self
+    }
+
+    pub fn first_line(mut self, line: usize) -> Lexer {
+        self.first_line = line;
+        self
+    }
+
+    pub fn max_tokens(mut self, limit: Option<usize>) -> Lexer {
+        self.max_tokens = limit;
+        self
+    }
+
+    pub fn max_text_length(mut self, limit: Option<usize>) -> Lexer {
+        self.max_text_length = limit;
+        self
+    }
+
+    /// All the tokens, or the first problem found.
+    pub fn tokenize(mut self) -> anyhow::Result<Vec<Token>> {
+        let tokens: Vec<_> = self.by_ref().collect();
+
+        match self.diagnostics.into_iter().next() {
+            Some(diagnostic) => Err(diagnostic.into()),
+            None => Ok(tokens)
+        }
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Line (starting at 1) and column (starting at 0) of a byte offset.
+    /// Tabs move the column to the next multiple of the tab width.
+    pub fn location(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|start| *start <= offset);
+        let line_start = self.line_starts[line - 1];
+
+        let (from, column) = match self.last_location.get() {
+            (last, column) if line_start <= last && last <= offset => (last, column),
+            _ => (line_start, 0)
+        };
+
+        let column = self.code[from..offset].chars().fold(column, |column, c| {
+            if c == '\t' {
+                (column / self.tab_width + 1) * self.tab_width
+            } else {
+                column + 1
+            }
+        });
+        self.last_location.set((offset, column));
+
+        (line + self.first_line - 1, column)
+    }
+
+    fn report(&mut self, error: Error, offset: usize) {
+        let (line, column) = self.location(offset);
+        self.diagnostics.push(Diagnostic { error, line, column });
+    }
+
+    // Whether there's only whitespace between the start of the line and `offset`
+    fn at_line_start(&self, offset: usize) -> bool {
+        let line_start = self.code[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+        self.code[line_start..offset].chars().all(char::is_whitespace)
+    }
+
     fn current_char(&self) -> Option<char> {
-        self.code.chars().nth(self.position)
+        self.code[self.position..].chars().next()
     }
 
-    fn advance(&mut self) {
-        self.position += 1;
-        self.current_column += 1;
+    fn peek_char(&self) -> Option<char> {
+        self.code[self.position..].chars().nth(1)
+    }
 
-        if let Some('\n') = self.current_char() {
-            self.current_line += 1;
-            self.position += 1;
-            self.current_column = 0;
+    fn advance(&mut self) {
+        if let Some(c) = self.current_char() {
+            self.position += c.len_utf8();
         }
     }
 
@@ -80,30 +300,93 @@ impl Lexer {
     }
 }
 
-lazy_static! {
-    static ref KEYWORDS: HashMap<String, TokenType> = {
-        let mut map = HashMap::new();
-        map.insert("var".to_string(), TokenType::Var);
-        map.insert("true".to_string(), TokenType::Truth);
-        map.insert("false".to_string(), TokenType::Truth);
-        map.insert("if".to_string(), TokenType::If);
-        map
-    };
+// Every reserved word and the token it becomes. Anything that needs to know the keywords should go through this table.
+pub const KEYWORDS: &[(&str, TokenType)] = &[
+    ("var", TokenType::Var),
+    ("true", TokenType::Truth),
+    ("false", TokenType::Truth),
+    ("if", TokenType::If),
+    ("with", TokenType::With),
+    ("match", TokenType::Match),
+    ("defer", TokenType::Defer),
+    ("for", TokenType::For),
+    ("func", TokenType::Func),
+    ("return", TokenType::Return),
+    ("yield", TokenType::Yield),
+    ("type", TokenType::Type),
+    ("contract", TokenType::Contract),
+    ("extend", TokenType::Extend),
+    ("import", TokenType::Import),
+    ("and", TokenType::And),
+    ("or", TokenType::Or),
+    ("in", TokenType::In),
+];
+
+// Operators and punctuation. Longer symbols go first, so `==` isn't lexed as two `=`.
+pub const SYMBOLS: &[(&str, TokenType)] = &[
+    ("...", TokenType::Ellipsis),
+    ("==", TokenType::Equal),
+    ("!=", TokenType::NotEqual),
+    ("<=", TokenType::LessEqual),
+    (">=", TokenType::GreaterEqual),
+    ("->", TokenType::Arrow),
+    ("+=", TokenType::PlusAssign),
+    ("-=", TokenType::MinusAssign),
+    ("*=", TokenType::StarAssign),
+    ("/=", TokenType::SlashAssign),
+    ("%=", TokenType::PercentAssign),
+    ("++", TokenType::Increment),
+    ("--", TokenType::Decrement),
+    ("=", TokenType::Assign),
+    ("<", TokenType::Less),
+    (">", TokenType::Greater),
+    ("+", TokenType::Plus),
+    ("-", TokenType::Minus),
+    ("*", TokenType::Star),
+    ("/", TokenType::Slash),
+    ("%", TokenType::Percent),
+    ("{", TokenType::LeftCurly),
+    ("}", TokenType::RightCurly),
+    ("(", TokenType::LeftParen),
+    (")", TokenType::RightParen),
+    ("[", TokenType::LeftBracket),
+    ("]", TokenType::RightBracket),
+    (",", TokenType::Comma),
+    (".", TokenType::Dot),
+    ("_", TokenType::Underscore),
+    (";", TokenType::SemiColon),
+    (":", TokenType::Colon),
+    ("@", TokenType::At),
+];
+
+pub fn keyword(word: &str) -> Option<TokenType> {
+    KEYWORDS.iter()
+        .find(|(keyword, _)| *keyword == word)
+        .map(|(_, token_type)| token_type.clone())
 }
 
 // Implement the Iterator trait for Lexer
 impl Iterator for Lexer {
     type Item = Token;
 
-    // FIXME: Handle errors while iterating
     fn next(&mut self) -> Option<Token> {
         self.ignore_whitespace();
         if let Some(curr) = self.current_char() {
+            let start = self.position;
+
+            if let Some(limit) = self.max_tokens.filter(|limit| self.token_count >= *limit) {
+                self.report(Error::TooManyTokens(limit), start);
+                self.position = self.code.len();
+                return None;
+            }
+            self.token_count += 1;
+            let (line, column) = self.location(start);
+
             let mut token = Token {
                 token_type: TokenType::Var,
                 value: String::new(),
-                line: self.current_line,
-                column: self.current_column,
+                line,
+                column,
             };
 
             if curr.is_alphabetic() {
@@ -117,15 +400,25 @@ impl Iterator for Lexer {
                     self.advance();
                 }
 
-                if let Some(token_type) = KEYWORDS.get(&token.value) {
-                    token.token_type = token_type.clone();
+                if let Some(token_type) = keyword(&token.value) {
+                    token.token_type = token_type;
                 } else {
                     token.token_type = TokenType::Name;
                 }
-            } else if curr.is_numeric() {
+            } else if curr.is_ascii_digit() {
+                // Only ASCII digits, so numbers read the same whatever the locale
                 token.token_type = TokenType::Number;
                 while let Some(c) = self.current_char() {
-                    if !c.is_numeric() {
+                    // A single dot followed by a digit makes it a dec, like `1.5`
+                    let decimal_point = c == '.'
+                        && !token.value.contains('.')
+                        && self.peek_char().is_some_and(|next| next.is_ascii_digit());
+                    // Digits can be grouped with `_` between them, like `1_000`
+                    let separator = c == '_'
+                        && token.value.ends_with(|c: char| c.is_ascii_digit())
+                        && self.peek_char().is_some_and(|next| next.is_ascii_digit());
+
+                    if !c.is_ascii_digit() && !decimal_point && !separator {
                         break;
                     }
 
@@ -135,51 +428,38 @@ impl Iterator for Lexer {
             } else if curr == '\"' {
                 token.token_type = TokenType::Text;
                 self.advance();
-                
-                let text_token = self.text();
-                match text_token {
-                    Ok(text_token) => {
-                        token.value = text_token.value;
-                    },
-                    Err(e) => {
-                        eprintln!("Error in text literal: {}", e);
-                        return None;
-                    }
+
+                token.value = self.text(start);
+                if let Some(limit) = self.max_text_length.filter(|limit| token.value.chars().count() > *limit) {
+                    self.report(Error::TextTooLong(limit), start);
+                }
+            } else if curr == '#' {
+                token.token_type = TokenType::Pragma;
+                if !self.at_line_start(start) {
+                    self.report(Error::MisplacedPragma, start);
                 }
+
+                self.advance();
+                while let Some(c) = self.current_char().filter(|c| *c != '\n') {
+                    token.value.push(c);
+                    self.advance();
+                }
+                token.value.truncate(token.value.trim_end().len());
             } else if curr == '\n' {
                 token.token_type = TokenType::NewLine;
                 token.value.push(curr);
                 self.advance();
-            } else if curr == '=' {
-                token.token_type = TokenType::Assign;
-                token.value.push(curr);
-                self.advance();
-            } else if curr == '{' {
-                token.token_type = TokenType::LeftCurly;
-                token.value.push(curr);
-                self.advance();
-            } else if curr == '}' {
-                token.token_type = TokenType::RightCurly;
-                token.value.push(curr);
-                self.advance();
-            } else if curr == '(' {
-                token.token_type = TokenType::LeftParen;
-                token.value.push(curr);
-                self.advance();
-            } else if curr == ')' {
-                token.token_type = TokenType::RightParen;
-                token.value.push(curr);
-                self.advance();
-            } else if curr == ';' {
-                token.token_type = TokenType::SemiColon;
-                token.value.push(curr);
-                self.advance();
-            } else if curr == ':' {
-                token.token_type = TokenType::DebugPrint;
-                token.value.push(curr);
-                self.advance();
+            } else if let Some((symbol, token_type)) = SYMBOLS.iter().find(|(symbol, _)| self.code[self.position..].starts_with(symbol)) {
+                token.token_type = token_type.clone();
+                token.value.push_str(symbol);
+                for _ in symbol.chars() {
+                    self.advance();
+                }
             } else {
-                panic!("Unexpected character: {}", curr);
+                // Nothing after it can be trusted to mean what it looks like
+                self.report(Error::UnexpectedCharacter(curr), start);
+                self.position = self.code.len();
+                return None;
             }
 
             Some(token)
@@ -190,71 +470,173 @@ impl Iterator for Lexer {
 }
 
 impl Lexer {
-    fn escape_char(&mut self) -> Option<char> {
-        let escape: HashMap<char, char> = [
-            ('\\', '\\'),
-            ('n', '\n'),
-            ('r', '\r'),
-            ('t', '\t'),
-            ('b', '\x08'),
-            ('a', '\x07'),
-            ('v', '\x0B'),
-            ('\'', '\''),
-            ('\"', '\"'),
-            ('?', '\x3F'),
-        ].iter().cloned().collect();
-
-        let escapee = match self.current_char() {
-            Some(c) => c,
-            None => return None // Unexpected end of file
+    // After a backslash. `None` means an unexpected end of file
+    fn escape_char(&mut self, backslash: usize) -> Option<char> {
+        let escapee = self.current_char()?;
+        self.advance();
+
+        let escaped = match escapee {
+            '\\' => '\\',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'b' => '\x08',
+            'a' => '\x07',
+            'v' => '\x0B',
+            '0' => '\0',
+            '\'' => '\'',
+            '\"' => '\"',
+            '?' => '?',
+            'x' => {
+                // \x7F. Only ascii, so every string stays valid utf-8
+                let digits: String = (0..2).filter_map(|_| self.hex_digit()).collect();
+
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(byte) if digits.len() == 2 && byte <= 0x7F => byte as char,
+                    _ => {
+                        self.report(Error::InvalidEscape(format!("\\x{} must be two hex digits up to 7F", digits)), backslash);
+                        '\u{FFFD}'
+                    }
+                }
+            },
+            'u' => {
+                // \u{1F600}
+                let mut digits = String::new();
+                let mut closed = false;
+
+                if self.current_char() == Some('{') {
+                    self.advance();
+
+                    while let Some(digit) = self.hex_digit() {
+                        digits.push(digit);
+                    }
+
+                    if self.current_char() == Some('}') {
+                        self.advance();
+                        closed = true;
+                    }
+                }
+
+                let c = u32::from_str_radix(&digits, 16).ok()
+                    .filter(|_| closed && digits.len() <= 6)
+                    .and_then(char::from_u32);
+
+                match c {
+                    Some(c) => c,
+                    None => {
+                        self.report(Error::InvalidEscape(format!("\\u{{{}}} is not a valid unicode escape", digits)), backslash);
+                        '\u{FFFD}'
+                    }
+                }
+            },
+            other => {
+                // Keep the character, so lexing can go on
+                self.report(Error::UnknownEscape(other), backslash);
+                other
+            }
         };
 
-        let found_in_dict = escape.get(&escapee);
-        self.advance();
-        if let Some(c) = found_in_dict {
-            return Some(*c);
-        } else {
-            return Some(escapee);
-        }
+        Some(escaped)
     }
 
-    fn text(&mut self) -> anyhow::Result<Token> {
-        let mut token = Token {
-            token_type: TokenType::Text,
-            value: String::new(),
-            line: self.current_line,
-            column: self.current_column,
-        };
+    fn hex_digit(&mut self) -> Option<char> {
+        let c = self.current_char().filter(|c| c.is_ascii_hexdigit())?;
+        self.advance();
 
+        Some(c)
+    }
 
-        let mut found_end = false;
+    // Right after the opening quote, at `start`.
+    // A string that isn't closed before the end of the line ends there, so the parser can keep going.
+    fn text(&mut self, start: usize) -> String {
+        let mut value = String::new();
 
         while let Some(c) = self.current_char() {
             if c == '\"' {
-                found_end = true;
                 self.advance();
+                return value;
+            }
+
+            if c == '\n' {
                 break;
             }
 
             if c == '\\' {
+                let backslash = self.position;
                 self.advance();
-                let escaped = match self.escape_char() {
-                    Some(c) => c,
-                    None => return Err(anyhow::anyhow!("Unexpected end of file"))
-                };
-
-                token.value.push(escaped);
+                match self.escape_char(backslash) {
+                    Some(escaped) => value.push(escaped),
+                    None => break
+                }
             } else {
-                token.value.push(c);
+                value.push(c);
                 self.advance();
             }
         }
 
-        if found_end {
-            Ok(token)
-        } else {
-            Err(anyhow::anyhow!("Untermited string literal"))
-        } 
+        self.report(Error::UnterminatedString, start);
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Lexer, Error, Token, TokenType};
+
+    fn text(code: &str) -> anyhow::Result<String> {
+        let tokens = Lexer::new(code.to_string()).tokenize()?;
+
+        Ok(tokens[0].value.clone())
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(text(r#""a\tb\0""#).unwrap(), "a\tb\0");
+        assert_eq!(text(r#""\x41\u{1F600}""#).unwrap(), "A\u{1F600}");
 
+        let mut lexer = Lexer::new(r#"var s = "bad \q escape""#.to_string());
+        let _ = lexer.by_ref().count();
+        let diagnostic = &lexer.diagnostics()[0];
+        assert_eq!(diagnostic.error, Error::UnknownEscape('q'));
+        assert_eq!((diagnostic.line, diagnostic.column), (1, 13));
+
+        assert!(text(r#""\xFF""#).is_err());
+        assert!(text(r#""\u{110000}""#).is_err());
+        assert!(text(r#""\u{41""#).is_err());
+    }
+
+    #[test]
+    fn test_unterminated_string() {
+        let mut lexer = Lexer::new("var a = 1\nvar s = \"open\nvar b = 2".to_string());
+        let tokens: Vec<_> = lexer.by_ref().collect();
+
+        let diagnostic = &lexer.diagnostics()[0];
+        assert_eq!(diagnostic.error, Error::UnterminatedString);
+        assert_eq!((diagnostic.line, diagnostic.column), (2, 8));
+
+        // The rest of the program is still there
+        let text = tokens.iter().find(|t| t.token_type == TokenType::Text).unwrap();
+        assert_eq!(text.value, "open");
+        assert_eq!(tokens.last().unwrap().value, "2");
+    }
+
+    #[test]
+    fn test_positions() {
+        let code = "var a = 1\r\n\n\tvar é = \"ñ\"\n\t  x\tb".to_string();
+        let tokens = Lexer::new(code.clone()).tokenize().unwrap();
+        let positions = |tokens: &[Token]| tokens.iter()
+            .filter(|t| t.token_type != TokenType::NewLine)
+            .map(|t| (t.value.clone(), t.line, t.column))
+            .collect::<Vec<_>>();
+
+        assert_eq!(positions(&tokens), [
+            ("var".to_string(), 1, 0), ("a".to_string(), 1, 4), ("=".to_string(), 1, 6), ("1".to_string(), 1, 8),
+            ("var".to_string(), 3, 4), ("é".to_string(), 3, 8), ("=".to_string(), 3, 10), ("ñ".to_string(), 3, 12),
+            ("x".to_string(), 4, 6), ("b".to_string(), 4, 8),
+        ]);
+
+        let tokens = Lexer::new(code).tab_width(2).tokenize().unwrap();
+        let last = &positions(&tokens)[8..];
+        assert_eq!(last, [("x".to_string(), 4, 4), ("b".to_string(), 4, 6)]);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,176 @@
+use crate::base::{lexer::Token, parser::Ast, suggest::{self, NotFound}};
+
+// The kinds of warnings the analyzer gives, each with a name to turn it off by.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    ConstantCondition, // A condition that only uses literals
+    DeadCode, // Code that can never run
+    DivisionByZero, // Dividing by a literal zero
+    Deprecated, // Using something marked `@deprecated`
+}
+
+impl Lint {
+    pub const ALL: [Lint; 4] = [Lint::ConstantCondition, Lint::DeadCode, Lint::DivisionByZero, Lint::Deprecated];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::ConstantCondition => "constant-condition",
+            Lint::DeadCode => "dead-code",
+            Lint::DivisionByZero => "division-by-zero",
+            Lint::Deprecated => "deprecated",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Lint, NotFound> {
+        Lint::ALL.into_iter()
+            .find(|lint| lint.name() == name)
+            .ok_or_else(|| suggest::with_suggestions(
+                format!("There's no lint called {}", name),
+                name,
+                Lint::ALL.iter().map(Lint::name)
+            ))
+    }
+}
+
+impl std::fmt::Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// Whether an expression only uses literals, so it's the same every time it runs
+pub(crate) fn is_constant(ast: &Ast) -> bool {
+    match ast {
+        Ast::Number(_) | Ast::Truth(_) | Ast::Text(_) => true,
+        Ast::BinaryOperation(left, _, right) => is_constant(left) && is_constant(right),
+        Ast::Comparison(first, rest) => is_constant(first) && rest.iter().all(|(_, operand)| is_constant(operand)),
+        Ast::Negation(_, value) => is_constant(value),
+        _ => false
+    }
+}
+
+// The first token of a statement or expression, to point warnings at it
+pub(crate) fn first_token(ast: &Ast) -> Option<&Token> {
+    match ast {
+        Ast::Number(token) | Ast::Truth(token) | Ast::Text(token) | Ast::Variable(token) |
+        Ast::Declaration(token, _) | Ast::TypedDeclaration(token, ..) | Ast::FunctionDeclaration(token, ..) | Ast::Negation(token, _) |
+        Ast::TypeDeclaration(token, ..) | Ast::ContractDeclaration(token, _) | Ast::Extension(token, _) | Ast::Import(token, _) | Ast::Match(token, ..) | Ast::Defer(token, _) | Ast::For(token, ..) => Some(token),
+        Ast::PatternDeclaration(pattern, _) => Some(pattern.token()),
+        Ast::MultipleDeclaration(declarations) => declarations.first().map(|(token, _)| token),
+        Ast::MemberAccess(node, _) | Ast::Assignment(node, _) | Ast::BinaryOperation(node, ..) | Ast::Comparison(node, _) |
+        Ast::FunctionCall(node, _) | Ast::Spread(node) | Ast::Yield(node) | Ast::If(node, _) | Ast::DebugPrint(node) => first_token(node),
+        Ast::MultipleAssignment(nodes, _) | Ast::List(nodes) | Ast::Block(nodes) => nodes.first().and_then(|node| first_token(node)),
+        Ast::Map(entries) => entries.first().and_then(|(key, _)| first_token(key)),
+        Ast::Function(parameters, _, body) => parameters.first().map(|(name, _)| name).or_else(|| first_token(body)),
+        Ast::Return(value) => value.as_ref().and_then(|value| first_token(value)),
+        Ast::Allow(_, statement) | Ast::Annotated(_, statement) => first_token(statement),
+    }
+}
+
+// Whether pragmas turned `lint` off for a statement
+pub(crate) fn allows(ast: &Ast, lint: Lint) -> bool {
+    matches!(ast, Ast::Allow(names, _) if names.iter().any(|name| name.value == lint.name()))
+}
+
+pub(crate) fn is_return(ast: &Ast) -> bool {
+    match ast {
+        Ast::Return(_) => true,
+        Ast::Allow(_, statement) => is_return(statement),
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lint;
+    use crate::{base::annotations::Kind, exec::interpreter::Interpreter};
+
+    fn lints(code: &str) -> Vec<(Lint, usize, usize)> {
+        let mut interpreter = Interpreter::new();
+        let _ = interpreter.eval(code.to_string());
+
+        interpreter.semantic_analyzer.take_warnings().into_iter()
+            .map(|warning| (warning.lint, warning.span.line, warning.span.column))
+            .collect()
+    }
+
+    #[test]
+    fn test_lints() {
+        assert_eq!(lints("if false {\n    : 1\n}"), [(Lint::DeadCode, 1, 3)]);
+        assert_eq!(lints("if true {\n    : 1\n}"), [(Lint::ConstantCondition, 1, 3)]);
+        assert_eq!(lints("if 1 < 2 {\n    : 1\n}"), [(Lint::ConstantCondition, 1, 3)]);
+        assert!(lints("var x = 1\nif x < 2 {\n    : 1\n}").is_empty());
+
+        let after_return = "func f() -> int {\n    return 1\n    var unused = 2\n    return unused\n}";
+        assert_eq!(lints(after_return), [(Lint::DeadCode, 3, 8)]);
+
+        let mut interpreter = Interpreter::new();
+        interpreter.semantic_analyzer.allow(Lint::from_name("dead-code").unwrap());
+        interpreter.eval("if false {\n    : 1\n}".to_string()).unwrap();
+        assert!(interpreter.semantic_analyzer.take_warnings().is_empty());
+
+        let error = Lint::from_name("dead-cod").err().unwrap();
+        assert_eq!(error.to_string(), "There's no lint called dead-cod. Did you mean `dead-code`?");
+    }
+
+    #[test]
+    fn test_pragmas() {
+        // For the next statement only
+        let code = "#allow(constant-condition)\nif true {\n    : 1\n}\nif true {\n    : 2\n}";
+        assert_eq!(lints(code), [(Lint::ConstantCondition, 5, 3)]);
+
+        // For the rest of the block
+        let code = "func f() -> int {\n    return 1\n    #!allow(dead-code, constant-condition)\n    if true {\n        return 2\n    }\n}\nif true {\n    : 1\n}";
+        assert_eq!(lints(code), [(Lint::ConstantCondition, 8, 3)]);
+
+        let mut interpreter = Interpreter::new();
+        let error = interpreter.eval("#allow(dead-code, unsued)\nif false {\n    : 1\n}".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "There's no lint called unsued");
+
+        let error = interpreter.eval("#deny(dead-code)\n: 1".to_string()).err().unwrap();
+        assert!(error.to_string().starts_with("Expected a pragma like `#allow(lint)`"));
+
+        let error = interpreter.eval(": 1 #allow(dead-code)".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Pragmas go on a line of their own (line 1, column 4)");
+    }
+
+    #[test]
+    fn test_annotations() {
+        let code = "@deprecated(\"use g\")\nfunc f() -> int {\n    return 1\n}\n: f()";
+        assert_eq!(lints(code), [(Lint::Deprecated, 5, 2)]);
+
+        let code = "@deprecated\ntype Point {\n    x: int\n}\nvar p = Point(1)\n#allow(deprecated)\nvar q = Point(2)";
+        assert_eq!(lints(code), [(Lint::Deprecated, 5, 8)]);
+
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("@test\n@export func checks() {\n    : 1\n}\n@export var count = 2".to_string()).unwrap();
+        let names = |kind| interpreter.semantic_analyzer.annotated(kind).map(|symbol| symbol.name().to_string()).collect::<Vec<_>>();
+        assert_eq!(names(Kind::Test), ["checks"]);
+        assert_eq!(names(Kind::Export), ["checks", "count"]);
+        assert!(names(Kind::Deprecated).is_empty());
+
+        let error = |code: &str| Interpreter::new().eval(code.to_string()).err().unwrap().to_string();
+        assert_eq!(error("@tset\nfunc f() {\n}"), "There's no annotation called @tset. Did you mean `test`?");
+        assert_eq!(error("@test\nfunc f(x: int) {\n}"), "@test goes on functions without parameters (line 1, column 1)");
+        assert_eq!(error("@deprecated(1)\nvar x = 1"), "The reason for @deprecated should be a string (line 1, column 1)");
+        assert_eq!(error("@export @export var x = 1"), "@export is given more than once (line 1, column 9)");
+        assert_eq!(error("func f() {\n    @export var x = 1\n}"), "Only top-level declarations can be marked @export (line 2, column 5)");
+        assert_eq!(error("@test\n: 1"), "Annotations go before a var, func, type or contract declaration (line 1, column 0)");
+        assert_eq!(error("@cfg var x = 1"), "@cfg takes the name of a feature, like @cfg(debug) (line 1, column 1)");
+    }
+
+    #[test]
+    fn test_cfg() {
+        let code = "@cfg(debug)\nfunc log(message: string) -> string {\n    return \"debug: \" + message\n}\n@cfg(release)\nfunc log(message: string) -> string {\n    return message\n}\n@cfg(release)\nvar unused = missing + 1\nlog(\"started\")";
+
+        let mut interpreter = Interpreter::new();
+        interpreter.semantic_analyzer.define("debug");
+        assert_eq!(interpreter.eval(code.to_string()).unwrap().as_text().unwrap(), "debug: started");
+        assert!(interpreter.eval("unused".to_string()).is_err());
+
+        let mut interpreter = Interpreter::new();
+        interpreter.semantic_analyzer.define("release");
+        assert_eq!(interpreter.eval(code.to_string()).err().unwrap().to_string(), "Variable missing not found");
+    }
+}
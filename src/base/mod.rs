@@ -1,3 +1,16 @@
+pub mod annotations;
+pub mod bundle;
+pub mod call_site;
+pub mod diagnostics;
 pub mod lexer;
+pub mod lints;
+pub mod modules;
 pub mod parser;
-pub mod semantic_analyzer;
\ No newline at end of file
+pub mod scope_tree;
+pub mod semantic_analyzer;
+pub mod slots;
+pub(crate) mod stack;
+pub mod suggest;
+pub mod syntax;
+pub mod xref;
+pub mod incremental;
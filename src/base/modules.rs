@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use crate::base::lexer::Token;
+
+// `import name` analyzes another file as a module, in a scope of its own under the global one.
+// Other code sees it as a namespace, with only the members marked `@export`.
+//
+// Hosts choose where modules come from by setting a loader. Without one, code can't import
+// anything, so embedding odo doesn't give scripts access to the file system by default.
+
+pub const EXTENSION: &str = "odo";
+
+/// Finds and reads the source of the modules code imports.
+pub trait ModuleLoader: Send + Sync {
+    /// Where the module called `name` is. `importer` is the module importing it, or None for the
+    /// code the host evaluates.
+    fn resolve(&self, name: &str, importer: Option<&Path>) -> anyhow::Result<PathBuf>;
+
+    fn read(&self, path: &Path) -> anyhow::Result<String>;
+}
+
+/// Modules as `name.odo` files, next to the module importing them or in one of the roots.
+#[derive(Debug, Clone, Default)]
+pub struct FileLoader {
+    roots: Vec<PathBuf>,
+}
+
+impl FileLoader {
+    pub fn new(roots: Vec<PathBuf>) -> FileLoader {
+        FileLoader { roots }
+    }
+}
+
+impl ModuleLoader for FileLoader {
+    fn resolve(&self, name: &str, importer: Option<&Path>) -> anyhow::Result<PathBuf> {
+        let file = Path::new(name).with_extension(EXTENSION);
+        let beside = importer.and_then(Path::parent).map(|directory| directory.join(&file));
+
+        let path = beside.into_iter()
+            .chain(self.roots.iter().map(|root| root.join(&file)))
+            .find(|path| path.is_file())
+            .ok_or_else(|| anyhow::anyhow!("Module {} not found. Looked for {}", name, file.display()))?;
+
+        // The same file imported through different paths is still one module
+        Ok(path.canonicalize().unwrap_or(path))
+    }
+
+    fn read(&self, path: &Path) -> anyhow::Result<String> {
+        std::fs::read_to_string(path)
+            .map_err(|error| anyhow::anyhow!("Could not read module {}: {}", path.display(), error))
+    }
+}
+
+/// An error in the code of an imported module. It reads like the error it wraps, followed by the
+/// module it's in.
+#[derive(Debug)]
+pub struct ModuleError {
+    pub path: PathBuf,
+    pub error: anyhow::Error,
+}
+
+impl std::fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} (in module {})", self.error, self.path.display())
+    }
+}
+
+impl std::error::Error for ModuleError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+/// Modules importing each other in a loop, which would never finish analyzing. `chain` starts and
+/// ends with the same module, and `imports[i]` is the name in the import statement of `chain[i]`
+/// that imports `chain[i + 1]`.
+#[derive(Debug)]
+pub struct CircularImport {
+    pub chain: Vec<PathBuf>,
+    pub imports: Vec<Token>,
+}
+
+impl CircularImport {
+    // The file the loop is closed in, where the last import is
+    pub fn path(&self) -> &Path {
+        &self.chain[self.chain.len() - 2]
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned()
+}
+
+impl std::fmt::Display for CircularImport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let names: Vec<_> = self.chain.iter().map(|path| file_name(path)).collect();
+        write!(f, "Circular import: {}", names.join(" -> "))?;
+
+        for (path, name) in self.chain.iter().zip(&self.imports) {
+            write!(f, "\n    {} imports {} (line {}, column {})", file_name(path), name.value, name.line, name.column)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for CircularImport {}
@@ -1,16 +1,30 @@
 use anyhow::Context;
 
-use crate::base::lexer::{Token, TokenType};
+use crate::base::{annotations::{self, Annotation}, lexer::{self, Token, TokenType}, lints, stack};
+
+// How deep expressions, blocks and types can nest. Parsing recurses for every level,
+// so without a limit, hostile input could use up all the memory of whoever embeds odo.
+// Hosts can lower it with `max_depth`.
+pub const MAX_NESTING: usize = 1000;
 
 pub struct Parser {
     // tokens is a peekable iterator on a collection of Tokens
-    tokens: std::iter::Peekable<std::vec::IntoIter<Token>>
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+    // Levels of nesting around the current token
+    depth: usize,
+    max_depth: usize,
+    // Lints turned off with `#!allow` in each block being parsed, innermost last
+    block_allows: Vec<Vec<Token>>,
 }
 
 #[derive(Debug)]
-enum Error {
+pub(crate) enum Error {
     SuddenEndOfFile,
     UnexpectedToken(TokenType, Token), // Expected, got
+    ReservedWord(Token, &'static str), // Keyword, what it was used as
+    TooDeeplyNested(Token, usize), // Where it went past the limit, and the limit
+    InvalidPragma(Token),
+    MisplacedAnnotation(Token), // The `@`
 }
 
 impl Error {
@@ -20,6 +34,42 @@ impl Error {
                 format!("Expected token of type {:?} but got {:?}", expected, got)
             }
             Error::SuddenEndOfFile => "Unexpected end of file".to_string(),
+            Error::ReservedWord(token, usage) => {
+                format!(
+                    "`{}` is a reserved word and cannot be used as a {} (line {}, column {})",
+                    token.value, usage, token.line, token.column
+                )
+            }
+            Error::TooDeeplyNested(token, limit) => {
+                format!(
+                    "Code is nested more than {} levels deep (line {}, column {})",
+                    limit, token.line, token.column
+                )
+            }
+            Error::InvalidPragma(token) => {
+                format!(
+                    "Expected a pragma like `#allow(lint)` or `#!allow(lint)`, but got `#{}` (line {}, column {})",
+                    token.value, token.line, token.column
+                )
+            }
+            Error::MisplacedAnnotation(token) => {
+                format!(
+                    "Annotations go before a var, func, type or contract declaration (line {}, column {})",
+                    token.line, token.column
+                )
+            }
+        }
+    }
+}
+
+impl Error {
+    // The token the error is about. None at the end of the file.
+    pub(crate) fn token(&self) -> Option<&Token> {
+        match self {
+            Error::SuddenEndOfFile => None,
+            Error::UnexpectedToken(_, token) | Error::ReservedWord(token, _) |
+            Error::TooDeeplyNested(token, _) | Error::InvalidPragma(token) |
+            Error::MisplacedAnnotation(token) => Some(token)
         }
     }
 }
@@ -32,8 +82,43 @@ impl std::fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+// `allow(a, b)` or `!allow(a, b)`: whether it's for the whole block, and the names of the lints
+fn parse_pragma(pragma: &Token) -> anyhow::Result<(bool, Vec<Token>)> {
+    let (whole_block, text) = match pragma.value.strip_prefix('!') {
+        Some(text) => (true, text),
+        None => (false, pragma.value.as_str())
+    };
+
+    let arguments = text.strip_prefix("allow(")
+        .and_then(|text| text.strip_suffix(')'))
+        .ok_or_else(|| Error::InvalidPragma(pragma.clone()))?;
+
+    // Columns of each name, for reporting unknown lints. The pragma starts with `#`, and maybe a `!`.
+    let mut column = pragma.column + 1 + usize::from(whole_block) + "allow(".len();
+    let mut lints = vec![];
+    for argument in arguments.split(',') {
+        let name = argument.trim();
+        if name.is_empty() {
+            return Err(Error::InvalidPragma(pragma.clone()).into());
+        }
+
+        let leading = argument.chars().take_while(|c| c.is_whitespace()).count();
+        lints.push(Token {
+            token_type: TokenType::Name,
+            value: name.to_string(),
+            line: pragma.line,
+            column: column + leading,
+        });
+        column += argument.chars().count() + 1;
+    }
+
+    Ok((whole_block, lints))
+}
+
 // The AST
 pub type Node = Box<Ast>;
+// (parameter name, type name) pairs, and the return type name
+pub type Signature = (Vec<(Token, Token)>, Option<Token>);
 
 #[derive(Debug, Clone)]
 pub enum Ast {
@@ -42,22 +127,94 @@ pub enum Ast {
     Truth(Token),
     Text(Token),
     Variable(Token),
+    MemberAccess(Node, Token), // namespace.member
     Assignment(Node, Node),
+    MultipleAssignment(Vec<Node>, Vec<Node>), // a, b = b, a
     Declaration(Token, Node),
+    TypedDeclaration(Token, Token, Node), // var name: type = value
+    PatternDeclaration(Pattern, Node), // var [first, ...rest] = list
+    MultipleDeclaration(Vec<(Token, Node)>), // var a = 1, b = 2
+    BinaryOperation(Node, Token, Node), // left, operator, right
+    Comparison(Node, Vec<(Token, Node)>), // 0 <= x < 10: the first operand, then each operator and the operand after it
+    Negation(Token, Node), // -value
 
     FunctionCall(Node, Vec<Node>),
+    List(Vec<Node>), // [a, b]
+    Spread(Node), // ...list, in calls and list literals
+    Map(Vec<(Node, Node)>), // {key: value}
+
+    // Functions
+    FunctionDeclaration(Token, Vec<(Token, Token)>, Option<Token>, Node), // Name, (parameter, type), return type, body
+    Function(Vec<(Token, Token)>, Option<Token>, Node), // Anonymous function
+    Return(Option<Node>),
+    Yield(Node),
 
     // Control flow
     If(Node, Node /*, Option<Node> */),
+    Match(Token, Node, Vec<(Pattern, Option<Node>, Node)>), // `match` for errors, value, (pattern, guard, body)
+    Defer(Token, Node), // Runs when the block or function it's in ends
+    For(Token, Token, Node, Node), // `for` for errors, name, what it goes through, body
+
+    // User types
+    TypeDeclaration(Token, Vec<Token>, Vec<(Token, Token)>, Vec<Node>), // Name, contracts, (field, type), method declarations
+    ContractDeclaration(Token, Vec<(Token, Signature)>), // Name, (method, signature)
+    Extension(Token, Vec<Node>), // Type name, method declarations
+
+    Allow(Vec<Token>, Node), // Lints turned off by pragmas, and the statement they're off for
+    Annotated(Vec<Annotation>, Node), // `@name` lines, and the declaration they're for
+    Import(Token, Token), // `import` for errors, the module's name
 
     DebugPrint(Node) // Temporary
 }
 
+// What `match` arms and pattern declarations compare values against, taking them apart
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Wildcard(Token), // _
+    Binding(Token), // Any value, bound to a name
+    Literal(Node), // 1, -1, "text", true
+    Record(Token, Vec<(Token, Pattern)>), // Point { x: 0, y }. Type, (field, pattern)
+    List(Token, Vec<Pattern>, Option<Box<Pattern>>), // [first, ...rest]. `[`, items, the rest
+}
+
+impl Pattern {
+    // Where the pattern starts
+    pub fn token(&self) -> &Token {
+        match self {
+            Pattern::Wildcard(token) | Pattern::Binding(token) | Pattern::Record(token, _) | Pattern::List(token, ..) => token,
+            Pattern::Literal(node) => lints::first_token(node).expect("Literals are made of tokens")
+        }
+    }
+}
+
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Parser {
         Parser {
-            tokens: tokens.into_iter().peekable()
+            tokens: tokens.into_iter().peekable(),
+            depth: 0,
+            max_depth: MAX_NESTING,
+            block_allows: Vec::new(),
+        }
+    }
+
+    /// Lowers the nesting limit. It can't go over `MAX_NESTING`.
+    pub fn max_depth(mut self, limit: usize) -> Parser {
+        self.max_depth = limit.min(MAX_NESTING);
+        self
+    }
+
+    // Runs `parse` one level of nesting deeper
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Parser) -> anyhow::Result<T>) -> anyhow::Result<T> {
+        if self.depth >= self.max_depth {
+            let token = self.tokens.peek().cloned().ok_or(Error::SuddenEndOfFile)?;
+            return Err(Error::TooDeeplyNested(token, self.max_depth).into());
         }
+
+        self.depth += 1;
+        let result = stack::guarded(|| parse(self));
+        self.depth -= 1;
+
+        result
     }
 
     fn consume(&mut self, kind: TokenType) -> anyhow::Result<Token> {
@@ -75,6 +232,26 @@ impl Parser {
         }
     }
 
+    // A name the program defines. Keywords get their own error, instead of a generic unexpected token.
+    fn consume_name(&mut self, usage: &'static str) -> anyhow::Result<Token> {
+        if let Some(token) = self.tokens.peek().filter(|token| lexer::keyword(&token.value).is_some()) {
+            return Err(Error::ReservedWord(token.clone(), usage).into());
+        }
+
+        self.consume(TokenType::Name)
+    }
+
+    // A name, or a path to a member of a module or namespace like `geometry.Point`, as one token
+    fn consume_path(&mut self, usage: &'static str) -> anyhow::Result<Token> {
+        let mut path = self.consume_name(usage)?;
+        while self.next_of(&[TokenType::Dot]).is_some() {
+            let member = self.consume_name(usage)?;
+            path.value = format!("{}.{}", path.value, member.value);
+        }
+
+        Ok(path)
+    }
+
     fn next_is(&mut self, kind: TokenType) -> bool {
         match self.tokens.peek() {
             Some(token) => token.token_type == kind,
@@ -82,6 +259,14 @@ impl Parser {
         }
     }
 
+    // Consumes the next token if it's one of `kinds`
+    fn next_of(&mut self, kinds: &[TokenType]) -> Option<Token> {
+        let token = self.tokens.peek().filter(|token| kinds.contains(&token.token_type))?.clone();
+        self.tokens.next();
+
+        Some(token)
+    }
+
     fn ignore_newline(&mut self) {
         while let Some(token) = self.tokens.peek() {
             if token.token_type == TokenType::NewLine {
@@ -94,11 +279,18 @@ impl Parser {
 
     pub fn parse(&mut self) -> anyhow::Result<Node> {
         let mut ast: Vec<Node> = Vec::new();
+        self.block_allows.push(vec![]);
         
-        while let Some(_) = self.tokens.peek() {
-            ast.push(self.parse_statement()?);
+        while self.tokens.peek().is_some() {
+            let allowed = self.pragmas()?;
+            if self.tokens.peek().is_none() {
+                break;
+            }
+
+            ast.push(self.block_statement(allowed)?);
         }
         
+        self.block_allows.pop();
         Ok(Box::new(Ast::Block(ast)))
     }
 
@@ -107,22 +299,57 @@ impl Parser {
 
         self.ignore_newline();
 
-        if let Ok(_) = self.consume(TokenType::RightCurly) {
+        if self.consume(TokenType::RightCurly).is_ok() {
             return Ok(ast);
         }
         
-        while let Some(_) = self.tokens.peek() {
+        self.block_allows.push(vec![]);
+        while self.tokens.peek().is_some() {
+            let allowed = self.pragmas()?;
+
             // check terminators
-            if self.tokens.peek().unwrap().token_type == TokenType::RightCurly {
+            if self.tokens.peek().is_none_or(|token| token.token_type == TokenType::RightCurly) {
                 break;
             }
             
-            ast.push(self.parse_statement()?);
+            ast.push(self.block_statement(allowed)?);
         }
+        self.block_allows.pop();
         
         Ok(ast)
     }
 
+    // `#allow(lint, ...)` turns lints off for the statement after it, and `#!allow(lint, ...)` for
+    // the rest of the block it's in. Reads the pragmas before a statement, and returns the lints
+    // for the statement.
+    fn pragmas(&mut self) -> anyhow::Result<Vec<Token>> {
+        let mut allowed = vec![];
+
+        while let Some(pragma) = self.next_of(&[TokenType::Pragma]) {
+            let (whole_block, lints) = parse_pragma(&pragma)?;
+            match self.block_allows.last_mut() {
+                Some(block_allows) if whole_block => block_allows.extend(lints),
+                _ => allowed.extend(lints)
+            }
+
+            self.ignore_newline();
+        }
+
+        Ok(allowed)
+    }
+
+    // A statement of a block, wrapped in the lints pragmas turned off for it
+    fn block_statement(&mut self, allowed: Vec<Token>) -> anyhow::Result<Node> {
+        let statement = self.parse_statement()?;
+
+        let lints: Vec<Token> = self.block_allows.iter().flatten().cloned().chain(allowed).collect();
+        if lints.is_empty() {
+            return Ok(statement);
+        }
+
+        Ok(Box::new(Ast::Allow(lints, statement)))
+    }
+
     fn check_statement_terminator(&mut self) -> anyhow::Result<()> {
         // Consume statement terminators
         let token = match self.tokens.peek() {
@@ -130,7 +357,7 @@ impl Parser {
             None => return Ok(()) // End of file is a valid statement terminator
         };
 
-        let block_terminators = vec![TokenType::RightCurly]; // Anything that would work as termination in a wrap block
+        let block_terminators = [TokenType::RightCurly]; // Anything that would work as termination in a wrap block
 
         if token.token_type == TokenType::SemiColon {
             let _ = self.consume(TokenType::SemiColon)?;
@@ -161,7 +388,7 @@ impl Parser {
     }
 
     pub fn parse_statement(&mut self) -> anyhow::Result<Node> {
-        let result = self.parse_statement_without_terminator()?;
+        let result = self.nested(|parser| parser.parse_statement_without_terminator())?;
 
         self.check_statement_terminator()?;
 
@@ -180,14 +407,96 @@ impl Parser {
             TokenType::Var => self.parse_declaration(),
             TokenType::LeftCurly => self.parse_block(),
             TokenType::If => self.parse_if(),
-            TokenType::DebugPrint => {
-                self.consume(TokenType::DebugPrint).unwrap();
-                let expr = self.parse_postfix()?;
+            TokenType::With => self.parse_with(),
+            TokenType::Defer => self.parse_defer(),
+            TokenType::For => self.parse_for(),
+            TokenType::Func => self.parse_function_declaration(),
+            TokenType::Type => self.parse_type_declaration(),
+            TokenType::Contract => self.parse_contract(),
+            TokenType::Extend => self.parse_extension(),
+            TokenType::Return => self.parse_return(),
+            TokenType::Yield => self.parse_yield(),
+            TokenType::At => self.parse_annotated(),
+            TokenType::Import => {
+                let keyword = self.consume(TokenType::Import)?;
+                let name = self.consume_name("module name")?;
+
+                Ok(Box::new(Ast::Import(keyword, name)))
+            },
+            TokenType::Colon => {
+                self.consume(TokenType::Colon).unwrap();
+                let expr = self.parse_expression()?;
 
                 Ok(Box::new(Ast::DebugPrint(expr)))
             },
-            _ => self.parse_postfix()
+            _ => self.parse_expression_statement()
+        }
+    }
+
+    // `@name` or `@name(argument, ...)`, before a declaration
+    fn parse_annotated(&mut self) -> anyhow::Result<Node> {
+        let start = self.tokens.peek().cloned().ok_or(Error::SuddenEndOfFile)?;
+
+        let mut annotations = vec![];
+        while self.next_of(&[TokenType::At]).is_some() {
+            let name = self.consume(TokenType::Name)?;
+
+            let mut arguments = vec![];
+            if self.consume(TokenType::LeftParen).is_ok() {
+                while let Some(argument) = self.next_of(&[TokenType::Name, TokenType::Number, TokenType::Text]) {
+                    arguments.push(argument);
+                    if self.consume(TokenType::Comma).is_err() {
+                        break;
+                    }
+                }
+                self.consume(TokenType::RightParen)?;
+            }
+
+            annotations.push(Annotation { name, arguments });
+            self.ignore_newline();
+        }
+
+        let declaration = self.parse_statement_without_terminator()?;
+        if annotations::declared_name(&declaration).is_none() {
+            return Err(Error::MisplacedAnnotation(start).into());
+        }
+
+        Ok(Box::new(Ast::Annotated(annotations, declaration)))
+    }
+
+    // An expression, or `a, b = b, a`. Commas only mean multiple targets at the start of a statement,
+    // so they don't get confused with arguments.
+    fn parse_expression_statement(&mut self) -> anyhow::Result<Node> {
+        let expr = self.parse_expression()?;
+
+        if !self.next_is(TokenType::Comma) {
+            return Ok(expr);
+        }
+
+        let mut targets = vec![expr];
+        while self.consume(TokenType::Comma).is_ok() {
+            targets.push(self.parse_or()?);
         }
+
+        let assign = self.consume(TokenType::Assign)
+            .context("Expected an assignment after multiple targets ('=')")?;
+
+        let mut values = vec![self.parse_expression()?];
+        while self.consume(TokenType::Comma).is_ok() {
+            values.push(self.parse_expression()?);
+        }
+
+        if targets.len() != values.len() {
+            return Err(anyhow::anyhow!(
+                "Assigning {} values to {} targets (line {}, column {})",
+                values.len(),
+                targets.len(),
+                assign.line,
+                assign.column
+            ));
+        }
+
+        Ok(Box::new(Ast::MultipleAssignment(targets, values)))
     }
 
     fn parse_block(&mut self) -> anyhow::Result<Node> {
@@ -195,29 +504,104 @@ impl Parser {
         self.ignore_newline();
         let mut nodes = Vec::new();
 
-        while let Some(token) = self.tokens.peek().cloned() {
-            if token.token_type == TokenType::RightCurly {
+        self.block_allows.push(vec![]);
+        while self.tokens.peek().is_some() {
+            let allowed = self.pragmas()?;
+
+            if self.tokens.peek().is_none_or(|token| token.token_type == TokenType::RightCurly) {
                 break;
             }
 
-            nodes.push(self.parse_statement()?);
+            nodes.push(self.block_statement(allowed)?);
         }
+        self.block_allows.pop();
 
         let _ = self.consume(TokenType::RightCurly)?;
 
         Ok(Box::new(Ast::Block(nodes)))
     }
 
+    // `var a = 1`, `var a = 1, b = 2`, `var a, b = 1, 2` or `var a: int = 1`
     fn parse_declaration(&mut self) -> anyhow::Result<Node> {
         let _ = self.consume(TokenType::Var)?;
         self.ignore_newline();
 
-        let name = self.consume(TokenType::Name)?;
+        if self.next_is(TokenType::LeftBracket) {
+            let pattern = self.parse_pattern()?;
+            return self.parse_pattern_declaration(pattern);
+        }
+
+        let name = self.consume_path("variable name")?;
+
+        if self.next_is(TokenType::LeftCurly) || name.value.contains('.') {
+            let pattern = self.parse_record_pattern(name)?;
+            return self.parse_pattern_declaration(pattern);
+        }
+
+        if self.next_of(&[TokenType::Colon]).is_some() {
+            let type_name = self.parse_type()?;
+            let _ = self.consume(TokenType::Assign)
+                .context("Expected an assignment statement ('=')")?;
+
+            return Ok(Box::new(Ast::TypedDeclaration(name, type_name, self.parse_expression()?)));
+        }
+
+        let mut declarations = if self.next_is(TokenType::Comma) {
+            let mut names = vec![name];
+            while self.consume(TokenType::Comma).is_ok() {
+                names.push(self.consume_name("variable name")?);
+            }
+
+            let assign = self.consume(TokenType::Assign)
+                .context("Expected an assignment statement ('=')")?;
+
+            let mut values = vec![self.parse_expression()?];
+            while self.consume(TokenType::Comma).is_ok() {
+                values.push(self.parse_expression()?);
+            }
+
+            if names.len() != values.len() {
+                return Err(anyhow::anyhow!(
+                    "Declared {} variables but gave {} values (line {}, column {})",
+                    names.len(),
+                    values.len(),
+                    assign.line,
+                    assign.column
+                ));
+            }
+
+            names.into_iter().zip(values).collect()
+        } else {
+            let mut declarations = Vec::new();
+            let mut name = name;
+            loop {
+                let _ = self.consume(TokenType::Assign)
+                    .context("Expected an assignment statement ('=')")?;
+                declarations.push((name, self.parse_expression()?));
+
+                if self.consume(TokenType::Comma).is_err() {
+                    break;
+                }
+                name = self.consume_name("variable name")?;
+            }
+
+            declarations
+        };
+
+        if declarations.len() == 1 {
+            let (name, expr) = declarations.remove(0);
+            return Ok(Box::new(Ast::Declaration(name, expr)));
+        }
+
+        Ok(Box::new(Ast::MultipleDeclaration(declarations)))
+    }
+
+    // `var [first, ...rest] = list` or `var Point { x, y } = point`
+    fn parse_pattern_declaration(&mut self, pattern: Pattern) -> anyhow::Result<Node> {
         let _ = self.consume(TokenType::Assign)
             .context("Expected an assignment statement ('=')")?;
-        let expr = self.parse_postfix()?;
 
-        Ok(Box::new(Ast::Declaration(name, expr)))
+        Ok(Box::new(Ast::PatternDeclaration(pattern, self.parse_expression()?)))
     }
 
     fn parse_assignment(&mut self, target_node: Node) -> anyhow::Result<Node> {
@@ -226,7 +610,7 @@ impl Parser {
 
         self.consume(TokenType::Assign)
             .context("Expected an assignment statement ('=')")?;
-        let expr = self.parse_postfix()?;
+        let expr = self.parse_expression()?;
 
         Ok(Box::new(Ast::Assignment(target_node, expr)))
     }
@@ -245,7 +629,7 @@ impl Parser {
                 None => break,
             };
             
-            args.push(self.parse_postfix()?);
+            args.push(self.parse_item()?);
 
             self.ignore_newline();
 
@@ -258,18 +642,132 @@ impl Parser {
         Ok(Box::new(Ast::FunctionCall(callee, args)))
     }
 
-    fn parse_postfix(&mut self) -> anyhow::Result<Node> {
-        let mut expr = self.parse_factor()?;
+    fn parse_expression(&mut self) -> anyhow::Result<Node> {
+        self.nested(|parser| parser.parse_assignment_expression())
+    }
+
+    fn parse_assignment_expression(&mut self) -> anyhow::Result<Node> {
+        let expr = self.parse_or()?;
+
+        match self.tokens.peek().map(|token| token.token_type.clone()) {
+            Some(TokenType::Assign) => self.parse_assignment(expr),
+            Some(kind) if kind.compound_operator().is_some() => self.parse_compound_assignment(expr),
+            Some(TokenType::Increment | TokenType::Decrement) => {
+                let token = self.tokens.next().expect("We just peeked");
+                Err(unsupported_step(&token))
+            },
+            _ => Ok(expr)
+        }
+    }
+
+    // `x += 1` is `x = x + 1`
+    fn parse_compound_assignment(&mut self, target_node: Node) -> anyhow::Result<Node> {
+        let token = self.tokens.next().ok_or(Error::SuddenEndOfFile)?;
+        let operator_type = token.token_type.compound_operator()
+            .ok_or(anyhow::anyhow!("Expected a compound assignment"))?;
+
+        let operator = Token {
+            token_type: operator_type,
+            value: token.value.trim_end_matches('=').to_string(),
+            ..token
+        };
+
+        let expr = self.parse_expression()?;
+        let value = Box::new(Ast::BinaryOperation(target_node.clone(), operator, expr));
+
+        Ok(Box::new(Ast::Assignment(target_node, value)))
+    }
+
+    fn parse_or(&mut self) -> anyhow::Result<Node> {
+        let mut expr = self.parse_and()?;
+
+        while self.next_is(TokenType::Or) {
+            let operator = self.consume(TokenType::Or)?;
+            expr = Box::new(Ast::BinaryOperation(expr, operator, self.parse_and()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> anyhow::Result<Node> {
+        let mut expr = self.parse_comparison()?;
+
+        while self.next_is(TokenType::And) {
+            let operator = self.consume(TokenType::And)?;
+            expr = Box::new(Ast::BinaryOperation(expr, operator, self.parse_comparison()?));
+        }
+
+        Ok(expr)
+    }
+
+    // `a < b <= c` means `a < b and b <= c`, like in Python.
+    // The middle operands are repeated in the desugared tree, so they're evaluated twice.
+    // `0 <= x < 10` means `0 <= x and x < 10`, with `x` only evaluated once
+    fn parse_comparison(&mut self) -> anyhow::Result<Node> {
+        let first = self.parse_additive()?;
+
+        let mut rest = vec![];
+        while let Some(operator) = self.tokens.peek().filter(|token| token.token_type.is_comparison()).cloned() {
+            self.tokens.next();
+            rest.push((operator, self.parse_additive()?));
+        }
+
+        Ok(match rest.len() {
+            0 => first,
+            1 => {
+                let (operator, right) = rest.remove(0);
+                Box::new(Ast::BinaryOperation(first, operator, right))
+            },
+            _ => Box::new(Ast::Comparison(first, rest))
+        })
+    }
 
+    fn parse_additive(&mut self) -> anyhow::Result<Node> {
+        let mut expr = self.parse_multiplicative()?;
+
+        while let Some(operator) = self.next_of(&[TokenType::Plus, TokenType::Minus]) {
+            expr = Box::new(Ast::BinaryOperation(expr, operator, self.parse_multiplicative()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_multiplicative(&mut self) -> anyhow::Result<Node> {
+        let mut expr = self.parse_unary()?;
+
+        while let Some(operator) = self.next_of(&[TokenType::Star, TokenType::Slash, TokenType::Percent]) {
+            expr = Box::new(Ast::BinaryOperation(expr, operator, self.parse_unary()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> anyhow::Result<Node> {
         self.ignore_newline();
 
+        if let Some(token) = self.next_of(&[TokenType::Increment, TokenType::Decrement]) {
+            return Err(unsupported_step(&token));
+        }
+
+        match self.next_of(&[TokenType::Minus]) {
+            Some(operator) => Ok(Box::new(Ast::Negation(operator, self.nested(|parser| parser.parse_unary())?))),
+            None => self.parse_postfix()
+        }
+    }
+
+    fn parse_postfix(&mut self) -> anyhow::Result<Node> {
+        let mut expr = self.parse_factor()?;
+
         while let Some(token) = self.tokens.peek().cloned() {
             match token.token_type {
-                TokenType::Assign => {
-                    expr = self.parse_assignment(expr)?;
-                },
                 TokenType::LeftParen => {
                     expr = self.parse_function_call(expr)?;
+                },
+                TokenType::Dot => {
+                    let _ = self.consume(TokenType::Dot)?;
+                    let member = self.consume(TokenType::Name)?;
+
+                    expr = Box::new(Ast::MemberAccess(expr, member));
                 }
                 _ => break
             }
@@ -297,20 +795,497 @@ impl Parser {
             TokenType::Name => {
                 Ok(Box::new(Ast::Variable(self.tokens.next().expect("We just peeked"))))
             },
-            _ => return Err(anyhow::anyhow!("Unexpected token {:?}", self.tokens.peek().expect("We just peeked").token_type))
+            TokenType::Func => self.parse_function_literal(),
+            TokenType::Match => self.parse_match(),
+            TokenType::LeftParen => {
+                let _ = self.consume(TokenType::LeftParen)?;
+                let expr = self.parse_expression()?;
+                self.ignore_newline();
+                let _ = self.consume(TokenType::RightParen)?;
+
+                Ok(expr)
+            },
+            TokenType::LeftBracket => self.parse_list(),
+            // Blocks are statements, so a curly brace in an expression is a map
+            TokenType::LeftCurly => self.parse_map(),
+            _ => Err(anyhow::anyhow!("Unexpected token {:?}", self.tokens.peek().expect("We just peeked").token_type))
         }
     }
 }
 
+// Collections
+impl Parser {
+    // Items separated by commas, until `end`. Newlines and a trailing comma are allowed.
+    fn parse_items<T>(&mut self, end: TokenType, mut item: impl FnMut(&mut Parser) -> anyhow::Result<T>) -> anyhow::Result<Vec<T>> {
+        let mut items = Vec::new();
+
+        loop {
+            self.ignore_newline();
+            if self.consume(end.clone()).is_ok() {
+                break;
+            }
+
+            items.push(item(self)?);
+
+            self.ignore_newline();
+            if self.consume(TokenType::Comma).is_err() {
+                let _ = self.consume(end)?;
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+
+    // An expression, or `...list` to expand it in place
+    fn parse_item(&mut self) -> anyhow::Result<Node> {
+        if self.consume(TokenType::Ellipsis).is_ok() {
+            return Ok(Box::new(Ast::Spread(self.parse_expression()?)));
+        }
+
+        self.parse_expression()
+    }
+
+    fn parse_list(&mut self) -> anyhow::Result<Node> {
+        let _ = self.consume(TokenType::LeftBracket)?;
+        let items = self.parse_items(TokenType::RightBracket, |parser| parser.parse_item())?;
+
+        Ok(Box::new(Ast::List(items)))
+    }
+
+    fn parse_map(&mut self) -> anyhow::Result<Node> {
+        let _ = self.consume(TokenType::LeftCurly)?;
+        let entries = self.parse_items(TokenType::RightCurly, |parser| {
+            let key = parser.parse_expression()?;
+            let _ = parser.consume(TokenType::Colon)
+                .context("Expected ':' between a key and its value")?;
+            let value = parser.parse_expression()?;
+
+            Ok((key, value))
+        })?;
+
+        Ok(Box::new(Ast::Map(entries)))
+    }
+}
+
+fn unsupported_step(token: &Token) -> anyhow::Error {
+    let suggestion = if token.token_type == TokenType::Increment { "+= 1" } else { "-= 1" };
+
+    anyhow::anyhow!(
+        "odo doesn't have {}. Use `x {}` instead (line {}, column {})",
+        token.value,
+        suggestion,
+        token.line,
+        token.column
+    )
+}
+
+// Functions
+impl Parser {
+    fn parse_function_declaration(&mut self) -> anyhow::Result<Node> {
+        let _ = self.consume(TokenType::Func)?;
+        let name = self.consume_name("function name")?;
+
+        let (parameters, return_type) = self.parse_function_signature(None)?;
+        let body = self.parse_block()?;
+
+        Ok(Box::new(Ast::FunctionDeclaration(name, parameters, return_type, body)))
+    }
+
+    fn parse_function_literal(&mut self) -> anyhow::Result<Node> {
+        let _ = self.consume(TokenType::Func)?;
+
+        let (parameters, return_type) = self.parse_function_signature(None)?;
+        let body = self.parse_block()?;
+
+        Ok(Box::new(Ast::Function(parameters, return_type, body)))
+    }
+
+    // (name: type, ...) -> type. Methods of `owner` start with `self`, which has no type written.
+    fn parse_function_signature(&mut self, owner: Option<&Token>) -> anyhow::Result<Signature> {
+        let _ = self.consume(TokenType::LeftParen)?;
+        self.ignore_newline();
+
+        let mut parameters = Vec::new();
+
+        while self.tokens.peek().is_some_and(|token| token.token_type != TokenType::RightParen) {
+            let parameter = self.consume_name("parameter name")?;
+            if let Some(owner) = owner.filter(|_| parameters.is_empty() && parameter.value == "self") {
+                parameters.push((parameter, owner.clone()));
+            } else {
+                let _ = self.consume(TokenType::Colon)
+                    .context("Function parameters need a type (`name: type`)")?;
+                let type_name = self.parse_type()?;
+
+                parameters.push((parameter, type_name));
+            }
+
+            self.ignore_newline();
+
+            if self.next_is(TokenType::Comma) {
+                self.consume(TokenType::Comma)?;
+                self.ignore_newline();
+            } else {
+                break;
+            }
+        }
+
+        let _ = self.consume(TokenType::RightParen)?;
+
+        let return_type = if self.next_is(TokenType::Arrow) {
+            let _ = self.consume(TokenType::Arrow)?;
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        Ok((parameters, return_type))
+    }
+
+    // A type name, `list<int>` for what a list or map holds, or `func(int, string) -> int` for a function type.
+    // Function types are passed on with the name the analyzer gives them, like `<int,string:int>`
+    fn parse_type(&mut self) -> anyhow::Result<Token> {
+        let Some(func) = self.next_of(&[TokenType::Func]) else {
+            let name = self.consume_path("type name")?;
+            if self.next_of(&[TokenType::Less]).is_none() {
+                return Ok(name);
+            }
+
+            let argument = self.nested(|parser| parser.parse_type())?;
+            let _ = self.consume(TokenType::Greater)
+                .context("Expected `>` after the type of what it holds")?;
+
+            return Ok(Token {
+                value: format!("{}<{}>", name.value, argument.value),
+                ..name
+            });
+        };
+
+        let _ = self.consume(TokenType::LeftParen)?;
+        let arguments = self.parse_items(TokenType::RightParen, |parser| parser.nested(|parser| parser.parse_type()))?;
+
+        let return_type = if self.next_of(&[TokenType::Arrow]).is_some() {
+            Some(self.nested(|parser| parser.parse_type())?)
+        } else {
+            None
+        };
+
+        let arguments: Vec<_> = arguments.into_iter().map(|argument| argument.value).collect();
+        let return_name = return_type.map(|token| token.value).unwrap_or_default();
+
+        Ok(Token {
+            token_type: TokenType::Name,
+            value: format!("<{}:{}>", arguments.join(","), return_name),
+            ..func
+        })
+    }
+
+    fn parse_return(&mut self) -> anyhow::Result<Node> {
+        let _ = self.consume(TokenType::Return)?;
+
+        // A return without value ends where the statement does
+        let value = match self.tokens.peek() {
+            None => None,
+            Some(token) if [TokenType::NewLine, TokenType::SemiColon, TokenType::RightCurly].contains(&token.token_type) => None,
+            Some(_) => Some(self.parse_expression()?)
+        };
+
+        Ok(Box::new(Ast::Return(value)))
+    }
+
+    fn parse_yield(&mut self) -> anyhow::Result<Node> {
+        let _ = self.consume(TokenType::Yield)?;
+        let value = self.parse_expression()?;
+
+        Ok(Box::new(Ast::Yield(value)))
+    }
+}
+
+// User types
+impl Parser {
+    // type Point: Printable {
+    //     x: int
+    //     func show(self) -> string { ... }
+    // }
+    fn parse_type_declaration(&mut self) -> anyhow::Result<Node> {
+        let _ = self.consume(TokenType::Type)?;
+        let name = self.consume_name("type name")?;
+        let contracts = self.parse_contract_list()?;
+
+        let _ = self.consume(TokenType::LeftCurly)?;
+        let mut fields = Vec::new();
+        let mut methods = Vec::new();
+        loop {
+            self.ignore_newline();
+            if self.next_of(&[TokenType::RightCurly]).is_some() {
+                break;
+            }
+
+            if self.next_is(TokenType::Func) {
+                let (method, (parameters, return_type)) = self.parse_method(&name)?;
+                let body = self.parse_block()?;
+
+                methods.push(Box::new(Ast::FunctionDeclaration(method, parameters, return_type, body)));
+            } else {
+                let field = self.consume_name("field name")?;
+                let _ = self.consume(TokenType::Colon)
+                    .context("Fields need a type (`name: type`)")?;
+
+                fields.push((field, self.parse_type()?));
+            }
+
+            self.check_statement_terminator()?;
+        }
+
+        Ok(Box::new(Ast::TypeDeclaration(name, contracts, fields, methods)))
+    }
+
+    // `: Printable, Comparable` after a type's name
+    fn parse_contract_list(&mut self) -> anyhow::Result<Vec<Token>> {
+        let mut contracts = Vec::new();
+        if self.next_of(&[TokenType::Colon]).is_none() {
+            return Ok(contracts);
+        }
+
+        loop {
+            contracts.push(self.consume_path("contract name")?);
+            if self.next_of(&[TokenType::Comma]).is_none() {
+                return Ok(contracts);
+            }
+        }
+    }
+
+    // contract Printable {
+    //     func show(self) -> string
+    // }
+    fn parse_contract(&mut self) -> anyhow::Result<Node> {
+        let _ = self.consume(TokenType::Contract)?;
+        let name = self.consume_name("contract name")?;
+
+        let _ = self.consume(TokenType::LeftCurly)?;
+        let mut methods = Vec::new();
+        loop {
+            self.ignore_newline();
+            if self.next_of(&[TokenType::RightCurly]).is_some() {
+                break;
+            }
+
+            methods.push(self.parse_method(&name)?);
+            self.check_statement_terminator()?;
+        }
+
+        Ok(Box::new(Ast::ContractDeclaration(name, methods)))
+    }
+
+    // extend Point {
+    //     func length(self) -> dec { ... }
+    // }
+    fn parse_extension(&mut self) -> anyhow::Result<Node> {
+        let _ = self.consume(TokenType::Extend)?;
+        let name = self.consume_name("type name")?;
+
+        let _ = self.consume(TokenType::LeftCurly)?;
+        let mut methods = Vec::new();
+        loop {
+            self.ignore_newline();
+            if self.next_of(&[TokenType::RightCurly]).is_some() {
+                break;
+            }
+
+            let (method, (parameters, return_type)) = self.parse_method(&name)?;
+            let body = self.parse_block()?;
+
+            methods.push(Box::new(Ast::FunctionDeclaration(method, parameters, return_type, body)));
+            self.check_statement_terminator()?;
+        }
+
+        Ok(Box::new(Ast::Extension(name, methods)))
+    }
+
+    // `func name(self, ...) -> type`, without the body
+    fn parse_method(&mut self, owner: &Token) -> anyhow::Result<(Token, Signature)> {
+        let _ = self.consume(TokenType::Func)?;
+        let name = self.consume_name("method name")?;
+        let signature = self.parse_function_signature(Some(owner))?;
+
+        if signature.0.first().is_none_or(|(parameter, _)| parameter.value != "self") {
+            return Err(anyhow::anyhow!(
+                "Methods take `self` as their first parameter (line {}, column {})",
+                name.line,
+                name.column
+            ));
+        }
+
+        Ok((name, signature))
+    }
+}
+
 // Control flow implementations
 impl Parser {
     fn parse_if(&mut self) -> anyhow::Result<Node> {
         let _ = self.consume(TokenType::If)?;
-        let condition = self.parse_postfix()?;
-        let body = self.parse_statement()?;
+        let condition = self.parse_expression()?;
+        // The enclosing statement takes care of the terminator
+        let body = self.parse_statement_without_terminator()?;
 
         Ok(Box::new(Ast::If(condition, body)))
     }
+
+    // match value {
+    //     Point { x: 0, y }: ...
+    //     _: ...
+    // }
+    // Each arm is a statement. When they're all expressions of the same type, the match has their value.
+    fn parse_match(&mut self) -> anyhow::Result<Node> {
+        let keyword = self.consume(TokenType::Match)?;
+        let value = self.parse_expression()?;
+
+        let _ = self.consume(TokenType::LeftCurly)?;
+        let mut arms = Vec::new();
+        loop {
+            self.ignore_newline();
+            if self.next_of(&[TokenType::RightCurly]).is_some() {
+                break;
+            }
+
+            let pattern = self.parse_pattern()?;
+            let guard = match self.next_of(&[TokenType::If]) {
+                Some(_) => Some(self.parse_expression()?),
+                None => None
+            };
+
+            let _ = self.consume(TokenType::Colon)
+                .context("Expected ':' between a pattern and what runs when it matches")?;
+            let body = self.nested(|parser| parser.parse_statement_without_terminator())?;
+
+            arms.push((pattern, guard, body));
+            self.check_statement_terminator()?;
+        }
+
+        Ok(Box::new(Ast::Match(keyword, value, arms)))
+    }
+
+    fn parse_pattern(&mut self) -> anyhow::Result<Pattern> {
+        self.ignore_newline();
+        let token = self.tokens.peek().cloned().ok_or(Error::SuddenEndOfFile)?;
+
+        match token.token_type {
+            TokenType::Underscore => {
+                let _ = self.consume(TokenType::Underscore)?;
+                Ok(Pattern::Wildcard(token))
+            },
+            TokenType::Number | TokenType::Text | TokenType::Truth => Ok(Pattern::Literal(self.parse_factor()?)),
+            TokenType::Minus => {
+                let operator = self.consume(TokenType::Minus)?;
+                let number = self.consume(TokenType::Number)?;
+
+                Ok(Pattern::Literal(Box::new(Ast::Negation(operator, Box::new(Ast::Number(number))))))
+            },
+            TokenType::LeftBracket => self.nested(|parser| parser.parse_list_pattern()),
+            TokenType::Name => {
+                let name = self.consume_path("variable name")?;
+                // Only types can be named by a path, so one can't be a binding
+                if !self.next_is(TokenType::LeftCurly) && !name.value.contains('.') {
+                    return Ok(Pattern::Binding(name));
+                }
+
+                self.nested(|parser| parser.parse_record_pattern(name))
+            },
+            _ => Err(anyhow::anyhow!("Expected a pattern but got {:?} (line {}, column {})", token.value, token.line, token.column))
+        }
+    }
+
+    // [first, second, ...rest]
+    fn parse_list_pattern(&mut self) -> anyhow::Result<Pattern> {
+        let bracket = self.consume(TokenType::LeftBracket)?;
+
+        let mut rest = None;
+        let items = self.parse_items(TokenType::RightBracket, |parser| {
+            if rest.is_some() {
+                return Err(anyhow::anyhow!("The rest of the list (`...rest`) has to be the last item of the pattern"));
+            }
+
+            if let Some(ellipsis) = parser.next_of(&[TokenType::Ellipsis]) {
+                let pattern = parser.parse_pattern()?;
+                if !matches!(pattern, Pattern::Binding(_) | Pattern::Wildcard(_)) {
+                    return Err(anyhow::anyhow!(
+                        "The rest of the list can only be bound to a name or ignored with `..._` (line {}, column {})",
+                        ellipsis.line, ellipsis.column
+                    ));
+                }
+
+                rest = Some(Box::new(pattern));
+                return Ok(None);
+            }
+
+            parser.parse_pattern().map(Some)
+        })?;
+
+        Ok(Pattern::List(bracket, items.into_iter().flatten().collect(), rest))
+    }
+
+    // Point { x: 0, y }. A field without a pattern is bound to a variable with its name.
+    fn parse_record_pattern(&mut self, name: Token) -> anyhow::Result<Pattern> {
+        let _ = self.consume(TokenType::LeftCurly)?;
+
+        let fields = self.parse_items(TokenType::RightCurly, |parser| {
+            let field = parser.consume_name("field name")?;
+            let pattern = match parser.next_of(&[TokenType::Colon]) {
+                Some(_) => parser.parse_pattern()?,
+                None => Pattern::Binding(field.clone())
+            };
+
+            Ok((field, pattern))
+        })?;
+
+        Ok(Pattern::Record(name, fields))
+    }
+
+    // `with a = 1, b = 2 { ... }` is a block that starts by declaring its temporaries
+    fn parse_with(&mut self) -> anyhow::Result<Node> {
+        let _ = self.consume(TokenType::With)?;
+
+        let mut nodes = Vec::new();
+        loop {
+            let name = self.consume_name("variable name")?;
+            let _ = self.consume(TokenType::Assign)
+                .context("Expected an assignment in the with statement ('=')")?;
+            let expr = self.parse_expression()?;
+
+            nodes.push(Box::new(Ast::Declaration(name, expr)));
+
+            if self.consume(TokenType::Comma).is_err() {
+                break;
+            }
+        }
+
+        match *self.parse_block()? {
+            Ast::Block(body) => nodes.extend(body),
+            _ => unreachable!("parse_block always returns a block")
+        }
+
+        Ok(Box::new(Ast::Block(nodes)))
+    }
+
+    // for item in items { ... }
+    fn parse_for(&mut self) -> anyhow::Result<Node> {
+        let keyword = self.consume(TokenType::For)?;
+        let name = self.consume_name("loop variable")?;
+        let _ = self.consume(TokenType::In)
+            .context("Expected `in` after the name of the loop variable")?;
+        let iterable = self.parse_expression()?;
+        let body = self.parse_block()?;
+
+        Ok(Box::new(Ast::For(keyword, name, iterable, body)))
+    }
+
+    // `defer close(file)`
+    fn parse_defer(&mut self) -> anyhow::Result<Node> {
+        let keyword = self.consume(TokenType::Defer)?;
+        let expr = self.parse_expression()?;
+
+        Ok(Box::new(Ast::Defer(keyword, expr)))
+    }
 }
 
 #[cfg(test)]
@@ -337,6 +1312,46 @@ mod tests {
         let mut parser = parser("x = 1");
         let ast = parser.parse_statement().unwrap();
 
-        assert_eq!(format!("{:?}", ast), "Assignment(Token { token_type: Name, value: \"x\", line: 1, column: 0 }, Number(Token { token_type: Number, value: \"1\", line: 1, column: 4 }))");
+        assert_eq!(format!("{:?}", ast), "Assignment(Variable(Token { token_type: Name, value: \"x\", line: 1, column: 0 }), Number(Token { token_type: Number, value: \"1\", line: 1, column: 4 }))");
+    }
+
+    #[test]
+    fn test_reserved_words() {
+        let error = |code: &str| parser(code).parse_statement().err().unwrap().to_string();
+
+        assert_eq!(error("var if = 1"), "`if` is a reserved word and cannot be used as a variable name (line 1, column 4)");
+        assert_eq!(error("func return() {}"), "`return` is a reserved word and cannot be used as a function name (line 1, column 5)");
+        assert_eq!(error("func f(in: int) {}"), "`in` is a reserved word and cannot be used as a parameter name (line 1, column 7)");
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        use crate::base::parser::MAX_NESTING;
+
+        let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+
+        assert!(parser(&nested(MAX_NESTING - 2)).parse_statement().is_ok());
+
+        // Far past what the stack could take one level at a time
+        let error = parser(&nested(100_000)).parse_statement().err().unwrap().to_string();
+        assert_eq!(error, format!("Code is nested more than {} levels deep (line 1, column {})", MAX_NESTING, MAX_NESTING - 1));
+
+        let negations = format!("{}1", "-".repeat(100_000));
+        assert!(parser(&negations).parse_statement().is_err());
+        let blocks = format!("{}{}", "{".repeat(100_000), "}".repeat(100_000));
+        assert!(parser(&blocks).parse_statement().is_err());
+    }
+
+    #[test]
+    fn test_function_types() {
+        let ast = parser("func f(g: func(int, func() -> int)) -> func(int) -> int { return g }").parse_statement().unwrap();
+
+        match *ast {
+            crate::base::parser::Ast::FunctionDeclaration(_, parameters, return_type, _) => {
+                assert_eq!(parameters[0].1.value, "<int,<:int>:>");
+                assert_eq!(return_type.unwrap().value, "<int:int>");
+            },
+            other => panic!("Expected a function declaration, got {:?}", other)
+        }
     }
-}
\ No newline at end of file
+}
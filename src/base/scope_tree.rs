@@ -0,0 +1,190 @@
+use serde::Serialize;
+
+use crate::base::semantic_analyzer::{SemanticAnalyzer, Symbol, SymbolId, SymbolVariant, TableId};
+
+/// A snapshot of the analyzer's scopes, with names instead of ids. Meant for debugging and tooling.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScopeTree {
+    pub name: String,
+    pub id: TableId,
+    pub symbols: Vec<SymbolEntry>,
+    // Nested scopes, and the tables of the namespaces declared in this one
+    pub children: Vec<ScopeTree>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolEntry {
+    #[serde(skip)]
+    pub id: SymbolId,
+    pub name: String,
+    pub kind: &'static str,
+    // Type of the value, for variables and functions
+    pub type_name: Option<String>,
+    // Whether a variable or function has a value. Only the interpreter knows, so it's None in
+    // the analyzer's dumps.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bound: Option<bool>,
+}
+
+impl SemanticAnalyzer {
+    pub fn dump_scopes(&self) -> anyhow::Result<ScopeTree> {
+        self.scope_tree(self.global_scope_id)
+    }
+
+    /// The scopes a name in `scope_id` is looked up in, from the global one down to it, with
+    /// everything nested in it. Function types, which can't be named, are left out.
+    pub fn dump_scope_chain(&self, scope_id: TableId) -> anyhow::Result<ScopeTree> {
+        let mut tree = self.scope_tree(scope_id)?;
+
+        let mut parent = self.scope(scope_id)?.parent();
+        while let Some(id) = parent {
+            let scope = self.scope(id)?;
+            let symbols = scope.symbols()
+                .filter(|symbol| !matches!(symbol.variant, SymbolVariant::FunctionType(_)))
+                .map(|symbol| self.symbol_entry(symbol))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            tree = ScopeTree { name: scope.name().to_string(), id, symbols, children: vec![tree] };
+            tree.symbols.sort_by(|a, b| a.name.cmp(&b.name));
+            parent = scope.parent();
+        }
+
+        Ok(tree)
+    }
+
+    fn scope_tree(&self, id: TableId) -> anyhow::Result<ScopeTree> {
+        let scope = self.scope(id)?;
+
+        let mut symbols = scope.symbols()
+            .map(|symbol| self.symbol_entry(symbol))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        symbols.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let namespaces = scope.symbols().filter_map(|symbol| match symbol.variant {
+            SymbolVariant::Namespace(ref namespace) => Some(namespace.table_id()),
+            _ => None
+        });
+        let nested = self.scopes()
+            .filter(|child| child.parent() == Some(id))
+            .map(|child| child.id());
+
+        let mut children = namespaces.chain(nested)
+            .map(|child| self.scope_tree(child))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        children.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(ScopeTree { name: scope.name().to_string(), id, symbols, children })
+    }
+
+    fn symbol_entry(&self, symbol: &Symbol) -> anyhow::Result<SymbolEntry> {
+        let kind = match symbol.variant {
+            SymbolVariant::Variable(_) => "variable",
+            SymbolVariant::Function(_) => "function",
+            SymbolVariant::NativeFunction(_) => "native",
+            SymbolVariant::Primitive | SymbolVariant::FunctionType(_) | SymbolVariant::Parametric(_) | SymbolVariant::TypeParameter |
+            SymbolVariant::Record(_) => "type",
+            SymbolVariant::Contract(_) => "contract",
+            SymbolVariant::Namespace(_) => "namespace",
+        };
+
+        let type_name = match symbol.value_type_id() {
+            Ok(type_id) => self.name_of_type(type_id)?,
+            Err(_) => None
+        };
+
+        Ok(SymbolEntry { id: symbol.symbol_id, name: symbol.name().to_string(), kind, type_name, bound: None })
+    }
+}
+
+impl ScopeTree {
+    // Marks the variables and functions `bound` has a value for, here and in every nested scope
+    pub(crate) fn mark_bound(&mut self, bound: &dyn Fn(SymbolId) -> bool) {
+        for symbol in &mut self.symbols {
+            if matches!(symbol.kind, "variable" | "function") {
+                symbol.bound = Some(bound(symbol.id));
+            }
+        }
+
+        for child in &mut self.children {
+            child.mark_bound(bound);
+        }
+    }
+
+    fn write(&self, f: &mut std::fmt::Formatter, depth: usize) -> std::fmt::Result {
+        let indent = "  ".repeat(depth);
+        writeln!(f, "{}{}", indent, self.name)?;
+
+        for symbol in &self.symbols {
+            let kind = match symbol.bound {
+                Some(true) => format!("{}, bound", symbol.kind),
+                Some(false) => format!("{}, no value", symbol.kind),
+                None => symbol.kind.to_string()
+            };
+
+            match &symbol.type_name {
+                Some(type_name) => writeln!(f, "{}  {}: {} ({})", indent, symbol.name, type_name, kind)?,
+                None => writeln!(f, "{}  {} ({})", indent, symbol.name, kind)?,
+            }
+        }
+
+        for child in &self.children {
+            child.write(f, depth + 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for ScopeTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::exec::interpreter::Interpreter;
+
+    #[test]
+    fn test_dump_scopes() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var a = 1\nfunc f(x: int) -> int {\n    return x\n}".to_string()).unwrap();
+
+        let tree = interpreter.semantic_analyzer.dump_scopes().unwrap();
+        let text = tree.to_string();
+
+        assert!(text.starts_with("global_table\n"));
+        assert!(text.contains("  int (type)\n"));
+        assert!(text.contains("  repl_scope\n    a: int (variable)\n    f: <int:int> (function)\n    function f\n      x: int (variable)\n"), "{}", text);
+
+        let json = serde_json::to_value(&tree).unwrap();
+        assert_eq!(json["name"], "global_table");
+    }
+
+    #[test]
+    fn test_stable_order() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var c = 1\nvar a = 2\nfunc f() {\n    { var zeta = 1 }\n    { var alpha = 2 }\n}\nvar b = 3".to_string()).unwrap();
+
+        let analyzer = &interpreter.semantic_analyzer;
+        let names: Vec<_> = analyzer.scope(analyzer.repl_scope_id).unwrap().symbols().map(|symbol| symbol.name()).collect();
+        assert_eq!(names, ["c", "a", "f", "b"]);
+
+        // Scopes with the same name stay in the order they were created
+        let text = analyzer.dump_scopes().unwrap().to_string();
+        assert!(text.contains("    function f\n      block\n        zeta: int (variable)\n      block\n        alpha: int (variable)\n"), "{}", text);
+    }
+
+    #[test]
+    fn test_scope_chain() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var a = 1\nfunc f(x: int) -> int {\n    return x\n}".to_string()).unwrap();
+
+        let text = interpreter.scope_chain().unwrap().to_string();
+        assert!(text.starts_with("global_table\n"), "{}", text);
+        assert!(text.contains("  print: <string:> (native)\n"), "{}", text);
+        // Only the scopes on the way to the repl's, and no unnameable function types
+        assert!(!text.contains("function take") && !text.contains("<string:> (type)"), "{}", text);
+        assert!(text.ends_with("  repl_scope\n    a: int (variable, bound)\n    f: <int:int> (function, bound)\n    function f\n      x: int (variable, no value)\n"), "{}", text);
+    }
+}
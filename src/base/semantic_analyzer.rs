@@ -1,16 +1,75 @@
 use crate::base::parser::Ast;
 
-use std::collections::HashMap;
+use std::{collections::{HashMap, HashSet}, path::{Path, PathBuf}, sync::Arc};
+use indexmap::IndexMap;
 use uuid::Uuid;
 use lazy_static::lazy_static;
+use serde::{Serialize, Deserialize};
 
-use super::{parser::Node, lexer::Token};
+use super::{annotations::{self, Annotation}, call_site::CallSite, modules::{CircularImport, ModuleError, ModuleLoader}, lexer::Lexer, parser::Parser, parser::{Node, Pattern, Signature}, slots::{Layout, Slot}, stack, suggest, lexer::{Token, TokenType}, lints::{self, Lint}, xref::{CrossReferences, Span}};
 
+#[derive(Clone)]
 pub struct SemanticAnalyzer {
-    scopes: HashMap<Uuid, SymbolTable>,
+    // In the order they were created, like the symbols in each, so everything listed from them comes out the same every run
+    // Shared with clones of the analyzer until one of them changes a table
+    scopes: IndexMap<Uuid, Arc<SymbolTable>>,
+    // Problems that don't stop the program from running
+    warnings: Vec<Warning>,
+    // Lints turned off with `allow`
+    allowed: HashSet<Lint>,
+    // Features defined for `@cfg`, like debug
+    features: HashSet<String>,
+    // Stricter rules, for code that should be checked more than a script usually is
+    strict: bool,
+    // Where every symbol is declared and used
+    xref: CrossReferences,
     pub current_scope_id: TableId,
     pub repl_scope_id: TableId,
-    global_scope_id: TableId
+    pub global_scope_id: TableId,
+    // Type parameters of generic native signatures, like the `T` in `list<T>`. They aren't part
+    // of any scope chain, so they can't hide or be hidden by names in odo code.
+    type_parameters_id: TableId,
+    // The functions being analyzed, innermost last
+    function_stack: Vec<FunctionContext>,
+    // Slots of the environments the code being analyzed will run in, innermost last.
+    // Empty at the top level, where declarations are global.
+    frames: Vec<Layout>,
+    // Where imported modules come from. Without one, nothing can be imported.
+    loader: Option<Arc<dyn ModuleLoader>>,
+    // The top-level scope of each module imported so far, by path
+    modules: HashMap<PathBuf, TableId>,
+    // The modules being analyzed, innermost last, with the name in the import statement that
+    // brought each one in
+    importing: Vec<(PathBuf, Token)>,
+    // The file the host's code comes from, if it comes from one
+    main_module: Option<PathBuf>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub lint: Lint,
+    pub message: String,
+    pub span: Span,
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "warning[{}]: {} (line {}, column {})", self.lint, self.message, self.span.line, self.span.column)
+    }
+}
+
+#[derive(Clone)]
+struct FunctionContext {
+    return_id: Option<SymbolId>,
+    yields: bool,
+    // What a function that returns a seq yields
+    item_id: Option<SymbolId>
+}
+
+impl Default for SemanticAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SemanticAnalyzer {
@@ -21,80 +80,560 @@ impl SemanticAnalyzer {
         global_table.symbols.insert(DEC_TYPE.symbol_id, DEC_TYPE.clone());
         global_table.symbols.insert(TEXT_TYPE.symbol_id, TEXT_TYPE.clone());
         global_table.symbols.insert(TRUTH_TYPE.symbol_id, TRUTH_TYPE.clone());
+        global_table.symbols.insert(ANY_TYPE.symbol_id, ANY_TYPE.clone());
+        global_table.symbols.insert(OPAQUE_TYPE.symbol_id, OPAQUE_TYPE.clone());
+        global_table.symbols.insert(LIST_TYPE.symbol_id, LIST_TYPE.clone());
+        global_table.symbols.insert(MAP_TYPE.symbol_id, MAP_TYPE.clone());
+        global_table.symbols.insert(SEQ_TYPE.symbol_id, SEQ_TYPE.clone());
 
         let id = global_table.table_id;
 
         let mut repl_scope = SymbolTable::new("repl_scope".to_string());
         let repl_scope_id = repl_scope.table_id;
         repl_scope.parent = Some(id);
+
+        let type_parameters = SymbolTable::new("type_parameters".to_string());
+        let type_parameters_id = type_parameters.table_id;
         
         SemanticAnalyzer {
             scopes: {
-                let mut map = HashMap::new();
-                map.insert(global_table.table_id, global_table);
-                map.insert(repl_scope.table_id, repl_scope);
+                let mut map = IndexMap::new();
+                map.insert(global_table.table_id, Arc::new(global_table));
+                map.insert(repl_scope.table_id, Arc::new(repl_scope));
+                map.insert(type_parameters_id, Arc::new(type_parameters));
                 map
             },
+            warnings: Vec::new(),
+            allowed: HashSet::new(),
+            features: HashSet::new(),
+            strict: false,
+            xref: CrossReferences::default(),
             current_scope_id: id,
             repl_scope_id,
-            global_scope_id: id
+            global_scope_id: id,
+            type_parameters_id,
+            function_stack: Vec::new(),
+            frames: Vec::new(),
+            loader: None,
+            modules: HashMap::new(),
+            importing: Vec::new(),
+            main_module: None
         }
     }
 
     pub fn global_scope(&self) -> anyhow::Result<&SymbolTable> {
-        self.scopes.get(&self.global_scope_id)
+        self.scopes.get(&self.global_scope_id).map(Arc::as_ref)
             .ok_or(anyhow::anyhow!("There should always be a global scope"))
     }
 
     pub fn global_scope_mut(&mut self) -> anyhow::Result<&mut SymbolTable> {
-        self.scopes.get_mut(&self.global_scope_id)
+        self.scopes.get_mut(&self.global_scope_id).map(Arc::make_mut)
             .ok_or(anyhow::anyhow!("There should always be a global scope"))
     }
 
     pub fn current_scope(&self) -> anyhow::Result<&SymbolTable> {
-        self.scopes.get(&self.current_scope_id)
+        self.scopes.get(&self.current_scope_id).map(Arc::as_ref)
             .ok_or(anyhow::anyhow!("There should always be a scope"))
     }
 
     pub fn current_scope_mut(&mut self) -> anyhow::Result<&mut SymbolTable> {
-        self.scopes.get_mut(&self.current_scope_id)
+        self.scopes.get_mut(&self.current_scope_id).map(Arc::make_mut)
         .ok_or(anyhow::anyhow!("There should always be a scope"))
     }
+
+    pub fn cross_references(&self) -> &CrossReferences {
+        &self.xref
+    }
+
+    pub fn scopes(&self) -> impl Iterator<Item = &SymbolTable> {
+        self.scopes.values().map(Arc::as_ref)
+    }
+
+    pub fn scope_count(&self) -> usize {
+        self.scopes.len()
+    }
+
+    /// Removes a scope that can't be used anymore, returning it.
+    pub fn dispose_scope(&mut self, id: TableId) -> Option<SymbolTable> {
+        self.scopes.shift_remove(&id).map(Arc::unwrap_or_clone)
+    }
+
+    pub fn scope(&self, id: TableId) -> anyhow::Result<&SymbolTable> {
+        self.scopes.get(&id).map(Arc::as_ref)
+            .ok_or(anyhow::anyhow!("Scope not found"))
+    }
+
+    pub fn scope_mut(&mut self, id: TableId) -> anyhow::Result<&mut SymbolTable> {
+        self.scopes.get_mut(&id).map(Arc::make_mut)
+            .ok_or(anyhow::anyhow!("Scope not found"))
+    }
+
+    /// Every symbol that can be used by name from the current scope, innermost first.
+    /// Symbols hidden by one with the same name in an inner scope are left out.
+    pub fn visible_symbols(&self) -> Vec<&Symbol> {
+        let mut names = HashSet::new();
+        let mut symbols = Vec::new();
+
+        let mut scope = self.scopes.get(&self.current_scope_id);
+        while let Some(table) = scope {
+            symbols.extend(table.symbols().filter(|symbol| names.insert(symbol.name.as_str())));
+            scope = table.parent.and_then(|parent| self.scopes.get(&parent));
+        }
+
+        symbols
+    }
+
+    // Names of the visible symbols of some kind, to suggest when a name isn't found
+    fn visible_names(&self, kind: impl Fn(&SymbolVariant) -> bool) -> Vec<&str> {
+        self.visible_symbols().into_iter()
+            .filter(|symbol| kind(&symbol.variant))
+            .map(|symbol| symbol.name())
+            .collect()
+    }
+
+    /// Declares a namespace in the given scope, returning the id of the table that holds its members.
+    /// Declaring an existing namespace again returns the same table, so it can be extended.
+    pub fn declare_namespace(&mut self, scope_id: TableId, name: &str) -> anyhow::Result<TableId> {
+        if let Some(symbol) = self.scope(scope_id)?.lookup(name.to_string()) {
+            return match symbol.variant {
+                SymbolVariant::Namespace(ref namespace) => Ok(namespace.table_id),
+                _ => Err(anyhow::anyhow!("{} already exists and is not a namespace", name))
+            };
+        }
+
+        let table = SymbolTable::new(name.to_string());
+        let table_id = table.table_id;
+        self.scopes.insert(table_id, Arc::new(table));
+
+        let symbol = Symbol::new(name.to_string(), SymbolVariant::Namespace(NamespaceSymbol { table_id }));
+        self.scope_mut(scope_id)?.insert(symbol);
+
+        Ok(table_id)
+    }
+
+    /// Removes the native called `path` from `scope_id`, returning it. Natives in namespaces
+    /// are found through their names, like `math.sqrt`.
+    pub(crate) fn take_native(&mut self, scope_id: TableId, path: &str) -> anyhow::Result<Option<Symbol>> {
+        let mut names: Vec<_> = path.split('.').collect();
+        let name = names.pop().unwrap_or_default();
+
+        let mut table_id = scope_id;
+        for namespace in names {
+            match self.scope(table_id)?.lookup(namespace.to_string()).map(|symbol| &symbol.variant) {
+                Some(SymbolVariant::Namespace(namespace)) => table_id = namespace.table_id,
+                _ => return Ok(None)
+            }
+        }
+
+        let native = self.scope(table_id)?.symbols()
+            .find(|symbol| symbol.name == name && matches!(symbol.variant, SymbolVariant::NativeFunction(_)))
+            .map(|symbol| symbol.symbol_id);
+
+        Ok(native.and_then(|id| self.scope_mut(table_id).ok()?.symbols.shift_remove(&id)))
+    }
+
+    /// Removes a function type nothing is declared with anymore, like the one of a native
+    /// that was removed. Types are made again when they're needed.
+    pub(crate) fn forget_function_type(&mut self, type_id: SymbolId) -> anyhow::Result<()> {
+        let global = self.global_scope()?;
+        if !global.lookup_id(type_id).is_some_and(|symbol| matches!(symbol.variant, SymbolVariant::FunctionType(_))) {
+            return Ok(());
+        }
+
+        // Any symbol that mentions the id: values of the type, functions taking it, ...
+        let id = type_id.to_string();
+        let used = self.scopes.values()
+            .flat_map(|scope| scope.symbols())
+            .any(|symbol| serde_json::to_string(&symbol.variant).is_ok_and(|variant| variant.contains(&id)));
+
+        if !used {
+            self.global_scope_mut()?.symbols.shift_remove(&type_id);
+        }
+
+        Ok(())
+    }
+}
+
+/// The analyzer's state at some point, to undo everything analyzed after it.
+pub struct Checkpoint {
+    scope_id: TableId,
+    symbols: Vec<SymbolId>,
+    scopes: Vec<TableId>,
+    xref: (usize, usize),
+}
+
+impl SemanticAnalyzer {
+    pub fn create_scope(&mut self, name: &str, parent: TableId) -> TableId {
+        let mut scope = SymbolTable::new(name.to_string());
+        let id = scope.table_id;
+        scope.parent = Some(parent);
+        self.scopes.insert(id, Arc::new(scope));
+
+        id
+    }
+
+    /// Remembers what's declared in `scope_id`, and which scopes exist.
+    pub fn checkpoint(&self, scope_id: TableId) -> anyhow::Result<Checkpoint> {
+        Ok(Checkpoint {
+            scope_id,
+            symbols: self.scope(scope_id)?.symbols.keys().copied().collect(),
+            scopes: self.scopes.keys().copied().collect(),
+            xref: self.xref.mark(),
+        })
+    }
+
+    /// Forgets the symbols declared in the checkpoint's scope since it was taken, and the scopes created since.
+    pub fn rollback(&mut self, checkpoint: &Checkpoint) -> anyhow::Result<()> {
+        let added: Vec<_> = self.scope(checkpoint.scope_id)?.symbols.keys()
+            .filter(|id| !checkpoint.symbols.contains(id))
+            .copied()
+            .collect();
+
+        // Only copied when something has to go, since forks may share it
+        if !added.is_empty() {
+            let scope = self.scope_mut(checkpoint.scope_id)?;
+            for symbol_id in &added {
+                scope.symbols.shift_remove(symbol_id);
+            }
+        }
+        for symbol_id in added {
+            self.xref.forget(symbol_id);
+        }
+
+        self.scopes.retain(|id, _| checkpoint.scopes.contains(id));
+        self.modules.retain(|_, table_id| checkpoint.scopes.contains(table_id));
+        self.xref.rewind(checkpoint.xref);
+
+        Ok(())
+    }
+}
+
+// Compiled programs
+impl SemanticAnalyzer {
+    /// What was added since the checkpoint: the scopes created, and the symbols declared in its scope.
+    pub fn changes_since(&self, checkpoint: &Checkpoint) -> anyhow::Result<(Vec<SymbolTable>, Vec<Symbol>)> {
+        let scopes = self.scopes.values()
+            .filter(|scope| !checkpoint.scopes.contains(&scope.table_id))
+            .map(|scope| scope.as_ref().clone())
+            .collect();
+
+        let symbols = self.scope(checkpoint.scope_id)?.symbols.values()
+            .filter(|symbol| !checkpoint.symbols.contains(&symbol.symbol_id))
+            .cloned()
+            .collect();
+
+        Ok((scopes, symbols))
+    }
+
+    /// Adds what `changes_since` returned, to `scope_id`.
+    pub fn install(&mut self, scopes: Vec<SymbolTable>, scope_id: TableId, symbols: Vec<Symbol>) -> anyhow::Result<()> {
+        for scope in scopes {
+            self.scopes.insert(scope.table_id, Arc::new(scope));
+        }
+
+        let scope = self.scope_mut(scope_id)?;
+        for symbol in symbols {
+            scope.insert(symbol);
+        }
+
+        Ok(())
+    }
+
+    /// Every symbol in the global scope and in the namespaces under it, by qualified name (`module.member`).
+    pub fn qualified_symbols(&self) -> anyhow::Result<Vec<(String, Symbol)>> {
+        let mut symbols = Vec::new();
+        let mut pending = vec![(String::new(), self.global_scope_id)];
+
+        while let Some((prefix, table_id)) = pending.pop() {
+            for symbol in self.scope(table_id)?.symbols.values() {
+                let name = format!("{}{}", prefix, symbol.name);
+
+                if let SymbolVariant::Namespace(ref namespace) = symbol.variant {
+                    pending.push((format!("{}.", name), namespace.table_id));
+                }
+
+                symbols.push((name, symbol.clone()));
+            }
+        }
+
+        Ok(symbols)
+    }
+
+    /// How code names `name` in `table_id`, like `math.sqrt`. None when it isn't the global scope
+    /// or a namespace in it.
+    pub(crate) fn qualified_name(&self, table_id: TableId, name: &str) -> anyhow::Result<Option<String>> {
+        let mut pending = vec![(String::new(), self.global_scope_id)];
+
+        while let Some((prefix, scope_id)) = pending.pop() {
+            if scope_id == table_id {
+                return Ok(Some(format!("{}{}", prefix, name)));
+            }
+
+            for symbol in self.scope(scope_id)?.symbols.values() {
+                if let SymbolVariant::Namespace(ref namespace) = symbol.variant {
+                    pending.push((format!("{}{}.", prefix, symbol.name), namespace.table_id));
+                }
+            }
+        }
+
+        Ok(None)
+    }
 }
 
+/// The method that says how values of a type declared in odo read, for `:` and the repl.
+/// It takes only `self` and returns a string.
+pub const TO_TEXT: &str = "toText";
+
 lazy_static! {
     /// This stores the primitive types
-    static ref INT_TYPE: Symbol = Symbol::new("int".to_string(), SymbolVariant::Primitive);
-    static ref DEC_TYPE: Symbol = Symbol::new("dec".to_string(), SymbolVariant::Primitive); // Equivalent to float
-    static ref TEXT_TYPE: Symbol = Symbol::new("string".to_string(), SymbolVariant::Primitive);
-    static ref TRUTH_TYPE: Symbol = Symbol::new("truth".to_string(), SymbolVariant::Primitive);
+    pub static ref INT_TYPE: Symbol = Symbol::stable("int".to_string(), SymbolVariant::Primitive, "type int");
+    pub static ref DEC_TYPE: Symbol = Symbol::stable("dec".to_string(), SymbolVariant::Primitive, "type dec"); // Equivalent to float
+    pub static ref TEXT_TYPE: Symbol = Symbol::stable("string".to_string(), SymbolVariant::Primitive, "type string");
+    pub static ref TRUTH_TYPE: Symbol = Symbol::stable("truth".to_string(), SymbolVariant::Primitive, "type truth");
+    /// Opts a value out of static checks. It's checked when running instead, wherever it's used as another type
+    pub static ref ANY_TYPE: Symbol = Symbol::stable("any".to_string(), SymbolVariant::Primitive, "type any");
+    /// Handles to host objects. Natives check the concrete type when they extract them
+    pub static ref OPAQUE_TYPE: Symbol = Symbol::stable("opaque".to_string(), SymbolVariant::Primitive, "type opaque");
+    /// Lists hold values of any type
+    pub static ref LIST_TYPE: Symbol = Symbol::stable("list".to_string(), SymbolVariant::Primitive, "type list");
+    /// Maps from keys (ints, strings, truths, or lists of them) to values of any type
+    pub static ref MAP_TYPE: Symbol = Symbol::stable("map".to_string(), SymbolVariant::Primitive, "type map");
+    /// Values made lazily by a function that yields them
+    pub static ref SEQ_TYPE: Symbol = Symbol::stable("seq".to_string(), SymbolVariant::Primitive, "type seq");
 }
 
 pub type SemanticNode = Box<SemanticAst>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SemanticAst {
     Block(Vec<SemanticAst>, TableId),
     Number(Token),
     Truth(Token),
     Text(Token),
-    Variable(SymbolId),
+    // Locals are read from their slot, and globals, which have none, by symbol
+    Variable(SymbolId, Option<Slot>),
+    // Code at the top level that declares names of its own (a block, a match or a loop), with the
+    // slots of the environment it runs in
+    Frame(Arc<Layout>, SemanticNode),
     // It should also store the infered type
     Declaration(SymbolId, Uuid, SemanticNode),
+    PatternDeclaration(SemanticPattern, SemanticNode, Token), // pattern, value, where the pattern starts for errors
+    MultipleDeclaration(Vec<(SymbolId, SemanticNode)>),
     Assignment(SymbolId, SemanticNode),
+    MultipleAssignment(Vec<(SymbolId, SemanticNode)>), // Every value is evaluated before assigning
+    BinaryOperation(SemanticNode, BinaryOperator, SemanticNode, Token), // left, operator, right, operator token for errors
+    Comparison(SemanticNode, Vec<(BinaryOperator, SemanticNode, Token)>), // 0 <= x < 10: the first operand, then each operator and the operand after it
+    Negation(SemanticNode, Token),
     FunctionCall(SemanticNode, Vec<SemanticNode>),
+    // A call with spread arguments, with the parameter types to check them against when running
+    SpreadCall(SemanticNode, Vec<SemanticNode>, Vec<SymbolId>),
+    Spread(SemanticNode),
+    List(Vec<SemanticNode>),
+    Map(Vec<(SemanticNode, SemanticNode)>),
+    FunctionDeclaration(SymbolId, Arc<FunctionDefinition>),
+    Function(Arc<FunctionDefinition>), // Anonymous function
+    Return(Option<SemanticNode>),
+    Yield(SemanticNode),
     If(SemanticNode, SemanticNode),
+    // The first arm whose pattern matches runs
+    Match(SemanticNode, Vec<MatchArm>),
+    // Shared with the list of what runs when the block ends
+    Defer(Arc<SemanticAst>),
+    // Loop variable, where the loop keeps what's left to go through, what it goes through, body, scope of the loop variable
+    For(SymbolId, Uuid, SemanticNode, SemanticNode, TableId),
+    // A value typed any, used where the type is known. Checked against it when running
+    Checked(SemanticNode, SymbolId, Option<Token>), // value, expected type, where it starts for errors
+    // The methods of a type or of an `extend` block. Contracts have none
+    TypeDeclaration(Vec<SemanticAst>),
+    Construct(SymbolId, String, Vec<(String, SemanticNode)>), // type, its name, (field, value) for each field
+    Field(SemanticNode, usize), // value, index of the field
+    // Methods are found by name on the type the receiver has when running, so calls through a contract
+    // get the method of whatever type is behind it
+    MethodCall(SemanticNode, String, Vec<SemanticNode>, #[serde(skip)] CallSite), // receiver, method, arguments after self, what it called before
+    // An import, with the module's top-level statements the first time it's imported
+    Module(TableId, Arc<Vec<SemanticAst>>),
     DebugPrint(SemanticNode)
 }
 
-type TableId = Uuid;
+impl SemanticAst {
+    /// The nodes directly under this one. Function bodies count as children of the function.
+    pub fn children(&self) -> Vec<&SemanticAst> {
+        match self {
+            SemanticAst::Block(statements, _) => statements.iter().collect(),
+            SemanticAst::Number(_) | SemanticAst::Truth(_) | SemanticAst::Text(_) | SemanticAst::Variable(..) => vec![],
+            SemanticAst::Frame(_, node) => vec![node],
+            SemanticAst::Declaration(_, _, value) | SemanticAst::Assignment(_, value) | SemanticAst::PatternDeclaration(_, value, _) => vec![value],
+            SemanticAst::MultipleDeclaration(pairs) | SemanticAst::MultipleAssignment(pairs) => {
+                pairs.iter().map(|(_, value)| value.as_ref()).collect()
+            },
+            SemanticAst::BinaryOperation(left, _, right, _) => vec![left, right],
+            SemanticAst::Comparison(first, rest) => std::iter::once(first).chain(rest.iter().map(|(_, operand, _)| operand)).map(|node| node.as_ref()).collect(),
+            SemanticAst::Negation(value, _) | SemanticAst::Spread(value) |
+            SemanticAst::Yield(value) | SemanticAst::DebugPrint(value) | SemanticAst::Checked(value, ..) | SemanticAst::Field(value, _) => vec![value],
+            SemanticAst::FunctionCall(callee, arguments) | SemanticAst::SpreadCall(callee, arguments, _) |
+            SemanticAst::MethodCall(callee, _, arguments, _) => {
+                std::iter::once(callee).chain(arguments).map(|node| node.as_ref()).collect()
+            },
+            SemanticAst::List(items) => items.iter().map(|item| item.as_ref()).collect(),
+            SemanticAst::Construct(_, _, fields) => fields.iter().map(|(_, value)| value.as_ref()).collect(),
+            SemanticAst::Map(entries) => entries.iter().flat_map(|(key, value)| [key.as_ref(), value.as_ref()]).collect(),
+            SemanticAst::FunctionDeclaration(_, definition) | SemanticAst::Function(definition) => definition.body.iter().collect(),
+            SemanticAst::Return(value) => value.iter().map(|value| value.as_ref()).collect(),
+            SemanticAst::If(condition, body) => vec![condition, body],
+            SemanticAst::Defer(node) => vec![node.as_ref()],
+            SemanticAst::For(_, _, iterable, body, _) => vec![iterable, body],
+            SemanticAst::TypeDeclaration(methods) => methods.iter().collect(),
+            SemanticAst::Module(_, statements) => statements.iter().collect(),
+            SemanticAst::Match(value, arms) => std::iter::once(value.as_ref())
+                .chain(arms.iter().flat_map(|arm| arm.guard.iter().chain(std::iter::once(&arm.body))))
+                .collect(),
+        }
+    }
+
+    /// Scopes of the blocks in this node that aren't inside a function. Nothing can refer to them once it ran.
+    pub fn block_scopes(&self) -> Vec<TableId> {
+        match self {
+            SemanticAst::FunctionDeclaration(..) | SemanticAst::Function(_) => vec![],
+            SemanticAst::Block(_, scope_id) => std::iter::once(*scope_id)
+                .chain(self.children().into_iter().flat_map(|child| child.block_scopes()))
+                .collect(),
+            SemanticAst::For(.., scope_id) => std::iter::once(*scope_id)
+                .chain(self.children().into_iter().flat_map(|child| child.block_scopes()))
+                .collect(),
+            SemanticAst::Match(_, arms) => arms.iter().map(|arm| arm.scope_id)
+                .chain(self.children().into_iter().flat_map(|child| child.block_scopes()))
+                .collect(),
+            _ => self.children().into_iter().flat_map(|child| child.block_scopes()).collect()
+        }
+    }
+
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            SemanticAst::Block(..) => "Block",
+            SemanticAst::Number(_) => "Number",
+            SemanticAst::Truth(_) => "Truth",
+            SemanticAst::Text(_) => "Text",
+            SemanticAst::Variable(..) => "Variable",
+            SemanticAst::Frame(..) => "Frame",
+            SemanticAst::Declaration(..) => "Declaration",
+            SemanticAst::PatternDeclaration(..) => "PatternDeclaration",
+            SemanticAst::MultipleDeclaration(_) => "MultipleDeclaration",
+            SemanticAst::Assignment(..) => "Assignment",
+            SemanticAst::MultipleAssignment(_) => "MultipleAssignment",
+            SemanticAst::BinaryOperation(..) => "BinaryOperation",
+            SemanticAst::Comparison(..) => "Comparison",
+            SemanticAst::Negation(..) => "Negation",
+            SemanticAst::FunctionCall(..) => "FunctionCall",
+            SemanticAst::SpreadCall(..) => "SpreadCall",
+            SemanticAst::Spread(_) => "Spread",
+            SemanticAst::List(_) => "List",
+            SemanticAst::Map(_) => "Map",
+            SemanticAst::FunctionDeclaration(..) => "FunctionDeclaration",
+            SemanticAst::Function(_) => "Function",
+            SemanticAst::Return(_) => "Return",
+            SemanticAst::Yield(_) => "Yield",
+            SemanticAst::If(..) => "If",
+            SemanticAst::Match(..) => "Match",
+            SemanticAst::Defer(_) => "Defer",
+            SemanticAst::For(..) => "For",
+            SemanticAst::Checked(..) => "Checked",
+            SemanticAst::TypeDeclaration(_) => "TypeDeclaration",
+            SemanticAst::Construct(..) => "Construct",
+            SemanticAst::Field(..) => "Field",
+            SemanticAst::MethodCall(..) => "MethodCall",
+            SemanticAst::Module(..) => "Module",
+            SemanticAst::DebugPrint(_) => "DebugPrint",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchArm {
+    pub pattern: SemanticPattern,
+    // Checked after the pattern binds its names. The arm only runs when it's true.
+    pub guard: Option<SemanticAst>,
+    pub body: SemanticAst,
+    // Where the names the pattern binds live
+    pub scope_id: TableId
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SemanticPattern {
+    Wildcard,
+    Binding(SymbolId),
+    Literal(SemanticNode),
+    Record(SymbolId, Vec<(usize, SemanticPattern)>), // type, (field index, pattern)
+    List(Vec<SemanticPattern>, Option<Box<SemanticPattern>>), // items, the rest
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BinaryOperator {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+    In,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+impl BinaryOperator {
+    fn from_token(token: &Token) -> anyhow::Result<BinaryOperator> {
+        Ok(match token.token_type {
+            TokenType::Equal => BinaryOperator::Equal,
+            TokenType::NotEqual => BinaryOperator::NotEqual,
+            TokenType::Less => BinaryOperator::Less,
+            TokenType::LessEqual => BinaryOperator::LessEqual,
+            TokenType::Greater => BinaryOperator::Greater,
+            TokenType::GreaterEqual => BinaryOperator::GreaterEqual,
+            TokenType::And => BinaryOperator::And,
+            TokenType::Or => BinaryOperator::Or,
+            TokenType::In => BinaryOperator::In,
+            TokenType::Plus => BinaryOperator::Add,
+            TokenType::Minus => BinaryOperator::Subtract,
+            TokenType::Star => BinaryOperator::Multiply,
+            TokenType::Slash => BinaryOperator::Divide,
+            TokenType::Percent => BinaryOperator::Remainder,
+            _ => return Err(anyhow::anyhow!("{} is not a binary operator", token.value))
+        })
+    }
+}
+
+pub type TableId = Uuid;
+
+/// Everything the interpreter needs to call a function declared in odo.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub parameters: Vec<SymbolId>,
+    pub parameter_types: Vec<SymbolId>,
+    pub return_id: Option<SymbolId>,
+    pub body: Vec<SemanticAst>,
+    pub scope_id: TableId,
+    // Slots of the environment every call gets, parameters first
+    pub layout: Arc<Layout>,
+    // Functions that yield can only run as coroutines
+    pub is_coroutine: bool,
+    // Functions that return a seq don't run when called. The seq runs them as its items are needed.
+    pub is_generator: bool
+}
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct SymbolTable {
-    #[allow(dead_code)]
     name: String,
     table_id: TableId,
     parent: Option<TableId>,
-    symbols: HashMap<TableId, Symbol>
+    symbols: IndexMap<TableId, Symbol>,
+    // The top level of an imported module. Code outside it only sees what's exported.
+    #[serde(default)]
+    module: bool
 }
 
 impl SymbolTable {
@@ -103,7 +642,8 @@ impl SymbolTable {
             name,
             table_id: TableId::new_v4(),
             parent: None,
-            symbols: HashMap::new()
+            symbols: IndexMap::new(),
+            module: false
         }
     }
 
@@ -111,15 +651,25 @@ impl SymbolTable {
         self.symbols.insert(symbol.symbol_id, symbol);
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn id(&self) -> TableId {
+        self.table_id
+    }
+
+    pub fn parent(&self) -> Option<TableId> {
+        self.parent
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.values()
+    }
+
     // Lookup by name
     pub fn lookup(&self, name: String) -> Option<&Symbol> {
-        for symbol in self.symbols.values() {
-            if symbol.name == name {
-                return Some(symbol);
-            }
-        }
-
-        None
+        self.symbols.values().find(|symbol| symbol.name == name)
     }
 
     // Lookup by id
@@ -130,38 +680,106 @@ impl SymbolTable {
 
 pub type SymbolId = Uuid;
 
-#[derive(Clone, Debug)]
+// FNV-1a, since the standard library's hashers can change between versions
+fn stable_id(key: &str) -> SymbolId {
+    let mut hash: u128 = 0x6c62272e07bb014262b821756295c58d;
+    for byte in key.bytes() {
+        hash ^= byte as u128;
+        hash = hash.wrapping_mul(0x0000000001000000000000000000013b);
+    }
+
+    // Marked as a custom (version 8) uuid, so it never looks like a random one
+    let mut bytes = hash.to_be_bytes();
+    bytes[6] = (bytes[6] & 0x0f) | 0x80;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    SymbolId::from_bytes(bytes)
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Symbol {
     name: String,
     pub symbol_id: SymbolId,
-    pub variant: SymbolVariant
+    pub variant: SymbolVariant,
+    // What the declaration was annotated with, like `@deprecated`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<Annotation>
 }
 
 impl Symbol {
     pub fn new(name: String, kind: SymbolVariant) -> Self {
         Symbol {
-            name: name,
+            name,
             symbol_id: SymbolId::new_v4(),
-            variant: kind
+            variant: kind,
+            annotations: Vec::new()
+        }
+    }
+
+    // For what every interpreter has or makes the same way: builtin types, natives, and types made
+    // from other types. The id comes from `key`, so a program analyzed by one interpreter uses the
+    // same ids for them as the interpreters that run it, and doesn't have to be rewritten for each.
+    pub(crate) fn stable(name: String, kind: SymbolVariant, key: &str) -> Self {
+        Symbol {
+            name,
+            symbol_id: stable_id(key),
+            variant: kind,
+            annotations: Vec::new()
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn annotation(&self, kind: annotations::Kind) -> Option<&Annotation> {
+        self.annotations.iter().find(|annotation| annotation.kind() == Some(kind))
+    }
+
+    // The warning for using it, when it's marked `@deprecated`
+    fn deprecation(&self) -> Option<String> {
+        let annotation = self.annotation(annotations::Kind::Deprecated)?;
+
+        Some(match annotation.arguments.first() {
+            Some(reason) => format!("{} is deprecated: {}", self.name, reason.value),
+            None => format!("{} is deprecated", self.name)
+        })
+    }
+
+    // The type of the value this symbol is bound to
+    pub fn value_type_id(&self) -> anyhow::Result<SymbolId> {
+        match self.variant {
+            SymbolVariant::Variable(ref var) => Ok(var.type_id),
+            SymbolVariant::NativeFunction(ref func) => Ok(func.type_id),
+            SymbolVariant::Function(ref func) => Ok(func.type_id),
+            SymbolVariant::Namespace(_) => Err(anyhow::anyhow!("{} is a namespace, not a value", self.name)),
+            SymbolVariant::Primitive | SymbolVariant::FunctionType(_) | SymbolVariant::Parametric(_) |
+            SymbolVariant::TypeParameter | SymbolVariant::Record(_) | SymbolVariant::Contract(_) => Err(anyhow::anyhow!("{} is a type, not a value", self.name))
         }
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum SymbolVariant {
     Variable(VariableSymbol),
     Primitive, // Primitives only need their name
     FunctionType(FunctionTypeSymbol),
-    NativeFunction(NativeFunctionSymbol)
+    Parametric(ParametricTypeSymbol), // list<int>
+    TypeParameter, // The T in a generic signature. It stands for whatever type each call uses
+    Record(RecordSymbol), // A type declared in odo, with fields and methods
+    Contract(ContractSymbol), // Methods a type can promise to have
+    NativeFunction(NativeFunctionSymbol),
+    Function(FunctionSymbol),
+    Namespace(NamespaceSymbol)
 }
 
 // Symbol variants:
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct VariableSymbol {
     type_id: SymbolId
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FunctionTypeSymbol {
     return_id: Option<SymbolId>,
     argument_ids: Vec<SymbolId>
@@ -175,6 +793,10 @@ impl FunctionTypeSymbol {
         }
     }
 
+    pub fn return_id(&self) -> Option<SymbolId> {
+        self.return_id
+    }
+
     pub fn construct_type_name(return_id: Option<SymbolId>, argument_ids: Vec<SymbolId>, semantic_analyzer: &SemanticAnalyzer) -> anyhow::Result<String> {
         // Format for a function type name:
         // <arg1,arg2,...,argn:return>
@@ -196,13 +818,48 @@ impl FunctionTypeSymbol {
             }
         }
 
+        name.push(':');
+
+        if let Some(return_id) = return_id {
+            let return_name = semantic_analyzer.name_of_type(return_id)?.unwrap_or("<unknown>".to_string());
+            name.push_str(&return_name);
+        }
+
         name.push('>');
 
         Ok(name)
     }
 }
 
-#[derive(Clone, Debug)]
+// A list or map that says what it holds. Plain `list` and `map` hold anything, and
+// go wherever a list or map of some type is expected without being checked.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ParametricTypeSymbol {
+    base_id: SymbolId, // list or map
+    argument_id: SymbolId // The type of the items, or of the values for maps
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordSymbol {
+    fields: Vec<(String, SymbolId)>, // In the order they're declared, which is the order constructors take them in
+    // The methods live in their own table, which isn't part of any scope chain
+    table_id: TableId,
+    contracts: Vec<SymbolId>
+}
+
+impl RecordSymbol {
+    pub fn table_id(&self) -> TableId {
+        self.table_id
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ContractSymbol {
+    // Their function types take the contract itself as `self`
+    methods: Vec<(String, SymbolId)>
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct NativeFunctionSymbol {
     type_id: SymbolId
 }
@@ -215,6 +872,23 @@ impl NativeFunctionSymbol {
     }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FunctionSymbol {
+    type_id: SymbolId
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NamespaceSymbol {
+    // The members live in their own table, which isn't part of any scope chain
+    table_id: TableId
+}
+
+impl NamespaceSymbol {
+    pub fn table_id(&self) -> TableId {
+        self.table_id
+    }
+}
+
 // Semantic analysis
 
 /// This is what is returned when a grammatical Node is analyzed
@@ -226,13 +900,36 @@ pub struct SemanticResult {
     // Does this node have side effects, for example.
 }
 
+impl SemanticResult {
+    /// The type of the node's value, if it has one
+    pub fn type_id(&self) -> Option<SymbolId> {
+        self.type_id
+    }
+}
+
 impl SemanticAnalyzer {
     pub fn analyze(&mut self, ast: Node) -> anyhow::Result<SemanticResult> {
-        let ast = ast.clone();
-        Ok(self.analyze_node(ast)?)
+        self.analyze_node(ast)
     }
 
     pub fn analyze_node(&mut self, ast: Node) -> anyhow::Result<SemanticResult> {
+        let token = expression_token(&ast).cloned();
+        let result = stack::guarded(|| self.analyze_ast(ast))?;
+
+        // Remembered for hovers
+        if let (Some(token), Some(type_id)) = (token, result.type_id) {
+            self.xref.typed(&token, type_id);
+        }
+
+        Ok(result)
+    }
+
+    #[allow(clippy::boxed_local)]
+    fn analyze_ast(&mut self, ast: Node) -> anyhow::Result<SemanticResult> {
+        if self.frames.is_empty() && matches!(*ast, Ast::Block(_) | Ast::Match(..) | Ast::For(..)) {
+            return self.analyze_frame(ast);
+        }
+
         match *ast {
             Ast::Block(nodes) => {
                 // Create a scope and set it as the current scope
@@ -241,19 +938,16 @@ impl SemanticAnalyzer {
 
                 scope.parent = Some(self.current_scope_id);
 
-                self.scopes.insert(id, scope);
-                self.push_scope(id);
-                
-                let mut semantic_nodes = Vec::new();
+                self.check_reachable(&nodes)?;
 
-                for node in nodes {
-                    semantic_nodes.push(*self.analyze_node(node)?.node);
-                }
+                self.scopes.insert(id, Arc::new(scope));
 
-                let node = SemanticAst::Block(semantic_nodes, id);
+                // Back to the parent scope even if a statement fails
+                let semantic_nodes = self.in_scope(id, |analyzer| nodes.into_iter()
+                    .map(|node| analyzer.analyze_node(node).map(|result| *result.node))
+                    .collect::<anyhow::Result<Vec<_>>>())?;
 
-                // Set the current scope to the parent scope
-                self.pop_scope()?;
+                let node = SemanticAst::Block(semantic_nodes, id);
 
                 Ok(SemanticResult {
                     node: Box::new(node),
@@ -261,11 +955,12 @@ impl SemanticAnalyzer {
                 })
             },
             Ast::Number(token) => {
+                let type_id = if token.value.contains('.') { DEC_TYPE.symbol_id } else { INT_TYPE.symbol_id };
                 let node = SemanticAst::Number(token);
 
                 Ok(SemanticResult {
                     node: Box::new(node),
-                    type_id: Some(INT_TYPE.symbol_id)
+                    type_id: Some(type_id)
                 })
             },
             Ast::Truth(token) => {
@@ -288,15 +983,43 @@ impl SemanticAnalyzer {
                 // lookup the variable and return it's type
                 let name_node = Ast::Variable(token.clone());
                 let symbol = self.current_scope()?.symbol_from_node(&name_node, self)?
-                    .ok_or(anyhow::anyhow!("Variable {} not found", token.value))?;
+                    .ok_or_else(|| self.unknown_variable(&token))?;
 
-                let type_id = match symbol.variant {
-                    SymbolVariant::Variable(ref var) => var.type_id,
-                    SymbolVariant::NativeFunction(ref func) => func.type_id,
-                    _ => panic!("Symbol does not contain a value")
-                };
+                let type_id = symbol.value_type_id()?;
+                let symbol_id = symbol.symbol_id;
+                let deprecation = symbol.deprecation();
+                self.xref.refer(&token, symbol_id);
+                self.warn_deprecated(deprecation, &token)?;
 
-                let node = SemanticAst::Variable(symbol.symbol_id);
+                let node = SemanticAst::Variable(symbol_id, self.slot(symbol_id));
+
+                Ok(SemanticResult {
+                    node: Box::new(node),
+                    type_id: Some(type_id)
+                })
+            },
+            Ast::MemberAccess(target, field) if self.is_value(&target) => {
+                let value = self.analyze_node(target)?;
+                let (index, type_id) = self.field(value.type_id, &field)?;
+
+                Ok(SemanticResult {
+                    node: Box::new(SemanticAst::Field(value.node, index)),
+                    type_id: Some(type_id)
+                })
+            },
+            Ast::MemberAccess(target, member) => {
+                // Members are resolved statically, so they end up as plain variables
+                let namespace_id = self.namespace_from_node(&target)?;
+                let symbol = self.member(namespace_id, &member.value, Some(&member))?;
+
+                let type_id = symbol.value_type_id()?;
+                let symbol_id = symbol.symbol_id;
+                let deprecation = symbol.deprecation();
+                self.xref.refer(&member, symbol_id);
+                self.warn_deprecated(deprecation, &member)?;
+
+                // Namespaces only have globals
+                let node = SemanticAst::Variable(symbol_id, None);
 
                 Ok(SemanticResult {
                     node: Box::new(node),
@@ -310,142 +1033,350 @@ impl SemanticAnalyzer {
                 let type_id = result_node.type_id
                     .ok_or(anyhow::anyhow!("Variable initialization must be a valid expression (Must return value)"))?;
 
-                // Check if the variable has already been declared
-                if self.current_scope()?
-                    .symbol_from_node(&Ast::Variable(token.clone()), &self)?
-                    .is_some()
-                {
-                    return Err(anyhow::anyhow!("Variable called {} already exists.", token.value));
-                }
-
-                // Create a new symbol and insert it into the symbol table
-                let symbol = Symbol::new(token.value.clone(), SymbolVariant::Variable(VariableSymbol {
-                    type_id: type_id
-                }));
-
-                self.current_scope_mut()?
-                    .symbols.insert(symbol.symbol_id, symbol.clone());
+                let symbol_id = self.declare_variable(&token, type_id)?;
 
-                let node = SemanticAst::Declaration(symbol.symbol_id, symbol.symbol_id, result_node.node);
+                let node = SemanticAst::Declaration(symbol_id, symbol_id, result_node.node);
 
                 Ok(SemanticResult {
                     node: Box::new(node),
                     type_id: None
                 })
             },
-            Ast::Assignment(target, node) => {
-                let result_node = self.analyze_node(node)?;
+            Ast::TypedDeclaration(token, type_name, node) => {
+                if self.strict && type_name.value == ANY_TYPE.name {
+                    return Err(anyhow::anyhow!(
+                        "Strict mode needs a type other than any for variables (line {}, column {})",
+                        type_name.line, type_name.column
+                    ));
+                }
 
-                let target_symbol = self.symbol_from_node(&*target)?
-                .ok_or(anyhow::anyhow!("Symbol not found"))?;
+                let type_id = self.signature_type_id(&type_name.value)
+                    .map_err(|error| match error.downcast::<suggest::NotFound>() {
+                        Ok(not_found) => not_found.at(&type_name).into(),
+                        Err(error) => error
+                    })?;
+                let at = lints::first_token(&node).cloned();
+                let result_node = self.analyze_node(node)?;
+                let value = self.expect_type(result_node, type_id, at)?;
 
-                // Get the type of the target
-                // TODO: Expand the kinds of symbol that can be assigned to
-                let type_id = match target_symbol.variant {
-                    SymbolVariant::Variable(ref var) => var.type_id,
-                    _ => panic!("Symbol is not a variable")
-                };
+                let symbol_id = self.declare_variable(&token, type_id)?;
 
-                // Check if the type of the assignment is the same as the type of the variable
-                if result_node.type_id.ok_or(anyhow::anyhow!("Assignment must be a valid expression (Must return value)"))? != type_id {
-                    let expected_name = self.name_of_type(type_id)?.unwrap_or("<unknown>".to_string());
-                    let got_name = self.name_of_type(
-                        result_node.type_id
-                            .ok_or(anyhow::anyhow!("Assignment must be a valid expression (Must return value)"))?
-                        )?
-                        .unwrap_or("<unknown>".to_string());
+                Ok(SemanticResult {
+                    node: Box::new(SemanticAst::Declaration(symbol_id, symbol_id, value)),
+                    type_id: None
+                })
+            },
+            Ast::MultipleDeclaration(declarations) => {
+                // Every value is analyzed before declaring anything, so `var a = 1, b = a` can't see the new `a`
+                let mut values = Vec::new();
+                for (token, node) in declarations {
+                    if values.iter().any(|(other, _): &(Token, SemanticResult)| other.value == token.value) {
+                        return Err(anyhow::anyhow!("Variable {} is declared twice in the same statement", token.value));
+                    }
 
-                    return Err(
-                        anyhow::anyhow!(
-                            "Type mismatch: Expected type {:?} but got type {:?}",
-                            expected_name,
-                            got_name
-                        )
-                    );
+                    values.push((token, self.analyze_node(node)?));
                 }
 
-                let node = SemanticAst::Assignment(target_symbol.symbol_id, result_node.node);
+                let mut nodes = Vec::new();
+                for (token, value) in values {
+                    let type_id = value.type_id
+                        .ok_or(anyhow::anyhow!("Variable initialization must be a valid expression (Must return value)"))?;
+
+                    nodes.push((self.declare_variable(&token, type_id)?, value.node));
+                }
 
                 Ok(SemanticResult {
-                    node: Box::new(node),
+                    node: Box::new(SemanticAst::MultipleDeclaration(nodes)),
                     type_id: None
                 })
             },
-            Ast::FunctionCall(callee, args) => {
-                let callee_result = self.analyze_node(callee)?;
-                let callee_variant = &self.current_scope()?
-                    .symbol_from_id(callee_result.type_id.ok_or(anyhow::anyhow!(""))?, &self)
-                    .ok_or(anyhow::anyhow!("Symbol not found"))?
-                    .variant;
-
-                let callee_type = match callee_variant {
-                    SymbolVariant::FunctionType(ref func) => func.clone(),
-                    _ => panic!("Symbol is not a function")
-                };
+            Ast::BinaryOperation(left, operator, right) => self.analyze_binary_operation(left, operator, right),
+            Ast::Comparison(first, rest) => self.analyze_comparison(first, rest),
+            Ast::List(items) => {
+                let mut nodes = Vec::new();
+                // What the items have in common. Spreads could hold anything, and so could an empty list.
+                let mut item_type = None;
+                let mut agree = true;
+                for item in items {
+                    if let Ast::Spread(list) = *item {
+                        nodes.push(self.analyze_spread(list)?);
+                        agree = false;
+                        continue;
+                    }
 
-                // Check that the number of arguments is correct
-                if args.len() != callee_type.argument_ids.len() {
-                    return Err(anyhow::anyhow!("Incorrect number of arguments"));
-                }
+                    let result = self.analyze_node(item)?;
+                    let type_id = result.type_id.ok_or(anyhow::anyhow!("List items must be valid expressions (Must return value)"))?;
+                    agree &= *item_type.get_or_insert(type_id) == type_id;
 
-                let mut arg_nodes = vec![];
-
-                // Check that the types of the arguments are correct
-                for (i, arg) in args.clone().iter().enumerate() {
-                    let arg_result = self.analyze_node(arg.clone())?;
-                    arg_nodes.push(arg_result.node);
-                    let arg_type_id = arg_result.type_id
-                        .ok_or(anyhow::anyhow!("Function argument must be a valid expression (Must return value)"))?;
-
-                    if arg_type_id != callee_type.argument_ids[i] {
-                        let expected_name = self.name_of_type(callee_type.argument_ids[i])?.unwrap_or("<unknown>".to_string());
-                        let got_name = self.name_of_type(arg_type_id)?.unwrap_or("<unknown>".to_string());
-
-                        return Err(
-                            anyhow::anyhow!(
-                                "Type mismatch: Expected type {:?} but got type {:?}",
-                                expected_name,
-                                got_name
-                            )
-                        );
-                    }
+                    nodes.push(result.node);
                 }
 
-                let node = SemanticAst::FunctionCall(
-                    callee_result.node,
-                    arg_nodes
-                );
+                let type_id = match item_type {
+                    Some(item_type) if agree => self.parametric_type_id(LIST_TYPE.symbol_id, item_type)?,
+                    _ => LIST_TYPE.symbol_id
+                };
 
                 Ok(SemanticResult {
-                    node: Box::new(node),
-                    type_id: callee_type.return_id
+                    node: Box::new(SemanticAst::List(nodes)),
+                    type_id: Some(type_id)
                 })
             },
-            Ast::If(condition, body) => {
-                let condition = self.analyze_node(condition)?;
-                let body = self.analyze_node(body)?;
+            Ast::Map(entries) => {
+                let mut nodes = Vec::new();
+                for (key, value) in entries {
+                    let key = self.analyze_node(key)?;
+                    let key_type = key.type_id.ok_or(anyhow::anyhow!("Map keys must be valid expressions (Must return value)"))?;
+                    if !self.hashable(key_type) {
+                        let name = self.name_of_type(key_type)?.unwrap_or_default();
+                        return Err(anyhow::anyhow!("Map keys can be ints, strings, truths, or lists of them, not {}", name));
+                    }
 
-                // Check that the condition is a truth
-                let condition_type = condition.type_id
-                    .ok_or(anyhow::anyhow!("If condition must be a valid expression (Must return value)"))?;
+                    let value = self.analyze_node(value)?;
+                    let _ = value.type_id.ok_or(anyhow::anyhow!("Map values must be valid expressions (Must return value)"))?;
 
-                if condition_type != TRUTH_TYPE.symbol_id {
-                    return Err(anyhow::anyhow!("If condition must be a truth"));
+                    nodes.push((key.node, value.node));
                 }
 
-                let node = SemanticAst::If(condition.node, body.node);
+                Ok(SemanticResult {
+                    node: Box::new(SemanticAst::Map(nodes)),
+                    type_id: Some(MAP_TYPE.symbol_id)
+                })
+            },
+            Ast::Negation(operator, node) => {
+                let result = self.analyze_node(node)?;
+                let type_id = result.type_id
+                    .ok_or(anyhow::anyhow!("Negation must be applied to a value"))?;
+
+                if type_id != INT_TYPE.symbol_id && type_id != DEC_TYPE.symbol_id && type_id != ANY_TYPE.symbol_id {
+                    return Err(anyhow::anyhow!(
+                        "Only numbers can be negated (line {}, column {})",
+                        operator.line,
+                        operator.column
+                    ));
+                }
 
                 Ok(SemanticResult {
-                    node: Box::new(node),
-                    type_id: None
+                    node: Box::new(SemanticAst::Negation(result.node, operator)),
+                    type_id: Some(type_id)
                 })
             },
-            Ast::DebugPrint(node) => {
+            Ast::Assignment(target, node) => {
+                let at = lints::first_token(&node).cloned();
                 let result_node = self.analyze_node(node)?;
+                let (target_id, value) = self.assignment_target(&target, result_node, at)?;
 
-                // This is not important. Just check that there's a value to print (type_id is some).
-                let _ = result_node.type_id.ok_or(anyhow::anyhow!("DebugPrint must be a valid expression (Must return value)"))?;
-                // Return nothing
+                let node = SemanticAst::Assignment(target_id, value);
+
+                Ok(SemanticResult {
+                    node: Box::new(node),
+                    type_id: None
+                })
+            },
+            Ast::MultipleAssignment(targets, values) => {
+                if targets.len() != values.len() {
+                    return Err(anyhow::anyhow!("Assigning {} values to {} targets", values.len(), targets.len()));
+                }
+
+                let mut assignments = Vec::new();
+                for (target, value) in targets.into_iter().zip(values) {
+                    let at = lints::first_token(&value).cloned();
+                    let result_node = self.analyze_node(value)?;
+                    let (target_id, value) = self.assignment_target(&target, result_node, at)?;
+
+                    if assignments.iter().any(|(id, _)| *id == target_id) {
+                        return Err(anyhow::anyhow!("The same variable can't be assigned twice in one statement"));
+                    }
+
+                    assignments.push((target_id, value));
+                }
+
+                Ok(SemanticResult {
+                    node: Box::new(SemanticAst::MultipleAssignment(assignments)),
+                    type_id: None
+                })
+            },
+            Ast::FunctionCall(callee, args) if self.record_from_node(&callee).is_some() => self.analyze_construction(*callee, args),
+            Ast::FunctionCall(callee, args) if matches!(*callee, Ast::MemberAccess(ref target, _) if self.is_value(target)) => {
+                let Ast::MemberAccess(target, method) = *callee else { unreachable!("Just matched") };
+                self.analyze_method_call(target, method, args)
+            },
+            Ast::FunctionCall(callee, args) => {
+                let callee_result = self.analyze_node(callee)?;
+                self.analyze_call(callee_result, args)
+            },
+            Ast::Spread(_) => Err(anyhow::anyhow!("Spreading (...) is only allowed in function calls and list literals")),
+            Ast::FunctionDeclaration(name, parameters, return_type, body) => {
+                self.analyze_function_declaration(name, parameters, return_type, *body)
+            },
+            Ast::Function(parameters, return_type, body) => {
+                self.analyze_function_literal(parameters, return_type, *body)
+            },
+            Ast::Return(value) => {
+                let expected = self.function_stack.last()
+                    .ok_or(anyhow::anyhow!("Can't return outside of a function"))?
+                    .return_id;
+
+                let at = value.as_ref().and_then(|value| lints::first_token(value)).cloned();
+                let mut value = match value {
+                    Some(value) => Some(self.analyze_node(value)?),
+                    None => None
+                };
+
+                let got = match value {
+                    Some(ref value) => Some(value.type_id
+                        .ok_or(anyhow::anyhow!("Return must be a valid expression (Must return value)"))?),
+                    None => None
+                };
+
+                let fits = match (got, expected) {
+                    (Some(got), Some(expected)) => self.compatible(got, expected),
+                    (got, expected) => got == expected
+                };
+
+                let checked = matches!((got, expected), (Some(got), Some(expected)) if !self.compatible(got, expected) && self.checked_when_running(got, expected));
+                if let (Some(expected_id), true) = (expected, checked) {
+                    value = value.map(|value| SemanticResult {
+                        node: Box::new(SemanticAst::Checked(value.node, expected_id, at)),
+                        type_id: expected
+                    });
+                } else if !fits {
+                    let expected_name = match expected {
+                        Some(id) => self.name_of_type(id)?.unwrap_or("<unknown>".to_string()),
+                        None => "nothing".to_string()
+                    };
+                    let got_name = match got {
+                        Some(id) => self.name_of_type(id)?.unwrap_or("<unknown>".to_string()),
+                        None => "nothing".to_string()
+                    };
+
+                    return Err(
+                        anyhow::anyhow!(
+                            "Type mismatch: Function should return {:?} but returns {:?}",
+                            expected_name,
+                            got_name
+                        )
+                    );
+                }
+
+                let node = SemanticAst::Return(value.map(|value| value.node));
+
+                Ok(SemanticResult {
+                    node: Box::new(node),
+                    type_id: None
+                })
+            },
+            Ast::Yield(value) => {
+                let at = lints::first_token(&value).cloned();
+                let value = self.analyze_node(value)?;
+                let _ = value.type_id.ok_or(anyhow::anyhow!("Yield must be a valid expression (Must return value)"))?;
+
+                let context = self.function_stack.last_mut()
+                    .ok_or(anyhow::anyhow!("Can't yield outside of a function"))?;
+                context.yields = true;
+
+                // Generators yield the items of the seq they return
+                let value = match context.item_id {
+                    Some(item_id) => self.expect_type(value, item_id, at)?,
+                    None => value.node
+                };
+
+                let node = SemanticAst::Yield(value);
+
+                Ok(SemanticResult {
+                    node: Box::new(node),
+                    type_id: None
+                })
+            },
+            Ast::If(condition, body) => {
+                self.check_condition(&condition)?;
+
+                let at = lints::first_token(&condition).cloned();
+                let condition = self.analyze_node(condition)?;
+                let body = self.analyze_node(body)?;
+
+                // Check that the condition is a truth
+                let condition_type = condition.type_id
+                    .ok_or(anyhow::anyhow!("If condition must be a valid expression (Must return value)"))?;
+
+                let condition = match condition_type {
+                    id if id == TRUTH_TYPE.symbol_id => condition.node,
+                    id if id == ANY_TYPE.symbol_id => Box::new(SemanticAst::Checked(condition.node, TRUTH_TYPE.symbol_id, at)),
+                    _ => return Err(anyhow::anyhow!("If condition must be a truth"))
+                };
+
+                let node = SemanticAst::If(condition, body.node);
+
+                Ok(SemanticResult {
+                    node: Box::new(node),
+                    type_id: None
+                })
+            },
+            Ast::TypeDeclaration(name, contracts, fields, methods) => self.analyze_type_declaration(name, contracts, fields, methods),
+            Ast::ContractDeclaration(name, methods) => self.analyze_contract(name, methods),
+            Ast::Match(keyword, value, arms) => self.analyze_match(keyword, value, arms),
+            Ast::For(keyword, name, iterable, body) => self.analyze_for(keyword, name, iterable, body),
+            Ast::Defer(_, expr) => {
+                // Whatever it returns is dropped
+                let expr = self.analyze_node(expr)?;
+
+                Ok(SemanticResult {
+                    node: Box::new(SemanticAst::Defer(Arc::new(*expr.node))),
+                    type_id: None
+                })
+            },
+            Ast::PatternDeclaration(pattern, value) => self.analyze_pattern_declaration(pattern, value),
+            Ast::Extension(name, methods) => self.analyze_extension(name, methods),
+            Ast::Allow(names, statement) => {
+                let lints = names.iter()
+                    .map(|name| Lint::from_name(&name.value).map_err(|error| error.at(name)))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // Lints that were already off stay off after the statement
+                let added: Vec<_> = lints.into_iter().filter(|lint| self.allowed.insert(*lint)).collect();
+                let result = self.analyze_node(statement);
+                for lint in added {
+                    self.allowed.remove(&lint);
+                }
+
+                result
+            },
+            Ast::Annotated(annotations, declaration) => {
+                let name = annotations::declared_name(&declaration)
+                    .ok_or(anyhow::anyhow!("Annotations go before a declaration"))?
+                    .clone();
+                self.check_annotations(&annotations, &declaration)?;
+
+                // Declarations for features that aren't defined aren't analyzed, so they can use
+                // names that only exist with the feature
+                let disabled = annotations.iter()
+                    .filter(|annotation| annotation.kind() == Some(annotations::Kind::Cfg))
+                    .flat_map(|annotation| &annotation.arguments)
+                    .any(|feature| !self.features.contains(&feature.value));
+                if disabled {
+                    return Ok(SemanticResult {
+                        node: Box::new(SemanticAst::MultipleDeclaration(vec![])), // Declares nothing
+                        type_id: None
+                    });
+                }
+
+                let result = self.analyze_node(declaration)?;
+                let scope = self.current_scope_mut()?;
+                let symbol_id = scope.lookup(name.value.clone())
+                    .ok_or(anyhow::anyhow!("{} should have been declared", name.value))?
+                    .symbol_id;
+                if let Some(symbol) = scope.symbols.get_mut(&symbol_id) {
+                    symbol.annotations = annotations;
+                }
+
+                Ok(result)
+            },
+            Ast::Import(keyword, name) => self.analyze_import(keyword, name),
+            Ast::DebugPrint(node) => {
+                let result_node = self.analyze_node(node)?;
+
+                // This is not important. Just check that there's a value to print (type_id is some).
+                let _ = result_node.type_id.ok_or(anyhow::anyhow!("DebugPrint must be a valid expression (Must return value)"))?;
+                // Return nothing
 
                 let node = SemanticAst::DebugPrint(result_node.node);
 
@@ -457,6 +1388,272 @@ impl SemanticAnalyzer {
         }
     }
 
+    // Calls to functions in a value. Constructors and methods are handled before getting here
+    fn analyze_call(&mut self, callee_result: SemanticResult, args: Vec<Node>) -> anyhow::Result<SemanticResult> {
+        let callee_type_id = callee_result.type_id.ok_or(anyhow::anyhow!(""))?;
+        let callee_variant = &self.current_scope()?
+            .symbol_from_id(callee_type_id, self)
+            .ok_or(anyhow::anyhow!("Symbol not found"))?
+            .variant;
+
+        let callee_type = match callee_variant {
+            SymbolVariant::FunctionType(ref func) => func.clone(),
+            _ => return Err(anyhow::anyhow!("Only functions can be called"))
+        };
+
+        // With a spread, how many arguments there are is only known when running
+        let has_spread = args.iter().any(|arg| matches!(**arg, Ast::Spread(_)));
+
+        // Check that the number of arguments is correct
+        let fixed_args = args.iter().filter(|arg| !matches!(***arg, Ast::Spread(_))).count();
+        if (!has_spread && args.len() != callee_type.argument_ids.len()) || fixed_args > callee_type.argument_ids.len() {
+            return Err(anyhow::anyhow!("Incorrect number of arguments"));
+        }
+
+        // Every argument is analyzed before checking them, so generic functions know what their
+        // type parameters stand for. Arguments after a spread could end up in any position, so
+        // they're checked when running.
+        let mut arguments = vec![];
+        let mut position_known = true;
+        for arg in args {
+            if let Ast::Spread(list) = *arg {
+                arguments.push((SemanticResult { node: self.analyze_spread(list)?, type_id: None }, None, false));
+                position_known = false;
+                continue;
+            }
+
+            let at = lints::first_token(&arg).cloned();
+            let arg_result = self.analyze_node(arg)?;
+            if arg_result.type_id.is_none() {
+                return Err(anyhow::anyhow!("Function argument must be a valid expression (Must return value)"));
+            }
+
+            arguments.push((arg_result, at, position_known));
+        }
+
+        let (parameter_types, return_id) = if self.is_generic(callee_type_id) {
+            let known_types: Vec<_> = arguments.iter().map(|(result, _, known)| result.type_id.filter(|_| *known)).collect();
+            self.instantiate(&callee_type, &known_types)?
+        } else {
+            (callee_type.argument_ids.clone(), callee_type.return_id)
+        };
+
+        // Check that the types of the arguments are correct
+        let mut arg_nodes = vec![];
+        for (i, (result, at, known)) in arguments.into_iter().enumerate() {
+            arg_nodes.push(if known { self.expect_type(result, parameter_types[i], at)? } else { result.node });
+        }
+
+        let node = if has_spread {
+            SemanticAst::SpreadCall(callee_result.node, arg_nodes, parameter_types)
+        } else {
+            SemanticAst::FunctionCall(callee_result.node, arg_nodes)
+        };
+
+        Ok(SemanticResult {
+            node: Box::new(node),
+            type_id: return_id
+        })
+    }
+
+    fn analyze_function_declaration(&mut self, name: Token, parameters: Vec<(Token, Token)>, return_type: Option<Token>, body: Ast) -> anyhow::Result<SemanticResult> {
+        let (parameter_types, return_id) = self.function_signature(&parameters, &return_type)?;
+        let type_id = self.function_type_id(return_id, parameter_types.clone())?;
+
+        if self.current_scope()?
+            .symbol_from_node(&Ast::Variable(name.clone()), self)?
+            .is_some()
+        {
+            return Err(anyhow::anyhow!("Function called {} already exists.", name.value));
+        }
+
+        // Declared before the body is analyzed, so the function can call itself
+        let symbol = Symbol::new(name.value.clone(), SymbolVariant::Function(FunctionSymbol { type_id }));
+        self.current_scope_mut()?.insert(symbol.clone());
+        self.lay_out(symbol.symbol_id);
+        self.xref.define(symbol.symbol_id, &name);
+
+        let definition = match self.analyze_function_body(name.value, parameters, parameter_types, return_id, body) {
+            Ok(definition) => definition,
+            Err(e) => {
+                // Don't leave a function behind that can't be called
+                self.current_scope_mut()?.symbols.shift_remove(&symbol.symbol_id);
+                return Err(e);
+            }
+        };
+
+        let node = SemanticAst::FunctionDeclaration(symbol.symbol_id, Arc::new(definition));
+
+        Ok(SemanticResult {
+            node: Box::new(node),
+            type_id: None
+        })
+    }
+
+    fn analyze_function_literal(&mut self, parameters: Vec<(Token, Token)>, return_type: Option<Token>, body: Ast) -> anyhow::Result<SemanticResult> {
+        let (parameter_types, return_id) = self.function_signature(&parameters, &return_type)?;
+        let type_id = self.function_type_id(return_id, parameter_types.clone())?;
+
+        let definition = self.analyze_function_body("<anonymous>".to_string(), parameters, parameter_types, return_id, body)?;
+
+        let node = SemanticAst::Function(Arc::new(definition));
+
+        Ok(SemanticResult {
+            node: Box::new(node),
+            type_id: Some(type_id)
+        })
+    }
+
+    fn function_signature(&mut self, parameters: &[(Token, Token)], return_type: &Option<Token>) -> anyhow::Result<(Vec<SymbolId>, Option<SymbolId>)> {
+        // `any` says nothing about the type, so strict mode wants a real one
+        let untyped = parameters.iter().map(|(_, type_name)| type_name).chain(return_type)
+            .find(|type_name| type_name.value.split(|c: char| !c.is_alphabetic()).any(|word| word == ANY_TYPE.name));
+        if let Some(type_name) = untyped.filter(|_| self.strict) {
+            return Err(anyhow::anyhow!(
+                "Strict mode needs a type other than any in function signatures (line {}, column {})",
+                type_name.line, type_name.column
+            ));
+        }
+
+        let parameter_types = parameters.iter()
+            .map(|(_, type_name)| self.signature_type_id(&type_name.value))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let return_id = match return_type {
+            Some(type_name) => Some(self.signature_type_id(&type_name.value)?),
+            None => None
+        };
+
+        Ok((parameter_types, return_id))
+    }
+
+    // Like `type_id_from_name`, but function types (`<int,int:int>`) and lists or maps of a type (`list<int>`)
+    // are created if nothing used them yet
+    fn signature_type_id(&mut self, name: &str) -> anyhow::Result<SymbolId> {
+        self.resolve_type(name, false)
+    }
+
+    /// Like `signature_type_id`, but single capital letters are type parameters, as in `<list<T>,T:list<T>>`.
+    /// Each call of a function with them in its signature decides what they stand for.
+    pub(crate) fn native_type_id(&mut self, name: &str) -> anyhow::Result<SymbolId> {
+        self.resolve_type(name, true)
+    }
+
+    fn resolve_type(&mut self, name: &str, generic: bool) -> anyhow::Result<SymbolId> {
+        if generic && name.len() == 1 && name.chars().all(|c| c.is_ascii_uppercase()) {
+            return self.type_parameter_id(name);
+        }
+
+        if let Some((base, argument)) = name.split_once('<').filter(|(base, _)| !base.is_empty()) {
+            let argument = argument.strip_suffix('>')
+                .ok_or(anyhow::anyhow!("Invalid type {}", name))?;
+
+            let base_id = self.type_id_from_name(base)?;
+            let argument_id = self.resolve_type(argument, generic)?;
+
+            return self.parametric_type_id(base_id, argument_id);
+        }
+
+        let Some(signature) = name.strip_prefix('<').and_then(|name| name.strip_suffix('>')) else {
+            return self.type_id_from_name(name);
+        };
+
+        let (arguments, return_name) = match split_type_names(signature, ':')[..] {
+            [arguments, return_name] => (arguments, return_name),
+            _ => return Err(anyhow::anyhow!("Invalid function type {}", name))
+        };
+
+        let argument_ids = split_type_names(arguments, ',').into_iter()
+            .filter(|argument| !argument.is_empty())
+            .map(|argument| self.resolve_type(argument, generic))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let return_id = match return_name {
+            "" => None,
+            return_name => Some(self.resolve_type(return_name, generic)?)
+        };
+
+        self.function_type_id(return_id, argument_ids)
+    }
+
+    fn analyze_function_body(&mut self, name: String, parameters: Vec<(Token, Token)>, parameter_types: Vec<SymbolId>, return_id: Option<SymbolId>, body: Ast) -> anyhow::Result<FunctionDefinition> {
+        let mut scope = SymbolTable::new(format!("function {}", name));
+        let scope_id = scope.table_id;
+        scope.parent = Some(self.current_scope_id);
+
+        let mut parameter_ids = Vec::new();
+        for ((parameter, _), type_id) in parameters.iter().zip(parameter_types.iter()) {
+            if scope.lookup(parameter.value.clone()).is_some() {
+                return Err(anyhow::anyhow!("Parameter {} is declared more than once", parameter.value));
+            }
+
+            if self.strict && self.current_scope()?.symbol_from_name(&parameter.value, self).is_some() {
+                return Err(anyhow::anyhow!(
+                    "Parameter {} hides a name declared outside the function, which strict mode doesn't allow (line {}, column {})",
+                    parameter.value, parameter.line, parameter.column
+                ));
+            }
+
+            let parameter_symbol = Symbol::new(parameter.value.clone(), SymbolVariant::Variable(VariableSymbol {
+                type_id: *type_id
+            }));
+            parameter_ids.push(parameter_symbol.symbol_id);
+            self.xref.define(parameter_symbol.symbol_id, parameter);
+            scope.insert(parameter_symbol);
+        }
+
+        // The body shares the scope of the parameters
+        let statements = match body {
+            Ast::Block(statements) => statements,
+            other => vec![Box::new(other)]
+        };
+
+        self.check_reachable(&statements)?;
+
+        self.scopes.insert(scope_id, Arc::new(scope));
+        self.push_scope(scope_id);
+        // A generator's return type is what calling it makes. Its body only yields.
+        let item_id = return_id
+            .filter(|id| self.base_type(*id) == SEQ_TYPE.symbol_id)
+            .map(|id| self.list_item_type(id));
+        let body_return_id = if item_id.is_some() { None } else { return_id };
+        self.function_stack.push(FunctionContext { return_id: body_return_id, yields: false, item_id });
+        self.frames.push(parameter_ids.iter().copied().collect());
+
+        let mut body = Vec::new();
+        let mut error = None;
+        for statement in statements {
+            match self.analyze_node(statement) {
+                Ok(result) => body.push(*result.node),
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            }
+        }
+
+        let context = self.function_stack.pop().expect("It was just pushed");
+        let layout = self.frames.pop().expect("It was just pushed");
+        self.pop_scope()?;
+
+        if let Some(e) = error {
+            self.scopes.shift_remove(&scope_id);
+            return Err(e);
+        }
+
+        Ok(FunctionDefinition {
+            name,
+            parameters: parameter_ids,
+            parameter_types,
+            return_id,
+            body,
+            scope_id,
+            layout: Arc::new(layout),
+            is_coroutine: context.yields,
+            is_generator: context.item_id.is_some()
+        })
+    }
+
     pub fn push_scope(&mut self, scope_id: TableId) {
         self.current_scope_id = scope_id;
     }
@@ -467,35 +1664,183 @@ impl SemanticAnalyzer {
 
         Ok(())
     }
+
+    /// Runs `f` with `scope_id` as the current scope, and goes back to the previous one afterwards,
+    /// whether `f` succeeds or not.
+    pub fn in_scope<T>(&mut self, scope_id: TableId, f: impl FnOnce(&mut SemanticAnalyzer) -> T) -> T {
+        let previous_scope_id = self.current_scope_id;
+        self.current_scope_id = scope_id;
+        let result = f(self);
+        self.current_scope_id = previous_scope_id;
+
+        result
+    }
+}
+
+// Splits on `separator`, except inside nested function types
+fn split_type_names(names: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, c) in names.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            _ if c == separator && depth == 0 => {
+                parts.push(&names[start..i]);
+                start = i + 1;
+            },
+            _ => {}
+        }
+    }
+    parts.push(&names[start..]);
+
+    parts
+}
+
+// The table with the members of a module or namespace
+fn members_of(symbol: &Symbol) -> anyhow::Result<TableId> {
+    match symbol.variant {
+        SymbolVariant::Namespace(ref namespace) => Ok(namespace.table_id),
+        _ => Err(anyhow::anyhow!("{} is not a module or namespace", symbol.name))
+    }
+}
+
+// `a` or `a.b.c`, for names that can be reached through modules and namespaces
+fn path_of(node: &Ast) -> Option<String> {
+    match node {
+        Ast::Variable(token) => Some(token.value.clone()),
+        Ast::MemberAccess(target, member) => Some(format!("{}.{}", path_of(target)?, member.value)),
+        _ => None
+    }
+}
+
+fn located(message: String, at: Option<&Token>) -> anyhow::Error {
+    match at {
+        Some(token) => anyhow::anyhow!("{} (line {}, column {})", message, token.line, token.column),
+        None => anyhow::anyhow!(message)
+    }
 }
 
 // To recursively handle symbols in scopes
 impl SemanticAnalyzer {
+    // Find the member table of the namespace a node refers to (`a` or `a.b`)
+    fn namespace_from_node(&self, node: &Ast) -> anyhow::Result<TableId> {
+        let symbol = match node {
+            Ast::Variable(token) => self.symbol_from_node(node)?
+                .ok_or_else(|| self.unknown_namespace(&token.value, Some(token)))?,
+            Ast::MemberAccess(target, member) => {
+                let parent_id = self.namespace_from_node(target)?;
+                self.member(parent_id, &member.value, Some(member))?
+            },
+            _ => return Err(anyhow::anyhow!("Only namespaces have members"))
+        };
+
+        members_of(symbol)
+    }
+
+    // What a name or a path like `geometry.Point` refers to. Everything before the last name has
+    // to be a module or a namespace, and the last one a member of it. None when a name without a
+    // path isn't declared, so callers can say what kind of thing they expected.
+    fn symbol_at_path(&self, path: &str, at: Option<&Token>) -> anyhow::Result<Option<&Symbol>> {
+        let mut names = path.split('.');
+        let first = names.next().unwrap_or_default();
+
+        let Some(mut symbol) = self.current_scope()?.symbol_from_name(first, self) else {
+            return match path.contains('.') {
+                true => Err(self.unknown_namespace(first, at)),
+                false => Ok(None)
+            };
+        };
+
+        for name in names {
+            symbol = self.member(members_of(symbol)?, name, at)?;
+        }
+
+        Ok(Some(symbol))
+    }
+
+    // A module that could be imported but wasn't gets its own error, since the fix is different
+    fn unknown_namespace(&self, name: &str, at: Option<&Token>) -> anyhow::Error {
+        let importer = self.importer();
+        if self.loader.as_ref().is_some_and(|loader| loader.resolve(name, importer).is_ok()) {
+            return located(format!("Module {} isn't imported. Add `import {}` before using it", name, name), at);
+        }
+
+        let error = suggest::with_suggestions(
+            format!("Module or namespace {} not found", name),
+            name,
+            self.visible_names(|variant| matches!(variant, SymbolVariant::Namespace(_)))
+        );
+
+        match at {
+            Some(token) => error.at(token).into(),
+            None => error.into()
+        }
+    }
+
+    fn member(&self, namespace_id: TableId, name: &str, at: Option<&Token>) -> anyhow::Result<&Symbol> {
+        let namespace = self.scope(namespace_id)?;
+        let kind = if namespace.module { "Module" } else { "Namespace" };
+        // Modules only show what they export
+        let visible = |symbol: &Symbol| !namespace.module || symbol.annotation(annotations::Kind::Export).is_some();
+
+        match namespace.lookup(name.to_string()) {
+            Some(symbol) if visible(symbol) => Ok(symbol),
+            Some(_) => Err(located(format!(
+                "{} exists in module {}, but it's private. Mark it @export to use it from other modules",
+                name, namespace.name
+            ), at)),
+            None => {
+                let error = suggest::with_suggestions(
+                    format!("{} {} has no member called {}", kind, namespace.name, name),
+                    name,
+                    namespace.symbols().filter(|symbol| visible(symbol)).map(|symbol| symbol.name())
+                );
+
+                Err(match at {
+                    Some(token) => error.at(token).into(),
+                    None => error.into()
+                })
+            }
+        }
+    }
+
+    fn unknown_variable(&self, token: &Token) -> anyhow::Error {
+        let name = &token.value;
+        let values = self.visible_names(|variant| matches!(
+            variant,
+            SymbolVariant::Variable(_) | SymbolVariant::Function(_) | SymbolVariant::NativeFunction(_)
+        ));
+
+        suggest::with_suggestions(format!("Variable {} not found", name), name, values).at(token).into()
+    }
+
     // Find symbol from node
     fn symbol_from_node(&self, node: &Ast) -> anyhow::Result<Option<&Symbol>> {
         self.current_scope()?
-            .symbol_from_node(node, &self)
+            .symbol_from_node(node, self)
     }
 }
 
 impl SymbolTable {
     fn symbol_from_node<'a>(&'a self, node: &Ast, semantic_analyzer: &'a SemanticAnalyzer) -> anyhow::Result<Option<&'a Symbol>> {
-        let result = match node {
-            Ast::Variable(token) => {
-                self.lookup(token.value.clone())
-            }
-            _ => return Err(anyhow::anyhow!("Expected a variable"))
-        };
+        match node {
+            Ast::Variable(token) => Ok(self.symbol_from_name(&token.value, semantic_analyzer)),
+            _ => Err(anyhow::anyhow!("Expected a variable"))
+        }
+    }
 
-        match result {
-            Some(symbol) => Ok(Some(symbol)),
-            None => {
-                if let Some(parent) = self.parent_scope(semantic_analyzer) {
-                    parent.symbol_from_node(node, semantic_analyzer)
-                } else {
-                    Ok(None)
-                }
-            }
+    pub fn symbol_from_name<'a>(&'a self, name: &str, semantic_analyzer: &'a SemanticAnalyzer) -> Option<&'a Symbol> {
+        let symbol = self.lookup(name.to_string());
+
+        if let Some(symbol) = symbol {
+            Some(symbol)
+        } else if let Some(parent) = self.parent_scope(semantic_analyzer) {
+            parent.symbol_from_name(name, semantic_analyzer)
+        } else {
+            None
         }
     }
 
@@ -514,16 +1859,52 @@ impl SymbolTable {
 
 // For report purposes
 impl SemanticAnalyzer {
-    fn name_of_type(&self, id: SymbolId) -> anyhow::Result<Option<String>> {
+    pub fn name_of_type(&self, id: SymbolId) -> anyhow::Result<Option<String>> {
+        // Type parameters, and types declared in scopes the current one can't see
         Ok(self.current_scope()?
-        .name_of_type(id, &self))
+        .name_of_type(id, self)
+        .or_else(|| self.scopes.values().find_map(|scope| scope.lookup_id(id)).map(|symbol| symbol.name.clone())))
+    }
+
+    // Function types are shared by every function with the same signature, so they can be compared by id
+    pub fn function_type_id(&mut self, return_id: Option<SymbolId>, argument_ids: Vec<SymbolId>) -> anyhow::Result<SymbolId> {
+        let name = FunctionTypeSymbol::construct_type_name(return_id, argument_ids.clone(), self)?;
+
+        if let Some(symbol) = self.global_scope()?.lookup(name.clone()) {
+            return Ok(symbol.symbol_id);
+        }
+
+        let key = format!("function type {}", name);
+        let symbol = Symbol::stable(name, SymbolVariant::FunctionType(FunctionTypeSymbol::new(return_id, argument_ids)), &key);
+        let id = symbol.symbol_id;
+        self.global_scope_mut()?.insert(symbol);
+
+        Ok(id)
+    }
+
+    // Find a type by the name it's written with (`int`, `string`, ...)
+    pub fn type_id_from_name(&self, name: &str) -> anyhow::Result<SymbolId> {
+        let symbol = self.symbol_at_path(name, None)?
+            .ok_or_else(|| {
+                let types = self.visible_symbols().into_iter()
+                    .filter(|symbol| matches!(symbol.variant, SymbolVariant::Primitive | SymbolVariant::Record(_) | SymbolVariant::Contract(_)))
+                    .map(|symbol| symbol.name());
+
+                suggest::with_suggestions(format!("Type {} not found", name), name, types)
+            })?;
+
+        match symbol.variant {
+            SymbolVariant::Primitive | SymbolVariant::FunctionType(_) | SymbolVariant::Parametric(_) |
+            SymbolVariant::Record(_) | SymbolVariant::Contract(_) => Ok(symbol.symbol_id),
+            _ => Err(anyhow::anyhow!("{} is not a type", name))
+        }
     }
 }
 
 impl SymbolTable {
     fn parent_scope<'a>(&self, semantic_analyzer: &'a SemanticAnalyzer) -> Option<&'a SymbolTable> {
         if let Some(parent_id) = self.parent {
-            return semantic_analyzer.scopes.get(&parent_id);
+            return semantic_analyzer.scopes.get(&parent_id).map(Arc::as_ref);
         }
 
         None
@@ -540,4 +1921,1548 @@ impl SymbolTable {
             None
         }
     }
-}
\ No newline at end of file
+}
+// Variables
+impl SemanticAnalyzer {
+    fn declare_variable(&mut self, token: &Token, type_id: SymbolId) -> anyhow::Result<SymbolId> {
+        // Check if the variable has already been declared
+        if self.current_scope()?
+            .symbol_from_node(&Ast::Variable(token.clone()), self)?
+            .is_some()
+        {
+            return Err(anyhow::anyhow!("Variable called {} already exists.", token.value));
+        }
+
+        // Create a new symbol and insert it into the symbol table
+        let symbol = Symbol::new(token.value.clone(), SymbolVariant::Variable(VariableSymbol {
+            type_id
+        }));
+
+        self.current_scope_mut()?
+            .symbols.insert(symbol.symbol_id, symbol.clone());
+        self.xref.define(symbol.symbol_id, token);
+        self.lay_out(symbol.symbol_id);
+
+        Ok(symbol.symbol_id)
+    }
+
+    // Gives a name declared in the running environment a slot in it. Globals don't get one.
+    fn lay_out(&mut self, symbol_id: SymbolId) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.add(symbol_id);
+        }
+    }
+
+    // Where a name is bound when code at this point runs. None for globals.
+    fn slot(&self, symbol_id: SymbolId) -> Option<Slot> {
+        self.frames.iter().rev().enumerate()
+            .find_map(|(depth, frame)| frame.slot_of(symbol_id).map(|index| Slot { depth, index }))
+    }
+
+    // A block, match or loop at the top level, which runs in an environment of its own
+    fn analyze_frame(&mut self, ast: Node) -> anyhow::Result<SemanticResult> {
+        self.frames.push(Layout::default());
+        let result = self.analyze_ast(ast);
+        let layout = self.frames.pop().expect("It was just pushed");
+        let result = result?;
+
+        Ok(SemanticResult {
+            node: Box::new(SemanticAst::Frame(Arc::new(layout), result.node)),
+            type_id: result.type_id
+        })
+    }
+
+    // `...list`, where its items are expected
+    fn analyze_spread(&mut self, list: Node) -> anyhow::Result<SemanticNode> {
+        let result = self.analyze_node(list)?;
+
+        if result.type_id.map(|id| self.base_type(id)) != Some(LIST_TYPE.symbol_id) {
+            return Err(anyhow::anyhow!("Only lists can be spread with ..."));
+        }
+
+        Ok(Box::new(SemanticAst::Spread(result.node)))
+    }
+
+    // Checks that `value` can be assigned to `target`, returning the variable's symbol
+    fn assignment_target(&mut self, target: &Ast, value: SemanticResult, at: Option<Token>) -> anyhow::Result<(SymbolId, SemanticNode)> {
+        let target_symbol = match (self.symbol_from_node(target)?, target) {
+            (Some(symbol), _) => symbol,
+            (None, Ast::Variable(token)) => return Err(self.unknown_variable(token)),
+            (None, _) => return Err(anyhow::anyhow!("Symbol not found"))
+        };
+
+        // Get the type of the target
+        // TODO: Expand the kinds of symbol that can be assigned to
+        let type_id = match target_symbol.variant {
+            SymbolVariant::Variable(ref var) => var.type_id,
+            _ => return Err(anyhow::anyhow!("Only variables can be assigned to"))
+        };
+
+        if value.type_id.is_none() {
+            return Err(anyhow::anyhow!("Assignment must be a valid expression (Must return value)"));
+        }
+
+        // Check if the type of the assignment is the same as the type of the variable
+        let value = self.expect_type(value, type_id, at)?;
+
+        let target_id = target_symbol.symbol_id;
+        if let Ast::Variable(token) = target {
+            self.xref.refer(token, target_id);
+        }
+
+        Ok((target_id, value))
+    }
+
+    // The node for a value used where `expected` is needed. Values typed any, and lists and maps that
+    // don't say what they hold, are checked when running. Anything goes where any is expected.
+    fn expect_type(&self, value: SemanticResult, expected: SymbolId, at: Option<Token>) -> anyhow::Result<SemanticNode> {
+        match value.type_id {
+            Some(type_id) if self.compatible(type_id, expected) => Ok(value.node),
+            Some(type_id) if self.checked_when_running(type_id, expected) => Ok(Box::new(SemanticAst::Checked(value.node, expected, at))),
+            got => {
+                let expected_name = self.name_of_type(expected)?.unwrap_or("<unknown>".to_string());
+                let got_name = match got {
+                    Some(id) => self.name_of_type(id)?.unwrap_or("<unknown>".to_string()),
+                    None => "nothing".to_string()
+                };
+
+                Err(anyhow::anyhow!(
+                    "Type mismatch: Expected type {:?} but got type {:?}",
+                    expected_name,
+                    got_name
+                ))
+            }
+        }
+    }
+}
+
+// Operators
+impl SemanticAnalyzer {
+    fn analyze_binary_operation(&mut self, left: Node, operator: Token, right: Node) -> anyhow::Result<SemanticResult> {
+        let op = BinaryOperator::from_token(&operator)?;
+
+        let left = self.analyze_node(left)?;
+        let right = self.analyze_node(right)?;
+        let type_id = self.operation_type(op, &operator, &left, &right)?;
+
+        let any = ANY_TYPE.symbol_id;
+        let (left_type, right_type) = (left.type_id.map(|id| self.base_type(id)), right.type_id.map(|id| self.base_type(id)));
+
+        // `and` and `or` only take truths
+        let truth = |node: SemanticNode, type_id: Option<SymbolId>| match type_id {
+            Some(id) if id == any && matches!(op, BinaryOperator::And | BinaryOperator::Or) => {
+                Box::new(SemanticAst::Checked(node, TRUTH_TYPE.symbol_id, Some(operator.clone())))
+            },
+            _ => node
+        };
+        let (left_node, right_node) = (truth(left.node, left_type), truth(right.node, right_type));
+
+        Ok(SemanticResult {
+            node: Box::new(SemanticAst::BinaryOperation(left_node, op, right_node, operator)),
+            type_id: Some(type_id)
+        })
+    }
+
+    // `0 <= x < 10`. Each operand is analyzed, and later runs, once.
+    fn analyze_comparison(&mut self, first: Node, rest: Vec<(Token, Node)>) -> anyhow::Result<SemanticResult> {
+        let mut left = self.analyze_node(first)?;
+        let first = left.node.clone();
+
+        let mut comparisons = vec![];
+        for (operator, right) in rest {
+            let op = BinaryOperator::from_token(&operator)?;
+            let right = self.analyze_node(right)?;
+            self.operation_type(op, &operator, &left, &right)?;
+
+            comparisons.push((op, right.node.clone(), operator));
+            left = right;
+        }
+
+        Ok(SemanticResult {
+            node: Box::new(SemanticAst::Comparison(first, comparisons)),
+            type_id: Some(TRUTH_TYPE.symbol_id)
+        })
+    }
+
+    // The type `left op right` has, or why the operator can't take them
+    fn operation_type(&mut self, op: BinaryOperator, operator: &Token, left: &SemanticResult, right: &SemanticResult) -> anyhow::Result<SymbolId> {
+        // Operators don't care what lists and maps hold
+        let left_type = left.type_id.map(|id| self.base_type(id))
+            .ok_or(anyhow::anyhow!("The left side of {} must return a value", operator.value))?;
+        let right_type = right.type_id.map(|id| self.base_type(id))
+            .ok_or(anyhow::anyhow!("The right side of {} must return a value", operator.value))?;
+
+        let numeric = |id: SymbolId| id == INT_TYPE.symbol_id || id == DEC_TYPE.symbol_id;
+
+        if self.strict && numeric(left_type) && numeric(right_type) && left_type != right_type {
+            return Err(anyhow::anyhow!(
+                "Strict mode doesn't convert between int and dec, so {} can't mix them (line {}, column {})",
+                operator.value, operator.line, operator.column
+            ));
+        }
+
+        // Mixing ints and decs makes a dec
+        let arithmetic_type = if left_type == INT_TYPE.symbol_id && right_type == INT_TYPE.symbol_id {
+            INT_TYPE.symbol_id
+        } else {
+            DEC_TYPE.symbol_id
+        };
+
+        let any = ANY_TYPE.symbol_id;
+        let type_id = match op {
+            // What an operation on values typed any does is only known when running, which is where it gets checked
+            _ if left_type == any || right_type == any => dynamic_operation_type(op, left_type, right_type),
+            BinaryOperator::And | BinaryOperator::Or => {
+                (left_type == TRUTH_TYPE.symbol_id && right_type == TRUTH_TYPE.symbol_id)
+                    .then_some(TRUTH_TYPE.symbol_id)
+            },
+            BinaryOperator::Equal | BinaryOperator::NotEqual => {
+                (left_type == right_type || (numeric(left_type) && numeric(right_type)))
+                    .then_some(TRUTH_TYPE.symbol_id)
+            },
+            // Numbers are ordered by value and strings alphabetically
+            BinaryOperator::Less | BinaryOperator::LessEqual | BinaryOperator::Greater | BinaryOperator::GreaterEqual => {
+                ((numeric(left_type) && numeric(right_type)) ||
+                    (left_type == TEXT_TYPE.symbol_id && right_type == TEXT_TYPE.symbol_id))
+                    .then_some(TRUTH_TYPE.symbol_id)
+            },
+            // Items of a list, keys of a map, or substrings
+            BinaryOperator::In => {
+                let valid = right_type == LIST_TYPE.symbol_id ||
+                    (right_type == MAP_TYPE.symbol_id && left.type_id.is_some_and(|id| self.hashable(id))) ||
+                    (right_type == TEXT_TYPE.symbol_id && left_type == TEXT_TYPE.symbol_id);
+
+                valid.then_some(TRUTH_TYPE.symbol_id)
+            },
+            // `+` also joins strings
+            BinaryOperator::Add if left_type == TEXT_TYPE.symbol_id && right_type == TEXT_TYPE.symbol_id => {
+                Some(TEXT_TYPE.symbol_id)
+            },
+            // `int / int` truncates towards zero, like `%` takes the sign of the left side
+            BinaryOperator::Add | BinaryOperator::Subtract | BinaryOperator::Multiply |
+            BinaryOperator::Divide | BinaryOperator::Remainder => {
+                (numeric(left_type) && numeric(right_type)).then_some(arithmetic_type)
+            }
+        };
+
+        let type_id = match type_id {
+            Some(type_id) => type_id,
+            None => return Err(anyhow::anyhow!(
+                "Operator {} can't be applied to {} and {} (line {}, column {})",
+                operator.value,
+                self.name_of_type(left_type)?.unwrap_or("<unknown>".to_string()),
+                self.name_of_type(right_type)?.unwrap_or("<unknown>".to_string()),
+                operator.line,
+                operator.column
+            ))
+        };
+
+        if matches!(op, BinaryOperator::Divide | BinaryOperator::Remainder) && is_literal_zero(&right.node) {
+            self.warn(Lint::DivisionByZero, format!("{} by zero will fail at runtime", if op == BinaryOperator::Divide { "Division" } else { "Remainder" }), operator)?;
+        }
+
+        Ok(type_id)
+    }
+
+    // In strict mode, warnings are errors
+    fn warn(&mut self, lint: Lint, message: String, token: &Token) -> anyhow::Result<()> {
+        if self.allowed.contains(&lint) {
+            return Ok(());
+        }
+
+        let warning = Warning { lint, message, span: Span::of(token) };
+        if self.strict {
+            return Err(anyhow::anyhow!("{}. Warnings are errors in strict mode", warning));
+        }
+
+        self.warnings.push(warning);
+        Ok(())
+    }
+
+    // Each annotation is known, given once, has the arguments it takes, and fits what it's on
+    fn check_annotations(&self, annotations: &[Annotation], declaration: &Ast) -> anyhow::Result<()> {
+        let mut seen = HashSet::new();
+        for annotation in annotations {
+            let name = &annotation.name;
+            let kind = annotations::Kind::from_name(&name.value).map_err(|error| error.at(name))?;
+            let at = format!("(line {}, column {})", name.line, name.column);
+
+            if !seen.insert(kind) {
+                return Err(anyhow::anyhow!("{} is given more than once {}", kind, at));
+            }
+
+            if annotation.arguments.len() > kind.max_arguments() {
+                return Err(match kind.max_arguments() {
+                    0 => anyhow::anyhow!("{} doesn't take arguments {}", kind, at),
+                    max => anyhow::anyhow!("{} takes at most {} argument{} {}", kind, max, if max == 1 { "" } else { "s" }, at)
+                });
+            }
+
+            match kind {
+                annotations::Kind::Deprecated if annotation.arguments.iter().any(|argument| argument.token_type != TokenType::Text) => {
+                    return Err(anyhow::anyhow!("The reason for {} should be a string {}", kind, at));
+                },
+                annotations::Kind::Test if !matches!(declaration, Ast::FunctionDeclaration(_, parameters, ..) if parameters.is_empty()) => {
+                    return Err(anyhow::anyhow!("{} goes on functions without parameters {}", kind, at));
+                },
+                annotations::Kind::Export if !self.frames.is_empty() => {
+                    return Err(anyhow::anyhow!("Only top-level declarations can be marked {} {}", kind, at));
+                },
+                annotations::Kind::Cfg if !matches!(annotation.arguments.as_slice(), [feature] if feature.token_type == TokenType::Name) => {
+                    return Err(anyhow::anyhow!("{} takes the name of a feature, like {}(debug) {}", kind, kind, at));
+                },
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Strict mode forbids mixing ints and decs, parameters that hide outer names and `any` in
+    /// function signatures, and turns warnings into errors.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Defines `feature`, so declarations marked `@cfg(feature)` are there from now on.
+    pub fn define(&mut self, feature: impl Into<String>) {
+        self.features.insert(feature.into());
+    }
+
+    /// Stops giving warnings for `lint`.
+    pub fn allow(&mut self, lint: Lint) {
+        self.allowed.insert(lint);
+    }
+
+    // `if false` never runs its body, and other conditions made of literals are always the same
+    fn check_condition(&mut self, condition: &Ast) -> anyhow::Result<()> {
+        let token = match lints::first_token(condition) {
+            Some(token) if lints::is_constant(condition) => token.clone(),
+            _ => return Ok(())
+        };
+
+        match condition {
+            Ast::Truth(_) if token.value == "false" => self.warn(Lint::DeadCode, "The body of this if never runs, since its condition is always false".to_string(), &token),
+            Ast::Truth(_) => self.warn(Lint::ConstantCondition, "This condition is always true".to_string(), &token),
+            _ => self.warn(Lint::ConstantCondition, "This condition only uses literals, so it's always the same".to_string(), &token),
+        }
+    }
+
+    // Statements after a `return` in the same block never run. Only the first one is reported.
+    fn check_reachable(&mut self, statements: &[Node]) -> anyhow::Result<()> {
+        let unreachable = statements.iter()
+            .skip_while(|statement| !lints::is_return(statement))
+            .nth(1)
+            .filter(|statement| !lints::allows(statement, Lint::DeadCode))
+            .and_then(|statement| lints::first_token(statement))
+            .cloned();
+
+        match unreachable {
+            Some(token) => self.warn(Lint::DeadCode, "This code never runs, since it comes after a return".to_string(), &token),
+            None => Ok(())
+        }
+    }
+
+    fn warn_deprecated(&mut self, deprecation: Option<String>, token: &Token) -> anyhow::Result<()> {
+        match deprecation {
+            Some(message) => self.warn(Lint::Deprecated, message, token),
+            None => Ok(())
+        }
+    }
+
+    /// Warnings found since the last call.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    // Goes back to an earlier copy of the analyzer, keeping the warnings found since
+    pub(crate) fn restore(&mut self, mut earlier: SemanticAnalyzer) {
+        earlier.warnings = std::mem::take(&mut self.warnings);
+        *self = earlier;
+    }
+}
+
+// Modules
+impl SemanticAnalyzer {
+    /// Lets code import modules, from wherever `loader` finds them.
+    pub fn set_module_loader(&mut self, loader: impl ModuleLoader + 'static) {
+        self.loader = Some(Arc::new(loader));
+    }
+
+    /// Sets the file the code evaluated from now on comes from. Modules it imports are looked for
+    /// next to it, and modules importing it back are a cycle.
+    pub fn set_main_module(&mut self, path: impl Into<PathBuf>) {
+        self.main_module = Some(path.into());
+    }
+
+    // The module whose code is being analyzed
+    fn importer(&self) -> Option<&Path> {
+        self.importing.last().map(|(path, _)| path.as_path())
+            .or(self.main_module.as_deref())
+    }
+
+    // Importing a module that's still being analyzed would start analyzing it all over again
+    fn import_cycle(&self, path: &Path, name: &Token) -> Option<CircularImport> {
+        let modules: Vec<(&Path, Option<&Token>)> = self.main_module.iter().map(|path| (path.as_path(), None))
+            .chain(self.importing.iter().map(|(path, name)| (path.as_path(), Some(name))))
+            .collect();
+        let start = modules.iter().position(|(importing, _)| *importing == path)?;
+
+        Some(CircularImport {
+            chain: modules[start..].iter().map(|(path, _)| path.to_path_buf())
+                .chain(std::iter::once(path.to_path_buf()))
+                .collect(),
+            imports: modules[start + 1..].iter().filter_map(|(_, name)| name.cloned())
+                .chain(std::iter::once(name.clone()))
+                .collect()
+        })
+    }
+
+    // `import name` binds the module as a namespace. Its code is analyzed the first time it's
+    // imported, and runs there. Importing it again only binds the name.
+    fn analyze_import(&mut self, keyword: Token, name: Token) -> anyhow::Result<SemanticResult> {
+        if !self.frames.is_empty() {
+            return Err(anyhow::anyhow!("Modules can only be imported at the top level (line {}, column {})", keyword.line, keyword.column));
+        }
+
+        let loader = self.loader.clone()
+            .ok_or_else(|| anyhow::anyhow!("Modules can't be imported here (line {}, column {})", keyword.line, keyword.column))?;
+        let path = loader.resolve(&name.value, self.importer())
+            .map_err(|error| anyhow::anyhow!("{} (line {}, column {})", error, name.line, name.column))?;
+        if let Some(cycle) = self.import_cycle(&path, &name) {
+            return Err(cycle.into());
+        }
+
+        let (table_id, statements) = match self.modules.get(&path) {
+            Some(table_id) => (*table_id, vec![]),
+            // A cycle is about every module in it, so it's reported as it is
+            None => self.analyze_module(&name, &path, loader.as_ref())
+                .map_err(|error| match error.is::<CircularImport>() {
+                    true => error,
+                    false => ModuleError { path: path.clone(), error }.into()
+                })?
+        };
+
+        let scope = self.current_scope()?;
+        match scope.lookup(name.value.clone()).map(|symbol| &symbol.variant) {
+            Some(SymbolVariant::Namespace(namespace)) if namespace.table_id == table_id => {},
+            Some(_) => return Err(anyhow::anyhow!("{} already exists (line {}, column {})", name.value, name.line, name.column)),
+            None => {
+                let symbol = Symbol::new(name.value.clone(), SymbolVariant::Namespace(NamespaceSymbol { table_id }));
+                self.xref.define(symbol.symbol_id, &name);
+                self.current_scope_mut()?.insert(symbol);
+            }
+        }
+
+        Ok(SemanticResult {
+            node: Box::new(SemanticAst::Module(table_id, Arc::new(statements))),
+            type_id: None
+        })
+    }
+
+    // A module's top level is a scope of its own under the global one, so it doesn't see the
+    // code importing it
+    fn analyze_module(&mut self, name: &Token, path: &Path, loader: &dyn ModuleLoader) -> anyhow::Result<(TableId, Vec<SemanticAst>)> {
+        let source = loader.read(path)?;
+        let tokens = Lexer::new(source).tokenize()?;
+        let nodes = Parser::new(tokens).statement_list()?;
+
+        let table_id = self.create_scope(&name.value, self.global_scope_id);
+        self.scope_mut(table_id)?.module = true;
+
+        self.importing.push((path.to_path_buf(), name.clone()));
+        let statements = self.in_scope(table_id, |analyzer| nodes.into_iter()
+            .map(|node| analyzer.analyze(node).map(|result| *result.node))
+            .collect::<anyhow::Result<Vec<_>>>());
+        self.importing.pop();
+
+        let statements = statements?;
+        self.modules.insert(path.to_path_buf(), table_id);
+
+        Ok((table_id, statements))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeInfo {
+    pub type_name: String,
+    // Where the symbol under the cursor was declared. Literals, operators and natives have none.
+    pub declaration: Option<Span>,
+}
+
+// A method's symbol, name, parameters, parameter types, return type and body, before analyzing the body
+type MethodDeclaration = (SymbolId, Token, Vec<(Token, Token)>, Vec<SymbolId>, Option<SymbolId>, Ast);
+
+// User types
+impl SemanticAnalyzer {
+    fn analyze_type_declaration(&mut self, name: Token, contracts: Vec<Token>, fields: Vec<(Token, Token)>, methods: Vec<Node>) -> anyhow::Result<SemanticResult> {
+        if self.symbol_from_node(&Ast::Variable(name.clone()))?.is_some() {
+            return Err(anyhow::anyhow!("Type called {} already exists.", name.value));
+        }
+
+        let contract_ids = contracts.iter()
+            .map(|contract| self.contract_id(contract))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let table = SymbolTable::new(format!("type {}", name.value));
+        let table_id = table.table_id;
+        self.scopes.insert(table_id, Arc::new(table));
+
+        // Declared before its fields and methods, so they can use it
+        let symbol = Symbol::new(name.value.clone(), SymbolVariant::Record(RecordSymbol {
+            fields: vec![],
+            table_id,
+            contracts: contract_ids
+        }));
+        let type_id = symbol.symbol_id;
+        self.current_scope_mut()?.insert(symbol);
+        self.xref.define(type_id, &name);
+
+        match self.type_body(&name, type_id, fields, methods) {
+            Ok(methods) => Ok(SemanticResult {
+                node: Box::new(SemanticAst::TypeDeclaration(methods)),
+                type_id: None
+            }),
+            Err(e) => {
+                // Don't leave a type behind that's only half there
+                self.current_scope_mut()?.symbols.shift_remove(&type_id);
+                self.scopes.shift_remove(&table_id);
+                Err(e)
+            }
+        }
+    }
+
+    fn type_body(&mut self, name: &Token, type_id: SymbolId, fields: Vec<(Token, Token)>, methods: Vec<Node>) -> anyhow::Result<Vec<SemanticAst>> {
+        let mut field_types: Vec<(String, SymbolId)> = Vec::new();
+        for (field, type_name) in fields {
+            if field_types.iter().any(|(other, _)| *other == field.value) {
+                return Err(anyhow::anyhow!("Field {} is declared more than once in {}", field.value, name.value));
+            }
+
+            let field_type = self.signature_type_id(&type_name.value)
+                .map_err(|error| match error.downcast::<suggest::NotFound>() {
+                    Ok(not_found) => not_found.at(&type_name).into(),
+                    Err(error) => error
+                })?;
+            field_types.push((field.value, field_type));
+        }
+
+        let Some(SymbolVariant::Record(mut record)) = self.type_variant(type_id) else {
+            return Err(anyhow::anyhow!("{} should be a type", name.value));
+        };
+        record.fields = field_types;
+        let table_id = record.table_id;
+        let contract_ids = record.contracts.clone();
+        self.current_scope_mut()?.symbols.get_mut(&type_id)
+            .ok_or(anyhow::anyhow!("{} should be declared", name.value))?
+            .variant = SymbolVariant::Record(record);
+
+        let declared = self.declare_methods(name, table_id, methods)?;
+
+        for contract_id in contract_ids {
+            self.check_conformance(name, type_id, contract_id)?;
+        }
+
+        self.analyze_methods(name, declared)
+    }
+
+    // `extend Point { ... }` adds methods to a type declared before
+    fn analyze_extension(&mut self, name: Token, methods: Vec<Node>) -> anyhow::Result<SemanticResult> {
+        let symbol = self.symbol_from_node(&Ast::Variable(name.clone()))?
+            .ok_or_else(|| suggest::with_suggestions(
+                format!("Type {} not found", name.value),
+                &name.value,
+                self.visible_names(|variant| matches!(variant, SymbolVariant::Record(_)))
+            ).at(&name))?;
+
+        let SymbolVariant::Record(ref record) = symbol.variant else {
+            return Err(anyhow::anyhow!(
+                "Only types declared with `type` can be extended, and {} isn't one (line {}, column {})",
+                name.value, name.line, name.column
+            ));
+        };
+        let table_id = record.table_id;
+        self.xref.refer(&name, symbol.symbol_id);
+
+        let existing: Vec<_> = self.scope(table_id)?.symbols.keys().copied().collect();
+        let result = self.declare_methods(&name, table_id, methods)
+            .and_then(|declared| self.analyze_methods(&name, declared));
+
+        match result {
+            Ok(methods) => Ok(SemanticResult {
+                node: Box::new(SemanticAst::TypeDeclaration(methods)),
+                type_id: None
+            }),
+            Err(e) => {
+                // None of the extension's methods stay if one of them fails
+                self.scope_mut(table_id)?.symbols.retain(|id, _| existing.contains(id));
+                Err(e)
+            }
+        }
+    }
+
+    // Every method is declared before analyzing any of them, so they can call each other
+    fn declare_methods(&mut self, name: &Token, table_id: TableId, methods: Vec<Node>) -> anyhow::Result<Vec<MethodDeclaration>> {
+        let mut declared = Vec::new();
+        for method in methods {
+            let Ast::FunctionDeclaration(method_name, parameters, return_type, body) = *method else {
+                return Err(anyhow::anyhow!("Types can only have fields and methods"));
+            };
+
+            if self.scope(table_id)?.lookup(method_name.value.clone()).is_some() {
+                return Err(anyhow::anyhow!("{} already has a method called {}", name.value, method_name.value));
+            }
+
+            let (parameter_types, return_id) = self.function_signature(&parameters, &return_type)?;
+            if method_name.value == TO_TEXT && (parameter_types.len() != 1 || return_id != Some(TEXT_TYPE.symbol_id)) {
+                return Err(anyhow::anyhow!(
+                    "{}.{} should only take self and return a string, since it's how {} values are shown (line {}, column {})",
+                    name.value, TO_TEXT, name.value, method_name.line, method_name.column
+                ));
+            }
+            let method_type_id = self.function_type_id(return_id, parameter_types.clone())?;
+
+            let symbol = Symbol::new(method_name.value.clone(), SymbolVariant::Function(FunctionSymbol { type_id: method_type_id }));
+            self.xref.define(symbol.symbol_id, &method_name);
+            declared.push((symbol.symbol_id, method_name, parameters, parameter_types, return_id, *body));
+            self.scope_mut(table_id)?.insert(symbol);
+        }
+
+        Ok(declared)
+    }
+
+    // Bodies see what's around the type, not its methods. Those are called on `self`.
+    fn analyze_methods(&mut self, name: &Token, declared: Vec<MethodDeclaration>) -> anyhow::Result<Vec<SemanticAst>> {
+        let mut nodes = Vec::new();
+        for (symbol_id, method_name, parameters, parameter_types, return_id, body) in declared {
+            let full_name = format!("{}.{}", name.value, method_name.value);
+            let definition = self.analyze_function_body(full_name, parameters, parameter_types, return_id, body)?;
+
+            nodes.push(SemanticAst::FunctionDeclaration(symbol_id, Arc::new(definition)));
+        }
+
+        Ok(nodes)
+    }
+
+    fn analyze_contract(&mut self, name: Token, methods: Vec<(Token, Signature)>) -> anyhow::Result<SemanticResult> {
+        if self.symbol_from_node(&Ast::Variable(name.clone()))?.is_some() {
+            return Err(anyhow::anyhow!("Contract called {} already exists.", name.value));
+        }
+
+        // Declared first, since the methods take it as `self`
+        let symbol = Symbol::new(name.value.clone(), SymbolVariant::Contract(ContractSymbol { methods: vec![] }));
+        let contract_id = symbol.symbol_id;
+        self.current_scope_mut()?.insert(symbol);
+        self.xref.define(contract_id, &name);
+
+        let mut method_types: Vec<(String, SymbolId)> = Vec::new();
+        for (method, (parameters, return_type)) in methods {
+            let signature = match self.function_signature(&parameters, &return_type) {
+                Ok(signature) if !method_types.iter().any(|(other, _)| *other == method.value) => Ok(signature),
+                Ok(_) => Err(anyhow::anyhow!("{} already has a method called {}", name.value, method.value)),
+                Err(e) => Err(e)
+            };
+
+            let method_type_id = signature.and_then(|(parameter_types, return_id)| self.function_type_id(return_id, parameter_types));
+            match method_type_id {
+                Ok(method_type_id) => method_types.push((method.value, method_type_id)),
+                Err(e) => {
+                    self.current_scope_mut()?.symbols.shift_remove(&contract_id);
+                    return Err(e);
+                }
+            }
+        }
+
+        self.current_scope_mut()?.symbols.get_mut(&contract_id)
+            .ok_or(anyhow::anyhow!("{} should be declared", name.value))?
+            .variant = SymbolVariant::Contract(ContractSymbol { methods: method_types });
+
+        Ok(SemanticResult {
+            node: Box::new(SemanticAst::TypeDeclaration(vec![])),
+            type_id: None
+        })
+    }
+
+    fn contract_id(&self, token: &Token) -> anyhow::Result<SymbolId> {
+        let symbol = self.symbol_at_path(&token.value, Some(token))?
+            .ok_or_else(|| suggest::with_suggestions(
+                format!("Contract {} not found", token.value),
+                &token.value,
+                self.visible_names(|variant| matches!(variant, SymbolVariant::Contract(_)))
+            ).at(token))?;
+
+        match symbol.variant {
+            SymbolVariant::Contract(_) => Ok(symbol.symbol_id),
+            _ => Err(anyhow::anyhow!("{} is not a contract (line {}, column {})", token.value, token.line, token.column))
+        }
+    }
+
+    // Every method of the contract has to be there, taking and returning the same types after `self`
+    fn check_conformance(&self, name: &Token, type_id: SymbolId, contract_id: SymbolId) -> anyhow::Result<()> {
+        let Some(SymbolVariant::Contract(contract)) = self.type_variant(contract_id) else {
+            return Err(anyhow::anyhow!("Semantic analysis error. Contracts should be declared"));
+        };
+        let contract_name = self.name_of_type(contract_id)?.unwrap_or("<unknown>".to_string());
+
+        for (method, expected_id) in &contract.methods {
+            let Some(got_id) = self.method_of(type_id, method) else {
+                return Err(anyhow::anyhow!(
+                    "{} says it follows {}, but has no method called {} (line {}, column {})",
+                    name.value, contract_name, method, name.line, name.column
+                ));
+            };
+
+            let got_type_id = self.scopes.values()
+                .find_map(|scope| scope.lookup_id(got_id))
+                .ok_or(anyhow::anyhow!("Method {} not found", method))?
+                .value_type_id()?;
+
+            let matches = match (self.type_variant(got_type_id), self.type_variant(*expected_id)) {
+                (Some(SymbolVariant::FunctionType(got)), Some(SymbolVariant::FunctionType(expected))) => {
+                    got.return_id == expected.return_id && got.argument_ids.get(1..) == expected.argument_ids.get(1..)
+                },
+                _ => false
+            };
+
+            if !matches {
+                return Err(anyhow::anyhow!(
+                    "{}.{} has type {} but {} needs {} (line {}, column {})",
+                    name.value,
+                    method,
+                    self.name_of_type(got_type_id)?.unwrap_or("<unknown>".to_string()),
+                    contract_name,
+                    self.name_of_type(*expected_id)?.unwrap_or("<unknown>".to_string()),
+                    name.line,
+                    name.column
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Symbols whose declarations were marked with `kind`, in the order they were declared.
+    pub fn annotated(&self, kind: annotations::Kind) -> impl Iterator<Item = &Symbol> {
+        self.scopes.values()
+            .flat_map(|scope| scope.symbols())
+            .filter(move |symbol| symbol.annotation(kind).is_some())
+    }
+
+    /// The symbol of the method called `name` on values of a type declared in odo, if it has one.
+    pub fn method_of(&self, type_id: SymbolId, name: &str) -> Option<SymbolId> {
+        let Some(SymbolVariant::Record(record)) = self.type_variant(type_id) else {
+            return None;
+        };
+
+        self.scopes.get(&record.table_id)?.lookup(name.to_string()).map(|symbol| symbol.symbol_id)
+    }
+
+    // The function type of a method, for values of a type declared in odo or of a contract
+    fn method_type(&self, type_id: SymbolId, name: &str) -> anyhow::Result<Option<SymbolId>> {
+        match self.type_variant(type_id) {
+            Some(SymbolVariant::Contract(contract)) => Ok(contract.methods.iter()
+                .find(|(method, _)| method == name)
+                .map(|(_, type_id)| *type_id)),
+            Some(SymbolVariant::Record(_)) => match self.method_of(type_id, name) {
+                Some(method_id) => {
+                    let symbol = self.scopes.values()
+                        .find_map(|scope| scope.lookup_id(method_id))
+                        .ok_or(anyhow::anyhow!("Method {} not found", name))?;
+
+                    Ok(Some(symbol.value_type_id()?))
+                },
+                None => Ok(None)
+            },
+            _ => Ok(None)
+        }
+    }
+
+    /// Whether values of `type_id` can go where `contract_id` is expected, because the type says it follows it.
+    pub fn conforms(&self, type_id: SymbolId, contract_id: SymbolId) -> bool {
+        matches!(self.type_variant(type_id), Some(SymbolVariant::Record(record)) if record.contracts.contains(&contract_id))
+    }
+
+    // The type a node names, when it's called to make a value of it
+    fn record_from_node(&self, node: &Ast) -> Option<SymbolId> {
+        let symbol = self.symbol_at_path(&path_of(node)?, None).ok()??;
+
+        matches!(symbol.variant, SymbolVariant::Record(_)).then_some(symbol.symbol_id)
+    }
+
+    // Whether a node is a value, instead of the path to a namespace (`a` or `a.b`). Names that
+    // don't exist count as namespaces, for the errors to say so.
+    fn is_value(&self, node: &Ast) -> bool {
+        let symbol = match node {
+            Ast::Variable(_) => self.symbol_from_node(node).ok().flatten(),
+            Ast::MemberAccess(target, _) if self.is_value(target) => return true,
+            Ast::MemberAccess(target, member) => self.namespace_from_node(target).ok()
+                .and_then(|namespace_id| self.scope(namespace_id).ok()?.lookup(member.value.clone())),
+            _ => return true
+        };
+
+        symbol.is_some_and(|symbol| symbol.value_type_id().is_ok())
+    }
+
+    // `Point(1, 2)` or `geometry.Point(1, 2)`, with a value for each field in order
+    fn analyze_construction(&mut self, callee: Ast, args: Vec<Node>) -> anyhow::Result<SemanticResult> {
+        let (Some(path), Ast::Variable(name) | Ast::MemberAccess(_, name)) = (path_of(&callee), &callee) else {
+            return Err(anyhow::anyhow!("Expected the name of a type"));
+        };
+        let symbol = self.symbol_at_path(&path, Some(name))?
+            .filter(|symbol| matches!(symbol.variant, SymbolVariant::Record(_)))
+            .ok_or(anyhow::anyhow!("{} is not a type", path))?;
+        let (type_id, type_name, deprecation) = (symbol.symbol_id, symbol.name.clone(), symbol.deprecation());
+        self.xref.refer(name, type_id);
+        self.warn_deprecated(deprecation, name)?;
+
+        let Some(SymbolVariant::Record(record)) = self.type_variant(type_id) else {
+            return Err(anyhow::anyhow!("{} is not a type", path));
+        };
+
+        if args.len() != record.fields.len() {
+            return Err(anyhow::anyhow!(
+                "{} has {} fields, but got {} values (line {}, column {})",
+                path, record.fields.len(), args.len(), name.line, name.column
+            ));
+        }
+
+        let mut values = Vec::new();
+        for (arg, (field, field_type)) in args.into_iter().zip(&record.fields) {
+            if matches!(*arg, Ast::Spread(_)) {
+                return Err(anyhow::anyhow!("Spreading (...) is only allowed in function calls and list literals"));
+            }
+
+            let at = lints::first_token(&arg).cloned();
+            let value = self.analyze_node(arg)?;
+            values.push((field.clone(), self.expect_type(value, *field_type, at)?));
+        }
+
+        Ok(SemanticResult {
+            node: Box::new(SemanticAst::Construct(type_id, type_name, values)),
+            type_id: Some(type_id)
+        })
+    }
+
+    // `value.method(...)`. Fields holding functions are called like any other function.
+    fn analyze_method_call(&mut self, target: Node, method: Token, args: Vec<Node>) -> anyhow::Result<SemanticResult> {
+        let receiver = self.analyze_node(target)?;
+        let receiver_type = receiver.type_id
+            .ok_or(anyhow::anyhow!("Methods must be called on a value"))?;
+
+        if let Some(method_id) = self.method_of(receiver_type, &method.value) {
+            self.xref.refer(&method, method_id);
+        }
+        let method_type_id = self.method_type(receiver_type, &method.value)?;
+
+        let Some(method_type_id) = method_type_id else {
+            let (index, type_id) = self.field(Some(receiver_type), &method)?;
+            let callee = SemanticResult { node: Box::new(SemanticAst::Field(receiver.node, index)), type_id: Some(type_id) };
+
+            return self.analyze_call(callee, args);
+        };
+
+        let Some(SymbolVariant::FunctionType(method_type)) = self.type_variant(method_type_id) else {
+            return Err(anyhow::anyhow!("Semantic analysis error. Methods should be functions"));
+        };
+
+        // `self` is the receiver
+        let parameter_types = &method_type.argument_ids[1..];
+        if args.len() != parameter_types.len() {
+            return Err(anyhow::anyhow!("Incorrect number of arguments"));
+        }
+
+        let mut arg_nodes = Vec::new();
+        for (arg, parameter_type) in args.into_iter().zip(parameter_types) {
+            if matches!(*arg, Ast::Spread(_)) {
+                return Err(anyhow::anyhow!("Methods can't be called with spread arguments"));
+            }
+
+            let at = lints::first_token(&arg).cloned();
+            let value = self.analyze_node(arg)?;
+            if value.type_id.is_none() {
+                return Err(anyhow::anyhow!("Function argument must be a valid expression (Must return value)"));
+            }
+
+            arg_nodes.push(self.expect_type(value, *parameter_type, at)?);
+        }
+
+        Ok(SemanticResult {
+            node: Box::new(SemanticAst::MethodCall(receiver.node, method.value, arg_nodes, CallSite::default())),
+            type_id: method_type.return_id
+        })
+    }
+
+    // The index and type of a field of a type declared in odo
+    fn field(&self, type_id: Option<SymbolId>, field: &Token) -> anyhow::Result<(usize, SymbolId)> {
+        let type_id = type_id.ok_or(anyhow::anyhow!("Fields must be read from a value"))?;
+        let type_name = self.name_of_type(type_id)?.unwrap_or("<unknown>".to_string());
+
+        let Some(SymbolVariant::Record(record)) = self.type_variant(type_id) else {
+            return Err(anyhow::anyhow!(
+                "Values of type {} have no fields or methods (line {}, column {})",
+                type_name, field.line, field.column
+            ));
+        };
+
+        record.fields.iter()
+            .position(|(name, _)| *name == field.value)
+            .map(|index| (index, record.fields[index].1))
+            .ok_or_else(|| {
+                let methods = self.scopes.get(&record.table_id).into_iter().flat_map(|table| table.symbols().map(|symbol| symbol.name()));
+                let names = record.fields.iter().map(|(name, _)| name.as_str()).chain(methods);
+
+                suggest::with_suggestions(format!("{} has no field or method called {}", type_name, field.value), &field.value, names)
+                    .at(field)
+                    .into()
+            })
+    }
+}
+
+// Loops
+impl SemanticAnalyzer {
+    fn analyze_for(&mut self, keyword: Token, name: Token, iterable: Node, body: Node) -> anyhow::Result<SemanticResult> {
+        let iterable = self.analyze_node(iterable)?;
+        let iterable_type = iterable.type_id
+            .ok_or(anyhow::anyhow!("for needs a value to go through (line {}, column {})", keyword.line, keyword.column))?;
+        let item_type = self.item_type(iterable_type, &keyword)?;
+
+        // What's left to go through is bound like a variable
+        let state = Uuid::new_v4();
+        self.lay_out(state);
+
+        let scope_id = self.create_scope("for loop", self.current_scope_id);
+        let (variable, body) = self.in_scope(scope_id, |analyzer| -> anyhow::Result<_> {
+            let variable = analyzer.declare_variable(&name, item_type)?;
+            Ok((variable, analyzer.analyze_node(body)?))
+        })?;
+
+        Ok(SemanticResult {
+            node: Box::new(SemanticAst::For(variable, state, iterable.node, body.node, scope_id)),
+            type_id: None
+        })
+    }
+
+    // What a for loop gets out of a value of the type. Lists and seqs give their items. Types declared in odo,
+    // and contracts, are iterators when they have these methods:
+    //     done(self) -> truth, whether there's nothing left
+    //     current(self) -> T, the item the loop gets
+    //     next(self) -> Self, the iterator for the rest
+    fn item_type(&self, type_id: SymbolId, at: &Token) -> anyhow::Result<SymbolId> {
+        if [LIST_TYPE.symbol_id, SEQ_TYPE.symbol_id].contains(&self.base_type(type_id)) || type_id == ANY_TYPE.symbol_id {
+            return Ok(self.list_item_type(type_id));
+        }
+
+        let type_name = self.name_of_type(type_id)?.unwrap_or("<unknown>".to_string());
+        if !matches!(self.type_variant(type_id), Some(SymbolVariant::Record(_) | SymbolVariant::Contract(_))) {
+            return Err(anyhow::anyhow!(
+                "for can't go through a value of type {}. It takes lists, seqs, and types with done, current and next methods (line {}, column {})",
+                type_name, at.line, at.column
+            ));
+        }
+
+        let mut returns = Vec::new();
+        for method in ["done", "current", "next"] {
+            let method_type = self.method_type(type_id, method)?.and_then(|id| match self.type_variant(id) {
+                Some(SymbolVariant::FunctionType(function)) if function.argument_ids.len() == 1 => Some(function.return_id),
+                _ => None
+            });
+
+            let Some(return_id) = method_type else {
+                return Err(anyhow::anyhow!(
+                    "for can't go through {}: it needs a method {}(self) to be an iterator (line {}, column {})",
+                    type_name, method, at.line, at.column
+                ));
+            };
+
+            returns.push((method, return_id));
+        }
+
+        for (method, return_id) in &returns {
+            let expected = match *method {
+                "done" => Some(TRUTH_TYPE.symbol_id),
+                "next" => Some(type_id),
+                _ => continue
+            };
+
+            if *return_id != expected {
+                let expected_name = self.name_of_type(expected.unwrap_or(type_id))?.unwrap_or("<unknown>".to_string());
+                return Err(anyhow::anyhow!(
+                    "{}.{} should return {} for {} to be an iterator (line {}, column {})",
+                    type_name, method, expected_name, type_name, at.line, at.column
+                ));
+            }
+        }
+
+        returns[1].1.ok_or(anyhow::anyhow!(
+            "{}.current should return the item for {} to be an iterator (line {}, column {})",
+            type_name, type_name, at.line, at.column
+        ))
+    }
+}
+
+// Patterns
+impl SemanticAnalyzer {
+    fn analyze_match(&mut self, keyword: Token, value: Node, arms: Vec<(Pattern, Option<Node>, Node)>) -> anyhow::Result<SemanticResult> {
+        let value = self.analyze_node(value)?;
+        let value_type = value.type_id
+            .ok_or(anyhow::anyhow!("match needs a value (line {}, column {})", keyword.line, keyword.column))?;
+
+        let mut semantic_arms = Vec::new();
+        let mut arm_types = Vec::new();
+        let mut matched_everything = false;
+        for (pattern, guard, body) in arms {
+            if matched_everything {
+                let message = "This arm can never run: an arm before it matches everything".to_string();
+                self.warn(Lint::DeadCode, message, pattern.token())?;
+            }
+
+            // Each arm has a scope for the names its pattern binds
+            let scope_id = self.create_scope("match arm", self.current_scope_id);
+            let (pattern, guard, body) = self.in_scope(scope_id, |analyzer| -> anyhow::Result<_> {
+                let pattern = analyzer.analyze_pattern(pattern, value_type)?;
+                let guard = match guard {
+                    Some(guard) => Some(analyzer.analyze_guard(guard)?),
+                    None => None
+                };
+
+                Ok((pattern, guard, analyzer.analyze_node(body)?))
+            })?;
+
+            // An arm with a guard can always let the value through to the next one
+            matched_everything |= guard.is_none() && self.covers(&pattern, value_type);
+            arm_types.push(body.type_id);
+            semantic_arms.push(MatchArm { pattern, guard, body: *body.node, scope_id });
+        }
+
+        let unguarded = semantic_arms.iter().filter(|arm| arm.guard.is_none()).map(|arm| &arm.pattern);
+        if !self.exhaustive(unguarded, value_type) {
+            let type_name = self.name_of_type(value_type)?.unwrap_or("<unknown>".to_string());
+            return Err(anyhow::anyhow!(
+                "match doesn't cover every {}. Add an arm with `_` for the rest (line {}, column {})",
+                type_name, keyword.line, keyword.column
+            ));
+        }
+
+        // When every arm is an expression of the same type, the match has its value
+        let type_id = match arm_types.first() {
+            Some(Some(first)) if arm_types.iter().all(|type_id| *type_id == Some(*first)) => Some(*first),
+            _ => None
+        };
+
+        Ok(SemanticResult {
+            node: Box::new(SemanticAst::Match(value.node, semantic_arms)),
+            type_id
+        })
+    }
+
+    // `var [first, ...rest] = xs`
+    fn analyze_pattern_declaration(&mut self, pattern: Pattern, value: Node) -> anyhow::Result<SemanticResult> {
+        let token = pattern.token().clone();
+        let value = self.analyze_node(value)?;
+        let type_id = value.type_id
+            .ok_or(anyhow::anyhow!("Variable initialization must be a valid expression (Must return value)"))?;
+
+        // Declared after the value is analyzed, like any other variable. None of them stay if the pattern is wrong.
+        let existing: Vec<_> = self.scope(self.current_scope_id)?.symbols.keys().copied().collect();
+        let pattern = match self.analyze_pattern(pattern, type_id) {
+            Ok(pattern) if !self.can_fail(&pattern, type_id) => Ok(pattern),
+            Ok(_) => {
+                let type_name = self.name_of_type(type_id)?.unwrap_or("<unknown>".to_string());
+                Err(anyhow::anyhow!(
+                    "The pattern doesn't match every {}, so it can only be used in a match (line {}, column {})",
+                    type_name, token.line, token.column
+                ))
+            },
+            Err(e) => Err(e)
+        };
+        if pattern.is_err() {
+            self.scope_mut(self.current_scope_id)?.symbols.retain(|id, _| existing.contains(id));
+        }
+
+        Ok(SemanticResult {
+            node: Box::new(SemanticAst::PatternDeclaration(pattern?, value.node, token)),
+            type_id: None
+        })
+    }
+
+    // `Point { x, y } if x > y: ...`
+    fn analyze_guard(&mut self, guard: Node) -> anyhow::Result<SemanticAst> {
+        let at = lints::first_token(&guard).cloned();
+        let guard = self.analyze_node(guard)?;
+        let guard_type = guard.type_id
+            .ok_or(anyhow::anyhow!("The guard of a match arm must be a valid expression (Must return value)"))?;
+
+        match guard_type {
+            id if id == TRUTH_TYPE.symbol_id => Ok(*guard.node),
+            id if id == ANY_TYPE.symbol_id => Ok(SemanticAst::Checked(guard.node, TRUTH_TYPE.symbol_id, at)),
+            id => {
+                let type_name = self.name_of_type(id)?.unwrap_or("<unknown>".to_string());
+                let position = at.map(|token| format!(" (line {}, column {})", token.line, token.column)).unwrap_or_default();
+                Err(anyhow::anyhow!("The guard of a match arm must be a truth, not {}{}", type_name, position))
+            }
+        }
+    }
+
+    // Declares the names a pattern binds in the current scope. `type_id` is the type of the values it's matched against.
+    fn analyze_pattern(&mut self, pattern: Pattern, type_id: SymbolId) -> anyhow::Result<SemanticPattern> {
+        let type_name = self.name_of_type(type_id)?.unwrap_or("<unknown>".to_string());
+        let never_matches = |what: &str, token: &Token| anyhow::anyhow!(
+            "A pattern of type {} can never match a value of type {} (line {}, column {})",
+            what, type_name, token.line, token.column
+        );
+
+        match pattern {
+            Pattern::Wildcard(_) => Ok(SemanticPattern::Wildcard),
+            Pattern::Binding(token) => Ok(SemanticPattern::Binding(self.declare_variable(&token, type_id)?)),
+            Pattern::Literal(node) => {
+                let token = lints::first_token(&node).cloned().expect("Literals are made of tokens");
+                let literal = self.analyze_node(node)?;
+                let literal_type = literal.type_id.ok_or(anyhow::anyhow!("Literals always have a type"))?;
+
+                let numeric = |id: SymbolId| id == INT_TYPE.symbol_id || id == DEC_TYPE.symbol_id;
+                let fits = type_id == ANY_TYPE.symbol_id || self.compatible(literal_type, type_id) || (numeric(literal_type) && numeric(type_id));
+                if !fits {
+                    let literal_name = self.name_of_type(literal_type)?.unwrap_or("<unknown>".to_string());
+                    return Err(never_matches(&literal_name, &token));
+                }
+
+                Ok(SemanticPattern::Literal(literal.node))
+            },
+            Pattern::Record(name, fields) => {
+                let record_id = self.record_from_node(&Ast::Variable(name.clone()))
+                    .ok_or_else(|| anyhow::anyhow!("{} is not a type with fields (line {}, column {})", name.value, name.line, name.column))?;
+                self.xref.refer(&name, record_id);
+
+                if record_id != type_id && type_id != ANY_TYPE.symbol_id && !self.conforms(record_id, type_id) {
+                    return Err(never_matches(&name.value, &name));
+                }
+
+                let Some(SymbolVariant::Record(record)) = self.type_variant(record_id) else {
+                    return Err(anyhow::anyhow!("{} is not a type with fields", name.value));
+                };
+
+                let mut patterns = Vec::new();
+                for (field, pattern) in fields {
+                    let index = record.fields.iter().position(|(other, _)| *other == field.value)
+                        .ok_or_else(|| suggest::with_suggestions(
+                            format!("{} has no field called {}", name.value, field.value),
+                            &field.value,
+                            record.fields.iter().map(|(other, _)| other.as_str())
+                        ).at(&field))?;
+
+                    patterns.push((index, self.analyze_pattern(pattern, record.fields[index].1)?));
+                }
+
+                Ok(SemanticPattern::Record(record_id, patterns))
+            },
+            Pattern::List(bracket, items, rest) => {
+                // What the items are depends on what the list says it holds
+                let item_type = match self.type_variant(type_id) {
+                    Some(SymbolVariant::Parametric(parametric)) if parametric.base_id == LIST_TYPE.symbol_id => parametric.argument_id,
+                    _ if type_id == LIST_TYPE.symbol_id || type_id == ANY_TYPE.symbol_id => ANY_TYPE.symbol_id,
+                    _ => return Err(never_matches("list", &bracket))
+                };
+
+                let items = items.into_iter()
+                    .map(|item| self.analyze_pattern(item, item_type))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+
+                let rest_type = if type_id == ANY_TYPE.symbol_id { LIST_TYPE.symbol_id } else { type_id };
+                let rest = match rest {
+                    Some(rest) => Some(Box::new(self.analyze_pattern(*rest, rest_type)?)),
+                    None => None
+                };
+
+                Ok(SemanticPattern::List(items, rest))
+            }
+        }
+    }
+
+    // Whether a pattern matches every value of a type
+    fn covers(&self, pattern: &SemanticPattern, type_id: SymbolId) -> bool {
+        match pattern {
+            SemanticPattern::Wildcard | SemanticPattern::Binding(_) => true,
+            SemanticPattern::Literal(_) => false,
+            SemanticPattern::Record(record_id, fields) => *record_id == type_id && fields.iter().all(|(index, pattern)| {
+                self.field_type(*record_id, *index).is_some_and(|field_type| self.covers(pattern, field_type))
+            }),
+            SemanticPattern::List(items, rest) => items.is_empty() && rest.is_some() && type_id != ANY_TYPE.symbol_id
+        }
+    }
+
+    // Whether a pattern can fail for reasons the analyzer could have seen. Lists that are too short
+    // or too long, and values typed any, are checked when running.
+    fn can_fail(&self, pattern: &SemanticPattern, type_id: SymbolId) -> bool {
+        match pattern {
+            SemanticPattern::List(items, _) => {
+                let item_type = self.list_item_type(type_id);
+                items.iter().any(|item| self.can_fail(item, item_type))
+            },
+            SemanticPattern::Record(record_id, fields) if *record_id == type_id || type_id == ANY_TYPE.symbol_id => fields.iter().any(|(index, pattern)| {
+                self.field_type(*record_id, *index).is_none_or(|field_type| self.can_fail(pattern, field_type))
+            }),
+            pattern => !self.covers(pattern, type_id)
+        }
+    }
+
+    // Whether some arm matches every value of the type, or all of them together do
+    fn exhaustive<'p>(&self, patterns: impl Iterator<Item = &'p SemanticPattern>, type_id: SymbolId) -> bool {
+        let patterns: Vec<_> = patterns.collect();
+        if patterns.iter().any(|pattern| self.covers(pattern, type_id)) {
+            return true;
+        }
+
+        if type_id == TRUTH_TYPE.symbol_id {
+            let literals: HashSet<_> = patterns.iter()
+                .filter_map(|pattern| match pattern {
+                    SemanticPattern::Literal(literal) => match literal.as_ref() {
+                        SemanticAst::Truth(token) => Some(token.value.as_str()),
+                        _ => None
+                    },
+                    _ => None
+                })
+                .collect();
+
+            return literals.contains("true") && literals.contains("false");
+        }
+
+        if self.base_type(type_id) != LIST_TYPE.symbol_id {
+            return false;
+        }
+
+        // Lists are covered when every length up to one that has a rest is
+        let item_type = self.list_item_type(type_id);
+        let mut lengths = HashSet::new();
+        let mut shortest_rest = None;
+        for pattern in patterns {
+            let SemanticPattern::List(items, rest) = pattern else { continue };
+            if !items.iter().all(|item| self.covers(item, item_type)) {
+                continue;
+            }
+
+            match rest {
+                Some(_) => shortest_rest = Some(shortest_rest.map_or(items.len(), |shortest: usize| shortest.min(items.len()))),
+                None => {
+                    lengths.insert(items.len());
+                }
+            }
+        }
+
+        shortest_rest.is_some_and(|shortest| (0..shortest).all(|length| lengths.contains(&length)))
+    }
+
+    fn field_type(&self, record_id: SymbolId, index: usize) -> Option<SymbolId> {
+        match self.type_variant(record_id) {
+            Some(SymbolVariant::Record(record)) => record.fields.get(index).map(|(_, type_id)| *type_id),
+            _ => None
+        }
+    }
+
+    // What a list or seq type says it holds. Plain ones hold any.
+    fn list_item_type(&self, type_id: SymbolId) -> SymbolId {
+        match self.type_variant(type_id) {
+            Some(SymbolVariant::Parametric(parametric)) => parametric.argument_id,
+            _ => ANY_TYPE.symbol_id
+        }
+    }
+}
+
+// Generics
+impl SemanticAnalyzer {
+    fn type_parameter_id(&mut self, name: &str) -> anyhow::Result<SymbolId> {
+        let table = self.scope_mut(self.type_parameters_id)?;
+        if let Some(symbol) = table.lookup(name.to_string()) {
+            return Ok(symbol.symbol_id);
+        }
+
+        let symbol = Symbol::stable(name.to_string(), SymbolVariant::TypeParameter, &format!("type parameter {}", name));
+        let id = symbol.symbol_id;
+        table.insert(symbol);
+
+        Ok(id)
+    }
+
+    // Like function types, `list<int>` is shared by everything that uses it. `list<any>` is just `list`.
+    pub(crate) fn parametric_type_id(&mut self, base_id: SymbolId, argument_id: SymbolId) -> anyhow::Result<SymbolId> {
+        let base_name = self.name_of_type(base_id)?.unwrap_or("<unknown>".to_string());
+        if base_id != LIST_TYPE.symbol_id && base_id != MAP_TYPE.symbol_id && base_id != SEQ_TYPE.symbol_id {
+            return Err(anyhow::anyhow!("Only list, map and seq can say what they hold, not {}", base_name));
+        }
+
+        if argument_id == ANY_TYPE.symbol_id {
+            return Ok(base_id);
+        }
+
+        let argument_name = self.name_of_type(argument_id)?.unwrap_or("<unknown>".to_string());
+        let name = format!("{}<{}>", base_name, argument_name);
+
+        if let Some(symbol) = self.global_scope()?.lookup(name.clone()) {
+            return Ok(symbol.symbol_id);
+        }
+
+        let key = format!("type {}", name);
+        let symbol = Symbol::stable(name, SymbolVariant::Parametric(ParametricTypeSymbol { base_id, argument_id }), &key);
+        let id = symbol.symbol_id;
+        self.global_scope_mut()?.insert(symbol);
+
+        Ok(id)
+    }
+
+    // Most types are in the global scope, or type parameters. Types declared in odo can be in any scope.
+    fn type_variant(&self, id: SymbolId) -> Option<SymbolVariant> {
+        [self.global_scope_id, self.type_parameters_id].iter()
+            .filter_map(|table_id| self.scopes.get(table_id)?.lookup_id(id))
+            .next()
+            .or_else(|| self.scopes.values().find_map(|scope| scope.lookup_id(id)))
+            .map(|symbol| symbol.variant.clone())
+    }
+
+    /// What values of a type look like when running: `list<int>` is a list like any other, and a
+    /// type parameter could be anything (None).
+    pub fn erased_type(&self, id: SymbolId) -> Option<SymbolId> {
+        match self.type_variant(id) {
+            Some(SymbolVariant::Parametric(parametric)) => Some(parametric.base_id),
+            Some(SymbolVariant::TypeParameter) => None,
+            _ => Some(id)
+        }
+    }
+
+    /// What a `list<...>`, `map<...>` or `seq<...>` type says it holds. None for other types.
+    pub fn held_type(&self, id: SymbolId) -> Option<SymbolId> {
+        match self.type_variant(id) {
+            Some(SymbolVariant::Parametric(parametric)) => Some(parametric.argument_id),
+            _ => None
+        }
+    }
+
+    // Whether values of a type can be map keys. Plain lists and `any` are checked when running.
+    fn hashable(&self, id: SymbolId) -> bool {
+        match self.type_variant(id) {
+            Some(SymbolVariant::Parametric(parametric)) => {
+                parametric.base_id == LIST_TYPE.symbol_id && self.hashable(parametric.argument_id)
+            },
+            _ => [INT_TYPE.symbol_id, TEXT_TYPE.symbol_id, TRUTH_TYPE.symbol_id, LIST_TYPE.symbol_id, ANY_TYPE.symbol_id].contains(&id)
+        }
+    }
+
+    // `list` for any `list<...>`
+    fn base_type(&self, id: SymbolId) -> SymbolId {
+        self.erased_type(id).unwrap_or(id)
+    }
+
+    // Whether a value of type `got` can go where `expected` is needed
+    fn compatible(&self, got: SymbolId, expected: SymbolId) -> bool {
+        if got == expected || expected == ANY_TYPE.symbol_id {
+            return true;
+        }
+
+        match (self.type_variant(got), self.type_variant(expected)) {
+            (Some(SymbolVariant::Parametric(got)), Some(SymbolVariant::Parametric(expected))) => {
+                got.base_id == expected.base_id && self.compatible(got.argument_id, expected.argument_id)
+            },
+            (Some(SymbolVariant::Parametric(got)), _) => got.base_id == expected,
+            (_, Some(SymbolVariant::Contract(_))) => self.conforms(got, expected),
+            _ => false
+        }
+    }
+
+    // Whether a value of type `got` could go where `expected` is needed, but only running can tell.
+    // That's values typed any, and plain lists and maps, since what they hold isn't known until then.
+    fn checked_when_running(&self, got: SymbolId, expected: SymbolId) -> bool {
+        if got == ANY_TYPE.symbol_id {
+            return true;
+        }
+
+        match (self.type_variant(got), self.type_variant(expected)) {
+            (Some(SymbolVariant::Parametric(got)), Some(SymbolVariant::Parametric(expected))) => {
+                got.base_id == expected.base_id &&
+                    (self.compatible(got.argument_id, expected.argument_id) || self.checked_when_running(got.argument_id, expected.argument_id))
+            },
+            (_, Some(SymbolVariant::Parametric(expected))) => got == expected.base_id,
+            _ => false
+        }
+    }
+
+    fn is_generic(&self, id: SymbolId) -> bool {
+        match self.type_variant(id) {
+            Some(SymbolVariant::TypeParameter) => true,
+            Some(SymbolVariant::Parametric(parametric)) => self.is_generic(parametric.argument_id),
+            Some(SymbolVariant::FunctionType(function)) => {
+                function.argument_ids.iter().chain(&function.return_id).any(|id| self.is_generic(*id))
+            },
+            _ => false
+        }
+    }
+
+    // Decides what the type parameters in `expected` stand for, so `got` fits it. False if it can't.
+    fn unify(&self, expected: SymbolId, got: SymbolId, bindings: &mut HashMap<SymbolId, SymbolId>) -> bool {
+        // Checked when running instead
+        if got == ANY_TYPE.symbol_id {
+            return true;
+        }
+
+        match (self.type_variant(expected), self.type_variant(got)) {
+            (Some(SymbolVariant::TypeParameter), _) => match bindings.get(&expected).copied() {
+                Some(bound) if self.compatible(got, bound) || self.checked_when_running(got, bound) => true,
+                // `list<int>` says more than the `list` it was bound to before
+                Some(bound) if self.compatible(bound, got) => {
+                    bindings.insert(expected, got);
+                    true
+                },
+                Some(_) => false,
+                None => {
+                    bindings.insert(expected, got);
+                    true
+                }
+            },
+            (Some(SymbolVariant::Parametric(expected)), Some(SymbolVariant::Parametric(got))) => {
+                expected.base_id == got.base_id && self.unify(expected.argument_id, got.argument_id, bindings)
+            },
+            (Some(SymbolVariant::Parametric(expected)), _) => got == expected.base_id,
+            (Some(SymbolVariant::FunctionType(expected)), Some(SymbolVariant::FunctionType(got))) => {
+                let returns = match (expected.return_id, got.return_id) {
+                    (Some(expected), Some(got)) => self.unify(expected, got, bindings),
+                    (expected, got) => expected == got
+                };
+
+                expected.argument_ids.len() == got.argument_ids.len() && returns &&
+                    expected.argument_ids.iter().zip(&got.argument_ids).all(|(expected, got)| self.unify(*expected, *got, bindings))
+            },
+            _ => self.compatible(got, expected)
+        }
+    }
+
+    // `expected` with its type parameters replaced by what they stand for. The ones nothing decided become any.
+    fn substitute(&mut self, id: SymbolId, bindings: &HashMap<SymbolId, SymbolId>) -> anyhow::Result<SymbolId> {
+        match self.type_variant(id) {
+            Some(SymbolVariant::TypeParameter) => Ok(bindings.get(&id).copied().unwrap_or(ANY_TYPE.symbol_id)),
+            Some(SymbolVariant::Parametric(parametric)) => {
+                let argument_id = self.substitute(parametric.argument_id, bindings)?;
+                self.parametric_type_id(parametric.base_id, argument_id)
+            },
+            Some(SymbolVariant::FunctionType(function)) => {
+                let argument_ids = function.argument_ids.iter()
+                    .map(|argument_id| self.substitute(*argument_id, bindings))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                let return_id = match function.return_id {
+                    Some(return_id) => Some(self.substitute(return_id, bindings)?),
+                    None => None
+                };
+
+                self.function_type_id(return_id, argument_ids)
+            },
+            _ => Ok(id)
+        }
+    }
+
+    // The parameter and return types of a generic function for one call, from the types of its arguments.
+    // Arguments whose position isn't known (None) don't decide anything.
+    fn instantiate(&mut self, function: &FunctionTypeSymbol, arguments: &[Option<SymbolId>]) -> anyhow::Result<(Vec<SymbolId>, Option<SymbolId>)> {
+        let mut bindings = HashMap::new();
+
+        for (expected, got) in function.argument_ids.iter().zip(arguments) {
+            let Some(got) = *got else { continue };
+
+            if !self.unify(*expected, got, &mut bindings) {
+                let expected = self.substitute(*expected, &bindings)?;
+
+                return Err(anyhow::anyhow!(
+                    "Type mismatch: Expected type {:?} but got type {:?}",
+                    self.name_of_type(expected)?.unwrap_or("<unknown>".to_string()),
+                    self.name_of_type(got)?.unwrap_or("<unknown>".to_string())
+                ));
+            }
+        }
+
+        let argument_ids = function.argument_ids.iter()
+            .map(|argument_id| self.substitute(*argument_id, &bindings))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let return_id = match function.return_id {
+            Some(return_id) => Some(self.substitute(return_id, &bindings)?),
+            None => None
+        };
+
+        Ok((argument_ids, return_id))
+    }
+}
+
+// Editor queries
+impl SemanticAnalyzer {
+    /// The type of the innermost expression at `span`, in the code analyzed so far.
+    pub fn type_at(&self, span: Span) -> Option<TypeInfo> {
+        let (type_id, declaration) = match self.xref.symbol_at(span) {
+            Some(symbol_id) => {
+                let symbol = self.scopes.values().find_map(|scope| scope.lookup_id(symbol_id))?;
+                (symbol.value_type_id().ok()?, self.xref.declaration_of(symbol_id))
+            },
+            None => (self.xref.type_at(span)?, None)
+        };
+
+        Some(TypeInfo {
+            type_name: self.name_of_type(type_id).ok()??,
+            declaration
+        })
+    }
+
+    /// Like `type_at`, for code that isn't part of the program, like a buffer in an editor.
+    /// It's analyzed in a scope that's thrown away afterwards, and statements that don't analyze are skipped.
+    pub fn type_at_in(&mut self, statements: Vec<Node>, span: Span) -> Option<TypeInfo> {
+        let previous_scope_id = self.current_scope_id;
+        let previous_xref = std::mem::take(&mut self.xref);
+        let previous_warnings = self.warnings.len();
+
+        let mut scope = SymbolTable::new("scratch".to_string());
+        let scope_id = scope.table_id;
+        scope.parent = Some(previous_scope_id);
+        self.scopes.insert(scope_id, Arc::new(scope));
+
+        for statement in statements {
+            let _ = self.in_scope(scope_id, |analyzer| analyzer.analyze_node(statement));
+        }
+
+        // Names declared before come from the program's own index
+        let info = self.type_at(span).map(|mut info| {
+            if info.declaration.is_none() {
+                info.declaration = self.xref.symbol_at(span).and_then(|id| previous_xref.declaration_of(id));
+            }
+            info
+        });
+
+        self.scopes.shift_remove(&scope_id);
+        self.current_scope_id = previous_scope_id;
+        self.xref = previous_xref;
+        self.warnings.truncate(previous_warnings);
+
+        info
+    }
+}
+
+// The token that stands for an expression when hovering over it
+fn expression_token(ast: &Ast) -> Option<&Token> {
+    match ast {
+        Ast::Number(token) | Ast::Truth(token) | Ast::Text(token) | Ast::Variable(token) => Some(token),
+        Ast::MemberAccess(_, member) => Some(member),
+        Ast::BinaryOperation(_, operator, _) | Ast::Negation(operator, _) => Some(operator),
+        _ => None
+    }
+}
+
+// The type of an operation where at least one side is typed any
+fn dynamic_operation_type(op: BinaryOperator, left_type: SymbolId, right_type: SymbolId) -> Option<SymbolId> {
+    match op {
+        BinaryOperator::And | BinaryOperator::Or => {
+            [left_type, right_type].iter().all(|id| *id == ANY_TYPE.symbol_id || *id == TRUTH_TYPE.symbol_id)
+                .then_some(TRUTH_TYPE.symbol_id)
+        },
+        BinaryOperator::Add | BinaryOperator::Subtract | BinaryOperator::Multiply |
+        BinaryOperator::Divide | BinaryOperator::Remainder => Some(ANY_TYPE.symbol_id),
+        _ => Some(TRUTH_TYPE.symbol_id)
+    }
+}
+
+fn is_literal_zero(node: &SemanticAst) -> bool {
+    match node {
+        SemanticAst::Number(token) => token.value.replace('_', "").parse::<f64>().is_ok_and(|n| n == 0.0),
+        _ => false
+    }
+}
@@ -0,0 +1,45 @@
+use indexmap::IndexSet;
+use serde::{Deserialize, Serialize};
+
+use super::semantic_analyzer::SymbolId;
+
+// Variables are read by position instead of by symbol id. Every name a function declares, in its
+// body or in the blocks inside it, gets a slot in the environment of each call, and the same goes
+// for code at the top level that gets an environment of its own. A variable then knows how many
+// environments out its name lives, and at which slot. Globals have none: later evaluations can
+// declare more of them, so they stay bound by symbol id.
+
+/// The names bound in an environment, in the order of their slots.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Layout(IndexSet<SymbolId>);
+
+impl Layout {
+    pub fn add(&mut self, symbol_id: SymbolId) {
+        self.0.insert(symbol_id);
+    }
+
+    pub fn slot_of(&self, symbol_id: SymbolId) -> Option<usize> {
+        self.0.get_index_of(&symbol_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl FromIterator<SymbolId> for Layout {
+    fn from_iter<I: IntoIterator<Item = SymbolId>>(symbols: I) -> Layout {
+        Layout(symbols.into_iter().collect())
+    }
+}
+
+/// Where a local's value is bound: `depth` environments out from the running one, at `index`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
@@ -0,0 +1,11 @@
+// Analysis and execution recurse once per level of nesting in the code, so deeply nested
+// expressions would overflow the host's stack long before any limit odo sets. Recursive steps
+// go through `guarded`, which moves to a new stack segment when this one is running out.
+
+// Space left on the stack below which a new segment is allocated
+const RED_ZONE: usize = 128 * 1024;
+const SEGMENT_SIZE: usize = 2 * 1024 * 1024;
+
+pub(crate) fn guarded<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(RED_ZONE, SEGMENT_SIZE, f)
+}
@@ -0,0 +1,110 @@
+use crate::base::{lexer::Token, xref::Span};
+
+// "Did you mean?" for names that aren't declared.
+
+const MAX_SUGGESTIONS: usize = 3;
+
+/// How many characters have to be inserted, removed or replaced, or adjacent ones swapped,
+/// to turn `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // Distances from the first i characters of `a` to every prefix of `b`, for the last two i
+    let mut before: Vec<usize> = Vec::new();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+
+        for j in 1..=b.len() {
+            let replaced = previous[j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            current[j] = replaced.min(previous[j] + 1).min(current[j - 1] + 1);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+
+        before = std::mem::replace(&mut previous, current);
+    }
+
+    previous[b.len()]
+}
+
+/// The candidates close enough to `name` to be what was meant, closest first.
+/// Ties keep the order of `candidates`.
+pub fn similar<'c>(name: &str, candidates: impl IntoIterator<Item = &'c str>) -> Vec<&'c str> {
+    // About one typo every three characters
+    let threshold = (name.chars().count() / 3).max(1);
+
+    let mut close: Vec<_> = candidates.into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .collect();
+    close.sort_by_key(|(distance, _)| *distance);
+
+    close.into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+/// A name that isn't declared, with the ones that are close to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotFound {
+    pub message: String,
+    pub suggestions: Vec<String>,
+    // Where the name was used, when it's known
+    pub span: Option<Span>,
+}
+
+impl NotFound {
+    pub fn at(mut self, token: &Token) -> NotFound {
+        self.span = Some(Span::of(token));
+        self
+    }
+}
+
+impl std::fmt::Display for NotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let quoted: Vec<_> = self.suggestions.iter()
+            .map(|suggestion| format!("`{}`", suggestion))
+            .collect();
+
+        match quoted.as_slice() {
+            [] => write!(f, "{}", self.message),
+            [only] => write!(f, "{}. Did you mean {}?", self.message, only),
+            [rest @ .., last] => write!(f, "{}. Did you mean {} or {}?", self.message, rest.join(", "), last),
+        }
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+/// `message`, followed by the suggestions for `name` if there are any.
+pub fn with_suggestions<'c>(message: String, name: &str, candidates: impl IntoIterator<Item = &'c str>) -> NotFound {
+    let suggestions = similar(name, candidates).into_iter()
+        .map(str::to_string)
+        .collect();
+
+    NotFound { message, suggestions, span: None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edit_distance, similar};
+
+    #[test]
+    fn test_similar_names() {
+        assert_eq!(edit_distance("count", "cuont"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+
+        let names = ["total", "count", "counter", "amount", "x"];
+        assert_eq!(similar("cont", names), ["count"]);
+        assert_eq!(similar("countr", names), ["count", "counter"]);
+        assert!(similar("y", ["total"]).is_empty());
+    }
+}
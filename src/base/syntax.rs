@@ -0,0 +1,179 @@
+use serde_json::json;
+
+use crate::base::lexer::{TokenType, KEYWORDS, SYMBOLS};
+
+// Editor syntax definitions, generated from the lexer's own tables so highlighting can't drift from the language.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    // TextMate grammar, used by VS Code, Sublime and most editors
+    TmLanguage,
+    // `grammar.js` for tree-sitter
+    TreeSitter,
+}
+
+impl std::str::FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tmlanguage" => Ok(Format::TmLanguage),
+            "tree-sitter" => Ok(Format::TreeSitter),
+            _ => Err(anyhow::anyhow!("Unknown syntax format {:?}. Expected tmlanguage or tree-sitter", s))
+        }
+    }
+}
+
+pub fn emit(format: Format) -> String {
+    match format {
+        Format::TmLanguage => tm_language(),
+        Format::TreeSitter => tree_sitter(),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Class {
+    Storage, // var, func, type, import
+    Control, // if, return, ...
+    Constant, // true, false
+    Operator, // and, ==, +, ...
+    Punctuation, // (, ;, ...
+}
+
+fn class(token_type: &TokenType) -> Class {
+    match token_type {
+        TokenType::Var | TokenType::Func | TokenType::Type | TokenType::Contract | TokenType::Extend | TokenType::Import => Class::Storage,
+        TokenType::Truth => Class::Constant,
+        TokenType::And | TokenType::Or | TokenType::In => Class::Operator,
+        TokenType::If | TokenType::With | TokenType::Match | TokenType::Defer | TokenType::For | TokenType::Return | TokenType::Yield => Class::Control,
+        TokenType::LeftCurly | TokenType::RightCurly |
+        TokenType::LeftParen | TokenType::RightParen |
+        TokenType::LeftBracket | TokenType::RightBracket |
+        TokenType::Comma | TokenType::Dot | TokenType::SemiColon | TokenType::Colon | TokenType::Underscore | TokenType::At => Class::Punctuation,
+        _ => Class::Operator,
+    }
+}
+
+fn keywords(of: Class) -> Vec<&'static str> {
+    KEYWORDS.iter().filter(|(_, t)| class(t) == of).map(|(word, _)| *word).collect()
+}
+
+fn symbols(of: Class) -> Vec<&'static str> {
+    SYMBOLS.iter().filter(|(_, t)| class(t) == of).map(|(symbol, _)| *symbol).collect()
+}
+
+fn escape_regex(text: &str) -> String {
+    text.chars().map(|c| {
+        if "\\.+*?()|[]{}^$/-".contains(c) {
+            format!("\\{}", c)
+        } else {
+            c.to_string()
+        }
+    }).collect()
+}
+
+fn words_pattern(words: &[&str]) -> String {
+    format!("\\b({})\\b", words.join("|"))
+}
+
+fn symbols_pattern(symbols: &[&str]) -> String {
+    // SYMBOLS is already longest first, so alternation picks `==` over `=`
+    symbols.iter().map(|s| escape_regex(s)).collect::<Vec<_>>().join("|")
+}
+
+const ESCAPE: &str = r"\\(u\{[0-9a-fA-F]{1,6}\}|x[0-9a-fA-F]{2}|.)";
+const NUMBER: &str = r"[0-9]+(_[0-9]+)*(\.[0-9]+(_[0-9]+)*)?";
+const NAME: &str = r"\p{L}+";
+
+fn tm_language() -> String {
+    let grammar = json!({
+        "name": "odo",
+        "scopeName": "source.odo",
+        "fileTypes": ["odo"],
+        "patterns": [
+            { "include": "#strings" },
+            { "match": words_pattern(&keywords(Class::Storage)), "name": "storage.type.odo" },
+            { "match": words_pattern(&keywords(Class::Control)), "name": "keyword.control.odo" },
+            { "match": words_pattern(&keywords(Class::Constant)), "name": "constant.language.odo" },
+            { "match": words_pattern(&keywords(Class::Operator)), "name": "keyword.operator.word.odo" },
+            { "match": format!("\\b{}\\b", NUMBER), "name": "constant.numeric.odo" },
+            { "match": symbols_pattern(&symbols(Class::Operator)), "name": "keyword.operator.odo" },
+            { "match": symbols_pattern(&symbols(Class::Punctuation)), "name": "punctuation.odo" },
+        ],
+        "repository": {
+            "strings": {
+                "name": "string.quoted.double.odo",
+                "begin": "\"",
+                "end": "\"|$",
+                "patterns": [{ "match": ESCAPE, "name": "constant.character.escape.odo" }]
+            }
+        }
+    });
+
+    serde_json::to_string_pretty(&grammar).expect("The grammar is plain json")
+}
+
+fn tree_sitter() -> String {
+    let choice = |items: Vec<&str>| {
+        let items: Vec<_> = items.iter().map(|item| json!(item).to_string()).collect();
+        format!("choice({})", items.join(", "))
+    };
+
+    let mut keyword_list = keywords(Class::Storage);
+    keyword_list.extend(keywords(Class::Control));
+
+    let mut operator_list = keywords(Class::Operator);
+    operator_list.extend(symbols(Class::Operator));
+
+    format!(r#"// Generated by `odo syntax --emit tree-sitter`. Regenerate it instead of editing.
+module.exports = grammar({{
+  name: 'odo',
+  extras: $ => [/[ \t\r]/],
+  word: $ => $.identifier,
+  rules: {{
+    source_file: $ => repeat($._token),
+    _token: $ => choice($.keyword, $.constant, $.operator, $.punctuation, $.string, $.number, $.identifier, $.newline),
+    keyword: $ => {},
+    constant: $ => {},
+    operator: $ => {},
+    punctuation: $ => {},
+    string: $ => seq('"', repeat(choice(token.immediate(/[^"\\\n]+/), $.escape_sequence)), '"'),
+    escape_sequence: $ => token.immediate(/{}/),
+    number: $ => /{}/,
+    identifier: $ => /{}/,
+    newline: $ => '\n',
+  }}
+}});
+"#,
+        choice(keyword_list),
+        choice(keywords(Class::Constant)),
+        choice(operator_list),
+        choice(symbols(Class::Punctuation)),
+        ESCAPE,
+        NUMBER,
+        NAME,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{emit, Format};
+    use crate::base::lexer::{KEYWORDS, SYMBOLS};
+
+    #[test]
+    fn test_definitions_cover_the_lexer() {
+        let tm_language = emit(Format::TmLanguage);
+        let grammar: serde_json::Value = serde_json::from_str(&tm_language).unwrap();
+        assert_eq!(grammar["scopeName"], "source.odo");
+
+        let tree_sitter = emit(Format::TreeSitter);
+        for (word, _) in KEYWORDS {
+            assert!(tm_language.contains(word));
+            assert!(tree_sitter.contains(&format!("\"{}\"", word)));
+        }
+
+        for (symbol, _) in SYMBOLS {
+            assert!(tree_sitter.contains(&serde_json::json!(symbol).to_string()));
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use crate::base::{lexer::Token, semantic_analyzer::SymbolId};
+
+// Where each symbol is declared and used, for go to definition, find references and renaming.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    // In characters
+    pub length: usize,
+}
+
+impl Span {
+    pub fn of(token: &Token) -> Span {
+        Span {
+            line: token.line,
+            column: token.column,
+            length: token.value.chars().count(),
+        }
+    }
+
+    // A single position, like an editor's cursor
+    pub fn at(line: usize, column: usize) -> Span {
+        Span { line, column, length: 0 }
+    }
+
+    pub fn contains(&self, other: &Span) -> bool {
+        self.line == other.line
+            && self.column <= other.column
+            && other.column + other.length <= self.column + self.length
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CrossReferences {
+    definitions: HashMap<SymbolId, Span>,
+    // Every use of a symbol by name, in the order they were analyzed
+    references: Vec<(Span, SymbolId)>,
+    // Types of literals and operations
+    types: Vec<(Span, SymbolId)>,
+}
+
+impl CrossReferences {
+    pub(crate) fn define(&mut self, symbol_id: SymbolId, name: &Token) {
+        self.definitions.insert(symbol_id, Span::of(name));
+    }
+
+    pub(crate) fn refer(&mut self, name: &Token, symbol_id: SymbolId) {
+        self.references.push((Span::of(name), symbol_id));
+    }
+
+    pub(crate) fn typed(&mut self, token: &Token, type_id: SymbolId) {
+        self.types.push((Span::of(token), type_id));
+    }
+
+    // How much has been recorded, to go back to it with `rewind`
+    pub(crate) fn mark(&self) -> (usize, usize) {
+        (self.references.len(), self.types.len())
+    }
+
+    pub(crate) fn rewind(&mut self, (references, types): (usize, usize)) {
+        self.references.truncate(references);
+        self.types.truncate(types);
+    }
+
+    pub(crate) fn forget(&mut self, symbol_id: SymbolId) {
+        self.definitions.remove(&symbol_id);
+    }
+
+    /// The symbol used or declared at `span`.
+    pub fn symbol_at(&self, span: Span) -> Option<SymbolId> {
+        self.references.iter()
+            .find(|(reference, _)| reference.contains(&span))
+            .map(|(_, symbol_id)| *symbol_id)
+            .or_else(|| self.definitions.iter()
+                .find(|(_, definition)| definition.contains(&span))
+                .map(|(symbol_id, _)| *symbol_id))
+    }
+
+    /// Where the symbol at `span` was declared. Natives and builtin types aren't declared in code, so they have none.
+    pub fn definition(&self, span: Span) -> Option<Span> {
+        self.declaration_of(self.symbol_at(span)?)
+    }
+
+    pub fn declaration_of(&self, symbol_id: SymbolId) -> Option<Span> {
+        self.definitions.get(&symbol_id).copied()
+    }
+
+    /// Type of the narrowest expression at `span`. Names are better looked up through `symbol_at`.
+    pub fn type_at(&self, span: Span) -> Option<SymbolId> {
+        self.types.iter()
+            .filter(|(expression, _)| expression.contains(&span))
+            .min_by_key(|(expression, _)| expression.length)
+            .map(|(_, type_id)| *type_id)
+    }
+
+    /// Every place a symbol is used, not counting its declaration.
+    pub fn references(&self, symbol_id: SymbolId) -> Vec<Span> {
+        self.references.iter()
+            .filter(|(_, id)| *id == symbol_id)
+            .map(|(span, _)| *span)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Span;
+    use crate::base::{lexer::Lexer, parser::Parser, semantic_analyzer::SemanticAnalyzer};
+
+    #[test]
+    fn test_definitions_and_references() {
+        let code = "var a = 1\nfunc f(x: int) -> int {\n    return x + a\n}\na = f(a)\n";
+        let tokens = Lexer::new(code.to_string()).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+
+        let mut analyzer = SemanticAnalyzer::new();
+        analyzer.analyze(ast).unwrap();
+        let xref = analyzer.cross_references();
+
+        // `a` in `return x + a`
+        assert_eq!(xref.definition(Span::at(3, 15)), Some(Span { line: 1, column: 4, length: 1 }));
+        // `x` in the same line goes to the parameter
+        assert_eq!(xref.definition(Span::at(3, 11)), Some(Span { line: 2, column: 7, length: 1 }));
+
+        let a = xref.symbol_at(Span::at(1, 4)).unwrap();
+        assert_eq!(xref.references(a), [
+            Span { line: 3, column: 15, length: 1 },
+            Span { line: 5, column: 6, length: 1 },
+            Span { line: 5, column: 0, length: 1 },
+        ]);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{
+    base::{
+        diagnostics::CompileError,
+        lexer::{Lexer, Token},
+        parser::{Node, Parser},
+        semantic_analyzer::{SemanticAnalyzer, SemanticAst, Warning},
+    },
+    exec::interpreter::Interpreter,
+};
+
+// The stages `Interpreter::eval` goes through before running anything, one at a time, for tools
+// that need what's in between: tokens to highlight, a tree to format, types to show.
+// Their errors are CompileErrors, so they report just like the ones from eval.
+
+/// The analyzed top-level statements of some code, and what was found while analyzing them.
+pub struct Analysis {
+    pub statements: Vec<AnalyzedStatement>,
+    pub warnings: Vec<Warning>,
+    /// Everything the code declared, and the standard library. Symbols in the statements
+    /// are looked up here.
+    pub analyzer: SemanticAnalyzer,
+}
+
+pub struct AnalyzedStatement {
+    pub node: SemanticAst,
+    /// The type of the value the statement ends with, if it has one
+    pub type_name: Option<String>,
+}
+
+/// The tokens in `code`.
+pub fn lex(code: &str) -> anyhow::Result<Vec<Token>> {
+    Lexer::new(code.to_string()).tokenize().map_err(|error| CompileError(error).into())
+}
+
+/// The top-level statements in `tokens`.
+pub fn parse(tokens: Vec<Token>) -> anyhow::Result<Vec<Node>> {
+    Parser::new(tokens).statement_list().map_err(|error| CompileError(error).into())
+}
+
+/// Analyzes the statements in `ast` like a script would be, with the standard library in scope.
+/// Fails on the first statement that doesn't pass.
+pub fn analyze(ast: Vec<Node>) -> anyhow::Result<Analysis> {
+    let mut analyzer = Interpreter::new().semantic_analyzer;
+    let scope_id = analyzer.repl_scope_id;
+
+    let statements = analyzer.in_scope(scope_id, |analyzer| ast.into_iter()
+        .map(|node| {
+            let result = analyzer.analyze(node)?;
+            let type_name = match result.type_id() {
+                Some(type_id) => analyzer.name_of_type(type_id)?,
+                None => None
+            };
+
+            Ok(AnalyzedStatement { node: *result.node, type_name })
+        })
+        .collect::<anyhow::Result<Vec<_>>>())
+        .map_err(CompileError)?;
+
+    Ok(Analysis { statements, warnings: analyzer.take_warnings(), analyzer })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze, lex, parse};
+    use crate::base::{diagnostics::is_compile_error, lexer::TokenType, semantic_analyzer::SemanticAst};
+
+    #[test]
+    fn test_stages() {
+        let tokens = lex("var a = 1\nprint(\"hi\")\na + 0.5").unwrap();
+        assert_eq!(tokens[0].token_type, TokenType::Var);
+
+        let ast = parse(tokens).unwrap();
+        assert_eq!(ast.len(), 3);
+
+        let analysis = analyze(ast).unwrap();
+        assert!(matches!(analysis.statements[0].node, SemanticAst::Declaration(..)));
+        assert_eq!(analysis.statements[2].type_name.as_deref(), Some("dec"));
+
+        assert!(is_compile_error(&lex("\"open").unwrap_err()));
+        assert_eq!(lex("var a = $").unwrap_err().to_string(), "Unexpected character '$' (line 1, column 8)");
+        assert!(is_compile_error(&parse(lex("var = 1").unwrap()).unwrap_err()));
+        assert!(is_compile_error(&analyze(parse(lex("a").unwrap()).unwrap()).err().unwrap()));
+    }
+}
@@ -0,0 +1,131 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use odo::exec::value::format::Limits;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub prompt: String,
+    pub colors: ColorScheme,
+    // Lines kept in the REPL's history file. 0 disables it.
+    pub history_size: usize,
+    // Statements each evaluation can run. No limit by default.
+    pub fuel: Option<u64>,
+    // Odo files evaluated into the global scope after the prelude, relative to the config directory
+    pub modules: Vec<PathBuf>,
+    // Columns a tab counts as in reported positions
+    pub tab_width: usize,
+    // How much of a value the repl and `:` print: levels of nesting, and items of each list or map
+    pub print_depth: usize,
+    pub print_width: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            prompt: "> ".to_string(),
+            colors: ColorScheme::Default,
+            history_size: 1000,
+            fuel: None,
+            modules: vec![],
+            tab_width: odo::base::lexer::DEFAULT_TAB_WIDTH,
+            print_depth: Limits::default().depth,
+            print_width: Limits::default().width,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorScheme {
+    Default,
+    None,
+}
+
+impl ColorScheme {
+    fn paint(&self, code: &str, text: &str) -> String {
+        match self {
+            ColorScheme::Default => format!("\x1b[{}m{}\x1b[0m", code, text),
+            ColorScheme::None => text.to_string(),
+        }
+    }
+
+    pub fn prompt(&self, text: &str) -> String {
+        self.paint("32", text)
+    }
+
+    pub fn error(&self, text: &str) -> String {
+        self.paint("31", text)
+    }
+
+    pub fn warning(&self, text: &str) -> String {
+        self.paint("33", text)
+    }
+
+    // For what's less important than what's around it, like traces
+    pub fn dim(&self, text: &str) -> String {
+        self.paint("90", text)
+    }
+
+    pub fn header(&self, text: &str) -> String {
+        self.paint("1", text)
+    }
+}
+
+// `$HOME/.config/odo`, where the config, the prelude and the history live
+pub fn directory() -> Option<PathBuf> {
+    let home = std::env::var("HOME").ok()?;
+
+    Some(PathBuf::from(home).join(".config").join("odo"))
+}
+
+impl Config {
+    // A missing file means the defaults
+    pub fn load() -> anyhow::Result<Config> {
+        match directory().map(|dir| dir.join("config.toml")) {
+            Some(path) if path.exists() => Config::from_file(&path),
+            _ => Ok(Config::default())
+        }
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<Config> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read config {}", path.display()))?;
+
+        toml::from_str(&text)
+            .with_context(|| format!("Invalid config {}", path.display()))
+    }
+
+    pub fn module_paths(&self) -> Vec<PathBuf> {
+        let base = directory().unwrap_or_default();
+
+        self.modules.iter()
+            .map(|path| base.join(path))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, ColorScheme};
+
+    #[test]
+    fn test_parse_config() {
+        let config: Config = toml::from_str(r#"
+            prompt = "odo> "
+            colors = "none"
+            fuel = 10000
+            modules = ["math.odo"]
+        "#).unwrap();
+
+        assert_eq!(config.prompt, "odo> ");
+        assert_eq!(config.colors, ColorScheme::None);
+        assert_eq!(config.fuel, Some(10000));
+        // Anything left out keeps its default
+        assert_eq!(config.history_size, 1000);
+
+        assert!(toml::from_str::<Config>("unknown = 1").is_err());
+    }
+}
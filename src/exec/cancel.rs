@@ -0,0 +1,28 @@
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+
+/// Stops the code an interpreter is running, from another thread, like a Ctrl-C handler.
+/// The interpreter checks it before each statement and each time around a loop, and stops
+/// with `RuntimeError::Cancelled`. Natives that are waiting, like `readLine`, aren't stopped.
+///
+/// Clones share the same state. Cancelling while nothing runs does nothing: every call from
+/// the host starts uncancelled.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn clear(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
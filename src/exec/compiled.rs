@@ -0,0 +1,187 @@
+use std::{collections::{HashMap, HashSet}, path::Path, sync::{Arc, OnceLock}};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::base::semantic_analyzer::{SemanticAnalyzer, SemanticAst, Symbol, SymbolTable, SymbolVariant, TableId};
+use crate::exec::interpreter::Interpreter;
+
+// Bumped whenever the saved representation changes
+const FORMAT: u32 = 3;
+
+/// An analyzed program that can be saved and run later without lexing, parsing or analyzing it again.
+#[derive(Serialize, Deserialize)]
+pub struct CompiledProgram {
+    format: u32,
+    // Builtin types, natives and the types made from them have the same ids everywhere, but the
+    // rest are random. Everything the program uses from outside is kept by name, so it can be
+    // linked with the interpreter that runs it.
+    global_scope_id: TableId,
+    pub(crate) scope_id: TableId,
+    pub(crate) externals: Vec<(String, Symbol)>,
+    // The program's own scopes, and what it declares at the top level
+    pub(crate) scopes: Vec<SymbolTable>,
+    pub(crate) declarations: Vec<Symbol>,
+    // Never changed once analyzed, so every interpreter running the program can share them
+    pub(crate) statements: Arc<Vec<SemanticAst>>,
+    // Every id the statements mention, found the first time the program is linked
+    #[serde(skip)]
+    statement_ids: OnceLock<HashSet<Uuid>>,
+}
+
+pub(crate) struct Linked {
+    pub scopes: Vec<SymbolTable>,
+    pub declarations: Vec<Symbol>,
+    pub statements: Arc<Vec<SemanticAst>>,
+}
+
+impl CompiledProgram {
+    pub(crate) fn new(analyzer: &SemanticAnalyzer, scope_id: TableId, scopes: Vec<SymbolTable>, declarations: Vec<Symbol>, statements: Vec<SemanticAst>) -> anyhow::Result<CompiledProgram> {
+        Ok(CompiledProgram {
+            format: FORMAT,
+            global_scope_id: analyzer.global_scope_id,
+            scope_id,
+            externals: analyzer.qualified_symbols()?,
+            scopes,
+            declarations,
+            statements: Arc::new(statements),
+            statement_ids: OnceLock::new(),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let text = serde_json::to_string(self)?;
+
+        std::fs::write(path, text)
+            .with_context(|| format!("Could not write compiled program {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<CompiledProgram> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read compiled program {}", path.display()))?;
+
+        let program: CompiledProgram = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid compiled program {}", path.display()))?;
+
+        if program.format != FORMAT {
+            return Err(anyhow::anyhow!("{} was compiled by a different version of odo. Build it again", path.display()));
+        }
+
+        Ok(program)
+    }
+
+    // Rewrites the ids of everything the program uses from outside to the ones in `analyzer`,
+    // which will run it in `scope_id`.
+    pub(crate) fn link(&self, analyzer: &mut SemanticAnalyzer, scope_id: TableId) -> anyhow::Result<Linked> {
+        let current: HashMap<_, _> = analyzer.qualified_symbols()?.into_iter().collect();
+
+        let new_types: Vec<_> = self.externals.iter()
+            .filter(|(name, symbol)| !current.contains_key(name) && matches!(symbol.variant, SymbolVariant::FunctionType(_) | SymbolVariant::Parametric(_)))
+            .map(|(_, symbol)| symbol.clone())
+            .collect();
+
+        let mut contents = serde_json::to_value((new_types, &self.scopes, &self.declarations))?;
+        let statement_ids = self.statement_ids.get_or_init(|| serde_json::to_value(&self.statements).map(|statements| ids_in(&statements)).unwrap_or_default());
+        let used: HashSet<_> = ids_in(&contents).union(statement_ids).copied().collect();
+
+        let mut ids = HashMap::new();
+        ids.insert(self.global_scope_id, analyzer.global_scope_id);
+        ids.insert(self.scope_id, scope_id);
+
+        for (name, symbol) in &self.externals {
+            match current.get(name) {
+                Some(existing) => {
+                    ids.insert(symbol.symbol_id, existing.symbol_id);
+                },
+                // Function types and lists of a type are created as they're needed, so the program brings its own
+                None if matches!(symbol.variant, SymbolVariant::FunctionType(_) | SymbolVariant::Parametric(_)) => {},
+                None if used.contains(&symbol.symbol_id) => {
+                    return Err(anyhow::anyhow!("The program needs {}, which isn't available here", name));
+                },
+                None => {}
+            }
+        }
+
+        // The statements are only copied when an id in them is different here
+        ids.retain(|from, to| from != to);
+        let statements = if statement_ids.iter().any(|id| ids.contains_key(id)) {
+            let mut statements = serde_json::to_value(&self.statements)?;
+            replace_ids(&mut statements, &ids);
+            serde_json::from_value(statements)?
+        } else {
+            self.statements.clone()
+        };
+
+        replace_ids(&mut contents, &ids);
+        let (new_types, scopes, declarations): (Vec<Symbol>, _, _) = serde_json::from_value(contents)?;
+
+        let global = analyzer.global_scope_mut()?;
+        for symbol in new_types {
+            global.insert(symbol);
+        }
+
+        Ok(Linked { scopes, declarations, statements })
+    }
+}
+
+/// A compiled prelude to start interpreters from, so it's only lexed, parsed and analyzed once.
+/// Clones share it, and it can be sent to other threads to start interpreters there.
+#[derive(Clone)]
+pub struct Program(Arc<CompiledProgram>);
+
+impl From<CompiledProgram> for Program {
+    fn from(program: CompiledProgram) -> Program {
+        Program(Arc::new(program))
+    }
+}
+
+impl Program {
+    /// Compiles `code` for interpreters with only the standard library. When it uses the host's
+    /// natives, compile it with `Interpreter::compile` on an interpreter that has them.
+    pub fn compile(code: impl Into<String>) -> anyhow::Result<Program> {
+        Interpreter::new().compile(code.into()).map(Program::from)
+    }
+
+    /// A new interpreter with everything the program declares, and that `reset` goes back to.
+    pub fn instantiate<'a>(&self) -> anyhow::Result<Interpreter<'a>> {
+        self.instantiate_with(|_| Ok(()))
+    }
+
+    /// Like `instantiate`, letting `bind` add the host's natives before the program runs.
+    pub fn instantiate_with<'a>(&self, bind: impl FnOnce(&mut Interpreter<'a>) -> anyhow::Result<()>) -> anyhow::Result<Interpreter<'a>> {
+        let mut interpreter = Interpreter::new();
+        bind(&mut interpreter)?;
+        interpreter.load(self)?;
+        interpreter.checkpoint();
+
+        Ok(interpreter)
+    }
+
+    pub fn compiled(&self) -> &CompiledProgram {
+        &self.0
+    }
+}
+
+// Ids are saved as strings, wherever they are in the program
+fn ids_in(value: &serde_json::Value) -> HashSet<Uuid> {
+    match value {
+        serde_json::Value::String(text) => Uuid::parse_str(text).into_iter().collect(),
+        serde_json::Value::Array(items) => items.iter().flat_map(ids_in).collect(),
+        serde_json::Value::Object(fields) => fields.values().flat_map(ids_in).collect(),
+        _ => HashSet::new()
+    }
+}
+
+fn replace_ids(value: &mut serde_json::Value, ids: &HashMap<Uuid, Uuid>) {
+    match value {
+        serde_json::Value::String(text) => {
+            if let Some(id) = Uuid::parse_str(text).ok().and_then(|id| ids.get(&id)) {
+                *text = id.to_string();
+            }
+        },
+        serde_json::Value::Array(items) => items.iter_mut().for_each(|item| replace_ids(item, ids)),
+        serde_json::Value::Object(fields) => fields.values_mut().for_each(|field| replace_ids(field, ids)),
+        _ => {}
+    }
+}
@@ -0,0 +1,402 @@
+use std::{collections::{BTreeMap, HashMap}, fmt::Write};
+
+use uuid::Uuid;
+
+use crate::base::{lexer::{Token, TokenType}, semantic_analyzer::{BinaryOperator, FunctionDefinition, SemanticAst, SemanticNode, Symbol, SymbolVariant, DEC_TYPE, INT_TYPE}};
+use crate::exec::compiled::CompiledProgram;
+
+// Source in other languages from an analyzed program, for places the interpreter can't go.
+// Experimental: only part of the language has a translation, and anything else is an error
+// instead of a guess. Numbers are JavaScript numbers, so ints are told apart from decs by the
+// types in the program, to truncate when dividing them.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    JavaScript,
+}
+
+impl std::str::FromStr for Target {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "js" => Ok(Target::JavaScript),
+            _ => Err(anyhow::anyhow!("Unknown emit target {:?}. Expected js", s))
+        }
+    }
+}
+
+pub fn emit(program: &CompiledProgram, target: Target) -> anyhow::Result<String> {
+    match target {
+        Target::JavaScript => javascript(program),
+    }
+}
+
+fn javascript(program: &CompiledProgram) -> anyhow::Result<String> {
+    let mut emitter = JavaScript::new(program);
+
+    let mut body = String::new();
+    for statement in program.statements.iter() {
+        emitter.statement(&mut body, statement, 0)?;
+    }
+
+    let mut output = String::new();
+    for helper in emitter.helpers.values() {
+        let _ = writeln!(output, "{}", helper);
+    }
+    if !emitter.helpers.is_empty() {
+        output.push('\n');
+    }
+    output.push_str(&body);
+
+    Ok(output)
+}
+
+// Natives with a translation, by name
+const NATIVES: &[(&str, &str)] = &[
+    ("print", "console.log"),
+    ("printError", "console.error"),
+    ("append", "((list, item) => [...list, item])"),
+    ("first", "((list) => list[0])"),
+    ("time", "(() => Date.now() / 1000)"),
+];
+
+// Definitions the output needs before the program, by name
+const EQUAL: &str = "const $equal = (a, b) => a === b || (Array.isArray(a) && Array.isArray(b) && a.length === b.length && a.every((item, i) => $equal(item, b[i]))) || (a instanceof Map && b instanceof Map && a.size === b.size && [...a].every(([key, value]) => b.has(key) && $equal(value, b.get(key))));";
+const CONTAINS: &str = "const $contains = (item, items) => typeof items === \"string\" ? items.includes(item) : items instanceof Map ? items.has(item) : items.some((other) => $equal(other, item));";
+
+// Words odo allows as names that JavaScript doesn't
+const RESERVED: &[&str] = &[
+    "await", "break", "case", "catch", "class", "const", "continue", "debugger", "default", "delete", "do",
+    "else", "enum", "export", "extends", "finally", "function", "import", "instanceof", "let", "new", "null",
+    "static", "super", "switch", "this", "throw", "try", "typeof", "undefined", "void", "while", "with", "yield",
+];
+
+struct JavaScript<'p> {
+    program: &'p CompiledProgram,
+    // The program's own symbols, by id
+    names: HashMap<Uuid, String>,
+    // Everything the program can refer to, by id, for the types of numbers
+    symbols: HashMap<Uuid, &'p Symbol>,
+    helpers: BTreeMap<&'static str, &'static str>,
+}
+
+impl<'p> JavaScript<'p> {
+    fn new(program: &'p CompiledProgram) -> JavaScript<'p> {
+        let names = program.scopes.iter()
+            .flat_map(|scope| scope.symbols())
+            .chain(&program.declarations)
+            .map(|symbol| (symbol.symbol_id, symbol.name().to_string()))
+            .collect();
+
+        let symbols = program.scopes.iter()
+            .flat_map(|scope| scope.symbols())
+            .chain(&program.declarations)
+            .chain(program.externals.iter().map(|(_, symbol)| symbol))
+            .map(|symbol| (symbol.symbol_id, symbol))
+            .collect();
+
+        JavaScript { program, names, symbols, helpers: BTreeMap::new() }
+    }
+
+    fn name(&self, id: Uuid) -> anyhow::Result<String> {
+        if let Some(name) = self.names.get(&id) {
+            return Ok(match RESERVED.contains(&name.as_str()) {
+                true => format!("{}_", name),
+                false => name.clone()
+            });
+        }
+
+        let (name, symbol) = self.program.externals.iter()
+            .find(|(_, symbol)| symbol.symbol_id == id)
+            .ok_or_else(|| anyhow::anyhow!("The program uses a symbol it doesn't know ({})", id))?;
+
+        match (&symbol.variant, NATIVES.iter().find(|(native, _)| native == name)) {
+            (SymbolVariant::NativeFunction(_), Some((_, translation))) => Ok(translation.to_string()),
+            _ => Err(anyhow::anyhow!("{} has no JavaScript translation yet", name))
+        }
+    }
+
+    fn helper(&mut self, name: &'static str, definition: &'static str) -> &'static str {
+        self.helpers.insert(name, definition);
+        name
+    }
+
+    fn statement(&mut self, output: &mut String, node: &SemanticAst, depth: usize) -> anyhow::Result<()> {
+        let indent = "    ".repeat(depth);
+
+        match node {
+            SemanticAst::Block(statements, _) => {
+                let _ = writeln!(output, "{}{{", indent);
+                self.statements(output, statements, depth + 1)?;
+                let _ = writeln!(output, "{}}}", indent);
+            },
+            SemanticAst::Frame(_, node) => self.statement(output, node, depth)?,
+            SemanticAst::Declaration(id, _, value) => {
+                let _ = writeln!(output, "{}let {} = {};", indent, self.name(*id)?, self.expression(value)?);
+            },
+            SemanticAst::MultipleDeclaration(pairs) | SemanticAst::MultipleAssignment(pairs) => {
+                let names = pairs.iter().map(|(id, _)| self.name(*id)).collect::<anyhow::Result<Vec<_>>>()?;
+                let values = pairs.iter().map(|(_, value)| self.expression(value)).collect::<anyhow::Result<Vec<_>>>()?;
+                let keyword = if matches!(node, SemanticAst::MultipleDeclaration(_)) { "let " } else { "" };
+                let _ = writeln!(output, "{}{}[{}] = [{}];", indent, keyword, names.join(", "), values.join(", "));
+            },
+            SemanticAst::FunctionDeclaration(id, definition) => {
+                let name = self.name(*id)?;
+                let _ = write!(output, "{}function {}", indent, name);
+                self.function(output, definition, depth)?;
+                output.push('\n');
+            },
+            SemanticAst::Return(value) => match value {
+                Some(value) => { let _ = writeln!(output, "{}return {};", indent, self.expression(value)?); },
+                None => { let _ = writeln!(output, "{}return;", indent); }
+            },
+            SemanticAst::If(condition, body) => {
+                let _ = write!(output, "{}if ({})", indent, self.expression(condition)?);
+                self.body(output, body, depth)?;
+            },
+            SemanticAst::For(variable, _, iterable, body, _) => {
+                let _ = write!(output, "{}for (let {} of {})", indent, self.name(*variable)?, self.expression(iterable)?);
+                self.body(output, body, depth)?;
+            },
+            SemanticAst::DebugPrint(value) => {
+                let _ = writeln!(output, "{}console.log({});", indent, self.expression(value)?);
+            },
+            _ => {
+                let _ = writeln!(output, "{}{};", indent, self.expression(node)?);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn statements(&mut self, output: &mut String, statements: &[SemanticAst], depth: usize) -> anyhow::Result<()> {
+        for statement in statements {
+            self.statement(output, statement, depth)?;
+        }
+
+        Ok(())
+    }
+
+    // After the header of an if or a for
+    fn body(&mut self, output: &mut String, body: &SemanticAst, depth: usize) -> anyhow::Result<()> {
+        match body {
+            SemanticAst::Frame(_, body) => self.body(output, body, depth),
+            SemanticAst::Block(statements, _) => {
+                output.push_str(" {\n");
+                self.statements(output, statements, depth + 1)?;
+                let _ = writeln!(output, "{}}}", "    ".repeat(depth));
+                Ok(())
+            },
+            _ => {
+                output.push('\n');
+                self.statement(output, body, depth + 1)
+            }
+        }
+    }
+
+    // The parameters and body, after the name
+    fn function(&mut self, output: &mut String, definition: &FunctionDefinition, depth: usize) -> anyhow::Result<()> {
+        if definition.is_coroutine || definition.is_generator {
+            return Err(anyhow::anyhow!("{} yields, and functions that yield have no JavaScript translation yet", definition.name));
+        }
+
+        let parameters = definition.parameters.iter().map(|id| self.name(*id)).collect::<anyhow::Result<Vec<_>>>()?;
+        let _ = writeln!(output, "({}) {{", parameters.join(", "));
+        self.statements(output, &definition.body, depth + 1)?;
+        let _ = write!(output, "{}}}", "    ".repeat(depth));
+
+        Ok(())
+    }
+
+    fn expression(&mut self, node: &SemanticAst) -> anyhow::Result<String> {
+        Ok(match node {
+            SemanticAst::Number(token) | SemanticAst::Truth(token) => token.value.clone(),
+            // JSON strings are JavaScript strings
+            SemanticAst::Text(token) => serde_json::to_string(&token.value)?,
+            SemanticAst::Variable(id, _) => self.name(*id)?,
+            SemanticAst::Frame(_, node) => self.expression(node)?,
+            SemanticAst::Assignment(id, value) => format!("{} = {}", self.name(*id)?, self.expression(value)?),
+            SemanticAst::BinaryOperation(left_node, BinaryOperator::Divide, right_node, token) => {
+                let (left, right) = (self.expression(left_node)?, self.expression(right_node)?);
+
+                match (self.is_int(left_node), self.is_int(right_node)) {
+                    (Some(true), Some(true)) => format!("Math.trunc({} / {})", left, right),
+                    (Some(false), _) | (_, Some(false)) => format!("({} / {})", left, right),
+                    _ => return Err(anyhow::anyhow!(
+                        "Division where the types don't say if it divides ints has no JavaScript translation yet (line {}, column {})",
+                        token.line, token.column
+                    ))
+                }
+            },
+            SemanticAst::BinaryOperation(left, operator, right, _) => {
+                let (left, right) = (self.expression(left)?, self.expression(right)?);
+                self.operation(*operator, &left, &right)
+            },
+            // Each operand is bound to a parameter, so it runs once, and only if the comparisons before it were true
+            SemanticAst::Comparison(first, rest) => self.comparison(first, rest, 0)?,
+            SemanticAst::Negation(value, token) if token.token_type == TokenType::Minus => format!("(-{})", self.expression(value)?),
+            SemanticAst::FunctionCall(callee, arguments) => {
+                let callee = self.expression(callee)?;
+                let arguments = arguments.iter().map(|argument| self.expression(argument)).collect::<anyhow::Result<Vec<_>>>()?;
+                format!("{}({})", callee, arguments.join(", "))
+            },
+            SemanticAst::List(items) => {
+                let items = items.iter().map(|item| self.expression(item)).collect::<anyhow::Result<Vec<_>>>()?;
+                format!("[{}]", items.join(", "))
+            },
+            SemanticAst::Map(entries) => {
+                let entries = entries.iter()
+                    .map(|(key, value)| Ok(format!("[{}, {}]", self.expression(key)?, self.expression(value)?)))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                format!("new Map([{}])", entries.join(", "))
+            },
+            SemanticAst::Function(definition) => {
+                let mut function = String::from("(function ");
+                self.function(&mut function, definition, 0)?;
+                function.push(')');
+                function
+            },
+            // JavaScript has no types to check against
+            SemanticAst::Checked(value, ..) => self.expression(value)?,
+            _ => return Err(anyhow::anyhow!("{} has no JavaScript translation yet", node.kind_name()))
+        })
+    }
+
+    fn operation(&mut self, operator: BinaryOperator, left: &str, right: &str) -> String {
+        match operator {
+            BinaryOperator::Equal => format!("{}({}, {})", self.helper("$equal", EQUAL), left, right),
+            BinaryOperator::NotEqual => format!("!{}({}, {})", self.helper("$equal", EQUAL), left, right),
+            BinaryOperator::In => {
+                self.helper("$equal", EQUAL);
+                format!("{}({}, {})", self.helper("$contains", CONTAINS), left, right)
+            },
+            _ => format!("({} {} {})", left, javascript_operator(operator), right)
+        }
+    }
+
+    // `a < b < c` is `(($0) => (($1) => $0 < $1 && (($2) => $1 < $2)(c))(b))(a)`
+    fn comparison(&mut self, left: &SemanticAst, rest: &[(BinaryOperator, SemanticNode, Token)], index: usize) -> anyhow::Result<String> {
+        let Some(((operator, right, _), rest)) = rest.split_first() else {
+            return Ok(String::new());
+        };
+
+        let (left_name, right_name) = (format!("${}", index), format!("${}", index + 1));
+        let mut body = self.operation(*operator, &left_name, &right_name);
+        if !rest.is_empty() {
+            body = format!("{} && {}", body, self.comparison(right, rest, index + 1)?);
+        }
+
+        let inner = format!("(({}) => {})({})", right_name, body, self.expression(right)?);
+        Ok(match index {
+            0 => format!("(({}) => {})({})", left_name, inner, self.expression(left)?),
+            _ => inner
+        })
+    }
+
+    // Whether a number is an int rather than a dec, when the program's types say
+    fn is_int(&self, node: &SemanticAst) -> Option<bool> {
+        let type_id = match node {
+            SemanticAst::Number(token) => return Some(!token.value.contains('.')),
+            SemanticAst::Frame(_, node) | SemanticAst::Negation(node, _) => return self.is_int(node),
+            SemanticAst::Checked(_, type_id, _) => *type_id,
+            SemanticAst::Variable(id, _) => self.symbols.get(id)?.value_type_id().ok()?,
+            // Mixing ints and decs makes a dec
+            SemanticAst::BinaryOperation(
+                left,
+                BinaryOperator::Add | BinaryOperator::Subtract | BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Remainder,
+                right,
+                _
+            ) => {
+                return match (self.is_int(left), self.is_int(right)) {
+                    (Some(false), _) | (_, Some(false)) => Some(false),
+                    (Some(true), Some(true)) => Some(true),
+                    _ => None
+                };
+            },
+            SemanticAst::FunctionCall(callee, _) => {
+                let SemanticAst::Variable(id, _) = callee.as_ref() else {
+                    return None;
+                };
+                let function_type = self.symbols.get(id)?.value_type_id().ok()?;
+                match &self.symbols.get(&function_type)?.variant {
+                    SymbolVariant::FunctionType(function_type) => function_type.return_id()?,
+                    _ => return None
+                }
+            },
+            _ => return None
+        };
+
+        match type_id {
+            id if id == INT_TYPE.symbol_id => Some(true),
+            id if id == DEC_TYPE.symbol_id => Some(false),
+            _ => None
+        }
+    }
+}
+
+fn javascript_operator(operator: BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Equal => "===",
+        BinaryOperator::NotEqual => "!==",
+        BinaryOperator::Less => "<",
+        BinaryOperator::LessEqual => "<=",
+        BinaryOperator::Greater => ">",
+        BinaryOperator::GreaterEqual => ">=",
+        BinaryOperator::And => "&&",
+        BinaryOperator::Or => "||",
+        BinaryOperator::In => "in",
+        BinaryOperator::Add => "+",
+        BinaryOperator::Subtract => "-",
+        BinaryOperator::Multiply => "*",
+        BinaryOperator::Divide => "/",
+        BinaryOperator::Remainder => "%",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{emit, Target};
+    use crate::exec::interpreter::Interpreter;
+
+    #[test]
+    fn test_javascript() {
+        let mut interpreter = Interpreter::new();
+        let program = interpreter.compile(
+            "var items = [1, 2]\nfunc total(numbers: list<int>) -> int {\n    var sum = 0\n    for item in numbers {\n        sum = sum + item\n    }\n    return sum\n}\nif 2 in items {\n    print(\"found\")\n}\n: total(append(items, 3))".to_string()
+        ).unwrap();
+
+        let output = emit(&program, Target::JavaScript).unwrap();
+        assert!(output.starts_with("const $contains"));
+        assert!(output.contains("let items = [1, 2];\nfunction total(numbers) {\n    let sum = 0;\n    for (let item of numbers) {\n        sum = (sum + item);\n    }\n"));
+        assert!(output.contains("if ($contains(2, items)) {\n    console.log(\"found\");\n}\n"));
+        assert!(output.ends_with("console.log(total(((list, item) => [...list, item])(items, 3)));\n"));
+
+        let program = interpreter.compile("defer print(\"bye\")".to_string()).unwrap();
+        assert_eq!(emit(&program, Target::JavaScript).unwrap_err().to_string(), "Defer has no JavaScript translation yet");
+    }
+
+    #[test]
+    fn test_javascript_matches_odo() {
+        let mut interpreter = Interpreter::new();
+        let javascript = |interpreter: &mut Interpreter, code: &str| interpreter.compile(code.to_string())
+            .and_then(|program| emit(&program, Target::JavaScript));
+
+        // Dividing ints truncates, like in odo
+        let output = javascript(&mut interpreter, "func half(n: int) -> int {\n    return n / 2\n}\n: half(7) / 2\n: 7.5 / 2").unwrap();
+        assert!(output.contains("return Math.trunc(n / 2);\n"), "{}", output);
+        assert!(output.contains("console.log(Math.trunc(half(7) / 2));\nconsole.log((7.5 / 2));\n"), "{}", output);
+        let output = javascript(&mut interpreter, "var x: any = 1\n: x / 2");
+        assert!(output.is_err_and(|e| e.to_string().starts_with("Division where the types don't say if it divides ints")));
+
+        // Maps are compared by what's in them, not as `{}`
+        let output = javascript(&mut interpreter, ": {\"a\": 1} == {\"b\": 2}").unwrap();
+        assert!(output.contains("a instanceof Map && b instanceof Map"), "{}", output);
+        assert!(!output.contains("JSON.stringify"), "{}", output);
+
+        // The middle of a chain is evaluated once
+        let output = javascript(&mut interpreter, "func f() -> int {\n    return 5\n}\n: 0 <= f() < 10").unwrap();
+        assert_eq!(output.matches("(f())").count(), 1, "{}", output);
+        assert!(output.ends_with("console.log((($0) => (($1) => ($0 <= $1) && (($2) => ($1 < $2))(10))(f()))(0));\n"), "{}", output);
+    }
+}
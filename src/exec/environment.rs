@@ -0,0 +1,184 @@
+use std::{collections::{HashMap, HashSet}, sync::{Arc, Mutex, MutexGuard}};
+
+use uuid::Uuid;
+
+use crate::base::{semantic_analyzer::{FunctionDefinition, SymbolId}, slots::{Layout, Slot}};
+
+// Bindings of running code, from symbols to the ids of their values in the value table. Each
+// environment has a slot for every name the analyzer found declared in it, so variables are read
+// by position (see `slots`).
+//
+// Every function call gets a new environment, whose parent is the environment the function was
+// created in. A function keeps that environment for as long as it lives, so it can still use the
+// locals around it after the call that created it has returned.
+//
+// Captures are by reference: a closure shares the bindings of the call that created it, so
+// assignments made by either are seen by both, and closures created by the same call share them
+// too. Blocks inside a function bind into the function's environment, while blocks at the top
+// level get one of their own, since their bindings are thrown away once they've run.
+// Top-level declarations are global and don't live in any environment.
+//
+// Forks of an interpreter have value tables of their own, so they get copies of the environments
+// too. Otherwise a rebinding made by one would point the others to a value they don't have.
+
+#[derive(Clone, Default)]
+pub struct Environment(Arc<Mutex<Bindings>>);
+
+#[derive(Default)]
+struct Bindings {
+    // Which symbol each slot is for, as the analyzer laid them out
+    layout: Arc<Layout>,
+    slots: Vec<Option<Uuid>>,
+    // Names without a slot. The analyzer lays out everything code declares, so it's normally empty.
+    unslotted: HashMap<SymbolId, Uuid>,
+    parent: Option<Environment>,
+}
+
+impl Bindings {
+    fn get(&self, symbol_id: SymbolId) -> Option<Uuid> {
+        match self.layout.slot_of(symbol_id) {
+            Some(index) => self.slots[index],
+            None => self.unslotted.get(&symbol_id).copied()
+        }
+    }
+
+    fn bound(&mut self, symbol_id: SymbolId) -> Option<&mut Uuid> {
+        match self.layout.slot_of(symbol_id) {
+            Some(index) => self.slots[index].as_mut(),
+            None => self.unslotted.get_mut(&symbol_id)
+        }
+    }
+}
+
+impl Environment {
+    pub fn new(parent: Option<Environment>, layout: Arc<Layout>) -> Environment {
+        let slots = vec![None; layout.len()];
+        Environment(Arc::new(Mutex::new(Bindings { layout, slots, unslotted: HashMap::new(), parent })))
+    }
+
+    fn bindings(&self) -> MutexGuard<'_, Bindings> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The value bound to `symbol_id` here or in any enclosing environment.
+    pub fn get(&self, symbol_id: SymbolId) -> Option<Uuid> {
+        let mut environment = self.clone();
+        loop {
+            let parent = {
+                let bindings = environment.bindings();
+                if let Some(value_id) = bindings.get(symbol_id) {
+                    return Some(value_id);
+                }
+                bindings.parent.clone()?
+            };
+            environment = parent;
+        }
+    }
+
+    /// The value in `slot`, without looking up any symbol.
+    pub fn slot(&self, slot: Slot) -> Option<Uuid> {
+        if slot.depth == 0 {
+            return self.bindings().slots.get(slot.index).copied().flatten();
+        }
+
+        let mut environment = self.bindings().parent.clone()?;
+        for _ in 1..slot.depth {
+            let parent = environment.bindings().parent.clone()?;
+            environment = parent;
+        }
+
+        let value_id = environment.bindings().slots.get(slot.index).copied().flatten();
+        value_id
+    }
+
+    pub fn declare(&self, symbol_id: SymbolId, value_id: Uuid) {
+        let mut bindings = self.bindings();
+        match bindings.layout.slot_of(symbol_id) {
+            Some(index) => bindings.slots[index] = Some(value_id),
+            None => { bindings.unslotted.insert(symbol_id, value_id); }
+        }
+    }
+
+    /// Rebinds `symbol_id` in the environment that has it. Returns false if none of them does.
+    pub fn assign(&self, symbol_id: SymbolId, value_id: Uuid) -> bool {
+        let mut environment = self.clone();
+        loop {
+            let parent = {
+                let mut bindings = environment.bindings();
+                if let Some(bound) = bindings.bound(symbol_id) {
+                    *bound = value_id;
+                    return true;
+                }
+                match bindings.parent.clone() {
+                    Some(parent) => parent,
+                    None => return false
+                }
+            };
+            environment = parent;
+        }
+    }
+
+    // A copy of this environment and the ones enclosing it, with the same bindings. `copies` has
+    // the copies made so far, keyed by what they're copies of, so environments shared before
+    // copying are still shared by their copies.
+    pub(crate) fn copy(&self, copies: &mut HashMap<*const (), Environment>) -> Environment {
+        let key = Arc::as_ptr(&self.0) as *const ();
+        if let Some(copy) = copies.get(&key) {
+            return copy.clone();
+        }
+
+        let (layout, slots, unslotted, parent) = {
+            let bindings = self.bindings();
+            (bindings.layout.clone(), bindings.slots.clone(), bindings.unslotted.clone(), bindings.parent.clone())
+        };
+        let parent = parent.map(|parent| parent.copy(copies));
+
+        let copy = Environment(Arc::new(Mutex::new(Bindings { layout, slots, unslotted, parent })));
+        copies.insert(key, copy.clone());
+        copy
+    }
+
+    // Adds the values bound here and in the enclosing environments to `values`, for the garbage
+    // collector. Environments already in `seen` are skipped.
+    pub(crate) fn mark(&self, seen: &mut HashSet<*const ()>, values: &mut Vec<Uuid>) {
+        let mut environment = self.clone();
+        while seen.insert(Arc::as_ptr(&environment.0) as *const ()) {
+            let parent = {
+                let bindings = environment.bindings();
+                values.extend(bindings.slots.iter().flatten());
+                values.extend(bindings.unslotted.values());
+                bindings.parent.clone()
+            };
+
+            match parent {
+                Some(parent) => environment = parent,
+                None => return
+            }
+        }
+    }
+}
+
+/// An odo function, with the environment it was created in.
+#[derive(Clone)]
+pub struct Closure {
+    pub definition: Arc<FunctionDefinition>,
+    // None for functions created at the top level, which only see globals
+    pub(crate) captured: Option<Environment>,
+}
+
+impl Closure {
+    pub fn new(definition: Arc<FunctionDefinition>, captured: Option<Environment>) -> Closure {
+        Closure { definition, captured }
+    }
+
+    // The same function, capturing copies of its environments (see `Environment::copy`)
+    pub(crate) fn copy(&self, copies: &mut HashMap<*const (), Environment>) -> Closure {
+        let captured = self.captured.as_ref().map(|captured| captured.copy(copies));
+        Closure { definition: self.definition.clone(), captured }
+    }
+
+    // A new environment for a call to the function
+    pub(crate) fn call_environment(&self) -> Environment {
+        Environment::new(self.captured.clone(), self.definition.layout.clone())
+    }
+}
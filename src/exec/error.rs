@@ -0,0 +1,29 @@
+// Errors from running code that hosts may want to tell apart from the program's own
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    // Something in odo or a native panicked. The interpreter recovered, and can keep running code.
+    Internal(String), // What the panic said
+    // The host cancelled it with a `CancellationToken`
+    Cancelled,
+    // Calls went deeper than the execution policy allows, usually from recursion that never stops
+    CallDepth(usize), // The limit
+}
+
+impl RuntimeError {
+    fn description(&self) -> String {
+        match self {
+            RuntimeError::Internal(message) => format!("Internal error: {}. This is a bug in odo or a native function, not in the program", message),
+            RuntimeError::Cancelled => "Cancelled".to_string(),
+            RuntimeError::CallDepth(limit) => format!("Too many calls inside each other: the execution policy allows {}", limit),
+        }
+    }
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl std::error::Error for RuntimeError {}
@@ -0,0 +1,71 @@
+use super::{interpreter::Interpreter, value::Value};
+use crate::base::semantic_analyzer::SemanticAst;
+
+// The interpreter lexes, parses and analyzes code, and then hands each top-level statement to
+// an executor to run. Executors can run them some other way, like compiling them first or
+// recording what they do, and leave the rest to the tree-walker through the context.
+
+/// Runs analyzed top-level statements for an interpreter.
+pub trait Executor<'a>: Send {
+    /// Runs `program` and returns the value it ends with, if any.
+    fn run(&mut self, program: &SemanticAst, ctx: &mut ExecCtx<'_, 'a>) -> anyhow::Result<Option<Value<'a>>>;
+}
+
+/// What an executor can use of the interpreter it runs for.
+pub struct ExecCtx<'i, 'a> {
+    interpreter: &'i mut Interpreter<'a>,
+}
+
+impl<'i, 'a> ExecCtx<'i, 'a> {
+    pub(crate) fn new(interpreter: &'i mut Interpreter<'a>) -> ExecCtx<'i, 'a> {
+        ExecCtx { interpreter }
+    }
+
+    /// Runs `node` by walking it, the way the interpreter does without an executor.
+    pub fn walk(&mut self, node: &SemanticAst) -> anyhow::Result<Option<Value<'a>>> {
+        self.interpreter.interpret(node).map(|result| result.value)
+    }
+
+    /// The interpreter running the program, with its values, symbols and output.
+    pub fn interpreter(&mut self) -> &mut Interpreter<'a> {
+        self.interpreter
+    }
+}
+
+/// The default executor: walks the analyzed tree.
+pub struct TreeWalker;
+
+impl<'a> Executor<'a> for TreeWalker {
+    fn run(&mut self, program: &SemanticAst, ctx: &mut ExecCtx<'_, 'a>) -> anyhow::Result<Option<Value<'a>>> {
+        ctx.walk(program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+
+    use super::{ExecCtx, Executor, TreeWalker};
+    use crate::{base::semantic_analyzer::SemanticAst, exec::{interpreter::Interpreter, value::Value}};
+
+    struct Counting(Arc<AtomicUsize>);
+
+    impl<'a> Executor<'a> for Counting {
+        fn run(&mut self, program: &SemanticAst, ctx: &mut ExecCtx<'_, 'a>) -> anyhow::Result<Option<Value<'a>>> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            TreeWalker.run(program, ctx)
+        }
+    }
+
+    #[test]
+    fn test_custom_executor() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_executor(Counting(count.clone()));
+
+        let result = interpreter.eval("var a = 2\nfunc double(x: int) -> int { return x * 2 }\ndouble(a)".to_string()).unwrap();
+        assert_eq!(result.as_int().unwrap(), 4);
+        // Only top-level statements go through it
+        assert_eq!(count.load(Ordering::Relaxed), 3);
+    }
+}
@@ -0,0 +1,38 @@
+use std::io::BufRead;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+// Where `readLine` and `readInt` read from: the process's stdin, unless the host
+// gave the interpreter a reader of its own with `Interpreter::set_input`.
+
+// Shared between the interpreter and every native that reads
+#[derive(Clone, Default)]
+pub(crate) struct Input {
+    // None for stdin. It isn't wrapped in a reader of its own, so nothing read by odo
+    // gets buffered away from a host that also reads stdin.
+    reader: Arc<Mutex<Option<Box<dyn BufRead + Send>>>>
+}
+
+impl Input {
+    fn reader(&self) -> MutexGuard<'_, Option<Box<dyn BufRead + Send>>> {
+        self.reader.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub(crate) fn set(&self, reader: Box<dyn BufRead + Send>) {
+        *self.reader() = Some(reader);
+    }
+
+    // The next line, without the line break. None at the end of input.
+    pub(crate) fn read_line(&self) -> anyhow::Result<Option<String>> {
+        let mut line = String::new();
+        let read = match self.reader().as_mut() {
+            Some(reader) => reader.read_line(&mut line)?,
+            None => std::io::stdin().read_line(&mut line)?
+        };
+
+        if read == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(line.trim_end_matches(['\n', '\r']).to_string()))
+    }
+}
@@ -0,0 +1,420 @@
+use std::{collections::HashMap, fmt::Write};
+
+use uuid::Uuid;
+
+use crate::base::{annotations::Annotation, lexer::{Token, TokenType}, parser::{Ast, Node, Pattern}};
+use crate::base::semantic_analyzer::{FunctionDefinition, SemanticAst, Symbol, SymbolVariant};
+use crate::exec::compiled::CompiledProgram;
+
+// A readable listing of an analyzed program: its scopes, and the tree the interpreter runs,
+// with names in place of symbol ids. Code that hasn't been analyzed can be listed too, as tokens
+// or as the tree the parser makes.
+
+pub fn inspect(program: &CompiledProgram) -> String {
+    let inspector = Inspector::new(program);
+    let mut output = String::new();
+
+    output.push_str("Scopes\n");
+    inspector.scope(&mut output, program.scope_id, 1);
+
+    output.push_str("\nProgram\n");
+    for statement in program.statements.iter() {
+        inspector.node(&mut output, statement, 1);
+    }
+
+    output
+}
+
+/// One token per line: where it starts, its type and its text.
+pub fn tokens(tokens: &[Token]) -> String {
+    let mut output = String::new();
+    for token in tokens {
+        let position = format!("{}:{}", token.line, token.column);
+        let _ = writeln!(output, "{:<8}{:<16}{:?}", position, format!("{:?}", token.token_type), token.value);
+    }
+
+    output
+}
+
+/// The statements as the parser left them, before names and types are resolved.
+pub fn syntax_tree(statements: &[Node]) -> String {
+    let mut output = String::new();
+    for statement in statements {
+        ast(&mut output, statement, 0);
+    }
+
+    output
+}
+
+fn text(token: &Token) -> String {
+    match token.token_type {
+        TokenType::Text => format!("{:?}", token.value),
+        _ => token.value.clone()
+    }
+}
+
+fn names(tokens: &[Token]) -> String {
+    tokens.iter().map(|token| token.value.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+fn parameters(parameters: &[(Token, Token)], return_type: &Option<Token>) -> String {
+    let parameters: Vec<_> = parameters.iter()
+        .map(|(name, type_name)| format!("{}: {}", name.value, type_name.value))
+        .collect();
+
+    match return_type {
+        Some(return_type) => format!("({}) -> {}", parameters.join(", "), return_type.value),
+        None => format!("({})", parameters.join(", "))
+    }
+}
+
+fn annotation(annotation: &Annotation) -> String {
+    match annotation.arguments.as_slice() {
+        [] => format!("@{}", annotation.name.value),
+        arguments => format!("@{}({})", annotation.name.value, arguments.iter().map(text).collect::<Vec<_>>().join(", "))
+    }
+}
+
+fn ast(output: &mut String, node: &Ast, depth: usize) {
+    let indent = "  ".repeat(depth);
+    let line = |output: &mut String, text: String| { let _ = writeln!(output, "{}{}", indent, text); };
+    let children = |output: &mut String, nodes: &mut dyn Iterator<Item = &Node>| {
+        for node in nodes {
+            ast(output, node, depth + 1);
+        }
+    };
+
+    match node {
+        Ast::Block(statements) => {
+            line(output, "Block".to_string());
+            children(output, &mut statements.iter());
+        },
+        Ast::Number(token) => line(output, format!("Number {}", token.value)),
+        Ast::Truth(token) => line(output, format!("Truth {}", token.value)),
+        Ast::Text(token) => line(output, format!("Text {}", text(token))),
+        Ast::Variable(token) => line(output, format!("Variable {}", token.value)),
+        Ast::MemberAccess(value, member) => {
+            line(output, format!("MemberAccess .{}", member.value));
+            children(output, &mut std::iter::once(value));
+        },
+        Ast::Assignment(target, value) => {
+            line(output, "Assignment".to_string());
+            children(output, &mut [target, value].into_iter());
+        },
+        Ast::MultipleAssignment(targets, values) => {
+            line(output, "MultipleAssignment".to_string());
+            children(output, &mut targets.iter().chain(values));
+        },
+        Ast::Declaration(name, value) => {
+            line(output, format!("Declaration {}", name.value));
+            children(output, &mut std::iter::once(value));
+        },
+        Ast::TypedDeclaration(name, type_name, value) => {
+            line(output, format!("TypedDeclaration {}: {}", name.value, type_name.value));
+            children(output, &mut std::iter::once(value));
+        },
+        Ast::PatternDeclaration(target, value) => {
+            line(output, "PatternDeclaration".to_string());
+            pattern(output, target, depth + 1);
+            children(output, &mut std::iter::once(value));
+        },
+        Ast::MultipleDeclaration(pairs) => {
+            let names: Vec<_> = pairs.iter().map(|(name, _)| name.clone()).collect();
+            line(output, format!("MultipleDeclaration {}", self::names(&names)));
+            children(output, &mut pairs.iter().map(|(_, value)| value));
+        },
+        Ast::BinaryOperation(left, operator, right) => {
+            line(output, format!("BinaryOperation {}", operator.value));
+            children(output, &mut [left, right].into_iter());
+        },
+        Ast::Comparison(first, rest) => {
+            let operators: Vec<_> = rest.iter().map(|(operator, _)| operator.clone()).collect();
+            line(output, format!("Comparison {}", names(&operators)));
+            children(output, &mut std::iter::once(first).chain(rest.iter().map(|(_, operand)| operand)));
+        },
+        Ast::Negation(operator, value) => {
+            line(output, format!("Negation {}", operator.value));
+            children(output, &mut std::iter::once(value));
+        },
+        Ast::FunctionCall(callee, arguments) => {
+            line(output, "FunctionCall".to_string());
+            children(output, &mut std::iter::once(callee).chain(arguments));
+        },
+        Ast::List(items) => {
+            line(output, "List".to_string());
+            children(output, &mut items.iter());
+        },
+        Ast::Spread(value) => {
+            line(output, "Spread".to_string());
+            children(output, &mut std::iter::once(value));
+        },
+        Ast::Map(entries) => {
+            line(output, "Map".to_string());
+            children(output, &mut entries.iter().flat_map(|(key, value)| [key, value]));
+        },
+        Ast::FunctionDeclaration(name, parameters, return_type, body) => {
+            line(output, format!("FunctionDeclaration {}{}", name.value, self::parameters(parameters, return_type)));
+            children(output, &mut std::iter::once(body));
+        },
+        Ast::Function(parameters, return_type, body) => {
+            line(output, format!("Function {}", self::parameters(parameters, return_type)));
+            children(output, &mut std::iter::once(body));
+        },
+        Ast::Return(value) => {
+            line(output, "Return".to_string());
+            children(output, &mut value.iter());
+        },
+        Ast::Yield(value) => {
+            line(output, "Yield".to_string());
+            children(output, &mut std::iter::once(value));
+        },
+        Ast::If(condition, body) => {
+            line(output, "If".to_string());
+            children(output, &mut [condition, body].into_iter());
+        },
+        Ast::Match(_, value, arms) => {
+            line(output, "Match".to_string());
+            children(output, &mut std::iter::once(value));
+
+            let arm_indent = "  ".repeat(depth + 1);
+            for (arm_pattern, guard, body) in arms {
+                let _ = writeln!(output, "{}Arm", arm_indent);
+                pattern(output, arm_pattern, depth + 2);
+                if let Some(guard) = guard {
+                    let _ = writeln!(output, "{}  Guard", arm_indent);
+                    ast(output, guard, depth + 3);
+                }
+                ast(output, body, depth + 2);
+            }
+        },
+        Ast::Defer(_, body) => {
+            line(output, "Defer".to_string());
+            children(output, &mut std::iter::once(body));
+        },
+        Ast::For(_, name, iterable, body) => {
+            line(output, format!("For {}", name.value));
+            children(output, &mut [iterable, body].into_iter());
+        },
+        Ast::TypeDeclaration(name, contracts, fields, methods) => {
+            match contracts.as_slice() {
+                [] => line(output, format!("TypeDeclaration {}", name.value)),
+                contracts => line(output, format!("TypeDeclaration {}: {}", name.value, names(contracts))),
+            }
+            for (field, type_name) in fields {
+                let _ = writeln!(output, "{}  Field {}: {}", indent, field.value, type_name.value);
+            }
+            children(output, &mut methods.iter());
+        },
+        Ast::ContractDeclaration(name, methods) => {
+            line(output, format!("ContractDeclaration {}", name.value));
+            for (method, (parameters, return_type)) in methods {
+                let _ = writeln!(output, "{}  Method {}{}", indent, method.value, self::parameters(parameters, return_type));
+            }
+        },
+        Ast::Extension(name, methods) => {
+            line(output, format!("Extension {}", name.value));
+            children(output, &mut methods.iter());
+        },
+        Ast::Allow(lints, statement) => {
+            line(output, format!("Allow {}", names(lints)));
+            children(output, &mut std::iter::once(statement));
+        },
+        Ast::Annotated(annotations, declaration) => {
+            line(output, format!("Annotated {}", annotations.iter().map(annotation).collect::<Vec<_>>().join(" ")));
+            children(output, &mut std::iter::once(declaration));
+        },
+        Ast::Import(_, name) => line(output, format!("Import {}", name.value)),
+        Ast::DebugPrint(value) => {
+            line(output, "DebugPrint".to_string());
+            children(output, &mut std::iter::once(value));
+        },
+    }
+}
+
+fn pattern(output: &mut String, pattern: &Pattern, depth: usize) {
+    let indent = "  ".repeat(depth);
+
+    match pattern {
+        Pattern::Wildcard(_) => { let _ = writeln!(output, "{}Wildcard", indent); },
+        Pattern::Binding(name) => { let _ = writeln!(output, "{}Binding {}", indent, name.value); },
+        Pattern::Literal(value) => {
+            let _ = writeln!(output, "{}Literal", indent);
+            ast(output, value, depth + 1);
+        },
+        Pattern::Record(type_name, fields) => {
+            let _ = writeln!(output, "{}Record {}", indent, type_name.value);
+            for (field, field_pattern) in fields {
+                let _ = writeln!(output, "{}  Field {}", indent, field.value);
+                self::pattern(output, field_pattern, depth + 2);
+            }
+        },
+        Pattern::List(_, items, rest) => {
+            let _ = writeln!(output, "{}ListPattern", indent);
+            for item in items {
+                self::pattern(output, item, depth + 1);
+            }
+            if let Some(rest) = rest {
+                let _ = writeln!(output, "{}  Rest", indent);
+                self::pattern(output, rest, depth + 2);
+            }
+        },
+    }
+}
+
+struct Inspector<'p> {
+    program: &'p CompiledProgram,
+    // Symbols and scopes, by id
+    names: HashMap<Uuid, String>,
+}
+
+impl<'p> Inspector<'p> {
+    fn new(program: &'p CompiledProgram) -> Inspector<'p> {
+        let mut names = HashMap::new();
+
+        for (name, symbol) in &program.externals {
+            names.insert(symbol.symbol_id, name.clone());
+        }
+
+        let own_symbols = program.scopes.iter()
+            .flat_map(|scope| scope.symbols())
+            .chain(&program.declarations);
+        for symbol in own_symbols {
+            names.insert(symbol.symbol_id, symbol.name().to_string());
+        }
+
+        for scope in &program.scopes {
+            names.insert(scope.id(), scope.name().to_string());
+        }
+        names.insert(program.scope_id, "program".to_string());
+
+        Inspector { program, names }
+    }
+
+    fn name(&self, id: Uuid) -> String {
+        self.names.get(&id).cloned().unwrap_or_else(|| format!("<{}>", id))
+    }
+
+    fn symbol(&self, symbol: &Symbol) -> String {
+        match symbol.variant {
+            SymbolVariant::Namespace(_) => format!("{} (namespace)", symbol.name()),
+            SymbolVariant::Primitive | SymbolVariant::FunctionType(_) | SymbolVariant::Parametric(_) | SymbolVariant::TypeParameter |
+            SymbolVariant::Record(_) => format!("{} (type)", symbol.name()),
+            SymbolVariant::Contract(_) => format!("{} (contract)", symbol.name()),
+            _ => match symbol.value_type_id() {
+                Ok(type_id) => format!("{}: {}", symbol.name(), self.name(type_id)),
+                Err(_) => symbol.name().to_string()
+            }
+        }
+    }
+
+    fn scope(&self, output: &mut String, id: Uuid, depth: usize) {
+        let indent = "  ".repeat(depth);
+        let _ = writeln!(output, "{}{}", indent, self.name(id));
+
+        let symbols = match self.program.scopes.iter().find(|scope| scope.id() == id) {
+            Some(scope) => scope.symbols().collect::<Vec<_>>(),
+            None => self.program.declarations.iter().collect()
+        };
+
+        let mut lines: Vec<_> = symbols.into_iter().map(|symbol| self.symbol(symbol)).collect();
+        lines.sort();
+        for line in lines {
+            let _ = writeln!(output, "{}  - {}", indent, line);
+        }
+
+        for child in self.program.scopes.iter().filter(|scope| scope.parent() == Some(id)) {
+            self.scope(output, child.id(), depth + 1);
+        }
+    }
+
+    fn signature(&self, definition: &FunctionDefinition) -> String {
+        let parameters: Vec<_> = definition.parameters.iter()
+            .zip(&definition.parameter_types)
+            .map(|(parameter, type_id)| format!("{}: {}", self.name(*parameter), self.name(*type_id)))
+            .collect();
+
+        match definition.return_id {
+            Some(return_id) => format!("({}) -> {}", parameters.join(", "), self.name(return_id)),
+            None => format!("({})", parameters.join(", "))
+        }
+    }
+
+    fn node(&self, output: &mut String, node: &SemanticAst, depth: usize) {
+        let indent = "  ".repeat(depth);
+        let kind = node.kind_name();
+
+        let line = match node {
+            SemanticAst::Block(_, scope_id) => format!("{} ({})", kind, self.name(*scope_id)),
+            SemanticAst::Number(token) | SemanticAst::Truth(token) => format!("{} {}", kind, token.value),
+            SemanticAst::Text(token) => format!("{} {:?}", kind, token.value),
+            SemanticAst::Frame(layout, _) => format!("{} ({} slots)", kind, layout.len()),
+            SemanticAst::Variable(id, _) | SemanticAst::Declaration(id, _, _) | SemanticAst::Assignment(id, _) => format!("{} {}", kind, self.name(*id)),
+            SemanticAst::MultipleDeclaration(pairs) | SemanticAst::MultipleAssignment(pairs) => {
+                let names: Vec<_> = pairs.iter().map(|(id, _)| self.name(*id)).collect();
+                format!("{} {}", kind, names.join(", "))
+            },
+            SemanticAst::BinaryOperation(_, _, _, operator) => format!("{} {}", kind, operator.value),
+            SemanticAst::FunctionDeclaration(id, definition) => format!("{} {}{}", kind, self.name(*id), self.signature(definition)),
+            SemanticAst::Function(definition) => format!("{} {}", kind, self.signature(definition)),
+            SemanticAst::Construct(_, name, _) | SemanticAst::MethodCall(_, name, _, _) => format!("{} {}", kind, name),
+            _ => kind.to_string()
+        };
+
+        let _ = writeln!(output, "{}{}", indent, line);
+        for child in node.children() {
+            self.node(output, child, depth + 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::exec::interpreter::Interpreter;
+
+    #[test]
+    fn test_inspect() {
+        let mut interpreter = Interpreter::new();
+        let program = interpreter.compile("var a = 1\nfunc f(x: int) -> int {\n    return x + a\n}\n: f(2)".to_string()).unwrap();
+        let listing = super::inspect(&program);
+
+        assert!(listing.contains("  program\n    - a: int\n    - f: <int:int>\n    function f\n      - x: int\n"), "{}", listing);
+        assert!(listing.contains("  FunctionDeclaration f(x: int) -> int\n    Return\n      BinaryOperation +\n        Variable x\n        Variable a\n"), "{}", listing);
+        assert!(listing.contains("  DebugPrint\n    FunctionCall\n      Variable f\n      Number 2\n"), "{}", listing);
+    }
+
+    #[test]
+    fn test_syntax_tree() {
+        let interpreter = Interpreter::new();
+
+        let statements = interpreter.parse("@cfg(debug)\nvar x: int = -f(1, \"a\")\nmatch x {\n    Point { x: 0, y } if y > 1 : y\n    _ : 0\n}").unwrap();
+        assert_eq!(super::syntax_tree(&statements), "\
+Annotated @cfg(debug)
+  TypedDeclaration x: int
+    Negation -
+      FunctionCall
+        Variable f
+        Number 1
+        Text \"a\"
+Match
+  Variable x
+  Arm
+    Record Point
+      Field x
+        Literal
+          Number 0
+      Field y
+        Binding y
+    Guard
+      BinaryOperation >
+        Variable y
+        Number 1
+    Variable y
+  Arm
+    Wildcard
+    Number 0
+");
+
+        let tokens = interpreter.tokenize("var s = \"hi\"").unwrap();
+        assert_eq!(super::tokens(&tokens), "1:0     Var             \"var\"\n1:4     Name            \"s\"\n1:6     Assign          \"=\"\n1:8     Text            \"hi\"\n");
+    }
+}
@@ -1,130 +1,885 @@
 use uuid::Uuid;
-use std::collections::HashMap;
-use super::value::{ValueTable, Value, PrimitiveValue, ValueVariant, FunctionValue};
+use std::{any::{Any, TypeId}, collections::{BTreeMap, HashMap, HashSet, VecDeque}, path::PathBuf, sync::{Arc, Mutex}, time::Instant};
+use super::value::format::{self, Limits};
+use super::value::numbers;
+use super::value::key::Key;
+use super::value::{ValueTable, Value, PrimitiveValue, ValueVariant, FunctionValue, ExtractionError, Record, Sequence};
+use super::policy::{ExecutionPolicy, IntegerOverflow};
+use super::replay::{InputLog, RecordedInput};
+use super::error::RuntimeError;
+use super::stats::Stats;
+use super::input::Input;
+use super::output::Output;
+use super::compiled::{CompiledProgram, Program};
+use super::cancel::CancellationToken;
+use super::environment::{Closure, Environment};
+use super::executor::{ExecCtx, Executor};
+use super::observer::{Observer, StatementRun};
 
-use crate::base::{semantic_analyzer::{SemanticAnalyzer, SemanticAst}, lexer::Lexer, parser::Parser};
+use crate::base::{stack, call_site::CallSite, diagnostics::CompileError, lexer::Token, modules::ModuleLoader, semantic_analyzer::{SemanticAnalyzer, SemanticAst, TO_TEXT, TableId, SemanticNode, SemanticPattern, MatchArm, FunctionDefinition, BinaryOperator, TypeInfo, SymbolVariant, ANY_TYPE, LIST_TYPE, MAP_TYPE}, lexer::{Lexer, DEFAULT_TAB_WIDTH}, parser::{Node, Parser}, scope_tree::ScopeTree, xref::Span};
+use crate::native::stdlib::{self, Std};
+
+pub mod closures;
 
 
 pub struct Interpreter<'a> {
     pub value_table: ValueTable<'a>,
     pub semantic_analyzer: SemanticAnalyzer,
+    pub(crate) policy: ExecutionPolicy,
+    symbol_to_value: HashMap<Uuid, Uuid>,
+    // Environments of the running function calls and top-level blocks, innermost last
+    frames: Vec<Environment>,
+    // Scopes of the running code, innermost last. The analyzer's current scope is only
+    // about analysis, so running code never moves it
+    running_scopes: Vec<TableId>,
+    // Set by `return` and `yield` to stop executing the current function
+    signal: Option<Signal<'a>>,
+    // While resuming a coroutine, the statement indices that lead to the `yield` it stopped at
+    resume_path: Option<VecDeque<usize>>,
+    // What `defer` left to run when each running block, function or evaluation ends, innermost last
+    deferred: Vec<Vec<Arc<SemanticAst>>>,
+    // Statements left before stopping, when the policy sets a limit
+    remaining_fuel: Option<u64>,
+    // Checked with the fuel, so hosts can stop running code
+    cancellation: CancellationToken,
+    // Handlers registered from odo with `on`, by event name
+    pub(crate) event_handlers: EventHandlers,
+    // Methods of the types declared so far, by symbol. Calls find them through the receiver's type.
+    methods: HashMap<Uuid, Closure>,
+    // Where natives get the clock, randomness and stdin from, so runs can be recorded and replayed
+    pub(crate) inputs: InputLog,
+    // Where the reading natives get lines from
+    pub(crate) input: Input,
+    // Where `:` and the printing natives write
+    pub(crate) output: Output,
+    // Columns a tab takes up in reported positions
+    tab_width: usize,
+    // Counters for `stats`. Allocations are counted by the value table, from this many on.
+    stats: Stats,
+    allocated_before_stats: u64,
+    // Compiled regular expressions, shared by the pattern natives
+    #[cfg(feature = "regex")]
+    pub(crate) pattern_cache: crate::native::patterns::PatternCache,
+    // What runs top-level statements, when it isn't the tree-walker
+    executor: Option<Box<dyn Executor<'a> + 'a>>,
+    // What's told about each statement that runs
+    observer: Option<Box<dyn Observer + 'a>>,
+    // Blocks and calls the running statement is inside of
+    statement_depth: usize,
+    // How much of a value `:` shows
+    format_limits: Limits,
+    // How the host's objects read when they're shown, by their type
+    opaque_texts: HashMap<TypeId, OpaqueText<'a>>,
+    // The session before each of the last lines `eval_atomic` ran, most recent last
+    undo_history: VecDeque<Snapshot>,
+    // Where `reset` goes back to. Right after the standard library, until the host moves it.
+    checkpoint: Option<Snapshot>,
+}
+
+
+pub(crate) type EventHandlers = Arc<Mutex<HashMap<String, Vec<Closure>>>>;
+
+type OpaqueText<'a> = Arc<dyn Fn(&(dyn Any + Send + Sync)) -> String + Sync + 'a>;
+
+// What a line can change in a session, to go back to. The garbage collector keeps the values
+// the bindings of every snapshot point to, so they're all still there.
+#[derive(Clone)]
+struct Snapshot {
+    analyzer: SemanticAnalyzer,
     symbol_to_value: HashMap<Uuid, Uuid>,
+    methods: HashMap<Uuid, Closure>,
+    handlers: HashMap<String, Vec<Closure>>,
+}
+
+// Lines `undo` can take back
+const UNDO_LIMIT: usize = 100;
+
+// The values `collect_garbage` keeps. Values own what's inside them, but functions and seqs
+// reach more values through the environments they hold.
+#[derive(Default)]
+struct Marker {
+    reachable: HashSet<Uuid>,
+    // Found, but not looked into yet
+    pending: Vec<Uuid>,
+    environments: HashSet<*const ()>,
+}
+
+impl Marker {
+    fn value(&mut self, value: &Value) {
+        match &value.content {
+            ValueVariant::Function(FunctionValue::User(closure)) => self.closures([closure]),
+            ValueVariant::List(items) => items.iter().for_each(|item| self.value(item)),
+            ValueVariant::Map(entries) => entries.values().for_each(|item| self.value(item)),
+            ValueVariant::Record(record) => record.fields.iter().for_each(|(_, field)| self.value(field)),
+            ValueVariant::Seq(Sequence(coroutine)) => {
+                let coroutine = coroutine.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                self.environment(&coroutine.frame);
+            },
+            ValueVariant::Nothing | ValueVariant::Primitive(_) | ValueVariant::Function(FunctionValue::Native(_)) | ValueVariant::Opaque(_) => {}
+        }
+    }
+
+    fn closures<'c>(&mut self, closures: impl IntoIterator<Item = &'c Closure>) {
+        for closure in closures {
+            if let Some(captured) = &closure.captured {
+                self.environment(captured);
+            }
+        }
+    }
+
+    // Closures can be bound in the environment they captured, so each is only looked into once
+    fn environment(&mut self, environment: &Environment) {
+        environment.mark(&mut self.environments, &mut self.pending);
+    }
+}
+
+enum Signal<'a> {
+    Return(Option<Value<'a>>),
+    Yield(Value<'a>, Vec<usize>) // Value, statement indices that lead to the yield
 }
 
 impl<'a> Interpreter<'a> {
     pub fn new<'new>() -> Interpreter<'new> {
-        Interpreter {
+        Interpreter::with_policy(ExecutionPolicy::default())
+    }
+
+    pub fn with_policy<'new>(policy: ExecutionPolicy) -> Interpreter<'new> {
+        Interpreter::with_stdlib(policy, Std::ALL)
+    }
+
+    /// An interpreter with only some parts of the standard library. With none, scripts can
+    /// only use the language itself and what the host binds.
+    pub fn with_stdlib<'new>(policy: ExecutionPolicy, modules: &[Std]) -> Interpreter<'new> {
+        let mut interpreter = Interpreter {
             value_table: ValueTable::new(),
             semantic_analyzer: SemanticAnalyzer::new(),
-            symbol_to_value: HashMap::new()
+            policy,
+            symbol_to_value: HashMap::new(),
+            frames: Vec::new(),
+            running_scopes: Vec::new(),
+            signal: None,
+            resume_path: None,
+            deferred: Vec::new(),
+            remaining_fuel: None,
+            cancellation: CancellationToken::new(),
+            event_handlers: EventHandlers::default(),
+            methods: HashMap::new(),
+            inputs: InputLog::default(),
+            input: Input::default(),
+            output: Output::default(),
+            tab_width: DEFAULT_TAB_WIDTH,
+            stats: Stats::default(),
+            allocated_before_stats: 0,
+            #[cfg(feature = "regex")]
+            pattern_cache: Default::default(),
+            executor: None,
+            observer: None,
+            statement_depth: 0,
+            format_limits: Limits::default(),
+            opaque_texts: HashMap::new(),
+            undo_history: VecDeque::new(),
+            checkpoint: None
+        };
+
+        stdlib::register_modules(&mut interpreter, modules).expect("The standard library should always bind");
+        // Parts of the standard library are written in odo, and evaluating them isn't the host's doing
+        interpreter.reset_stats();
+        interpreter.checkpoint();
+
+        interpreter
+    }
+
+    pub fn policy(&self) -> &ExecutionPolicy {
+        &self.policy
+    }
+
+    pub fn set_tab_width(&mut self, width: usize) {
+        self.tab_width = width;
+    }
+
+    /// Turns the analyzer's strict mode on or off, for everything evaluated from now on.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.semantic_analyzer.set_strict(strict);
+    }
+
+    /// Lets code evaluated from now on import modules, from wherever `loader` finds them.
+    pub fn set_module_loader(&mut self, loader: impl ModuleLoader + 'static) {
+        self.semantic_analyzer.set_module_loader(loader);
+    }
+
+    /// Sets the file the code evaluated from now on comes from, so modules importing it back are
+    /// reported as a cycle.
+    pub fn set_main_module(&mut self, path: impl Into<PathBuf>) {
+        self.semantic_analyzer.set_main_module(path);
+    }
+
+    /// Runs every top-level statement from now on with `executor`, instead of walking it.
+    pub fn set_executor(&mut self, executor: impl Executor<'a> + 'a) {
+        self.executor = Some(Box::new(executor));
+    }
+
+    /// Tells `observer` about every statement that runs from now on, until it's removed.
+    pub fn set_observer(&mut self, observer: impl Observer + 'a) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    pub fn remove_observer(&mut self) {
+        self.observer = None;
+    }
+
+    // Only called when there's an observer, since working out what to tell it isn't free
+    fn observe(&mut self, statement: &SemanticAst, value: Option<&Value<'a>>) {
+        let run = StatementRun {
+            statement,
+            description: self.describe(statement),
+            depth: self.statement_depth,
+            value: value.map(|value| format::format(value, self.format_limits)),
+            fuel: self.remaining_fuel,
+        };
+
+        if let Some(observer) = self.observer.as_mut() {
+            observer.statement(&run);
+        }
+    }
+
+    // The kind of statement, and the name or operator it's about
+    fn describe(&self, statement: &SemanticAst) -> String {
+        let name = |id: &Uuid| self.semantic_analyzer.scopes()
+            .find_map(|scope| scope.lookup_id(*id))
+            .map(|symbol| symbol.name().to_string());
+
+        let detail = match statement {
+            // Top-level code with names of its own reads as what it is
+            SemanticAst::Frame(_, node) => return self.describe(node),
+            SemanticAst::Variable(id, _) | SemanticAst::Declaration(id, ..) | SemanticAst::Assignment(id, _) |
+            SemanticAst::FunctionDeclaration(id, _) | SemanticAst::For(id, ..) => name(id),
+            SemanticAst::FunctionCall(callee, _) | SemanticAst::SpreadCall(callee, ..) => match callee.as_ref() {
+                SemanticAst::Variable(id, _) => name(id),
+                _ => None
+            },
+            SemanticAst::MultipleDeclaration(pairs) | SemanticAst::MultipleAssignment(pairs) => {
+                Some(pairs.iter().filter_map(|(id, _)| name(id)).collect::<Vec<_>>().join(", "))
+            },
+            SemanticAst::BinaryOperation(_, _, _, operator) | SemanticAst::Negation(_, operator) => Some(operator.value.clone()),
+            SemanticAst::Construct(_, name, _) | SemanticAst::MethodCall(_, name, ..) => Some(name.clone()),
+            _ => None
+        };
+
+        match detail {
+            Some(detail) if !detail.is_empty() => format!("{} {}", statement.kind_name(), detail),
+            _ => statement.kind_name().to_string()
+        }
+    }
+
+    /// A token that stops the code this interpreter runs, from any thread.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    /// How much of big values `:` and `format` show.
+    pub fn set_format_limits(&mut self, limits: Limits) {
+        self.format_limits = limits;
+    }
+
+    /// `value` as `:` prints it, cut short past the format limits. Values of types with a
+    /// `toText(self) -> string` method read as what it returns, unless it fails.
+    pub fn format(&mut self, value: &Value<'a>) -> String {
+        let limits = self.format_limits;
+
+        self.contained(|interpreter| {
+            interpreter.refuel();
+            interpreter.show(value)
+        }).unwrap_or_else(|_| format::format(value, limits))
+    }
+
+    /// How objects the host hands to scripts with `Value::opaque` read when they're shown,
+    /// instead of `<opaque>`.
+    pub fn bind_to_text<T: Any + Send + Sync>(&mut self, to_text: impl Fn(&T) -> String + Sync + 'a) {
+        let to_text = move |object: &(dyn Any + Send + Sync)| object.downcast_ref::<T>().map(&to_text).unwrap_or_default();
+        self.opaque_texts.insert(TypeId::of::<T>(), Arc::new(to_text));
+    }
+
+    // What `:` prints. Fails when a `toText` method does.
+    fn show(&mut self, value: &Value<'a>) -> anyhow::Result<String> {
+        let limits = self.format_limits;
+        let mut error = None;
+
+        let text = format::format_with(value, limits, &mut |value| match self.custom_text(value) {
+            Ok(text) => text,
+            Err(e) => {
+                error.get_or_insert(e);
+                None
+            }
+        });
+
+        error.map_or(Ok(text), Err)
+    }
+
+    // None for values shown by their structure
+    fn custom_text(&mut self, value: &Value<'a>) -> anyhow::Result<Option<String>> {
+        match &value.content {
+            ValueVariant::Opaque(object) => Ok(self.opaque_texts.get(&Any::type_id(&**object)).map(|to_text| to_text(object.as_ref()))),
+            ValueVariant::Record(record) => {
+                let method = self.semantic_analyzer.method_of(record.type_id, TO_TEXT)
+                    .and_then(|method_id| self.methods.get(&method_id))
+                    .cloned();
+                let Some(method) = method else {
+                    return Ok(None);
+                };
+
+                match self.call_function(&method, vec![value.clone()])?.map(|text| text.content) {
+                    Some(ValueVariant::Primitive(PrimitiveValue::Text(text))) => Ok(Some(text.into())),
+                    _ => Err(anyhow::anyhow!("Semantic analysis error. {}.toText should return a string", record.type_name))
+                }
+            },
+            _ => Ok(None)
+        }
+    }
+
+    /// Makes `readLine` and `readInt` read from `reader` instead of stdin.
+    pub fn set_input(&mut self, reader: Box<dyn std::io::BufRead + Send>) {
+        self.input.set(reader);
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            values_allocated: self.value_table.allocated() - self.allocated_before_stats,
+            live_symbols: self.semantic_analyzer.scopes().map(|scope| scope.symbols().count()).sum(),
+            ..self.stats.clone()
         }
     }
 
+    /// Starts counting again from zero, for example between runs being measured.
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+        self.allocated_before_stats = self.value_table.allocated();
+    }
+
+    fn enter_scope(&mut self, scope_id: TableId) {
+        self.running_scopes.push(scope_id);
+        self.stats.peak_scope_depth = self.stats.peak_scope_depth.max(self.running_scopes.len());
+    }
+
+    /// Scopes of the code that's running right now, innermost last. Empty between evaluations.
+    pub fn running_scopes(&self) -> &[TableId] {
+        &self.running_scopes
+    }
+
     pub fn bind_symbol_to_value(&mut self, symbol_id: Uuid, value_id: Uuid) {
         self.symbol_to_value.insert(symbol_id, value_id);
     }
 
-    fn interpret(&mut self, semantic_ast: SemanticAst) -> anyhow::Result<ExecutionResult<'a>> {
+    pub(crate) fn unbind_symbol(&mut self, symbol_id: Uuid) {
+        self.symbol_to_value.remove(&symbol_id);
+    }
+
+    fn value_id_of(&self, symbol_id: Uuid) -> Option<Uuid> {
+        if let Some(value_id) = self.frames.last().and_then(|frame| frame.get(symbol_id)) {
+            return Some(value_id);
+        }
+
+        self.symbol_to_value.get(&symbol_id).copied()
+    }
+
+    // New bindings belong to the running function, if there is one
+    fn declare_value(&mut self, symbol_id: Uuid, value: Value<'a>) {
+        match self.frames.last() {
+            Some(frame) => frame.declare(symbol_id, value.uuid),
+            None => self.bind_symbol_to_value(symbol_id, value.uuid)
+        }
+
+        self.value_table.insert(value);
+    }
+
+    fn assign_value(&mut self, symbol_id: Uuid, value: Value<'a>) {
+        let assigned = self.frames.last().is_some_and(|frame| frame.assign(symbol_id, value.uuid));
+        if !assigned {
+            self.bind_symbol_to_value(symbol_id, value.uuid);
+        }
+
+        self.value_table.insert(value); // Updates if it already existed
+    }
+
+    // Every call from the host starts with the full amount of fuel, and not cancelled
+    fn refuel(&mut self) {
+        self.remaining_fuel = self.policy.fuel;
+        self.cancellation.clear();
+    }
+
+    // Before each statement and each time around a loop
+    fn consume_fuel(&mut self) -> anyhow::Result<()> {
+        if self.cancellation.is_cancelled() {
+            return Err(RuntimeError::Cancelled.into());
+        }
+
+        if let Some(fuel) = self.remaining_fuel.as_mut() {
+            if *fuel == 0 {
+                return Err(anyhow::anyhow!("Out of fuel: the execution policy limits each run to {} statements", self.policy.fuel.unwrap_or_default()));
+            }
+
+            *fuel -= 1;
+        }
+
+        Ok(())
+    }
+
+    // Blocks outside functions can't run again once their statement ran, so their scopes
+    // and the values of their variables go away.
+    fn dispose_blocks(&mut self, statement: &SemanticAst) {
+        for scope_id in statement.block_scopes() {
+            if let Some(scope) = self.semantic_analyzer.dispose_scope(scope_id) {
+                for symbol in scope.symbols() {
+                    self.symbol_to_value.remove(&symbol.symbol_id);
+                }
+            }
+        }
+    }
+
+    fn interpret_statements(&mut self, nodes: &[SemanticAst]) -> anyhow::Result<()> {
+        // When resuming a coroutine, skip everything that ran before the yield
+        let start = match self.resume_path.as_mut() {
+            Some(path) => path.pop_front().ok_or(anyhow::anyhow!("Corrupted coroutine state"))?,
+            None => 0
+        };
+
+        // The defers that were skipped already ran before the coroutine stopped
+        let skipped = nodes[..start.min(nodes.len())].iter()
+            .filter_map(|node| match node {
+                SemanticAst::Defer(deferred) => Some(deferred.clone()),
+                _ => None
+            })
+            .collect();
+        self.deferred.push(skipped);
+
+        let result = self.run_statements(nodes, start);
+        let deferred = self.deferred.pop().unwrap_or_default();
+
+        // A coroutine that stopped will come back to this block
+        if let Some(Signal::Yield(..)) = self.signal {
+            return result;
+        }
+
+        self.run_deferred(deferred, result)
+    }
+
+    fn run_statements(&mut self, nodes: &[SemanticAst], start: usize) -> anyhow::Result<()> {
+        self.statement_depth += 1;
+        let result = self.run_nested_statements(nodes, start);
+        self.statement_depth -= 1;
+
+        result
+    }
+
+    fn run_nested_statements(&mut self, nodes: &[SemanticAst], start: usize) -> anyhow::Result<()> {
+        for (i, node) in nodes.iter().enumerate().skip(start) {
+            statement_event!(node.kind_name());
+            self.consume_fuel()?;
+            let result = self.interpret(node)?;
+            if self.observer.is_some() {
+                self.observe(node, result.value.as_ref());
+            }
+
+            if let Some(signal) = self.signal.as_mut() {
+                if let Signal::Yield(_, path) = signal {
+                    path.insert(0, i);
+                }
+
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Last deferred first. They all run even if the block or one of them failed,
+    // and the first error is the one reported.
+    fn run_deferred(&mut self, deferred: Vec<Arc<SemanticAst>>, result: anyhow::Result<()>) -> anyhow::Result<()> {
+        // A `return` is still on its way out of the function
+        let signal = self.signal.take();
+
+        let mut result = result;
+        for node in deferred.iter().rev() {
+            let outcome = self.interpret(node);
+            if result.is_ok() {
+                result = outcome.map(|_| ());
+            }
+        }
+
+        self.signal = signal;
+        result
+    }
+
+    fn call_function(&mut self, function: &Closure, args: Vec<Value<'a>>) -> anyhow::Result<Option<Value<'a>>> {
+        let frame = function.call_environment();
+        for (parameter, value) in function.definition.parameters.iter().zip(args) {
+            frame.declare(*parameter, value.uuid);
+            self.value_table.insert(value);
+        }
+
+        // Nothing runs until the sequence is gone through
+        if function.definition.is_generator {
+            let coroutine = Coroutine::new(function.definition.clone(), frame);
+            return Ok(Some(Value::new(ValueVariant::Seq(Sequence(Arc::new(Mutex::new(coroutine)))))));
+        }
+
+        self.call_function_with_frame(&function.definition, frame)
+    }
+
+    #[cfg_attr(feature = "trace", tracing::instrument(name = "call", level = "debug", skip_all, fields(function = function.name)))]
+    fn call_function_with_frame(&mut self, function: &FunctionDefinition, frame: Environment) -> anyhow::Result<Option<Value<'a>>> {
+        if self.frames.len() >= self.policy.max_call_depth {
+            return Err(RuntimeError::CallDepth(self.policy.max_call_depth).into());
+        }
+
+        self.frames.push(frame);
+        self.enter_scope(function.scope_id);
+        let result = self.interpret_statements(&function.body);
+        self.running_scopes.pop();
+        self.frames.pop();
+        result?;
+
+        match self.signal.take() {
+            Some(Signal::Return(value)) => Ok(value),
+            Some(Signal::Yield(..)) => Err(anyhow::anyhow!("{} yields, so it can only run as a coroutine", function.name)),
+            None if function.return_id.is_some() => Err(anyhow::anyhow!("{} ended without returning a value", function.name)),
+            None => Ok(None)
+        }
+    }
+
+    pub(crate) fn interpret(&mut self, semantic_ast: &SemanticAst) -> anyhow::Result<ExecutionResult<'a>> {
+        self.stats.nodes_evaluated += 1;
+        stack::guarded(|| self.interpret_node(semantic_ast))
+    }
+
+    fn execute(&mut self, statement: &SemanticAst) -> anyhow::Result<Option<Value<'a>>> {
+        let Some(mut executor) = self.executor.take() else {
+            return self.interpret(statement).map(|result| result.value);
+        };
+
+        // Put back even if it panics, so the interpreter keeps it after recovering
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| executor.run(statement, &mut ExecCtx::new(self))));
+        self.executor = Some(executor);
+
+        result.unwrap_or_else(|payload| std::panic::resume_unwind(payload))
+    }
+
+    fn interpret_node(&mut self, semantic_ast: &SemanticAst) -> anyhow::Result<ExecutionResult<'a>> {
         match semantic_ast {
             SemanticAst::Block(nodes, scope_id) => {
-                self.semantic_analyzer.push_scope(scope_id);
-                for node in nodes {
-                    self.interpret(node)?;
-                }
-                self.semantic_analyzer.pop_scope()?;
-                
+                self.enter_scope(*scope_id);
+                let result = self.interpret_statements(nodes);
+                self.running_scopes.pop();
+                result?;
+
+                Ok(ExecutionResult { value: None })
+            },
+            SemanticAst::Module(scope_id, statements) => {
+                // A module's top level runs like a block whose declarations are global
+                self.enter_scope(*scope_id);
+                let result = self.interpret_statements(statements);
+                self.running_scopes.pop();
+                result?;
+
                 Ok(ExecutionResult { value: None })
             },
+            SemanticAst::Frame(layout, node) => {
+                // Blocks, matches and loops outside functions bind into an environment of their own
+                self.frames.push(Environment::new(None, layout.clone()));
+                let result = self.interpret_node(node);
+                self.frames.pop();
+
+                result
+            },
             SemanticAst::Number(token) => {
-                let value = Value::new(ValueVariant::Primitive(PrimitiveValue::Int(token.value.parse::<i64>()?)));
+                let number = if token.value.contains('.') {
+                    PrimitiveValue::Dec(numbers::parse_dec(&token.value)?)
+                } else {
+                    PrimitiveValue::Int(self.int_literal(token)?)
+                };
+                let value = self.literal(ValueVariant::Primitive(number));
 
                 Ok(ExecutionResult { value: Some(value) })
             },
             SemanticAst::Truth(token) => {
-                let value = Value::new(ValueVariant::Primitive(PrimitiveValue::Bool(token.value.parse::<bool>()?)));
+                let value = self.literal(ValueVariant::Primitive(PrimitiveValue::Bool(token.value.parse::<bool>()?)));
 
                 Ok(ExecutionResult { value: Some(value) })
             },
             SemanticAst::Text(token) => {
-                let value = Value::new(ValueVariant::Primitive(PrimitiveValue::Text(token.value)));
+                let value = self.literal(ValueVariant::Primitive(PrimitiveValue::Text(token.value.as_str().into())));
 
                 Ok(ExecutionResult { value: Some(value) })
             },
-            SemanticAst::Variable(id) => {
-                let symbol = self.semantic_analyzer.current_scope().expect("There's always a scope")
-                    .symbol_from_id(id, &self.semantic_analyzer)
-                    .ok_or(anyhow::anyhow!("Symbol not found"))?;
-
-                let value = self.value_table.get(self.symbol_to_value[&symbol.symbol_id]).ok_or(anyhow::anyhow!("Value not found"))?;
+            SemanticAst::Variable(id, slot) => {
+                // Locals are read by position. Symbol ids are unique, and namespace members aren't
+                // reachable through the scope chain anyway, so the rest are looked up by id.
+                let value_id = match slot {
+                    Some(slot) => self.frames.last().and_then(|frame| frame.slot(*slot)),
+                    None => self.symbol_to_value.get(id).copied()
+                };
+                let value_id = value_id.or_else(|| self.value_id_of(*id)).ok_or(anyhow::anyhow!("Symbol not found"))?;
+                let value = self.value_table.get(value_id).ok_or(anyhow::anyhow!("Value not found"))?;
 
                 Ok(ExecutionResult { value: Some(value.clone()) })
             },
             SemanticAst::Declaration(target, _, node) => {
-                let result = self.interpret(*node)?;
+                let result = self.interpret(node)?;
                 let initial_value = result.value.ok_or(anyhow::anyhow!("Semantic analysis error. Should have value"))?;
 
-                let symbol = self.semantic_analyzer.current_scope()
-                    .expect("There's always a scope")
-                    .lookup_id(target).ok_or(anyhow::anyhow!("Symbol not found"))?;
+                self.declare_value(*target, initial_value);
+
+                Ok(ExecutionResult { value: None })
+            },
+            SemanticAst::PatternDeclaration(pattern, node, at) => {
+                let value = self.operand(node)?;
+                let description = value.type_name(&self.semantic_analyzer);
+
+                let mut bindings = Vec::new();
+                if !self.match_pattern(pattern, value, &mut bindings)? {
+                    return Err(anyhow::anyhow!("The {} doesn't match the pattern (line {}, column {})", description, at.line, at.column));
+                }
+
+                for (symbol_id, value) in bindings {
+                    self.declare_value(symbol_id, value);
+                }
+
+                Ok(ExecutionResult { value: None })
+            },
+            SemanticAst::MultipleDeclaration(declarations) => {
+                let mut values = Vec::new();
+                for (_, node) in declarations {
+                    values.push(self.operand(node)?);
+                }
+
+                for ((target, _), value) in declarations.iter().zip(values) {
+                    self.declare_value(*target, value);
+                }
 
-                self.symbol_to_value.insert(symbol.symbol_id, initial_value.uuid);
+                Ok(ExecutionResult { value: None })
+            },
+            SemanticAst::MultipleAssignment(assignments) => {
+                let mut values = Vec::new();
+                for (_, node) in assignments {
+                    values.push(self.operand(node)?);
+                }
 
-                self.value_table.insert(initial_value);
+                for ((target, _), value) in assignments.iter().zip(values) {
+                    self.assign_value(*target, value);
+                }
 
                 Ok(ExecutionResult { value: None })
             },
             SemanticAst::Assignment(target_id, node) => {
-                let result = self.interpret(*node)?;
+                let result = self.interpret(node)?;
                 let value = result.value.ok_or(anyhow::anyhow!("Semantic analysis error. Should have value"))?;
 
-                let symbol = self.semantic_analyzer.current_scope()
-                    .expect("There's always a scope").symbol_from_id(target_id, &self.semantic_analyzer)
-                    .ok_or(anyhow::anyhow!("Symbol not found"))?;
+                self.assign_value(*target_id, value);
 
-                self.symbol_to_value.insert(symbol.symbol_id, value.uuid);
+                Ok(ExecutionResult { value: None })
+            },
+            SemanticAst::BinaryOperation(left, operator, right, token) => {
+                let value = self.binary_operation(left, *operator, right, token)?;
 
-                self.value_table.insert(value); // Updates if it already existed
+                Ok(ExecutionResult { value: Some(value) })
+            },
+            SemanticAst::Comparison(first, rest) => {
+                let value = self.chained_comparison(first, rest)?;
 
-                Ok(ExecutionResult { value: None })
+                Ok(ExecutionResult { value: Some(value) })
             },
-            SemanticAst::FunctionCall(callee, args) => {
-                let callee_result = self.interpret(*callee)?;
-                let callee_value = callee_result.value.expect("Semantic analysis error. Should have value");
+            SemanticAst::Negation(node, token) => {
+                let overflow = self.policy.overflow;
+                let number = match self.operand(node)?.content {
+                    ValueVariant::Primitive(PrimitiveValue::Int(i)) => {
+                        let negated = match overflow {
+                            IntegerOverflow::Checked => i.checked_neg(),
+                            IntegerOverflow::Wrapping => Some(i.wrapping_neg()),
+                            IntegerOverflow::Saturating => Some(i.saturating_neg()),
+                        };
 
-                let callee_function = match callee_value.content {
-                    ValueVariant::Function(f) => f,
-                    _ => panic!("Semantic error. Should have been a function")
+                        PrimitiveValue::Int(negated.ok_or(anyhow::anyhow!("Integer overflow (line {}, column {})", token.line, token.column))?)
+                    },
+                    ValueVariant::Primitive(PrimitiveValue::Dec(d)) => PrimitiveValue::Dec(-d),
+                    _ => return Err(anyhow::anyhow!("Only numbers can be negated (line {}, column {})", token.line, token.column))
                 };
 
-                match callee_function {
-                    FunctionValue::Native(f) => {
-                        let mut arg_values = Vec::new();
-                        for arg in args {
-                            let arg_result = self.interpret(*arg)?;
-                            let arg_value = arg_result.value.expect("Semantic analysis error. Should have value");
-                            arg_values.push(arg_value);
-                        }
+                Ok(ExecutionResult { value: Some(Value::new(ValueVariant::Primitive(number))) })
+            },
+            SemanticAst::List(items) => {
+                let values = self.items(items)?;
+
+                Ok(ExecutionResult { value: Some(Value::list(values)) })
+            },
+            SemanticAst::Map(entries) => {
+                let mut map = BTreeMap::new();
+                for (key, value) in entries {
+                    // Lists and `any` keys are only known to be keys once they're made
+                    let key = Key::try_from(&self.operand(key)?)?;
+
+                    // Later entries win over repeated keys
+                    map.insert(key, self.operand(value)?);
+                }
+
+                Ok(ExecutionResult { value: Some(Value::map(map)) })
+            },
+            SemanticAst::FunctionCall(callee, args) => {
+                let callee_function = self.callee(callee)?;
+                let arg_values = self.items(args)?;
+
+                let result = self.call(callee_function, arg_values)?;
+
+                Ok(ExecutionResult { value: result })
+            },
+            SemanticAst::SpreadCall(callee, args, parameter_types) => {
+                let callee_function = self.callee(callee)?;
+                let arg_values = self.items(args)?;
+
+                self.check_arguments(&arg_values, parameter_types)?;
+                let result = self.call(callee_function, arg_values)?;
+
+                Ok(ExecutionResult { value: result })
+            },
+            SemanticAst::Spread(_) => Err(anyhow::anyhow!("Semantic analysis error. Spread outside of a call or list")),
+            SemanticAst::FunctionDeclaration(target, definition) => {
+                let value = Value::new(ValueVariant::Function(FunctionValue::User(self.closure(definition))));
 
-                        let result = f(arg_values);
+                self.declare_value(*target, value);
 
-                        Ok(ExecutionResult { value: result })
+                Ok(ExecutionResult { value: None })
+            },
+            SemanticAst::Function(definition) => {
+                let value = Value::new(ValueVariant::Function(FunctionValue::User(self.closure(definition))));
+
+                Ok(ExecutionResult { value: Some(value) })
+            },
+            SemanticAst::Return(node) => {
+                let value = match node {
+                    Some(node) => Some(self.interpret(node)?.value
+                        .ok_or(anyhow::anyhow!("Semantic analysis error. Should have value"))?),
+                    None => None
+                };
+
+                self.signal = Some(Signal::Return(value));
+
+                Ok(ExecutionResult { value: None })
+            },
+            SemanticAst::Yield(node) => {
+                if let Some(path) = &self.resume_path {
+                    if !path.is_empty() {
+                        return Err(anyhow::anyhow!("Corrupted coroutine state"));
                     }
+
+                    // This is where the coroutine stopped. Execution continues right after it.
+                    self.resume_path = None;
+                    return Ok(ExecutionResult { value: None });
+                }
+
+                let value = self.interpret(node)?.value
+                    .ok_or(anyhow::anyhow!("Semantic analysis error. Should have value"))?;
+
+                self.signal = Some(Signal::Yield(value, Vec::new()));
+
+                Ok(ExecutionResult { value: None })
+            },
+            SemanticAst::Checked(node, expected, at) => {
+                let value = self.operand(node)?;
+                if !self.has_type(&value, *expected) {
+                    let expected_name = self.semantic_analyzer.name_of_type(*expected)?.unwrap_or("<unknown>".to_string());
+                    let position = at.as_ref().map(|token| format!(" (line {}, column {})", token.line, token.column)).unwrap_or_default();
+
+                    return Err(anyhow::anyhow!(
+                        "Type mismatch: Expected type {:?} but got type {:?}{}",
+                        expected_name,
+                        value.type_name(&self.semantic_analyzer),
+                        position
+                    ));
                 }
+
+                Ok(ExecutionResult { value: Some(value) })
             },
             SemanticAst::If(condition, body) => {
-                let condition_result = self.interpret(*condition)?;
+                // A coroutine stopped inside the body already went through the condition
+                if self.resume_path.is_some() {
+                    self.interpret(body)?;
+                    return Ok(ExecutionResult { value: None });
+                }
+
+                let condition_result = self.interpret(condition)?;
                 let condition_value = condition_result.value.ok_or(anyhow::anyhow!("Semantic analysis error. Should have value"))?;
 
                 if let ValueVariant::Primitive(PrimitiveValue::Bool(true)) = condition_value.content {
-                    self.interpret(*body)?;
+                    self.interpret(body)?;
+                }
+
+                Ok(ExecutionResult { value: None })
+            },
+            SemanticAst::Match(value, arms) => {
+                let result = self.interpret_match(value, arms)?;
+
+                Ok(ExecutionResult { value: result })
+            },
+            SemanticAst::For(variable, state, iterable, body, scope_id) => {
+                self.enter_scope(*scope_id);
+                let result = self.interpret_for(*variable, *state, iterable, body);
+                self.running_scopes.pop();
+                result?;
+
+                Ok(ExecutionResult { value: None })
+            },
+            SemanticAst::Defer(node) => {
+                let deferred = self.deferred.last_mut()
+                    .ok_or(anyhow::anyhow!("Nothing to defer to: defer only runs inside a block, function or evaluation"))?;
+                deferred.push(node.clone());
+
+                Ok(ExecutionResult { value: None })
+            },
+            SemanticAst::TypeDeclaration(methods) => {
+                for method in methods {
+                    if let SemanticAst::FunctionDeclaration(id, definition) = method {
+                        let closure = self.closure(definition);
+                        self.methods.insert(*id, closure);
+                    }
                 }
 
                 Ok(ExecutionResult { value: None })
             },
+            SemanticAst::Construct(type_id, type_name, fields) => {
+                let mut values = Vec::new();
+                for (field, node) in fields {
+                    values.push((field.clone(), self.operand(node)?));
+                }
+
+                let record = Record { type_id: *type_id, type_name: type_name.clone(), fields: values };
+
+                Ok(ExecutionResult { value: Some(Value::new(ValueVariant::Record(record))) })
+            },
+            SemanticAst::Field(node, index) => {
+                let value = match self.operand(node)?.content {
+                    ValueVariant::Record(mut record) if *index < record.fields.len() => record.fields.swap_remove(*index).1,
+                    _ => return Err(anyhow::anyhow!("Semantic analysis error. Should have been a value with fields"))
+                };
+
+                Ok(ExecutionResult { value: Some(value) })
+            },
+            SemanticAst::MethodCall(receiver, name, args, site) => {
+                let receiver = self.operand(receiver)?;
+                let method = self.method_at(site, &receiver, name)?;
+
+                let mut arg_values = vec![receiver];
+                arg_values.extend(self.items(args)?);
+
+                let result = self.call_function(&method, arg_values)?;
+
+                Ok(ExecutionResult { value: result })
+            },
             SemanticAst::DebugPrint(node) => {
-                let result = self.interpret(*node)?;
+                let result = self.interpret(node)?;
 
-                println!("DebugPrint -> {:?}", result.value);
+                let text = match &result.value {
+                    Some(value) => self.show(value)?,
+                    None => "nothing".to_string()
+                };
+                self.output.out(&format!("{}\n", text));
 
                 Ok(ExecutionResult { value: None })
             }
@@ -156,28 +911,2603 @@ impl<'a> Interpreter<'a> {
     }
      */
     pub fn eval(&mut self, code: String) -> anyhow::Result<ExecutionResult<'a>> {
-        let lexer = Lexer::new(code);
-        let tokens: Vec<_> = lexer.collect();
+        let repl_id = self.semantic_analyzer.repl_scope_id;
 
-        let mut parser = Parser::new(tokens);
-        let statements = parser.statement_list()?;
+        self.eval_in_scope(code, repl_id)
+    }
 
-        let repl_id = self.semantic_analyzer.repl_scope_id;
-        self.semantic_analyzer.push_scope(repl_id);
+    /// Like `eval`, but everything the program prints is collected instead of written to the
+    /// process's stdout and stderr. Returns the result, and what went to stdout and stderr.
+    pub fn eval_captured(&mut self, code: String) -> (anyhow::Result<ExecutionResult<'a>>, String, String) {
+        self.capture_output(|interpreter| interpreter.eval(code))
+    }
+
+    /// Runs `f` with everything the program prints collected, like `eval_captured` does for `eval`.
+    pub fn capture_output<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> (T, String, String) {
+        let previous = self.output.capture();
+        let result = f(self);
+        let captured = self.output.release(previous);
+
+        (result, captured.stdout, captured.stderr)
+    }
+
+    /// Like `eval`, but all or nothing: when any statement fails, the session goes back to how
+    /// it was before, as if none of the code ran. What it already printed stays printed.
+    /// When it succeeds, `undo` can take it back.
+    pub fn eval_atomic(&mut self, code: String) -> anyhow::Result<ExecutionResult<'a>> {
+        let snapshot = self.snapshot();
+
+        let result = self.eval(code);
+
+        match result {
+            Ok(_) => {
+                if self.undo_history.len() == UNDO_LIMIT {
+                    self.undo_history.pop_front();
+                }
+                self.undo_history.push_back(snapshot);
+            },
+            Err(_) => self.restore(snapshot)
+        }
+
+        result
+    }
+
+    /// Takes back the declarations and assignments of the last code `eval_atomic` ran, going
+    /// back to how the session was before it. Returns false when there's nothing left to undo.
+    pub fn undo(&mut self) -> bool {
+        match self.undo_history.pop_back() {
+            Some(snapshot) => {
+                self.restore(snapshot);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Marks the session as it is now, for `reset` to go back to, like after loading a prelude.
+    pub fn checkpoint(&mut self) {
+        self.checkpoint = Some(self.snapshot());
+    }
+
+    /// Goes back to the last `checkpoint`, or to how the interpreter was built when there's
+    /// none. Nothing is left to undo.
+    pub fn reset(&mut self) {
+        if let Some(snapshot) = self.checkpoint.clone() {
+            self.restore(snapshot);
+        }
+        self.undo_history.clear();
+    }
+
+    /// A copy of the session as it is now, to evaluate code in without changing this one, like a
+    /// template set up once with the host's natives and a prelude. Symbol tables and values are
+    /// shared until one of them changes, so it's cheap no matter how much was evaluated.
+    /// The fork's `reset` goes back to this point, and it has nothing to undo.
+    ///
+    /// Natives bound before the fork are shared, and so are stdin, stdout and the input log.
+    /// Closures and seqs made before the fork get copies of the variables they captured, so
+    /// what one fork assigns to them isn't seen by the template or the other forks. Custom
+    /// executors and observers aren't copied.
+    pub fn fork(&mut self) -> Interpreter<'a> {
+        let mut captures = Captures::default();
+        let handlers = self.event_handlers.lock().unwrap().iter()
+            .map(|(event, closures)| (event.clone(), closures.iter().map(|closure| closure.copy(&mut captures.environments)).collect()))
+            .collect();
+        let event_handlers: EventHandlers = Arc::new(Mutex::new(handlers));
+        let methods = self.methods.iter()
+            .map(|(id, closure)| (*id, closure.copy(&mut captures.environments)))
+            .collect();
+
+        let mut fork = Interpreter {
+            value_table: self.value_table.fork(),
+            semantic_analyzer: self.semantic_analyzer.clone(),
+            policy: self.policy.clone(),
+            symbol_to_value: self.symbol_to_value.clone(),
+            frames: Vec::new(),
+            running_scopes: Vec::new(),
+            signal: None,
+            resume_path: None,
+            deferred: Vec::new(),
+            remaining_fuel: None,
+            cancellation: CancellationToken::new(),
+            event_handlers: event_handlers.clone(),
+            methods,
+            inputs: self.inputs.clone(),
+            input: self.input.clone(),
+            output: self.output.clone(),
+            tab_width: self.tab_width,
+            stats: Stats::default(),
+            allocated_before_stats: self.value_table.allocated(),
+            #[cfg(feature = "regex")]
+            pattern_cache: self.pattern_cache.clone(),
+            executor: None,
+            observer: None,
+            statement_depth: 0,
+            format_limits: self.format_limits,
+            opaque_texts: self.opaque_texts.clone(),
+            undo_history: VecDeque::new(),
+            checkpoint: None,
+        };
+
+        // `on` registers into the handlers of the interpreter that bound it
+        let on = self.semantic_analyzer.global_scope().ok()
+            .and_then(|scope| scope.lookup("on".to_string()))
+            .and_then(|symbol| self.symbol_to_value.get(&symbol.symbol_id));
+        if let Some(&uuid) = on {
+            let content = ValueVariant::Function(FunctionValue::Native(Arc::new(stdlib::on(event_handlers))));
+            fork.value_table.insert(Value { content, uuid });
+        }
+
+        let copied: Vec<_> = fork.value_table.values().filter_map(|value| captures.value(value)).collect();
+        for value in copied {
+            fork.value_table.insert(value);
+        }
+
+        fork.checkpoint();
+        fork
+    }
+
+    /// Removes the values nothing can reach anymore, like the ones of variables that were
+    /// assigned again or taken back. Returns how many were removed.
+    /// Coroutines the host holds don't keep their values, so they can't resume after this.
+    pub fn collect_garbage(&mut self) -> usize {
+        let mut marker = Marker::default();
+
+        let snapshots = self.undo_history.iter().chain(&self.checkpoint);
+        for snapshot in snapshots {
+            marker.pending.extend(snapshot.symbol_to_value.values());
+            marker.closures(snapshot.methods.values());
+            marker.closures(snapshot.handlers.values().flatten());
+        }
+
+        marker.pending.extend(self.symbol_to_value.values());
+        marker.closures(self.methods.values());
+        marker.closures(self.event_handlers.lock().unwrap().values().flatten());
+        for frame in &self.frames {
+            marker.environment(frame);
+        }
+
+        while let Some(value_id) = marker.pending.pop() {
+            if marker.reachable.insert(value_id) {
+                if let Some(value) = self.value_table.get(value_id) {
+                    marker.value(value);
+                }
+            }
+        }
+
+        self.value_table.retain(|value_id| marker.reachable.contains(value_id))
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            analyzer: self.semantic_analyzer.clone(),
+            symbol_to_value: self.symbol_to_value.clone(),
+            methods: self.methods.clone(),
+            handlers: self.event_handlers.lock().unwrap().clone(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.semantic_analyzer.restore(snapshot.analyzer);
+        self.symbol_to_value = snapshot.symbol_to_value;
+        self.methods = snapshot.methods;
+        *self.event_handlers.lock().unwrap() = snapshot.handlers;
+    }
+
+    /// Evaluates a startup script into the global scope, so everything it
+    /// declares is visible to every later `eval`.
+    pub fn set_prelude(&mut self, code: String) -> anyhow::Result<()> {
+        let global_id = self.semantic_analyzer.global_scope_id;
+
+        self.eval_in_scope(code, global_id)?;
+
+        Ok(())
+    }
+
+    // Source code goes through the policy's limits before anything else
+    fn lexer(&self, code: String) -> Lexer {
+        Lexer::new(code)
+            .tab_width(self.tab_width)
+            .max_tokens(self.policy.max_tokens)
+            .max_text_length(self.policy.max_text_length)
+    }
+
+    fn parser(&self, tokens: Vec<Token>) -> Parser {
+        match self.policy.max_depth {
+            Some(limit) => Parser::new(tokens).max_depth(limit),
+            None => Parser::new(tokens)
+        }
+    }
+
+    /// The scopes names in evaluated code are looked up in, from the global one down, with
+    /// what's declared in each and whether it has a value.
+    pub fn scope_chain(&self) -> anyhow::Result<ScopeTree> {
+        let mut tree = self.semantic_analyzer.dump_scope_chain(self.semantic_analyzer.repl_scope_id)?;
+        tree.mark_bound(&|symbol_id| self.symbol_to_value.contains_key(&symbol_id));
+
+        Ok(tree)
+    }
+
+    /// The variables evaluated code can use that have a value, innermost scope first, in the
+    /// order they were declared. Ones hidden by a name in an inner scope are left out.
+    pub fn variables(&self) -> anyhow::Result<Vec<Variable<'a>>> {
+        let analyzer = &self.semantic_analyzer;
+        let mut names = HashSet::new();
+        let mut variables = vec![];
+
+        let mut scope_id = Some(analyzer.repl_scope_id);
+        while let Some(id) = scope_id {
+            let scope = analyzer.scope(id)?;
+            for symbol in scope.symbols().filter(|symbol| names.insert(symbol.name())) {
+                if !matches!(symbol.variant, SymbolVariant::Variable(_)) {
+                    continue;
+                }
+
+                let Some(value) = self.symbol_to_value.get(&symbol.symbol_id).and_then(|value_id| self.value_table.get(*value_id)) else {
+                    continue;
+                };
+
+                variables.push(Variable {
+                    name: symbol.name().to_string(),
+                    type_name: analyzer.name_of_type(symbol.value_type_id()?)?.unwrap_or_else(|| ANY_TYPE.name().to_string()),
+                    value: value.clone(),
+                    scope: scope.name().to_string(),
+                });
+            }
+
+            scope_id = scope.parent();
+        }
+
+        Ok(variables)
+    }
+
+    /// The tokens of `code`, as evaluating it would read them.
+    pub fn tokenize(&self, code: &str) -> anyhow::Result<Vec<Token>> {
+        self.lexer(code.to_string()).tokenize()
+    }
+
+    /// The statements `code` parses to, before they're analyzed.
+    pub fn parse(&self, code: &str) -> anyhow::Result<Vec<Node>> {
+        self.parser(self.tokenize(code)?).statement_list()
+    }
+
+    /// The type at byte `offset` of `code`, for hovers and `:type`. The code is analyzed
+    /// against everything evaluated so far, but it doesn't run and nothing it declares is kept.
+    pub fn type_at(&mut self, code: &str, offset: usize) -> Option<TypeInfo> {
+        self.contained(|interpreter| Ok(interpreter.type_at_offset(code, offset))).ok().flatten()
+    }
+
+    fn type_at_offset(&mut self, code: &str, offset: usize) -> Option<TypeInfo> {
+        if !code.is_char_boundary(offset) {
+            return None;
+        }
+
+        let lexer = self.lexer(code.to_string());
+        let (line, column) = lexer.location(offset);
+        let tokens = lexer.tokenize().ok()?;
+        let statements = self.parser(tokens).statement_list().ok()?;
+
+        let repl_scope_id = self.semantic_analyzer.repl_scope_id;
+        self.semantic_analyzer.in_scope(repl_scope_id, |analyzer| analyzer.type_at_in(statements, Span::at(line, column)))
+    }
+
+    // Every way into running code goes through here, so a panic in odo or a native becomes an
+    // error instead of taking down the host. The state a panic can leave half-updated is reset.
+    fn contained<T>(&mut self, f: impl FnOnce(&mut Self) -> anyhow::Result<T>) -> anyhow::Result<T> {
+        let (frames, running_scopes, deferred, depth) = (self.frames.len(), self.running_scopes.len(), self.deferred.len(), self.statement_depth);
+        let analyzed_scope = self.semantic_analyzer.current_scope_id;
+
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(self))) {
+            Ok(result) => result,
+            Err(payload) => {
+                self.frames.truncate(frames);
+                self.running_scopes.truncate(running_scopes);
+                self.deferred.truncate(deferred);
+                self.statement_depth = depth;
+                self.semantic_analyzer.current_scope_id = analyzed_scope;
+                self.signal = None;
+                self.resume_path = None;
+
+                let message = payload.downcast_ref::<&str>().map(|message| message.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_string());
+
+                Err(RuntimeError::Internal(message).into())
+            }
+        }
+    }
+
+    fn eval_in_scope(&mut self, code: String, scope_id: Uuid) -> anyhow::Result<ExecutionResult<'a>> {
+        let started = Instant::now();
+        let result = self.contained(|interpreter| interpreter.eval_code(code, scope_id));
+        self.stats.record_eval(started.elapsed());
+
+        result
+    }
+
+    fn eval_code(&mut self, code: String, scope_id: Uuid) -> anyhow::Result<ExecutionResult<'a>> {
+        let tokens = {
+            let _span = phase_span!("lex");
+            self.lexer(code).tokenize().map_err(CompileError)?
+        };
+
+        let statements = {
+            let _span = phase_span!("parse");
+            self.parser(tokens).statement_list().map_err(CompileError)?
+        };
+
+        self.refuel();
+
+        let depth = self.running_scopes.len();
+        self.enter_scope(scope_id);
+        // Defers at the top level run when everything evaluated has
+        self.deferred.push(Vec::new());
+        let result = self.eval_statements(statements, scope_id);
+        let result = self.finish_deferred(result);
+        // An error can stop execution anywhere, with scopes still pushed
+        self.running_scopes.truncate(depth);
 
+        Ok(ExecutionResult { value: result? })
+    }
+
+    fn finish_deferred(&mut self, result: anyhow::Result<Option<Value<'a>>>) -> anyhow::Result<Option<Value<'a>>> {
+        let deferred = self.deferred.pop().unwrap_or_default();
+        match result {
+            Ok(value) => self.run_deferred(deferred, Ok(())).map(|_| value),
+            Err(e) => self.run_deferred(deferred, Err(e)).map(|_| None)
+        }
+    }
+
+    fn eval_statements(&mut self, statements: Vec<Node>, scope_id: TableId) -> anyhow::Result<Option<Value<'a>>> {
         let mut result = None;
         for node in statements {
-            let semantic_result = self.semantic_analyzer.analyze(node)?;
-            result = self.interpret(*semantic_result.node)?.value;
+            let semantic_result = {
+                let _span = phase_span!("analyze");
+                self.semantic_analyzer.in_scope(scope_id, |analyzer| analyzer.analyze(node)).map_err(CompileError)?
+            };
+
+            let _span = phase_span!("interpret");
+            statement_event!(semantic_result.node.kind_name());
+            let outcome = self.consume_fuel()
+                .and_then(|_| self.execute(&semantic_result.node));
+            if let (Ok(value), Some(_)) = (&outcome, &self.observer) {
+                self.observe(&semantic_result.node, value.as_ref());
+            }
+            self.dispose_blocks(&semantic_result.node);
+            result = outcome?;
+        }
+
+        Ok(result)
+    }
+}
+
+// Calls
+
+impl<'a> Interpreter<'a> {
+    fn callee(&mut self, callee: &SemanticAst) -> anyhow::Result<FunctionValue<'a>> {
+        match self.operand(callee)?.content {
+            ValueVariant::Function(f) => Ok(f),
+            _ => Err(anyhow::anyhow!("Semantic analysis error. Should have been a function"))
+        }
+    }
+
+    // Functions capture the environment they're created in
+    fn closure(&self, definition: &Arc<FunctionDefinition>) -> Closure {
+        Closure::new(definition.clone(), self.frames.last().cloned())
+    }
+
+    fn call(&mut self, function: FunctionValue<'a>, args: Vec<Value<'a>>) -> anyhow::Result<Option<Value<'a>>> {
+        match function {
+            FunctionValue::Native(f) => f(args),
+            FunctionValue::User(f) => self.call_function(&f, args)
+        }
+    }
+
+    // What's left to go through lives in the frame, so a coroutine can stop in the body and come back
+    fn interpret_for(&mut self, variable: Uuid, state: Uuid, iterable: &SemanticAst, body: &SemanticAst) -> anyhow::Result<()> {
+        if self.resume_path.is_some() {
+            self.interpret(body)?;
+            if self.signal.is_some() {
+                return Ok(());
+            }
+        } else {
+            let iterable = self.operand(iterable)?;
+            self.declare_value(state, iterable);
+        }
+
+        loop {
+            self.consume_fuel()?;
+            let remaining = self.value_id_of(state)
+                .and_then(|value_id| self.value_table.get(value_id))
+                .cloned()
+                .ok_or(anyhow::anyhow!("Value not found"))?;
+
+            let Some((item, rest)) = self.advance(remaining)? else {
+                return Ok(());
+            };
+
+            self.declare_value(state, rest);
+            self.declare_value(variable, item);
+
+            self.interpret(body)?;
+            // A return or a yield leaves the loop
+            if self.signal.is_some() {
+                return Ok(());
+            }
+        }
+    }
+
+    // The next item, and what's left after it. None when there's nothing left.
+    fn advance(&mut self, iterable: Value<'a>) -> anyhow::Result<Option<(Value<'a>, Value<'a>)>> {
+        match iterable.content {
+            ValueVariant::List(mut items) => {
+                if items.is_empty() {
+                    return Ok(None);
+                }
+
+                let item = items.remove(0);
+                Ok(Some((item, Value::list(items))))
+            },
+            ValueVariant::Record(_) => {
+                let done = self.call_method(&iterable, "done")?;
+                if matches!(done.content, ValueVariant::Primitive(PrimitiveValue::Bool(true))) {
+                    return Ok(None);
+                }
+
+                let item = self.call_method(&iterable, "current")?;
+                let rest = self.call_method(&iterable, "next")?;
+
+                Ok(Some((item, rest)))
+            },
+            ValueVariant::Seq(ref sequence) => {
+                // A generator that goes through its own sequence would wait on itself forever
+                let mut coroutine = sequence.0.try_lock()
+                    .map_err(|_| anyhow::anyhow!("A sequence can't go through itself"))?;
+                if coroutine.finished {
+                    return Ok(None);
+                }
+
+                match self.step(&mut coroutine)? {
+                    CoroutineState::Yielded(item) => Ok(Some((item, iterable.clone()))),
+                    CoroutineState::Finished(_) => Ok(None)
+                }
+            },
+            _ => Err(anyhow::anyhow!("for can't go through a value of type {}", iterable.type_name(&self.semantic_analyzer)))
+        }
+    }
+
+    // For the methods of the iterator protocol, which only take `self`
+    fn call_method(&mut self, receiver: &Value<'a>, name: &str) -> anyhow::Result<Value<'a>> {
+        let method = self.method(receiver, name)?;
+        self.call_function(&method, vec![receiver.clone()])?
+            .ok_or(anyhow::anyhow!("{} should return a value", name))
+    }
+
+    fn interpret_match(&mut self, value: &SemanticAst, arms: &[MatchArm]) -> anyhow::Result<Option<Value<'a>>> {
+        // A coroutine stopped inside an arm already chose it
+        let resumed_arm = match self.resume_path.as_mut() {
+            Some(path) => Some(path.pop_front().ok_or(anyhow::anyhow!("Corrupted coroutine state"))?),
+            None => None
+        };
+
+        let chosen = match resumed_arm {
+            Some(index) => Some(index),
+            None => {
+                let value = self.operand(value)?;
+                let mut chosen = None;
+                for (index, arm) in arms.iter().enumerate() {
+                    let mut bindings = Vec::new();
+                    if !self.match_pattern(&arm.pattern, value.clone(), &mut bindings)? {
+                        continue;
+                    }
+
+                    for (symbol_id, value) in bindings {
+                        self.declare_value(symbol_id, value);
+                    }
+
+                    // The guard sees what the pattern bound
+                    if let Some(guard) = &arm.guard {
+                        self.enter_scope(arm.scope_id);
+                        let passed = self.operand(guard);
+                        self.running_scopes.pop();
+                        if !matches!(passed?.content, ValueVariant::Primitive(PrimitiveValue::Bool(true))) {
+                            continue;
+                        }
+                    }
+
+                    chosen = Some(index);
+                    break;
+                }
+
+                chosen
+            }
+        };
+
+        // The analyzer makes sure some arm always matches
+        let index = chosen.ok_or(anyhow::anyhow!("Semantic analysis error. Some arm should have matched"))?;
+        let arm = arms.get(index).ok_or(anyhow::anyhow!("Corrupted coroutine state"))?;
+
+        self.enter_scope(arm.scope_id);
+        let result = self.interpret(&arm.body);
+        self.running_scopes.pop();
+
+        if let Some(Signal::Yield(_, path)) = self.signal.as_mut() {
+            path.insert(0, index);
+        }
+
+        Ok(result?.value)
+    }
+
+    // Whether the value matches, collecting what the pattern binds along the way
+    fn match_pattern(&mut self, pattern: &SemanticPattern, value: Value<'a>, bindings: &mut Vec<(Uuid, Value<'a>)>) -> anyhow::Result<bool> {
+        match pattern {
+            SemanticPattern::Wildcard => Ok(true),
+            SemanticPattern::Binding(symbol_id) => {
+                bindings.push((*symbol_id, value));
+                Ok(true)
+            },
+            SemanticPattern::Literal(literal) => {
+                let literal = self.operand(literal)?;
+                values_equal(&literal, &value)
+            },
+            SemanticPattern::Record(type_id, fields) => {
+                let ValueVariant::Record(record) = value.content else { return Ok(false) };
+                if record.type_id != *type_id {
+                    return Ok(false);
+                }
+
+                for (index, pattern) in fields {
+                    let field = record.fields.get(*index)
+                        .ok_or(anyhow::anyhow!("Semantic analysis error. The field should exist"))?
+                        .1.clone();
+                    if !self.match_pattern(pattern, field, bindings)? {
+                        return Ok(false);
+                    }
+                }
+
+                Ok(true)
+            },
+            SemanticPattern::List(patterns, rest) => {
+                let ValueVariant::List(mut items) = value.content else { return Ok(false) };
+                let fits = match rest {
+                    Some(_) => items.len() >= patterns.len(),
+                    None => items.len() == patterns.len()
+                };
+                if !fits {
+                    return Ok(false);
+                }
+
+                let remaining = items.split_off(patterns.len());
+                for (pattern, item) in patterns.iter().zip(items) {
+                    if !self.match_pattern(pattern, item, bindings)? {
+                        return Ok(false);
+                    }
+                }
+
+                match rest {
+                    Some(rest) => self.match_pattern(rest, Value::list(remaining), bindings),
+                    None => Ok(true)
+                }
+            }
+        }
+    }
+
+    // The method of the type the receiver has when running, even when it was called through a contract
+    fn method(&self, receiver: &Value<'a>, name: &str) -> anyhow::Result<Closure> {
+        let ValueVariant::Record(ref record) = receiver.content else {
+            return Err(anyhow::anyhow!("Semantic analysis error. Only values of types declared in odo have methods"));
+        };
+
+        self.semantic_analyzer.method_of(record.type_id, name)
+            .and_then(|method_id| self.methods.get(&method_id))
+            .cloned()
+            .ok_or(anyhow::anyhow!("{} has no method called {}", record.type_name, name))
+    }
+
+    // Like `method`, but through what the call site found for the receiver's type before
+    fn method_at(&mut self, site: &CallSite, receiver: &Value<'a>, name: &str) -> anyhow::Result<Closure> {
+        let ValueVariant::Record(ref record) = receiver.content else {
+            return self.method(receiver, name);
+        };
+
+        if let Some(method_id) = site.target(record.type_id) {
+            match self.methods.get(&method_id) {
+                Some(method) => {
+                    self.stats.call_site_hits += 1;
+                    return Ok(method.clone());
+                },
+                None => site.forget(record.type_id)
+            }
+        }
+
+        self.stats.call_site_misses += 1;
+        let method_id = self.semantic_analyzer.method_of(record.type_id, name)
+            .filter(|method_id| self.methods.contains_key(method_id))
+            .ok_or(anyhow::anyhow!("{} has no method called {}", record.type_name, name))?;
+        site.remember(record.type_id, method_id);
+
+        Ok(self.methods[&method_id].clone())
+    }
+
+    // Evaluates arguments or list items, expanding spreads in place
+    fn items(&mut self, nodes: &[SemanticNode]) -> anyhow::Result<Vec<Value<'a>>> {
+        let mut values = Vec::new();
+        for node in nodes {
+            match node.as_ref() {
+                SemanticAst::Spread(list) => match self.operand(list)?.content {
+                    ValueVariant::List(items) => values.extend(items),
+                    _ => return Err(anyhow::anyhow!("Semantic analysis error. Only lists can be spread"))
+                },
+                node => values.push(self.operand(node)?)
+            }
         }
 
-        self.semantic_analyzer.pop_scope()?;
+        Ok(values)
+    }
+
+    // For arguments the semantic analyzer couldn't check: spread ones, and the ones coming from the host
+    // Functions only know their signature statically, so any function passes for a function type.
+    // Lists and maps don't know what they hold either.
+    fn has_type(&self, value: &Value<'a>, type_id: Uuid) -> bool {
+        // Lists and maps are gone through, while seqs would have to be run to know
+        let held = self.semantic_analyzer.held_type(type_id);
+        let Some(type_id) = self.semantic_analyzer.erased_type(type_id).filter(|id| *id != ANY_TYPE.symbol_id) else {
+            return true;
+        };
+
+        match value.content {
+            ValueVariant::List(ref items) if type_id == LIST_TYPE.symbol_id => {
+                held.is_none_or(|held| items.iter().all(|item| self.has_type(item, held)))
+            },
+            ValueVariant::Map(ref entries) if type_id == MAP_TYPE.symbol_id => {
+                held.is_none_or(|held| entries.values().all(|value| self.has_type(value, held)))
+            },
+            ValueVariant::Function(_) => self.semantic_analyzer.current_scope().ok()
+                .and_then(|scope| scope.symbol_from_id(type_id, &self.semantic_analyzer))
+                .is_some_and(|symbol| matches!(symbol.variant, SymbolVariant::FunctionType(_))),
+            ValueVariant::Record(ref record) => record.type_id == type_id || self.semantic_analyzer.conforms(record.type_id, type_id),
+            _ => value.type_id() == Some(type_id)
+        }
+    }
+
+    fn check_arguments(&self, args: &[Value<'a>], parameter_types: &[Uuid]) -> anyhow::Result<()> {
+        if args.len() != parameter_types.len() {
+            return Err(anyhow::anyhow!("Incorrect number of arguments: expected {} but got {}", parameter_types.len(), args.len()));
+        }
+
+        for (value, type_id) in args.iter().zip(parameter_types) {
+            if !self.has_type(value, *type_id) {
+                let expected_name = self.semantic_analyzer.name_of_type(*type_id)?.unwrap_or("<unknown>".to_string());
+
+                return Err(anyhow::anyhow!(
+                    "Type mismatch: Expected type {:?} but got type {:?}",
+                    expected_name,
+                    value.type_name(&self.semantic_analyzer)
+                ));
+            }
+        }
 
-        Ok(ExecutionResult { value: result.clone() })
+        Ok(())
     }
 }
 
-pub struct ExecutionResult<'a> {
-    pub value: Option<Value<'a>>
+// Operators
+
+impl<'a> Interpreter<'a> {
+    fn operand(&mut self, node: &SemanticAst) -> anyhow::Result<Value<'a>> {
+        self.interpret(node)?.value.ok_or(anyhow::anyhow!("Semantic analysis error. Operand should have value"))
+    }
+
+    // Literals too big for an int follow the overflow policy too
+    // Small ints and truths are shared instead of made again every time a loop evaluates them
+    fn literal(&mut self, content: ValueVariant<'a>) -> Value<'a> {
+        match self.value_table.cached(&content) {
+            Some(value) => {
+                self.stats.cache_hits += 1;
+                value
+            },
+            None => {
+                self.stats.cache_misses += 1;
+                Value::new(content)
+            }
+        }
+    }
+
+    fn int_literal(&self, token: &Token) -> anyhow::Result<i64> {
+        let value = numbers::parse_int::<i128>(&token.value)
+            .map_err(|_| anyhow::anyhow!("Integer literal too large (line {}, column {})", token.line, token.column))?;
+
+        self.fit_int_literal(value, token)
+    }
+
+    fn fit_int_literal(&self, value: i128, token: &Token) -> anyhow::Result<i64> {
+        let position = || format!("line {}, column {}", token.line, token.column);
+
+        match self.policy.overflow {
+            IntegerOverflow::Checked => i64::try_from(value)
+                .map_err(|_| anyhow::anyhow!("Integer overflow ({})", position())),
+            IntegerOverflow::Wrapping => Ok(value as i64),
+            IntegerOverflow::Saturating => Ok(value.clamp(i64::MIN as i128, i64::MAX as i128) as i64),
+        }
+    }
+
+    fn truth_operand(&mut self, node: &SemanticAst) -> anyhow::Result<bool> {
+        match self.operand(node)?.content {
+            ValueVariant::Primitive(PrimitiveValue::Bool(b)) => Ok(b),
+            _ => Err(anyhow::anyhow!("Semantic analysis error. Operand should be a truth"))
+        }
+    }
+
+    fn binary_operation(&mut self, left: &SemanticAst, operator: BinaryOperator, right: &SemanticAst, token: &Token) -> anyhow::Result<Value<'a>> {
+        let result = match operator {
+            // The right side only runs when it can change the result
+            BinaryOperator::And => self.truth_operand(left)? && self.truth_operand(right)?,
+            BinaryOperator::Or => self.truth_operand(left)? || self.truth_operand(right)?,
+            BinaryOperator::Add | BinaryOperator::Subtract | BinaryOperator::Multiply |
+            BinaryOperator::Divide | BinaryOperator::Remainder => {
+                let left = self.operand(left)?;
+                let right = self.operand(right)?;
+
+                return arithmetic(operator, &left.content, &right.content, token, self.policy.overflow)?
+                    .ok_or_else(|| self.operator_error(token, &left, &right));
+            },
+            _ => {
+                let left = self.operand(left)?;
+                let right = self.operand(right)?;
+
+                self.compare(operator, &left, &right, token)?
+            }
+        };
+
+        Ok(Value::new(ValueVariant::Primitive(PrimitiveValue::Bool(result))))
+    }
+
+    // Like `and` between each pair, so it stops at the first that's false and the operands after it don't run
+    fn chained_comparison(&mut self, first: &SemanticAst, rest: &[(BinaryOperator, SemanticNode, Token)]) -> anyhow::Result<Value<'a>> {
+        let mut left = self.operand(first)?;
+        let mut result = true;
+
+        for (operator, right, token) in rest {
+            let right = self.operand(right)?;
+            if !self.compare(*operator, &left, &right, token)? {
+                result = false;
+                break;
+            }
+
+            left = right;
+        }
+
+        Ok(Value::new(ValueVariant::Primitive(PrimitiveValue::Bool(result))))
+    }
+
+    // ==, !=, in and the orderings
+    fn compare(&self, operator: BinaryOperator, left: &Value<'a>, right: &Value<'a>, token: &Token) -> anyhow::Result<bool> {
+        Ok(match operator {
+            BinaryOperator::In => match contains(right, left)? {
+                Some(found) => found,
+                None => return Err(self.operator_error(token, left, right))
+            },
+            BinaryOperator::Equal | BinaryOperator::NotEqual => values_equal(left, right)? == (operator == BinaryOperator::Equal),
+            _ => {
+                let ordering = compare_primitives(&left.content, &right.content)
+                    .ok_or_else(|| self.operator_error(token, left, right))?;
+
+                match operator {
+                    BinaryOperator::Less => ordering.is_lt(),
+                    BinaryOperator::LessEqual => ordering.is_le(),
+                    BinaryOperator::Greater => ordering.is_gt(),
+                    _ => ordering.is_ge(),
+                }
+            }
+        })
+    }
+
+    // Only values typed any get past the analyzer with operands the operator can't take
+    fn operator_error(&self, token: &Token, left: &Value, right: &Value) -> anyhow::Error {
+        anyhow::anyhow!(
+            "Operator {} can't be applied to {} and {} (line {}, column {})",
+            token.value,
+            left.type_name(&self.semantic_analyzer),
+            right.type_name(&self.semantic_analyzer),
+            token.line,
+            token.column
+        )
+    }
 }
 
+// `None` when the operator can't take these operands
+fn arithmetic<'a>(operator: BinaryOperator, left: &ValueVariant, right: &ValueVariant, token: &Token, overflow: IntegerOverflow) -> anyhow::Result<Option<Value<'a>>> {
+    use PrimitiveValue::*;
+
+    let position = || format!("line {}, column {}", token.line, token.column);
+
+    let (left, right) = match (left, right) {
+        (ValueVariant::Primitive(left), ValueVariant::Primitive(right)) => (left, right),
+        _ => return Ok(None)
+    };
+
+    let result = match (left, right) {
+        (Text(a), Text(b)) if operator == BinaryOperator::Add => Text(a.concat(b)),
+        (Int(a), Int(b)) => {
+            if matches!(operator, BinaryOperator::Divide | BinaryOperator::Remainder) && *b == 0 {
+                return Err(anyhow::anyhow!("Division by zero ({})", position()));
+            }
+
+            Int(integer_operation(operator, *a, *b, overflow).ok_or(anyhow::anyhow!("Integer overflow ({})", position()))?)
+        },
+        (Int(_) | Dec(_), Int(_) | Dec(_)) => {
+            let as_dec = |p: &PrimitiveValue| match p { Int(i) => *i as f64, Dec(d) => *d, _ => unreachable!() };
+            let (a, b) = (as_dec(left), as_dec(right));
+
+            if matches!(operator, BinaryOperator::Divide | BinaryOperator::Remainder) && b == 0.0 {
+                return Err(anyhow::anyhow!("Division by zero ({})", position()));
+            }
+
+            Dec(match operator {
+                BinaryOperator::Add => a + b,
+                BinaryOperator::Subtract => a - b,
+                BinaryOperator::Multiply => a * b,
+                BinaryOperator::Divide => a / b,
+                _ => a % b,
+            })
+        },
+        _ => return Ok(None)
+    };
+
+    Ok(Some(Value::new(ValueVariant::Primitive(result))))
+}
+
+// `None` means it overflowed under the checked policy. The divisor is never zero.
+fn integer_operation(operator: BinaryOperator, a: i64, b: i64, overflow: IntegerOverflow) -> Option<i64> {
+    use BinaryOperator::*;
+
+    match overflow {
+        IntegerOverflow::Checked => match operator {
+            Add => a.checked_add(b),
+            Subtract => a.checked_sub(b),
+            Multiply => a.checked_mul(b),
+            Divide => a.checked_div(b),
+            _ => a.checked_rem(b),
+        },
+        IntegerOverflow::Wrapping => Some(match operator {
+            Add => a.wrapping_add(b),
+            Subtract => a.wrapping_sub(b),
+            Multiply => a.wrapping_mul(b),
+            Divide => a.wrapping_div(b),
+            _ => a.wrapping_rem(b),
+        }),
+        IntegerOverflow::Saturating => Some(match operator {
+            Add => a.saturating_add(b),
+            Subtract => a.saturating_sub(b),
+            Multiply => a.saturating_mul(b),
+            Divide => a.saturating_div(b),
+            // Only `int.min % -1` overflows, and the remainder is 0 anyway
+            _ => a.wrapping_rem(b),
+        }),
+    }
+}
+
+// `None` when `in` can't take these operands
+fn contains(container: &Value, item: &Value) -> anyhow::Result<Option<bool>> {
+    match (&container.content, &item.content) {
+        (ValueVariant::List(items), _) => {
+            for candidate in items {
+                if values_equal(candidate, item)? {
+                    return Ok(Some(true));
+                }
+            }
+
+            Ok(Some(false))
+        },
+        (ValueVariant::Map(entries), _) => Ok(Some(entries.contains_key(&Key::try_from(item)?))),
+        (ValueVariant::Primitive(PrimitiveValue::Text(text)), ValueVariant::Primitive(PrimitiveValue::Text(sub))) => Ok(Some(text.contains(sub.as_str()))),
+        _ => Ok(None)
+    }
+}
+
+fn compare_primitives(left: &ValueVariant, right: &ValueVariant) -> Option<std::cmp::Ordering> {
+    use PrimitiveValue::*;
+
+    match (left, right) {
+        (ValueVariant::Primitive(left), ValueVariant::Primitive(right)) => match (left, right) {
+            (Int(a), Int(b)) => a.partial_cmp(b),
+            (Dec(a), Dec(b)) => a.partial_cmp(b),
+            (Int(a), Dec(b)) => (*a as f64).partial_cmp(b),
+            (Dec(a), Int(b)) => a.partial_cmp(&(*b as f64)),
+            (Text(a), Text(b)) => a.partial_cmp(b),
+            (Bool(a), Bool(b)) => a.partial_cmp(b),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+fn values_equal(left: &Value, right: &Value) -> anyhow::Result<bool> {
+    match (&left.content, &right.content) {
+        (ValueVariant::Nothing, ValueVariant::Nothing) => Ok(true),
+        (ValueVariant::List(a), ValueVariant::List(b)) => {
+            if a.len() != b.len() {
+                return Ok(false);
+            }
+
+            for (a, b) in a.iter().zip(b) {
+                if !values_equal(a, b)? {
+                    return Ok(false);
+                }
+            }
+
+            Ok(true)
+        },
+        (ValueVariant::Map(a), ValueVariant::Map(b)) => {
+            if a.len() != b.len() {
+                return Ok(false);
+            }
+
+            for ((a_key, a), (b_key, b)) in a.iter().zip(b) {
+                if a_key != b_key || !values_equal(a, b)? {
+                    return Ok(false);
+                }
+            }
+
+            Ok(true)
+        },
+        (ValueVariant::Opaque(a), ValueVariant::Opaque(b)) => Ok(Arc::ptr_eq(a, b)),
+        (ValueVariant::Seq(a), ValueVariant::Seq(b)) => Ok(Arc::ptr_eq(&a.0, &b.0)),
+        (ValueVariant::Record(a), ValueVariant::Record(b)) => {
+            if a.type_id != b.type_id {
+                return Ok(false);
+            }
+
+            for ((_, a), (_, b)) in a.fields.iter().zip(&b.fields) {
+                if !values_equal(a, b)? {
+                    return Ok(false);
+                }
+            }
+
+            Ok(true)
+        },
+        (ValueVariant::Primitive(_), ValueVariant::Primitive(_)) => {
+            Ok(compare_primitives(&left.content, &right.content).is_some_and(|ordering| ordering.is_eq()))
+        },
+        (ValueVariant::Function(_), ValueVariant::Function(_)) => Err(anyhow::anyhow!("Functions can't be compared")),
+        _ => Ok(false)
+    }
+}
+
+// Copies of what closures and seqs captured, for `fork`. Each is copied once, so what was
+// shared before copying is still shared by the copies.
+#[derive(Default)]
+struct Captures {
+    environments: HashMap<*const (), Environment>,
+    sequences: HashMap<*const (), Sequence>,
+}
+
+impl Captures {
+    // The value with copies of what it captured. None if it didn't capture anything.
+    fn value<'a>(&mut self, value: &Value<'a>) -> Option<Value<'a>> {
+        let content = match &value.content {
+            ValueVariant::Function(FunctionValue::User(closure)) if closure.captured.is_some() => {
+                ValueVariant::Function(FunctionValue::User(closure.copy(&mut self.environments)))
+            },
+            ValueVariant::Seq(sequence) => ValueVariant::Seq(self.sequence(sequence)),
+            ValueVariant::List(items) => ValueVariant::List(self.values(items)?),
+            ValueVariant::Map(entries) => {
+                let values = self.values(entries.values())?;
+                ValueVariant::Map(entries.keys().cloned().zip(values).collect())
+            },
+            ValueVariant::Record(record) => {
+                let values = self.values(record.fields.iter().map(|(_, value)| value))?;
+                let fields = record.fields.iter().map(|(name, _)| name.clone()).zip(values).collect();
+                ValueVariant::Record(Record { fields, ..record.clone() })
+            },
+            _ => return None
+        };
+
+        Some(Value { content, uuid: value.uuid })
+    }
+
+    // All of the values, if any of them captured something
+    fn values<'v, 'a: 'v>(&mut self, values: impl IntoIterator<Item = &'v Value<'a>>) -> Option<Vec<Value<'a>>> {
+        let values: Vec<_> = values.into_iter().map(|value| (value, self.value(value))).collect();
+        if values.iter().all(|(_, copy)| copy.is_none()) {
+            return None;
+        }
+
+        Some(values.into_iter().map(|(value, copy)| copy.unwrap_or_else(|| value.clone())).collect())
+    }
+
+    fn sequence(&mut self, sequence: &Sequence) -> Sequence {
+        let key = Arc::as_ptr(&sequence.0) as *const ();
+        if let Some(copy) = self.sequences.get(&key) {
+            return copy.clone();
+        }
+
+        let coroutine = sequence.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let copy = Sequence(Arc::new(Mutex::new(Coroutine {
+            function: coroutine.function.clone(),
+            frame: coroutine.frame.copy(&mut self.environments),
+            resume_path: coroutine.resume_path.clone(),
+            finished: coroutine.finished
+        })));
+        self.sequences.insert(key, copy.clone());
+        copy
+    }
+}
+
+// Coroutines
+
+/// An odo function suspended at a `yield`, created with `Interpreter::spawn`.
+pub struct Coroutine {
+    function: Arc<FunctionDefinition>,
+    frame: Environment,
+    // None until the first resume
+    resume_path: Option<VecDeque<usize>>,
+    finished: bool
+}
+
+#[derive(Debug)]
+pub enum CoroutineState<'a> {
+    Yielded(Value<'a>),
+    Finished(Option<Value<'a>>)
+}
+
+impl Coroutine {
+    fn new(function: Arc<FunctionDefinition>, frame: Environment) -> Coroutine {
+        Coroutine {
+            function,
+            frame,
+            resume_path: None,
+            finished: false
+        }
+    }
+
+    /// Runs the coroutine until its next `yield`, or until the function ends.
+    pub fn resume<'a>(&mut self, interpreter: &mut Interpreter<'a>) -> anyhow::Result<CoroutineState<'a>> {
+        // A coroutine that failed can't be resumed, even when it was a panic that stopped it
+        interpreter.contained(|interpreter| interpreter.resume(self))
+            .inspect_err(|_| self.finished = true)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+impl<'a> Interpreter<'a> {
+    // Binds arguments coming from the host, which the semantic analyzer never saw
+    fn checked_frame(&mut self, function: &Closure, args: Vec<Value<'a>>) -> anyhow::Result<Environment> {
+        self.check_arguments(&args, &function.definition.parameter_types)?;
+
+        let frame = function.call_environment();
+        for (parameter, value) in function.definition.parameters.iter().zip(args) {
+            frame.declare(*parameter, value.uuid);
+            self.value_table.insert(value);
+        }
+
+        Ok(frame)
+    }
+
+    /// Prepares a call to the odo function called `name`, without running any of it.
+    pub fn spawn(&mut self, name: &str, args: Vec<Value<'a>>) -> anyhow::Result<Coroutine> {
+        let repl_scope = self.semantic_analyzer.scope(self.semantic_analyzer.repl_scope_id)?;
+        let symbol_id = repl_scope.symbol_from_name(name, &self.semantic_analyzer)
+            .ok_or(anyhow::anyhow!("Function {} not found", name))?
+            .symbol_id;
+
+        let value = self.value_id_of(symbol_id)
+            .and_then(|value_id| self.value_table.get(value_id))
+            .ok_or(anyhow::anyhow!("Function {} not found", name))?;
+
+        let closure = match &value.content {
+            ValueVariant::Function(FunctionValue::User(closure)) => closure.clone(),
+            _ => return Err(anyhow::anyhow!("{} is not a function declared in odo", name))
+        };
+
+        let frame = self.checked_frame(&closure, args)?;
+
+        Ok(Coroutine::new(closure.definition, frame))
+    }
+
+    fn resume(&mut self, coroutine: &mut Coroutine) -> anyhow::Result<CoroutineState<'a>> {
+        if coroutine.finished {
+            return Err(anyhow::anyhow!("Coroutine {} has already finished", coroutine.function.name));
+        }
+
+        self.refuel();
+        self.step(coroutine)
+    }
+
+    // Runs until the next yield. Sequences step from inside running code, so whatever that
+    // code was doing is put back afterwards.
+    fn step(&mut self, coroutine: &mut Coroutine) -> anyhow::Result<CoroutineState<'a>> {
+        let signal = self.signal.take();
+        let resume_path = std::mem::replace(&mut self.resume_path, coroutine.resume_path.take());
+        self.frames.push(std::mem::take(&mut coroutine.frame));
+        self.enter_scope(coroutine.function.scope_id);
+
+        let result = self.interpret_statements(&coroutine.function.body);
+
+        self.running_scopes.pop();
+        coroutine.frame = self.frames.pop().expect("The coroutine's frame was just pushed");
+        let own_signal = std::mem::replace(&mut self.signal, signal);
+        self.resume_path = resume_path;
+
+        if let Err(e) = result {
+            coroutine.finished = true;
+            return Err(e);
+        }
+
+        match own_signal {
+            Some(Signal::Yield(value, path)) => {
+                coroutine.resume_path = Some(path.into());
+                Ok(CoroutineState::Yielded(value))
+            },
+            Some(Signal::Return(value)) => {
+                coroutine.finished = true;
+                Ok(CoroutineState::Finished(value))
+            },
+            None => {
+                coroutine.finished = true;
+
+                // A generator's return type is the seq, which ending just finishes
+                if coroutine.function.return_id.is_some() && !coroutine.function.is_generator {
+                    return Err(anyhow::anyhow!("{} ended without returning a value", coroutine.function.name));
+                }
+
+                Ok(CoroutineState::Finished(None))
+            }
+        }
+    }
+}
+
+// Events
+
+impl<'a> Interpreter<'a> {
+    /// Calls every handler registered from odo with `on(event, handler)`, in registration order.
+    pub fn emit(&mut self, event: &str, args: Vec<Value<'a>>) -> anyhow::Result<()> {
+        self.contained(|interpreter| interpreter.emit_event(event, args))
+    }
+
+    fn emit_event(&mut self, event: &str, args: Vec<Value<'a>>) -> anyhow::Result<()> {
+        let handlers = self.event_handlers.lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(event)
+            .cloned()
+            .unwrap_or_default();
+
+        self.refuel();
+        for handler in handlers {
+            let frame = self.checked_frame(&handler, args.clone())?;
+            self.call_function_with_frame(&handler.definition, frame)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Compiled programs
+
+impl<'a> Interpreter<'a> {
+    /// Analyzes `code` as if it was evaluated, without running it or keeping what it declares.
+    pub fn compile(&mut self, code: String) -> anyhow::Result<CompiledProgram> {
+        self.contained(|interpreter| interpreter.compile_code(code))
+    }
+
+    fn compile_code(&mut self, code: String) -> anyhow::Result<CompiledProgram> {
+        let tokens = self.lexer(code).tokenize().map_err(CompileError)?;
+        let nodes = self.parser(tokens).statement_list().map_err(CompileError)?;
+
+        let scope_id = self.semantic_analyzer.repl_scope_id;
+        let checkpoint = self.semantic_analyzer.checkpoint(scope_id)?;
+        let statements = self.semantic_analyzer.in_scope(scope_id, |analyzer| nodes.into_iter()
+            .map(|node| analyzer.analyze(node).map(|result| *result.node))
+            .collect::<anyhow::Result<Vec<_>>>());
+
+        let program = statements.and_then(|statements| {
+            let (scopes, declarations) = self.semantic_analyzer.changes_since(&checkpoint)?;
+            CompiledProgram::new(&self.semantic_analyzer, scope_id, scopes, declarations, statements)
+        });
+
+        self.semantic_analyzer.rollback(&checkpoint)?;
+
+        program.map_err(|error| CompileError(error).into())
+    }
+
+    /// Runs a compiled program like `eval` would run its source.
+    pub fn run_compiled(&mut self, program: CompiledProgram) -> anyhow::Result<ExecutionResult<'a>> {
+        self.contained(|interpreter| interpreter.run_program(&program))
+    }
+
+    /// Runs a program compiled once for many interpreters, like `run_compiled`.
+    pub fn load(&mut self, program: &Program) -> anyhow::Result<ExecutionResult<'a>> {
+        self.contained(|interpreter| interpreter.run_program(program.compiled()))
+    }
+
+    fn run_program(&mut self, program: &CompiledProgram) -> anyhow::Result<ExecutionResult<'a>> {
+        let scope_id = self.semantic_analyzer.repl_scope_id;
+        let program = program.link(&mut self.semantic_analyzer, scope_id)?;
+        self.semantic_analyzer.install(program.scopes, scope_id, program.declarations)?;
+
+        let started = Instant::now();
+        self.refuel();
+
+        let depth = self.running_scopes.len();
+        self.enter_scope(scope_id);
+        self.deferred.push(Vec::new());
+
+        let mut result = Ok(None);
+        for statement in program.statements.iter() {
+            let _span = phase_span!("interpret");
+            statement_event!(statement.kind_name());
+
+            result = self.consume_fuel()
+                .and_then(|_| self.execute(statement));
+            self.dispose_blocks(statement);
+            if result.is_err() {
+                break;
+            }
+        }
+
+        let result = self.finish_deferred(result);
+        self.running_scopes.truncate(depth);
+        self.stats.record_eval(started.elapsed());
+
+        Ok(ExecutionResult { value: result? })
+    }
+}
+
+// Replay
+
+impl<'a> Interpreter<'a> {
+    /// Starts keeping every nondeterministic input the script reads, until `stop_recording`.
+    pub fn record_inputs(&mut self) {
+        self.inputs.record();
+    }
+
+    pub fn stop_recording(&mut self) -> Vec<RecordedInput> {
+        self.inputs.stop()
+    }
+
+    /// Feeds a recording back to the script instead of reading the real inputs.
+    pub fn replay_inputs(&mut self, inputs: Vec<RecordedInput>) {
+        self.inputs.replay(inputs);
+    }
+}
+
+/// A variable and its value, for listings like the repl's `:vars`.
+pub struct Variable<'a> {
+    pub name: String,
+    pub type_name: String,
+    pub value: Value<'a>,
+    // The name of the scope it's declared in
+    pub scope: String,
+}
+
+pub struct ExecutionResult<'a> {
+    pub value: Option<Value<'a>>
+}
+
+impl<'a> ExecutionResult<'a> {
+    fn primitive(&self, expected: &'static str) -> Result<&PrimitiveValue, ExtractionError> {
+        let value = self.value.as_ref().ok_or(ExtractionError::NoValue)?;
+
+        match &value.content {
+            ValueVariant::Primitive(primitive) => Ok(primitive),
+            other => Err(ExtractionError::UnexpectedVariant(expected, other.variant_name()))
+        }
+    }
+
+    pub fn as_int(&self) -> Result<i64, ExtractionError> {
+        match self.primitive("Int")? {
+            PrimitiveValue::Int(i) => Ok(*i),
+            other => Err(ExtractionError::UnexpectedVariant("Int", other.variant_name()))
+        }
+    }
+
+    pub fn as_dec(&self) -> Result<f64, ExtractionError> {
+        match self.primitive("Dec")? {
+            PrimitiveValue::Dec(d) => Ok(*d),
+            other => Err(ExtractionError::UnexpectedVariant("Dec", other.variant_name()))
+        }
+    }
+
+    pub fn as_text(&self) -> Result<&str, ExtractionError> {
+        match self.primitive("Text")? {
+            PrimitiveValue::Text(t) => Ok(t),
+            other => Err(ExtractionError::UnexpectedVariant("Text", other.variant_name()))
+        }
+    }
+
+    pub fn as_bool(&self) -> Result<bool, ExtractionError> {
+        match self.primitive("Bool")? {
+            PrimitiveValue::Bool(b) => Ok(*b),
+            other => Err(ExtractionError::UnexpectedVariant("Bool", other.variant_name()))
+        }
+    }
+
+    pub fn as_opaque<T: Any + Send + Sync>(&self) -> Result<Arc<T>, ExtractionError> {
+        self.value.as_ref().ok_or(ExtractionError::NoValue)?
+            .as_opaque()
+    }
+
+    pub fn as_list(&self) -> Result<&[Value<'a>], ExtractionError> {
+        self.value.as_ref().ok_or(ExtractionError::NoValue)?
+            .as_list()
+    }
+
+    /// Statements that don't produce a value count as nothing too.
+    pub fn is_nothing(&self) -> bool {
+        matches!(self.value, None | Some(Value { content: ValueVariant::Nothing, .. }))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Interpreter;
+    use crate::base::xref::Span;
+    use crate::exec::policy::{ExecutionPolicy, IntegerOverflow};
+    use crate::exec::value::{PrimitiveValue, Value, ValueVariant};
+
+    #[test]
+    fn test_prelude_is_visible_from_eval() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_prelude("var answer = 42\n".to_string()).unwrap();
+
+        let result = interpreter.eval("answer".to_string()).unwrap();
+
+        assert_eq!(result.as_int().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_typed_extraction() {
+        let mut interpreter = Interpreter::new();
+
+        let result = interpreter.eval("\"odo\"".to_string()).unwrap();
+        assert_eq!(result.as_text().unwrap(), "odo");
+        assert_eq!(result.as_int().unwrap_err().to_string(), "Expected a value of type Int but got Text");
+
+        let result = interpreter.eval("var x = 1".to_string()).unwrap();
+        assert!(result.is_nothing());
+    }
+
+    #[test]
+    fn test_native_module() {
+        use crate::native::function::NativeFunctionBindable;
+
+        let mut interpreter = Interpreter::new();
+        interpreter.register_module("game", |game| {
+            game.module("entities", |entities| {
+                entities.function("count", &[], Some("int"), |_| {
+                    Ok(Some(Value::new(ValueVariant::Primitive(PrimitiveValue::Int(3)))))
+                })
+            })
+        }).unwrap();
+
+        let result = interpreter.eval("game.entities.count()".to_string()).unwrap();
+        assert_eq!(result.as_int().unwrap(), 3);
+
+        assert!(interpreter.eval("game.missing()".to_string()).is_err());
+    }
+
+    // Modules by name, for tests that import without files
+    struct Sources(Vec<(&'static str, &'static str)>);
+
+    impl crate::base::modules::ModuleLoader for Sources {
+        fn resolve(&self, name: &str, _importer: Option<&std::path::Path>) -> anyhow::Result<std::path::PathBuf> {
+            self.0.iter().find(|(module, _)| *module == name)
+                .map(|(module, _)| std::path::PathBuf::from(format!("{}.odo", module)))
+                .ok_or_else(|| anyhow::anyhow!("Module {} not found", name))
+        }
+
+        fn read(&self, path: &std::path::Path) -> anyhow::Result<String> {
+            let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+            self.0.iter().find(|(module, _)| *module == name)
+                .map(|(_, source)| source.to_string())
+                .ok_or_else(|| anyhow::anyhow!("Could not read {}", path.display()))
+        }
+    }
+
+    #[test]
+    fn test_modules() {
+        let geometry = "var scale = 2\nprint(\"loaded\")\n@export\nfunc area(w: int, h: int) -> int {\n    return scale * w * h\n}";
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.eval("import geometry".to_string()).err().unwrap().to_string(), "Modules can't be imported here (line 1, column 0)");
+
+        interpreter.set_module_loader(Sources(vec![("geometry", geometry), ("peek", "var seen = secret")]));
+        let (result, output, _) = interpreter.eval_captured("import geometry\nimport geometry\ngeometry.area(2, 3)".to_string());
+        assert_eq!(result.unwrap().as_int().unwrap(), 12);
+        // Its top level runs once, however many times it's imported
+        assert_eq!(output, "loaded\n");
+
+        let error = interpreter.eval("geometry.scale".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "scale exists in module geometry, but it's private. Mark it @export to use it from other modules (line 1, column 9)");
+        let error = interpreter.eval("geometry.scael".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Module geometry has no member called scael");
+
+        // Modules don't see the code importing them
+        interpreter.eval("var secret = 1".to_string()).unwrap();
+        let error = interpreter.eval("import peek".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Variable secret not found (in module peek.odo)");
+
+        let error = interpreter.eval("func f() {\n    import geometry\n}".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Modules can only be imported at the top level (line 2, column 4)");
+        assert_eq!(interpreter.eval("import shapes".to_string()).err().unwrap().to_string(), "Module shapes not found (line 1, column 7)");
+    }
+
+    #[test]
+    fn test_circular_imports() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_module_loader(Sources(vec![("a", "var x = 1\nimport b"), ("b", "import c"), ("c", "import a"), ("main", "import main")]));
+        let error = interpreter.eval("import a".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Circular import: a.odo -> b.odo -> c.odo -> a.odo\n    a.odo imports b (line 2, column 7)\n    b.odo imports c (line 1, column 7)\n    c.odo imports a (line 1, column 7)");
+
+        // The file the host's code comes from is part of the chain too
+        interpreter.set_main_module("main.odo");
+        assert_eq!(interpreter.eval("import main".to_string()).err().unwrap().to_string(), "Circular import: main.odo -> main.odo\n    main.odo imports main (line 1, column 7)");
+
+        // Nothing is left half imported
+        assert_eq!(interpreter.eval("a.x".to_string()).err().unwrap().to_string(), "Module a isn't imported. Add `import a` before using it (line 1, column 0)");
+    }
+
+    #[test]
+    fn test_qualified_names() {
+        let shapes = "@export\ntype Point {\n    x: int\n    y: int\n}\ntype Secret {\n    code: int\n}\n@export\ncontract Shape {\n    func area(self) -> int\n}";
+        let mut interpreter = Interpreter::new();
+        interpreter.set_module_loader(Sources(vec![("shapes", shapes), ("unused", "")]));
+        interpreter.eval("import shapes".to_string()).unwrap();
+
+        // Types, constructors, patterns and contracts can all be reached through the module
+        let code = "var p: shapes.Point = shapes.Point(3, 4)\nvar shapes.Point { x, y } = p\ntype Square: shapes.Shape {\n    side: int\n    func area(self) -> int {\n        return self.side * self.side\n    }\n}\nmatch p {\n    shapes.Point { x: 3, y: b } : b + Square(x).area()\n    _ : 0\n}";
+        assert_eq!(interpreter.eval(code.to_string()).unwrap().as_int().unwrap(), 13);
+
+        let error = |interpreter: &mut Interpreter, code: &str| interpreter.eval(code.to_string()).err().unwrap().to_string();
+        assert_eq!(error(&mut interpreter, "shape.Point(1, 2)"), "Module or namespace shape not found. Did you mean `shapes`?");
+        assert_eq!(error(&mut interpreter, "unused.Point(1, 2)"), "Module unused isn't imported. Add `import unused` before using it (line 1, column 0)");
+        assert_eq!(error(&mut interpreter, "shapes.Pont(1, 2)"), "Module shapes has no member called Pont. Did you mean `Point`?");
+        assert_eq!(error(&mut interpreter, "var s: shapes.Secret = 1"), "Secret exists in module shapes, but it's private. Mark it @export to use it from other modules");
+        assert_eq!(error(&mut interpreter, "var n: p.Point = 1"), "p is not a module or namespace");
+    }
+
+    #[test]
+    fn test_choosing_the_stdlib() {
+        use crate::native::stdlib::Std;
+
+        let mut bare = Interpreter::with_stdlib(ExecutionPolicy::default(), &[]);
+        assert!(bare.semantic_analyzer.global_scope().unwrap().symbols()
+            .all(|symbol| !matches!(symbol.variant, crate::base::semantic_analyzer::SymbolVariant::NativeFunction(_))));
+        assert!(bare.eval("print(\"hi\")".to_string()).is_err());
+        assert_eq!(bare.eval("1 + 2".to_string()).unwrap().as_int().unwrap(), 3);
+
+        let mut lists = Interpreter::with_stdlib(ExecutionPolicy::default(), &[Std::Lists]);
+        lists.eval("func three() -> seq<int> {\n    yield 1\n    yield 2\n    yield 3\n}".to_string()).unwrap();
+        assert_eq!(lists.eval("take(2, three())".to_string()).unwrap().as_list().unwrap().len(), 2);
+        assert!(lists.eval("typeOf(1)".to_string()).is_err());
+
+        // Without a checkpoint, reset goes back to how it was built
+        lists.eval("var items = first([[1]])".to_string()).unwrap();
+        lists.reset();
+        assert!(lists.eval("items".to_string()).is_err());
+        assert_eq!(lists.eval("first([4])".to_string()).unwrap().as_int().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_rebinding_natives() {
+        use crate::native::function::NativeFunctionBindable;
+
+        let dec = |d| Ok(Some(Value::new(ValueVariant::Primitive(PrimitiveValue::Dec(d)))));
+        let mut interpreter = Interpreter::new();
+        interpreter.bind_function("version", &["int", "truth", "string"], Some("dec"), move |_| dec(1.0)).unwrap();
+        interpreter.eval("func current() -> dec {\n    return version(1, true, \"a\")\n}".to_string()).unwrap();
+        let symbols = |interpreter: &Interpreter| interpreter.semantic_analyzer.global_scope().unwrap().symbols().count();
+        let before = symbols(&interpreter);
+
+        // Code that already calls it gets the new one
+        interpreter.bind_function("version", &["int", "truth", "string"], Some("dec"), move |_| dec(2.0)).unwrap();
+        assert_eq!(interpreter.eval("current()".to_string()).unwrap().as_dec().unwrap(), 2.0);
+        assert_eq!(symbols(&interpreter), before);
+
+        // The old function type goes when nothing has it anymore
+        interpreter.bind_function("version", &[], Some("dec"), move |_| dec(3.0)).unwrap();
+        assert_eq!(interpreter.eval("version()".to_string()).unwrap().as_dec().unwrap(), 3.0);
+        assert_eq!(symbols(&interpreter), before - 1);
+
+        interpreter.unbind_function("version").unwrap();
+        assert!(interpreter.eval("version()".to_string()).is_err());
+        assert!(interpreter.unbind_function("version").is_err());
+        assert_eq!(symbols(&interpreter), before - 2);
+
+        interpreter.register_module("game", |game| game.function("count", &[], Some("dec"), move |_| dec(4.0))).unwrap();
+        interpreter.unbind_function("game.count").unwrap();
+        assert!(interpreter.eval("game.count()".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_opaque_handles() {
+        use crate::native::function::NativeFunctionBindable;
+
+        struct Connection { name: String }
+
+        let mut interpreter = Interpreter::new();
+        interpreter.bind_function("connect", &[], Some("opaque"), |_| {
+            Ok(Some(Value::opaque(Connection { name: "db".to_string() })))
+        }).unwrap();
+        interpreter.bind_function("connectionName", &["opaque"], Some("string"), |args| {
+            let connection = args[0].as_opaque::<Connection>()?;
+            Ok(Some(Value::new(ValueVariant::Primitive(PrimitiveValue::Text(connection.name.as_str().into())))))
+        }).unwrap();
+
+        let result = interpreter.eval("connectionName(connect())".to_string()).unwrap();
+        assert_eq!(result.as_text().unwrap(), "db");
+
+        let result = interpreter.eval("connect()".to_string()).unwrap();
+        assert!(result.as_opaque::<Connection>().is_ok());
+        assert!(result.as_opaque::<String>().is_err());
+    }
+
+    #[test]
+    fn test_stateful_native() {
+        use crate::native::function::NativeFunctionBindable;
+
+        let mut points = Vec::new();
+
+        {
+            let mut interpreter = Interpreter::new();
+            interpreter.bind_mut_function("plot", &["int"], None, |args| {
+                if let ValueVariant::Primitive(PrimitiveValue::Int(i)) = args[0].content {
+                    points.push(i);
+                }
+                Ok(None)
+            }).unwrap();
+
+            interpreter.eval("plot(1)\nplot(2)".to_string()).unwrap();
+        }
+
+        assert_eq!(points, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_functions() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("func pick(first: truth, a: string, b: string) -> string {\n    if first { return a }\n    return b\n}".to_string()).unwrap();
+
+        let result = interpreter.eval("pick(false, \"a\", \"b\")".to_string()).unwrap();
+        assert_eq!(result.as_text().unwrap(), "b");
+
+        assert!(interpreter.eval("func broken() -> int { return true }".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_coroutine() {
+        use super::CoroutineState;
+
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("func producer(first: int) {\n    yield first\n    if true {\n        yield 2\n    }\n    yield 3\n}".to_string()).unwrap();
+
+        let first = Value::new(ValueVariant::Primitive(PrimitiveValue::Int(1)));
+        let mut coroutine = interpreter.spawn("producer", vec![first]).unwrap();
+
+        for expected in 1..=3 {
+            match coroutine.resume(&mut interpreter).unwrap() {
+                CoroutineState::Yielded(Value { content: ValueVariant::Primitive(PrimitiveValue::Int(i)), .. }) => assert_eq!(i, expected),
+                other => panic!("Unexpected state {:?}", other)
+            }
+        }
+
+        assert!(matches!(coroutine.resume(&mut interpreter).unwrap(), CoroutineState::Finished(None)));
+        assert!(coroutine.is_finished());
+
+        // Yielding functions can't be called directly
+        assert!(interpreter.eval("producer(1)".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_atomic_eval() {
+        let mut interpreter = Interpreter::new();
+
+        assert!(interpreter.eval_atomic("var a = 1\nvar b = c".to_string()).is_err());
+        assert!(interpreter.eval("a".to_string()).is_err());
+
+        interpreter.eval("var x = 1".to_string()).unwrap();
+        assert!(interpreter.eval_atomic("x = 2\nvar y = 3\nvar z = 1 / 0".to_string()).is_err());
+        assert_eq!(interpreter.eval("x".to_string()).unwrap().as_int().unwrap(), 1);
+        assert!(interpreter.eval("y".to_string()).is_err());
+
+        // The names are free again
+        assert_eq!(interpreter.eval_atomic("var y = x + 1\ny".to_string()).unwrap().as_int().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut interpreter = Interpreter::new();
+
+        interpreter.eval_atomic("var a = 1".to_string()).unwrap();
+        interpreter.eval_atomic("a = 2\nvar b = 3".to_string()).unwrap();
+        // Failed lines aren't kept, so there's nothing of them to undo
+        assert!(interpreter.eval_atomic("var c = missing".to_string()).is_err());
+
+        assert!(interpreter.undo());
+        assert_eq!(interpreter.eval("a".to_string()).unwrap().as_int().unwrap(), 1);
+        assert!(interpreter.eval("b".to_string()).is_err());
+
+        assert!(interpreter.undo());
+        assert!(interpreter.eval("a".to_string()).is_err());
+        assert!(!interpreter.undo());
+    }
+
+    #[test]
+    fn test_reset_and_garbage() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("func counter(start: int) -> func() -> int {\n    var n = start\n    return func() -> int {\n        n += 1\n        return n\n    }\n}".to_string()).unwrap();
+        interpreter.checkpoint();
+
+        interpreter.eval_atomic("var next = counter(0)\nnext()".to_string()).unwrap();
+        interpreter.eval_atomic("var items = [1, 2]".to_string()).unwrap();
+        interpreter.eval_atomic("items = [3]".to_string()).unwrap();
+
+        // The old list stays while the line can still be undone
+        assert_eq!(interpreter.collect_garbage(), 0);
+        interpreter.eval("items = [4]".to_string()).unwrap();
+        assert!(interpreter.collect_garbage() > 0);
+        assert_eq!(interpreter.eval("items".to_string()).unwrap().as_list().unwrap().len(), 1);
+
+        // What closures captured stays too
+        assert_eq!(interpreter.eval("next()".to_string()).unwrap().as_int().unwrap(), 2);
+        assert!(interpreter.undo());
+        assert_eq!(interpreter.eval("items".to_string()).unwrap().as_list().unwrap().len(), 2);
+
+        interpreter.reset();
+        assert!(interpreter.eval("next".to_string()).is_err());
+        assert_eq!(interpreter.eval("counter(5)()".to_string()).unwrap().as_int().unwrap(), 6);
+    }
+
+    #[test]
+    fn test_fork() {
+        let mut template = Interpreter::new();
+        template.eval("var base = [1, 2]\nfunc double(n: int) -> int {\n    return n * 2\n}".to_string()).unwrap();
+
+        let mut first = template.fork();
+        let mut second = template.fork();
+        first.eval("base = [3]\nvar seen = 0\non(\"tick\", func(n: int) { seen = double(n) })".to_string()).unwrap();
+        assert_eq!(second.eval("base".to_string()).unwrap().as_list().unwrap().len(), 2);
+        assert!(second.eval("seen".to_string()).is_err());
+        assert!(template.eval("seen".to_string()).is_err());
+
+        // Handlers belong to the interpreter `on` ran in
+        first.emit("tick", vec![Value::new(ValueVariant::Primitive(PrimitiveValue::Int(4)))]).unwrap();
+        assert_eq!(first.eval("seen".to_string()).unwrap().as_int().unwrap(), 8);
+        assert!(template.event_handlers.lock().unwrap().is_empty());
+
+        first.reset();
+        assert_eq!(first.eval("base".to_string()).unwrap().as_list().unwrap().len(), 2);
+        // Values the forks share stay, even once the template stops using them
+        template.eval("base = [5, 6, 7]".to_string()).unwrap();
+        template.collect_garbage();
+        assert_eq!(second.eval("base".to_string()).unwrap().as_list().unwrap().len(), 2);
+        assert_eq!(second.eval("double(2)".to_string()).unwrap().as_int().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_fork_closures() {
+        let mut template = Interpreter::new();
+        template.eval("func counter(start: int) -> func() -> int {\n    var n = start\n    return func() -> int {\n        n += 1\n        return n\n    }\n}\nvar a = counter(0)\nfunc numbers() -> seq<int> {\n    for n in [1, 2, 3] {\n        yield n\n    }\n}\nvar s = numbers()".to_string()).unwrap();
+
+        let mut first = template.fork();
+        let mut second = template.fork();
+        assert_eq!(template.eval("a()".to_string()).unwrap().as_int().unwrap(), 1);
+        assert_eq!(first.eval("a()\na()".to_string()).unwrap().as_int().unwrap(), 2);
+        assert_eq!(second.eval("a()".to_string()).unwrap().as_int().unwrap(), 1);
+        assert_eq!(template.eval("a()".to_string()).unwrap().as_int().unwrap(), 2);
+        assert_eq!(first.eval("a()".to_string()).unwrap().as_int().unwrap(), 3);
+
+        // Seqs keep their place in each of them too
+        assert_eq!(first.eval("take(1, s)\nfirst(take(1, s))".to_string()).unwrap().as_int().unwrap(), 2);
+        assert_eq!(second.eval("first(take(1, s))".to_string()).unwrap().as_int().unwrap(), 1);
+        assert_eq!(template.eval("first(take(1, s))".to_string()).unwrap().as_int().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_cancellation() {
+        use crate::exec::error::RuntimeError;
+        use crate::native::function::NativeFunctionBindable;
+
+        let mut interpreter = Interpreter::new();
+        let token = interpreter.cancellation_token();
+        interpreter.bind_function("stop", &[], None, move |_| {
+            token.cancel();
+            Ok(None)
+        }).unwrap();
+
+        let (result, stdout, _) = interpreter.eval_captured("var a = 1\nfunc f() {\n    for x in [1, 2, 3] {\n        print(\"x\")\n        stop()\n    }\n}\nf()\na = 2".to_string());
+        assert_eq!(result.err().unwrap().downcast_ref::<RuntimeError>(), Some(&RuntimeError::Cancelled));
+        assert_eq!(stdout, "x\n");
+
+        // The next run starts over, from the top level
+        assert_eq!(interpreter.eval("a".to_string()).unwrap().as_int().unwrap(), 1);
+        assert!(interpreter.running_scopes().is_empty());
+    }
+
+    #[test]
+    fn test_defer() {
+        use super::CoroutineState;
+
+        let mut interpreter = Interpreter::new();
+        let f = "func f(n: int) -> int {\n    defer print(\"first\")\n    defer print(\"second\")\n    if n > 1 {\n        defer print(\"inner\")\n        return n\n    }\n    return 0\n}";
+        interpreter.eval(f.to_string()).unwrap();
+
+        // Last in, first out, and after the value to return is known
+        let (result, stdout, _) = interpreter.eval_captured("f(2)".to_string());
+        assert_eq!(result.unwrap().as_int().unwrap(), 2);
+        assert_eq!(stdout, "inner\nsecond\nfirst\n");
+
+        // Errors still run them
+        interpreter.eval("func g() -> int {\n    defer print(\"cleanup\")\n    return 1 / (1 - 1)\n}".to_string()).unwrap();
+        let (result, stdout, _) = interpreter.eval_captured("g()".to_string());
+        assert!(result.is_err());
+        assert_eq!(stdout, "cleanup\n");
+
+        let (_, stdout, _) = interpreter.eval_captured("defer print(\"end\")\nprint(\"start\")".to_string());
+        assert_eq!(stdout, "start\nend\n");
+
+        // A coroutine that stops runs them when it finishes, not when it yields
+        interpreter.eval("func producer() {\n    defer print(\"done\")\n    yield 1\n    yield 2\n}".to_string()).unwrap();
+        let mut coroutine = interpreter.spawn("producer", vec![]).unwrap();
+        let previous = interpreter.output.capture();
+        coroutine.resume(&mut interpreter).unwrap();
+        coroutine.resume(&mut interpreter).unwrap();
+        assert!(interpreter.output.release(previous).stdout.is_empty());
+
+        let previous = interpreter.output.capture();
+        assert!(matches!(coroutine.resume(&mut interpreter).unwrap(), CoroutineState::Finished(None)));
+        assert_eq!(interpreter.output.release(previous).stdout, "done\n");
+    }
+
+    #[test]
+    fn test_for() {
+        use super::CoroutineState;
+
+        let mut interpreter = Interpreter::new();
+        let countdown = "type Countdown {\n    n: int\n\
+            func done(self) -> truth {\n        return self.n == 0\n    }\n\
+            func current(self) -> int {\n        return self.n\n    }\n\
+            func next(self) -> Countdown {\n        return Countdown(self.n - 1)\n    }\n}";
+        interpreter.eval(countdown.to_string()).unwrap();
+
+        let sum = "var total = 0\nfor i in Countdown(4) {\n    total = total + i\n}\nfor x in [10, 20] {\n    total = total + x\n}\ntotal";
+        assert_eq!(interpreter.eval(sum.to_string()).unwrap().as_int().unwrap(), 40);
+
+        let first_big = "func firstBig(l: list<int>) -> int {\n    for x in l {\n        if x > 1 {\n            return x\n        }\n    }\n    return 0\n}\nfirstBig([1, 5, 7])";
+        assert_eq!(interpreter.eval(first_big.to_string()).unwrap().as_int().unwrap(), 5);
+
+        // A coroutine can stop in the middle of a loop
+        interpreter.eval("func evens() {\n    for i in Countdown(3) {\n        yield i * 2\n    }\n}".to_string()).unwrap();
+        let mut coroutine = interpreter.spawn("evens", vec![]).unwrap();
+        for expected in [6, 4, 2] {
+            match coroutine.resume(&mut interpreter).unwrap() {
+                CoroutineState::Yielded(Value { content: ValueVariant::Primitive(PrimitiveValue::Int(i)), .. }) => assert_eq!(i, expected),
+                other => panic!("Unexpected state {:?}", other)
+            }
+        }
+        assert!(matches!(coroutine.resume(&mut interpreter).unwrap(), CoroutineState::Finished(None)));
+
+        let error = interpreter.eval("for x in 3 {\n    : x\n}".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "for can't go through a value of type int. It takes lists, seqs, and types with done, current and next methods (line 1, column 0)");
+        let error = interpreter.eval("type Half {\n    func done(self) -> truth {\n        return true\n    }\n}\nfor x in Half() {\n    : x\n}".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "for can't go through Half: it needs a method current(self) to be an iterator (line 6, column 0)");
+    }
+
+    #[test]
+    fn test_number_formatting() {
+        let mut interpreter = Interpreter::new();
+        let text = |interpreter: &mut Interpreter, code: &str| interpreter.eval(code.to_string()).unwrap().as_text().unwrap().to_string();
+
+        assert_eq!(text(&mut interpreter, "toFixed(3.14159, 2)"), "3.14");
+        assert_eq!(text(&mut interpreter, "toHex(255)"), "ff");
+        assert_eq!(text(&mut interpreter, "toHex(-255)"), "-ff");
+        assert_eq!(text(&mut interpreter, "withSeparators(1234567)"), "1,234,567");
+        assert_eq!(text(&mut interpreter, "withSeparators(-9876.5)"), "-9,876.5");
+
+        let error = interpreter.eval("toFixed(1.0, -1)".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "toFixed takes from 0 to 20 places, not -1");
+
+        let (_, stdout, _) = interpreter.eval_captured(": 0.1 + 0.2".to_string());
+        assert_eq!(stdout, "0.30000000000000004\n");
+    }
+
+    #[test]
+    fn test_number_parsing() {
+        let mut interpreter = Interpreter::new();
+
+        assert_eq!(interpreter.eval("1_000_000 + parseInt(\"-2_000\")".to_string()).unwrap().as_int().unwrap(), 998000);
+        let (_, stdout, _) = interpreter.eval_captured(": 0.000_5 + parseDec(\"1.5\")".to_string());
+        assert_eq!(stdout, "1.5005\n");
+
+        let error = interpreter.eval("parseInt(\"12x4\")".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "parseInt can't read \"12x4\": unexpected 'x' at position 2");
+        let error = interpreter.eval("parseDec(\"1,5\")".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "parseDec can't read \"1,5\": unexpected ',' at position 1");
+
+        // Digits from other scripts aren't numbers
+        assert!(interpreter.eval("var a = ٣".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_sequences() {
+        let mut interpreter = Interpreter::new();
+        let naturals = "func naturalsFrom(n: int) -> seq<int> {\n    yield n\n    for x in naturalsFrom(n + 1) {\n        yield x\n    }\n}";
+        interpreter.eval(naturals.to_string()).unwrap();
+
+        // Only as much of an endless sequence as is needed runs
+        assert!(interpreter.eval("take(3, naturalsFrom(1)) == [1, 2, 3]".to_string()).unwrap().as_bool().unwrap());
+
+        // Copies share where they are
+        interpreter.eval("var numbers = naturalsFrom(0)\nvar skipped = take(2, numbers)".to_string()).unwrap();
+        assert_eq!(interpreter.eval("first(take(1, numbers))".to_string()).unwrap().as_int().unwrap(), 2);
+
+        let squares = "func squares(upTo: int) -> seq<int> {\n    for x in naturalsFrom(1) {\n        if x > upTo {\n            return\n        }\n        yield x * x\n    }\n}\n\
+            var total = 0\nfor square in squares(4) {\n    total = total + square\n}\ntotal";
+        assert_eq!(interpreter.eval(squares.to_string()).unwrap().as_int().unwrap(), 30);
+
+        let error = interpreter.eval("func words() -> seq<int> {\n    yield \"word\"\n}".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Type mismatch: Expected type \"int\" but got type \"string\"");
+    }
+
+    #[test]
+    fn test_event_handlers() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var last = 0\non(\"tick\", func(dt: int) { last = dt })".to_string()).unwrap();
+
+        interpreter.emit("tick", vec![Value::new(ValueVariant::Primitive(PrimitiveValue::Int(16)))]).unwrap();
+        assert_eq!(interpreter.eval("last".to_string()).unwrap().as_int().unwrap(), 16);
+
+        // Handlers check what the host sends them
+        assert!(interpreter.emit("tick", vec![]).is_err());
+        assert!(interpreter.emit("unknown", vec![]).is_ok());
+    }
+
+    #[test]
+    fn test_fuel_limit() {
+        let policy = ExecutionPolicy::default().fuel(Some(3));
+        let mut interpreter = Interpreter::with_policy(policy);
+
+        assert!(interpreter.eval("var a = 1\na = 2\na = 3".to_string()).is_ok());
+        // Fuel is per call, so the next eval starts again
+        assert!(interpreter.eval("a = 1".to_string()).is_ok());
+
+        let result = interpreter.eval("func f() { a = 1\na = 2 }\nf()".to_string());
+        assert!(result.is_err_and(|e| e.to_string().contains("Out of fuel")));
+    }
+
+    #[test]
+    fn test_call_depth_limit() {
+        use crate::exec::error::RuntimeError;
+
+        // Recursion that never stops is an error, with the default policy too
+        let mut interpreter = Interpreter::new();
+        let error = interpreter.eval("func f(n: int) -> int {\n    return f(n + 1)\n}\nf(0)".to_string()).err().unwrap();
+        assert_eq!(error.downcast_ref::<RuntimeError>(), Some(&RuntimeError::CallDepth(crate::exec::policy::MAX_CALL_DEPTH)));
+        // And the interpreter can keep going after it
+        assert_eq!(interpreter.eval("func g(n: int) -> int {\n    if n == 0 { return 0 }\n    return g(n - 1)\n}\ng(100)".to_string()).unwrap().as_int().unwrap(), 0);
+
+        let mut interpreter = Interpreter::with_policy(ExecutionPolicy::default().max_call_depth(50));
+        assert!(interpreter.eval("func g(n: int) -> int {\n    if n == 0 { return 0 }\n    return g(n - 1)\n}\ng(40)".to_string()).is_ok());
+        assert!(interpreter.eval("g(60)".to_string()).is_err_and(|e| e.downcast_ref::<RuntimeError>() == Some(&RuntimeError::CallDepth(50))));
+    }
+
+    #[test]
+    fn test_replay() {
+        let code = "var t = time()\nvar r = random()".to_string();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.record_inputs();
+        interpreter.eval(code.clone()).unwrap();
+        let recorded = interpreter.eval("r".to_string()).unwrap().as_dec().unwrap();
+        let inputs = interpreter.stop_recording();
+        assert_eq!(inputs.len(), 2);
+
+        let mut replayed = Interpreter::new();
+        replayed.replay_inputs(inputs.clone());
+        replayed.eval(code).unwrap();
+        assert_eq!(replayed.eval("r".to_string()).unwrap().as_dec().unwrap(), recorded);
+
+        // Reading in a different order than the recording is an error
+        let mut diverged = Interpreter::new();
+        diverged.replay_inputs(inputs);
+        assert!(diverged.eval("random()".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_with_block() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var total = 0\nwith a = 1, b = 2 {\n  total = b\n}".to_string()).unwrap();
+
+        assert_eq!(interpreter.eval("total".to_string()).unwrap().as_int().unwrap(), 2);
+        // The temporaries don't outlive the block
+        assert!(interpreter.eval("a".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var x = 5".to_string()).unwrap();
+
+        let cases = [
+            ("0 <= x < 10", true),
+            ("0 <= x < 5", false),
+            ("1 < 2 < 3 < 4", true),
+            ("x == 5 and x != 4", true),
+            ("x > 10 or \"a\" < \"b\"", true),
+        ];
+
+        for (code, expected) in cases {
+            assert_eq!(interpreter.eval(code.to_string()).unwrap().as_bool().unwrap(), expected, "{}", code);
+        }
+
+        assert!(interpreter.eval("1 < \"a\"".to_string()).is_err());
+        assert!(interpreter.eval("1 and true".to_string()).is_err());
+
+        // The middle of a chain runs once, and what's after a false comparison doesn't run
+        assert!(interpreter.eval("0 < \"a\" < 10".to_string()).is_err());
+        let (result, stdout, _) = interpreter.eval_captured("func f() -> int {\n    print(\"called\")\n    return x\n}\nvar ok = 0 <= f() < 10\nvar skipped = 10 < x < f()\n: ok".to_string());
+        result.unwrap();
+        assert_eq!(stdout, "called\ntrue\n");
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let mut interpreter = Interpreter::new();
+
+        assert_eq!(interpreter.eval("1 + 2 * 3".to_string()).unwrap().as_int().unwrap(), 7);
+        assert_eq!(interpreter.eval("7 / 2".to_string()).unwrap().as_int().unwrap(), 3);
+        assert_eq!(interpreter.eval("-7 / 2".to_string()).unwrap().as_int().unwrap(), -3);
+        assert_eq!(interpreter.eval("-7 % 3".to_string()).unwrap().as_int().unwrap(), -1);
+        assert_eq!(interpreter.eval("7 / 2.0".to_string()).unwrap().as_dec().unwrap(), 3.5);
+        assert_eq!(interpreter.eval("\"a\" + \"b\"".to_string()).unwrap().as_text().unwrap(), "ab");
+
+        interpreter.eval("var zero = 0".to_string()).unwrap();
+        let error = interpreter.eval("1 / zero".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Division by zero (line 1, column 2)");
+
+        // Literal zeros are caught before running
+        assert!(interpreter.semantic_analyzer.take_warnings().is_empty());
+        assert!(interpreter.eval("1 % 0".to_string()).is_err());
+        assert_eq!(interpreter.semantic_analyzer.take_warnings().len(), 1);
+    }
+
+    #[test]
+    fn test_overflow_policy() {
+        let code = "9223372036854775807 + 1".to_string();
+
+        let mut checked = Interpreter::new();
+        assert!(checked.eval(code.clone()).is_err_and(|e| e.to_string().starts_with("Integer overflow")));
+
+        let mut wrapping = Interpreter::with_policy(ExecutionPolicy::default().overflow(IntegerOverflow::Wrapping));
+        assert_eq!(wrapping.eval(code.clone()).unwrap().as_int().unwrap(), i64::MIN);
+        assert_eq!(wrapping.eval("9223372036854775808".to_string()).unwrap().as_int().unwrap(), i64::MIN);
+
+        let mut saturating = Interpreter::with_policy(ExecutionPolicy::default().overflow(IntegerOverflow::Saturating));
+        assert_eq!(saturating.eval(code).unwrap().as_int().unwrap(), i64::MAX);
+        assert_eq!(saturating.eval("-9223372036854775807 - 2".to_string()).unwrap().as_int().unwrap(), i64::MIN);
+    }
+
+    #[test]
+    fn test_source_limits() {
+        let policy = ExecutionPolicy::default()
+            .max_tokens(Some(8))
+            .max_depth(Some(3))
+            .max_text_length(Some(5));
+        let mut interpreter = Interpreter::with_policy(policy);
+
+        let error = |interpreter: &mut Interpreter, code: &str| interpreter.eval(code.to_string()).err().unwrap().to_string();
+
+        assert_eq!(error(&mut interpreter, "var a = 1 + 2 + 3 + 4"), "The code has more than 8 tokens (line 1, column 18)");
+        assert_eq!(error(&mut interpreter, "var b = ((1))"), "Code is nested more than 3 levels deep (line 1, column 10)");
+        assert_eq!(error(&mut interpreter, "var c = \"sixsix\""), "String literal longer than 5 characters (line 1, column 8)");
+
+        // Nothing that failed the limits ran, and code within them still does
+        assert!(interpreter.eval("var d = (\"five!\")".to_string()).is_ok());
+        assert!(interpreter.eval("a".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("func f(x: int) -> int {\n    { return x + 1 }\n}".to_string()).unwrap();
+        interpreter.reset_stats();
+
+        interpreter.eval("var a = f(1)".to_string()).unwrap();
+        let stats = interpreter.stats();
+
+        // Declaration, call, callee, argument, block, return and the addition with its two operands
+        assert_eq!(stats.nodes_evaluated, 9);
+        // The sum that ends up in `a`. The argument and the 1 added to it are cached.
+        assert_eq!(stats.values_allocated, 1);
+        assert_eq!((stats.cache_hits, stats.cache_misses), (2, 0));
+        // The repl scope, the function's and its block's
+        assert_eq!(stats.peak_scope_depth, 3);
+        assert_eq!(stats.evaluations, 1);
+        assert!(stats.longest_eval_time <= stats.total_eval_time);
+        assert!(stats.live_symbols > 0);
+
+        interpreter.reset_stats();
+        assert_eq!(interpreter.stats().nodes_evaluated, 0);
+        assert_eq!(interpreter.stats().values_allocated, 0);
+
+        // Shared values stay through garbage collection, and big ints and decs aren't shared
+        interpreter.eval("var flags = [true, false, true]".to_string()).unwrap();
+        interpreter.collect_garbage();
+        interpreter.eval("var big = [true, 5000, 1.5]".to_string()).unwrap();
+        let stats = interpreter.stats();
+        assert_eq!((stats.cache_hits, stats.cache_misses), (4, 2));
+        assert_eq!(stats.values_allocated, 2);
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var count = 1\nvar counter = 2\nfunc total(x: int) -> int { return x }".to_string()).unwrap();
+
+        let error = |interpreter: &mut Interpreter, code: &str| interpreter.eval(code.to_string()).err().unwrap().to_string();
+
+        assert_eq!(error(&mut interpreter, "countr"), "Variable countr not found. Did you mean `count` or `counter`?");
+        assert_eq!(error(&mut interpreter, "totl(1)"), "Variable totl not found. Did you mean `total`?");
+        assert_eq!(error(&mut interpreter, "cuont = 2"), "Variable cuont not found. Did you mean `count`?");
+        assert_eq!(error(&mut interpreter, "func f(x: itn) {}"), "Type itn not found. Did you mean `int`?");
+        assert_eq!(error(&mut interpreter, "zzz"), "Variable zzz not found");
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var x = 10\nx += 5\nx -= 1\nx *= 2\nx /= 4\nx %= 4".to_string()).unwrap();
+
+        assert_eq!(interpreter.eval("x".to_string()).unwrap().as_int().unwrap(), 3);
+
+        let error = interpreter.eval("x++".to_string()).err().unwrap();
+        assert!(error.to_string().contains("Use `x += 1` instead"));
+        assert!(interpreter.eval("--x".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_multiple_declaration() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var x = 1, y = \"two\"\nvar a, b = 3, 4".to_string()).unwrap();
+
+        assert_eq!(interpreter.eval("y".to_string()).unwrap().as_text().unwrap(), "two");
+        assert_eq!(interpreter.eval("x + a + b".to_string()).unwrap().as_int().unwrap(), 8);
+
+        assert!(interpreter.eval("var c, d = 1".to_string()).is_err());
+        assert!(interpreter.eval("var e = 1, e = 2".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_multiple_assignment() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var a, b = 1, 2\na, b = b, a".to_string()).unwrap();
+
+        assert_eq!(interpreter.eval("a".to_string()).unwrap().as_int().unwrap(), 2);
+        assert_eq!(interpreter.eval("b".to_string()).unwrap().as_int().unwrap(), 1);
+
+        assert!(interpreter.eval("a, b = 1".to_string()).is_err());
+        assert!(interpreter.eval("a, b = 1, \"two\"".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_in_operator() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var xs = [1, 2, (1 + 2)]\nvar ages = {\"ana\": 30, \"luis\": 28}".to_string()).unwrap();
+
+        let cases = [
+            ("3 in xs", true),
+            ("4 in xs", false),
+            ("\"ana\" in ages", true),
+            ("\"bob\" in ages", false),
+            ("\"od\" in \"odo\"", true),
+            ("[1] in [[1], [2]]", true),
+        ];
+
+        for (code, expected) in cases {
+            assert_eq!(interpreter.eval(code.to_string()).unwrap().as_bool().unwrap(), expected, "{}", code);
+        }
+
+        assert!(interpreter.eval("1.5 in ages".to_string()).is_err());
+        assert!(interpreter.eval("1 in 2".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_spread() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("func add(a: int, b: int, c: int) -> int { return a + b + c }\nvar rest = [2, 3]".to_string()).unwrap();
+
+        assert_eq!(interpreter.eval("add(1, ...rest)".to_string()).unwrap().as_int().unwrap(), 6);
+        assert_eq!(interpreter.eval("[0, ...rest, 4]".to_string()).unwrap().as_list().unwrap().len(), 4);
+
+        // Arity and types are checked once the list is known
+        assert!(interpreter.eval("add(...rest)".to_string()).is_err());
+        assert!(interpreter.eval("add(1, ...[\"two\", 3])".to_string()).is_err());
+        assert!(interpreter.eval("add(1, ...2)".to_string()).is_err());
+        assert!(interpreter.eval("var x = ...rest".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_type_of() {
+        let mut interpreter = Interpreter::new();
+
+        let result = interpreter.eval("typeOf(true)".to_string()).unwrap();
+        let value = result.value.unwrap();
+
+        assert!(matches!(value.content, ValueVariant::Primitive(PrimitiveValue::Text(ref name)) if name == "truth"));
+        assert_eq!(value.type_name(&interpreter.semantic_analyzer), "string");
+    }
+
+    #[test]
+    fn test_type_at() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var n = 2".to_string()).unwrap();
+
+        let code = "var half = n / 2.0\nhalf > 1";
+        let at = |interpreter: &mut Interpreter, needle: &str| interpreter.type_at(code, code.find(needle).unwrap());
+
+        let n = at(&mut interpreter, "n /").unwrap();
+        assert_eq!(n.type_name, "int");
+        assert_eq!(n.declaration, Some(Span { line: 1, column: 4, length: 1 }));
+
+        assert_eq!(at(&mut interpreter, "/").unwrap().type_name, "dec");
+        assert_eq!(at(&mut interpreter, "2.0").unwrap().type_name, "dec");
+        assert_eq!(at(&mut interpreter, ">").unwrap().type_name, "truth");
+
+        let half = at(&mut interpreter, "half >").unwrap();
+        assert_eq!(half.declaration, Some(Span { line: 1, column: 4, length: 4 }));
+
+        // Nothing from the buffer is kept
+        assert!(interpreter.eval("half".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_compiled_program() {
+        let code = "func double(x: int) -> int {\n    return x * 2\n}\nvar check = func(s: string, n: int) -> truth { return typeOf(n) == s }\nvar ok = check(\"int\", 1)\ndouble(10)";
+
+        let mut compiler = Interpreter::new();
+        let program = compiler.compile(code.to_string()).unwrap();
+        // Nothing is declared by compiling
+        assert!(compiler.eval("double".to_string()).is_err());
+
+        let path = std::env::temp_dir().join(format!("odo-test-{}.odoc", uuid::Uuid::new_v4()));
+        program.save(&path).unwrap();
+        let program = crate::exec::compiled::CompiledProgram::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut interpreter = Interpreter::new();
+        let result = interpreter.run_compiled(program).unwrap();
+        assert_eq!(result.as_int().unwrap(), 20);
+        assert!(interpreter.eval("ok".to_string()).unwrap().as_bool().unwrap());
+        assert_eq!(interpreter.eval("double(4)".to_string()).unwrap().as_int().unwrap(), 8);
+    }
+
+    #[test]
+    fn test_program() {
+        use crate::exec::compiled::Program;
+        use crate::native::function::NativeFunctionBindable;
+
+        fn shareable<T: Send + Sync>(_: &T) {}
+
+        let prelude = Program::compile("var greeting = \"hi\"\nfunc shout(s: string) -> string {\n    return s + \"!\"\n}").unwrap();
+        shareable(&prelude);
+
+        let results: Vec<String> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..2).map(|_| scope.spawn(|| {
+                let mut interpreter = prelude.instantiate().unwrap();
+                interpreter.eval("greeting = shout(greeting)".to_string()).unwrap();
+                interpreter.eval("greeting".to_string()).unwrap().as_text().unwrap().to_string()
+            })).collect();
+
+            threads.into_iter().map(|thread| thread.join().unwrap()).collect()
+        });
+        assert_eq!(results, ["hi!", "hi!"]);
+
+        // Natives the program uses are bound by the host first
+        let mut compiler = Interpreter::new();
+        compiler.bind_function("answer", &[], Some("int"), |_| Ok(Some(Value::new(ValueVariant::Primitive(PrimitiveValue::Int(42)))))).unwrap();
+        let program = Program::from(compiler.compile("var doubled = answer() * 2".to_string()).unwrap());
+        assert!(program.instantiate().is_err());
+
+        // Natives bound by the same name get the same id, so linking doesn't copy the statements
+        let mut host = Interpreter::new();
+        host.bind_function("answer", &[], Some("int"), |_| Ok(None)).unwrap();
+        let scope_id = host.semantic_analyzer.repl_scope_id;
+        let linked = program.compiled().link(&mut host.semantic_analyzer, scope_id).unwrap();
+        assert!(std::sync::Arc::ptr_eq(&linked.statements, &program.compiled().statements));
+
+        let mut interpreter = program.instantiate_with(|interpreter| {
+            interpreter.bind_function("answer", &[], Some("int"), |_| Ok(Some(Value::new(ValueVariant::Primitive(PrimitiveValue::Int(21))))))
+        }).unwrap();
+        interpreter.eval("doubled = 0".to_string()).unwrap();
+        interpreter.reset();
+        assert_eq!(interpreter.eval("doubled".to_string()).unwrap().as_int().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_block_scopes_are_disposed() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var f = func() -> int { return 0 }".to_string()).unwrap();
+        let scopes = interpreter.semantic_analyzer.scope_count();
+
+        for _ in 0..10 {
+            interpreter.eval("with a = 20 {\n    var b = a + 1\n    f = func() -> int {\n        { return 1 }\n    }\n}".to_string()).unwrap();
+            // Failing halfway through a block disposes it too
+            assert!(interpreter.eval("{ var c = 1 / 0 }".to_string()).is_err());
+        }
+
+        // Each function keeps its own scope and the block inside it
+        assert_eq!(interpreter.semantic_analyzer.scope_count(), scopes + 20);
+        assert_eq!(interpreter.eval("f()".to_string()).unwrap().as_int().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_failures_leave_scopes_alone() {
+        let mut interpreter = Interpreter::new();
+        let scope_id = interpreter.semantic_analyzer.current_scope_id;
+
+        // Failing to analyze, and failing to run, inside nested blocks and a function
+        assert!(interpreter.eval("{\n    {\n        var a = 1 + \"one\"\n    }\n}".to_string()).is_err());
+        assert!(interpreter.eval("func f() -> int {\n    { return 1 / 0 }\n}\n{ var b = f() }".to_string()).is_err());
+
+        assert_eq!(interpreter.semantic_analyzer.current_scope_id, scope_id);
+        assert!(interpreter.running_scopes().is_empty());
+
+        interpreter.eval("var c = 2".to_string()).unwrap();
+        assert_eq!(interpreter.eval("c".to_string()).unwrap().as_int().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_closures() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("func counter(start: int) -> func() -> int {\n    var n = start\n    return func() -> int {\n        n += 1\n        return n\n    }\n}".to_string()).unwrap();
+        interpreter.eval("var a = counter(0)\nvar b = counter(10)".to_string()).unwrap();
+
+        // Each call gets its own `n`, which outlives it
+        for expected in [1, 2, 3] {
+            assert_eq!(interpreter.eval("a()".to_string()).unwrap().as_int().unwrap(), expected);
+        }
+        assert_eq!(interpreter.eval("b()".to_string()).unwrap().as_int().unwrap(), 11);
+
+        // Captures are by reference, so closures from the same call share what they capture
+        interpreter.eval("var get = func() -> int { return 0 }\nvar set = func(x: int) {}\nfunc cell() {\n    var value = 1\n    get = func() -> int { return value }\n    set = func(x: int) { value = x }\n    value = 2\n}\ncell()".to_string()).unwrap();
+        assert_eq!(interpreter.eval("get()".to_string()).unwrap().as_int().unwrap(), 2);
+        interpreter.eval("set(5)".to_string()).unwrap();
+        assert_eq!(interpreter.eval("get()".to_string()).unwrap().as_int().unwrap(), 5);
+
+        // Locals of a top-level block outlive it too
+        interpreter.eval("{\n    var base = 100\n    get = func() -> int { return base }\n}".to_string()).unwrap();
+        assert_eq!(interpreter.eval("get()".to_string()).unwrap().as_int().unwrap(), 100);
+    }
+
+    #[test]
+    fn test_variable_slots() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var total = 0\nvar scale = 10".to_string()).unwrap();
+
+        // Top-level loops, blocks and matches read their locals from slots, and globals by symbol
+        interpreter.eval("for x in [1, 2, 3] {\n    var y = x * scale\n    {\n        var z = y + x\n        total += z\n    }\n}".to_string()).unwrap();
+        assert_eq!(interpreter.eval("total".to_string()).unwrap().as_int().unwrap(), 66);
+        assert_eq!(interpreter.eval("match [4, 5] {\n    [a, b] : a * b + scale\n    _ : 0\n}".to_string()).unwrap().as_int().unwrap(), 30);
+
+        // Functions inside functions read locals a few environments out
+        interpreter.eval("func outer(a: int) -> func(int) -> func() -> int {\n    var b = a * 2\n    return func(c: int) -> func() -> int {\n        var d = c + 1\n        return func() -> int { return a + b + d + scale }\n    }\n}".to_string()).unwrap();
+        assert_eq!(interpreter.eval("outer(1)(2)()".to_string()).unwrap().as_int().unwrap(), 16);
+
+        // Generators keep their slots between yields
+        interpreter.eval("func steps(from: int) -> seq<int> {\n    var at = from\n    for i in [1, 2] {\n        at += i\n        yield at\n    }\n}\nvar seen = 0\nfor s in steps(5) {\n    seen = seen * 100 + s\n}".to_string()).unwrap();
+        assert_eq!(interpreter.eval("seen".to_string()).unwrap().as_int().unwrap(), 608);
+    }
+
+    #[test]
+    fn test_captured_output() {
+        let mut interpreter = Interpreter::new();
+        let (result, stdout, stderr) = interpreter.eval_captured("print(\"hi\")\nprintError(\"oops\")\n: 1".to_string());
+
+        assert!(result.is_ok());
+        assert_eq!(stdout, "hi\n1\n");
+        assert_eq!(stderr, "oops\n");
+
+        // Each eval gets its own buffers
+        let (_, stdout, stderr) = interpreter.eval_captured("print(\"again\")".to_string());
+        assert_eq!(stdout, "again\n");
+        assert!(stderr.is_empty());
+    }
+
+    #[test]
+    fn test_reading_input() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_input(Box::new(std::io::Cursor::new("Ada\n 41 \nforty\n")));
+
+        assert_eq!(interpreter.eval("readLine()".to_string()).unwrap().as_text().unwrap(), "Ada");
+        assert_eq!(interpreter.eval("readInt() + 1".to_string()).unwrap().as_int().unwrap(), 42);
+        assert!(interpreter.eval("readInt()".to_string()).is_err());
+
+        // Nothing left
+        assert_eq!(interpreter.eval("readLine()".to_string()).unwrap().as_text().unwrap(), "");
+        assert!(interpreter.eval("readInt()".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_panics_are_contained() {
+        use crate::exec::error::RuntimeError;
+        use crate::native::function::NativeFunctionBindable;
+
+        let mut interpreter = Interpreter::new();
+        interpreter.bind_function("broken", &[], Some("int"), |_| panic!("broken native")).unwrap();
+        interpreter.eval("var kept = 1\nfunc call() -> int {\n    {\n        return broken()\n    }\n}".to_string()).unwrap();
+
+        let error = interpreter.eval("call()".to_string()).err().unwrap();
+        assert_eq!(error.downcast_ref::<RuntimeError>(), Some(&RuntimeError::Internal("broken native".to_string())));
+        assert!(interpreter.running_scopes().is_empty());
+
+        // The interpreter is still usable after it
+        assert_eq!(interpreter.eval("kept + 1".to_string()).unwrap().as_int().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_strict_mode() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var x = 1".to_string()).unwrap();
+        interpreter.set_strict(true);
+
+        for code in ["1 + 2.5", "func f(x: int) {}", "func g(value: any) {}", "func h() -> func(any) {\n    return func(v: int) {}\n}", "if false {\n    : 1\n}"] {
+            assert!(interpreter.eval(code.to_string()).is_err(), "{} should fail in strict mode", code);
+        }
+        assert!(interpreter.semantic_analyzer.take_warnings().is_empty());
+
+        assert_eq!(interpreter.eval("1.0 + 2.5".to_string()).unwrap().as_dec().unwrap(), 3.5);
+
+        interpreter.set_strict(false);
+        assert!(interpreter.eval("func f(x: int) -> dec {\n    return x + 0.5\n}".to_string()).is_ok());
+    }
+
+    #[test]
+    fn test_any_type() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var x: any = 1\nvar n: int = 2\nfunc double(v: int) -> int {\n    return v * 2\n}".to_string()).unwrap();
+
+        assert_eq!(interpreter.eval("double(x) + n".to_string()).unwrap().as_int().unwrap(), 4);
+        interpreter.eval("x = \"text\"".to_string()).unwrap();
+        assert_eq!(interpreter.eval("x + \"!\"".to_string()).unwrap().as_text().unwrap(), "text!");
+
+        // Checked where it's used instead
+        let error = interpreter.eval("n = x".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Type mismatch: Expected type \"int\" but got type \"string\" (line 1, column 4)");
+        let error = interpreter.eval("x * 2".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Operator * can't be applied to string and int (line 1, column 2)");
+
+        assert!(interpreter.eval("var m: int = \"text\"".to_string()).is_err());
+
+        interpreter.set_strict(true);
+        assert!(interpreter.eval("var y: any = 1".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_generics() {
+        use crate::native::function::NativeFunctionBindable;
+
+        let mut interpreter = Interpreter::new();
+        interpreter.bind_function("apply", &["<T:U>", "T"], Some("U"), |mut args| Ok(args.pop())).unwrap();
+        interpreter.eval("func half(n: int) -> dec {\n    return n / 2.0\n}\nvar names: list<string> = [\"a\"]".to_string()).unwrap();
+
+        // What T and U stand for comes from the arguments, and decides the return type
+        assert!(interpreter.eval("var d: dec = apply(half, 4)".to_string()).is_ok());
+        let error = interpreter.eval("apply(half, \"4\")".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Type mismatch: Expected type \"int\" but got type \"string\"");
+
+        assert_eq!(interpreter.eval("first(append(names, \"b\"))".to_string()).unwrap().as_text().unwrap(), "a");
+        let error = interpreter.eval("append(names, 1)".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Type mismatch: Expected type \"string\" but got type \"int\"");
+        let error = interpreter.eval("var n: int = first(names)".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Type mismatch: Expected type \"int\" but got type \"string\"");
+
+        // Lists of ints aren't lists of strings, while what plain lists hold is checked when running
+        let error = interpreter.eval("names = [1]".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Type mismatch: Expected type \"list<string>\" but got type \"list<int>\"");
+        assert!(interpreter.eval("var xs: list<int> = [\"a\"]".to_string()).is_err());
+        assert!(interpreter.eval("var plain: list = [\"b\"]\nnames = plain\nnames = []".to_string()).is_ok());
+        let error = interpreter.eval("plain = [1]\nnames = plain".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Type mismatch: Expected type \"list<string>\" but got type \"list\" (line 2, column 8)");
+
+        // Functions that take a list of ints don't get one of strings, told by the items or when running
+        interpreter.eval("func head(x: list<int>) -> int {\n    return first(x)\n}".to_string()).unwrap();
+        let error = interpreter.eval("head([\"zz\"])".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Type mismatch: Expected type \"list<int>\" but got type \"list<string>\"");
+        assert!(interpreter.eval("head(plain)".to_string()).is_ok());
+        assert!(interpreter.eval("head([1, \"zz\"])".to_string()).is_err());
+        assert_eq!(interpreter.eval("head([2, 3])".to_string()).unwrap().as_int().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_contracts() {
+        let mut interpreter = Interpreter::new();
+        let types = "contract Printable {\n    func show(self) -> string\n}\n\
+            type Point: Printable {\n    x: int\n    y: int\n\n    func show(self) -> string {\n        return \"point\"\n    }\n}\n\
+            type Label: Printable {\n    text: string\n    func show(self) -> string {\n        return self.text\n    }\n}\n\
+            func describe(value: Printable) -> string {\n    return value.show()\n}";
+        interpreter.eval(types.to_string()).unwrap();
+
+        // Each value gets the method of its own type
+        assert_eq!(interpreter.eval("describe(Point(1, 2))".to_string()).unwrap().as_text().unwrap(), "point");
+        assert_eq!(interpreter.eval("describe(Label(\"hi\"))".to_string()).unwrap().as_text().unwrap(), "hi");
+        assert_eq!(interpreter.eval("Point(1, 2).y".to_string()).unwrap().as_int().unwrap(), 2);
+        assert_eq!(interpreter.eval("typeOf(Label(\"hi\"))".to_string()).unwrap().as_text().unwrap(), "Label");
+        assert!(interpreter.eval("Point(1, 2) == Point(1, 2)".to_string()).unwrap().as_bool().unwrap());
+
+        let error = interpreter.eval("type Empty: Printable {\n    x: int\n}".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Empty says it follows Printable, but has no method called show (line 1, column 5)");
+        let error = interpreter.eval("type Wrong: Printable {\n    func show(self) -> int {\n        return 1\n    }\n}".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Wrong.show has type <Wrong:int> but Printable needs <Printable:string> (line 1, column 5)");
+        let error = interpreter.eval("type Plain {\n    x: int\n}\ndescribe(Plain(1))".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Type mismatch: Expected type \"Printable\" but got type \"Plain\"");
+
+        // The call in `describe` remembers the method it found for each type in the calls above
+        interpreter.reset_stats();
+        for _ in 0..3 {
+            interpreter.eval("describe(Point(1, 2))\ndescribe(Label(\"hi\"))".to_string()).unwrap();
+        }
+        assert_eq!((interpreter.stats().call_site_hits, interpreter.stats().call_site_misses), (6, 0));
+
+        // Even for types declared after it, and after those are undone
+        interpreter.checkpoint();
+        interpreter.eval_atomic("type Tag: Printable {\n    func show(self) -> string {\n        return \"tag\"\n    }\n}".to_string()).unwrap();
+        assert_eq!(interpreter.eval("describe(Tag())".to_string()).unwrap().as_text().unwrap(), "tag");
+        assert!(interpreter.undo());
+        interpreter.eval("type Tag: Printable {\n    func show(self) -> string {\n        return \"new tag\"\n    }\n}".to_string()).unwrap();
+        assert_eq!(interpreter.eval("describe(Tag())".to_string()).unwrap().as_text().unwrap(), "new tag");
+    }
+
+    #[test]
+    fn test_to_text() {
+        let mut interpreter = Interpreter::new();
+        let types = "type Point {\n    x: int\n    y: int\n    func toText(self) -> string {\n        return \"(\" + withSeparators(self.x) + \", \" + withSeparators(self.y) + \")\"\n    }\n}\n\
+            type Plain {\n    x: int\n}\n\
+            type Broken {\n    func toText(self) -> string {\n        return withSeparators(1 / 0)\n    }\n}";
+        interpreter.eval(types.to_string()).unwrap();
+
+        let mut shown = |code: &str| {
+            let value = interpreter.eval(code.to_string()).unwrap().value.unwrap();
+            interpreter.format(&value)
+        };
+        assert_eq!(shown("[Point(1, 2), Point(3, 4)]"), "[(1, 2), (3, 4)]");
+        assert_eq!(shown("Plain(1)"), "Plain(x: 1)");
+        // A failing toText doesn't keep the value from being shown
+        assert_eq!(shown("Broken()"), "Broken()");
+
+        let (result, stdout, _) = interpreter.eval_captured(": Point(5, 6)".to_string());
+        assert!(result.is_ok());
+        assert_eq!(stdout, "(5, 6)\n");
+        assert!(interpreter.eval(": Broken()".to_string()).is_err());
+
+        let error = interpreter.eval("type Wrong {\n    func toText(self) -> int {\n        return 1\n    }\n}".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Wrong.toText should only take self and return a string, since it's how Wrong values are shown (line 2, column 9)");
+
+        struct Handle(u32);
+        interpreter.bind_to_text(|handle: &Handle| format!("<handle {}>", handle.0));
+        assert_eq!(interpreter.format(&Value::opaque(Handle(7))), "<handle 7>");
+        assert_eq!(interpreter.format(&Value::opaque(())), "<opaque>");
+    }
+
+    #[test]
+    fn test_map_keys() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var pairs = [1, \"x\"]\nvar m = {1: \"a\", true: \"b\", pairs: \"c\", \"k\": \"d\"}".to_string()).unwrap();
+
+        let value = interpreter.eval("m".to_string()).unwrap().value.unwrap();
+        assert_eq!(interpreter.format(&value), "{true: \"b\", 1: \"a\", \"k\": \"d\", [1, \"x\"]: \"c\"}");
+        assert!(interpreter.eval("1 in m".to_string()).unwrap().as_bool().unwrap());
+        assert!(interpreter.eval("[1, \"x\"] in m".to_string()).unwrap().as_bool().unwrap());
+        assert!(!interpreter.eval("2 in m".to_string()).unwrap().as_bool().unwrap());
+
+        // Decs are refused before running when their type says so, and while running otherwise
+        let error = interpreter.eval("var bad = {1.5: \"a\"}".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Map keys can be ints, strings, truths, or lists of them, not dec");
+        assert!(interpreter.eval("0.5 in m".to_string()).is_err());
+        let error = interpreter.eval("var bad = {[1, 0.5]: \"a\"}".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "A dec can't be a map key. Keys are ints, strings, truths, or lists of them");
+    }
+
+    #[test]
+    fn test_extensions() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("type Point {\n    x: dec\n    y: dec\n}\nvar p = Point(3.0, 4.0)".to_string()).unwrap();
+
+        let extension = "extend Point {\n    func scaled(self, by: dec) -> Point {\n        return Point(self.x * by, self.y * by)\n    }\n\
+            func squared(self) -> dec {\n        return self.x * self.x + self.y * self.y\n    }\n}";
+        interpreter.eval(extension.to_string()).unwrap();
+
+        // Values made before the extension have its methods too
+        assert_eq!(interpreter.eval("p.scaled(2.0).squared()".to_string()).unwrap().as_dec().unwrap(), 100.0);
+
+        // A failed extension adds nothing
+        let error = interpreter.eval("extend Point {\n    func half(self) -> dec {\n        return self.x / 2.0\n    }\n    func bad(self) -> int {\n        return \"s\"\n    }\n}".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Type mismatch: Function should return \"int\" but returns \"string\"");
+        assert!(interpreter.eval("p.half()".to_string()).is_err());
+
+        let error = interpreter.eval("extend int {\n}".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Only types declared with `type` can be extended, and int isn't one (line 1, column 7)");
+    }
+
+    #[test]
+    fn test_patterns() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("type Point {\n    x: int\n    y: int\n}".to_string()).unwrap();
+
+        let code = "match Point(0, 5) {\n    Point { x: 0, y } : y * 10\n    Point { x, y } : x + y\n}";
+        assert_eq!(interpreter.eval(code.to_string()).unwrap().as_int().unwrap(), 50);
+
+        interpreter.eval("var Point { x, y: other } = Point(3, 4)\nvar [head, ...rest] = [1, 2, 3]".to_string()).unwrap();
+        assert_eq!(interpreter.eval("x + other + head".to_string()).unwrap().as_int().unwrap(), 8);
+        assert_eq!(interpreter.eval("rest".to_string()).unwrap().as_list().unwrap().len(), 2);
+
+        let describe = "func describe(l: list<int>) -> string {\n    return match l {\n        [] : \"empty\"\n        [a] : \"one\"\n        [a, b, ...more] : \"many\"\n    }\n}";
+        interpreter.eval(describe.to_string()).unwrap();
+        assert_eq!(interpreter.eval("describe([4])".to_string()).unwrap().as_text().unwrap(), "one");
+        assert_eq!(interpreter.eval("describe([4, 5, 6])".to_string()).unwrap().as_text().unwrap(), "many");
+
+        // Every value has to be covered
+        let error = interpreter.eval("match 3 {\n    1 : 1\n}".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "match doesn't cover every int. Add an arm with `_` for the rest (line 1, column 0)");
+        let error = interpreter.eval("match [1] {\n    [a, ...more] : a\n}".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "match doesn't cover every list<int>. Add an arm with `_` for the rest (line 1, column 0)");
+        assert!(interpreter.eval("match true {\n    true : 1\n    false : 2\n}".to_string()).is_ok());
+
+        let error = interpreter.eval("match \"s\" {\n    1 : 1\n    _ : 2\n}".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "A pattern of type int can never match a value of type string (line 2, column 4)");
+        let error = interpreter.eval("var [a, 1] = [1, 1]".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "The pattern doesn't match every list<int>, so it can only be used in a match (line 1, column 4)");
+        let error = interpreter.eval("var [a, b] = [1]".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "The list doesn't match the pattern (line 1, column 4)");
+    }
+
+    #[test]
+    fn test_match_guards() {
+        let mut interpreter = Interpreter::new();
+        let side = "type Point {\n    x: int\n    y: int\n}\n\
+            func side(p: Point) -> string {\n    return match p {\n        Point { x, y } if x > y : \"right\"\n        Point { x, y } if x < y : \"left\"\n        _ : \"middle\"\n    }\n}";
+        interpreter.eval(side.to_string()).unwrap();
+        assert_eq!(interpreter.eval("side(Point(3, 1))".to_string()).unwrap().as_text().unwrap(), "right");
+        assert_eq!(interpreter.eval("side(Point(1, 3))".to_string()).unwrap().as_text().unwrap(), "left");
+        assert_eq!(interpreter.eval("side(Point(2, 2))".to_string()).unwrap().as_text().unwrap(), "middle");
+
+        // Guarded arms don't count towards covering every value
+        let error = interpreter.eval("match 3 {\n    n if n > 1 : n\n}".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "match doesn't cover every int. Add an arm with `_` for the rest (line 1, column 0)");
+        let error = interpreter.eval("match 3 {\n    n if n : n\n    _ : 0\n}".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "The guard of a match arm must be a truth, not int (line 2, column 9)");
+    }
+
+    #[test]
+    fn test_deeply_nested_expressions() {
+        use crate::base::{lexer::Lexer, parser::Ast};
+
+        // Far deeper than the host stack allows with one frame per level. The parser
+        // can't read code this nested, so the tree is built by hand: 1 + (1 + (1 + ...))
+        const DEPTH: usize = 20_000;
+        let tokens = Lexer::new("1 +".to_string()).tokenize().unwrap();
+        let one = Box::new(Ast::Number(tokens[0].clone()));
+        let expression = (0..DEPTH).fold(one.clone(), |right, _| Box::new(Ast::BinaryOperation(one.clone(), tokens[1].clone(), right)));
+
+        let mut interpreter = Interpreter::new();
+        let scope_id = interpreter.semantic_analyzer.repl_scope_id;
+        let analyzed = interpreter.semantic_analyzer.in_scope(scope_id, |analyzer| analyzer.analyze(expression)).unwrap();
+        let result = interpreter.interpret(&analyzed.node).unwrap();
+
+        assert_eq!(result.as_int().unwrap(), DEPTH as i64 + 1);
+    }
+
+    #[test]
+    fn test_variables() {
+        let mut interpreter = Interpreter::new();
+        assert!(interpreter.variables().unwrap().is_empty());
+
+        interpreter.eval("var a = 1\nvar names = [\"x\"]\nfunc f() -> int {\n    return a\n}".to_string()).unwrap();
+        let variables = interpreter.variables().unwrap();
+        // Functions aren't listed
+        let rows: Vec<_> = variables.iter().map(|variable| (variable.name.as_str(), variable.type_name.as_str(), variable.scope.as_str())).collect();
+        assert_eq!(rows, [("a", "int", "repl_scope"), ("names", "list<string>", "repl_scope")]);
+        assert_eq!(super::format::format(&variables[1].value, Default::default()), "[\"x\"]");
+    }
+}
@@ -0,0 +1,421 @@
+use std::{collections::HashMap, sync::{Arc, Mutex, MutexGuard, Weak}};
+
+use super::{arithmetic, compare_primitives, contains, values_equal, Interpreter, Signal};
+use crate::base::{semantic_analyzer::{BinaryOperator, FunctionDefinition, SemanticAst}, stack};
+use crate::exec::{environment::{Closure, Environment}, executor::{ExecCtx, Executor}};
+use crate::exec::value::{numbers, FunctionValue, PrimitiveValue, Value, ValueVariant};
+
+// Walking the tree means matching on every node each time it runs, and parsing literals again
+// each time too. This compiles statements into closures instead, once: each node becomes a
+// closure that runs its children's closures, with its literals already parsed and what it reads
+// already picked out of the node. The closures do what the tree-walker would, down to the fuel,
+// the defers and the stats, so a program runs the same either way.
+//
+// Top-level statements are compiled when they run. Functions are compiled the first time compiled
+// code calls them, and kept for later calls. Functions that yield stop and resume in the middle
+// of their body, which only the tree-walker knows how to do, so those are always walked. So are
+// the kinds of node compiling doesn't speed up much, like matches: compiled code walks them.
+
+type Code<'a> = Box<dyn Fn(&mut Interpreter<'a>) -> anyhow::Result<Option<Value<'a>>> + Send + Sync + 'a>;
+
+// A block's statements, with their kind for tracing
+type Statements<'a> = Arc<[(&'static str, Code<'a>)]>;
+
+// Compiled bodies, by the address of their function's definition. Each entry keeps its
+// definition alive, so no other definition can get the same address.
+type Bodies<'a> = HashMap<usize, (Arc<FunctionDefinition>, Statements<'a>)>;
+
+/// Runs top-level statements by compiling them into closures first, along with the functions
+/// they call.
+#[derive(Default)]
+pub struct ClosureCompiler<'a> {
+    bodies: Arc<Mutex<Bodies<'a>>>,
+}
+
+impl<'a> ClosureCompiler<'a> {
+    pub fn new() -> ClosureCompiler<'a> {
+        ClosureCompiler::default()
+    }
+}
+
+impl<'a> Executor<'a> for ClosureCompiler<'a> {
+    fn run(&mut self, program: &SemanticAst, ctx: &mut ExecCtx<'_, 'a>) -> anyhow::Result<Option<Value<'a>>> {
+        let compiler = Compiler { bodies: Arc::downgrade(&self.bodies) };
+        let code = compiler.compile(program);
+
+        code(ctx.interpreter())
+    }
+}
+
+// Compiled code only holds on to the bodies weakly, since they hold compiled code themselves
+#[derive(Clone)]
+struct Compiler<'a> {
+    bodies: Weak<Mutex<Bodies<'a>>>,
+}
+
+// Counts the node and guards the stack, like `Interpreter::interpret`
+fn node<'a>(code: impl Fn(&mut Interpreter<'a>) -> anyhow::Result<Option<Value<'a>>> + Send + Sync + 'a) -> Code<'a> {
+    Box::new(move |interpreter| {
+        interpreter.stats.nodes_evaluated += 1;
+        stack::guarded(|| code(interpreter))
+    })
+}
+
+fn operand<'a>(code: &Code<'a>, interpreter: &mut Interpreter<'a>) -> anyhow::Result<Value<'a>> {
+    code(interpreter)?.ok_or(anyhow::anyhow!("Semantic analysis error. Operand should have value"))
+}
+
+fn truth_operand<'a>(code: &Code<'a>, interpreter: &mut Interpreter<'a>) -> anyhow::Result<bool> {
+    match operand(code, interpreter)?.content {
+        ValueVariant::Primitive(PrimitiveValue::Bool(b)) => Ok(b),
+        _ => Err(anyhow::anyhow!("Semantic analysis error. Operand should be a truth"))
+    }
+}
+
+fn lock<'l, 'a>(bodies: &'l Mutex<Bodies<'a>>) -> MutexGuard<'l, Bodies<'a>> {
+    bodies.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl<'a> Compiler<'a> {
+    fn compile(&self, ast: &SemanticAst) -> Code<'a> {
+        stack::guarded(|| self.compile_node(ast))
+    }
+
+    fn compile_node(&self, ast: &SemanticAst) -> Code<'a> {
+        match ast {
+            SemanticAst::Number(token) if token.value.contains('.') => match numbers::parse_dec(&token.value) {
+                Ok(number) => node(move |interpreter| Ok(Some(interpreter.literal(ValueVariant::Primitive(PrimitiveValue::Dec(number)))))),
+                Err(_) => self.walked(ast)
+            },
+            SemanticAst::Number(token) => match numbers::parse_int::<i128>(&token.value) {
+                // Only literals too big for an int depend on the overflow policy
+                Ok(number) => match i64::try_from(number) {
+                    Ok(number) => node(move |interpreter| Ok(Some(interpreter.literal(ValueVariant::Primitive(PrimitiveValue::Int(number)))))),
+                    Err(_) => {
+                        let token = token.clone();
+                        node(move |interpreter| {
+                            let number = interpreter.fit_int_literal(number, &token)?;
+                            Ok(Some(interpreter.literal(ValueVariant::Primitive(PrimitiveValue::Int(number)))))
+                        })
+                    }
+                },
+                Err(_) => self.walked(ast)
+            },
+            SemanticAst::Truth(token) => match token.value.parse::<bool>() {
+                Ok(truth) => node(move |interpreter| Ok(Some(interpreter.literal(ValueVariant::Primitive(PrimitiveValue::Bool(truth)))))),
+                Err(_) => self.walked(ast)
+            },
+            SemanticAst::Text(token) => {
+                let text = PrimitiveValue::Text(token.value.as_str().into());
+                node(move |interpreter| Ok(Some(interpreter.literal(ValueVariant::Primitive(text.clone())))))
+            },
+            SemanticAst::Variable(id, slot) => {
+                let (id, slot) = (*id, *slot);
+                node(move |interpreter| {
+                    let value_id = match slot {
+                        Some(slot) => interpreter.frames.last().and_then(|frame| frame.slot(slot)),
+                        None => interpreter.symbol_to_value.get(&id).copied()
+                    };
+                    let value_id = value_id.or_else(|| interpreter.value_id_of(id)).ok_or(anyhow::anyhow!("Symbol not found"))?;
+                    let value = interpreter.value_table.get(value_id).ok_or(anyhow::anyhow!("Value not found"))?;
+
+                    Ok(Some(value.clone()))
+                })
+            },
+            SemanticAst::Frame(layout, inner) => {
+                let (layout, inner) = (layout.clone(), self.compile(inner));
+                node(move |interpreter| {
+                    interpreter.frames.push(Environment::new(None, layout.clone()));
+                    let result = inner(interpreter);
+                    interpreter.frames.pop();
+
+                    result
+                })
+            },
+            SemanticAst::Block(statements, scope_id) => {
+                let (statements, scope_id) = (self.statements(statements), *scope_id);
+                node(move |interpreter| {
+                    interpreter.enter_scope(scope_id);
+                    let result = run_block(interpreter, &statements);
+                    interpreter.running_scopes.pop();
+                    result?;
+
+                    Ok(None)
+                })
+            },
+            SemanticAst::Declaration(target, _, value) => {
+                let (target, value) = (*target, self.compile(value));
+                node(move |interpreter| {
+                    let value = value(interpreter)?.ok_or(anyhow::anyhow!("Semantic analysis error. Should have value"))?;
+                    interpreter.declare_value(target, value);
+
+                    Ok(None)
+                })
+            },
+            SemanticAst::Assignment(target, value) => {
+                let (target, value) = (*target, self.compile(value));
+                node(move |interpreter| {
+                    let value = value(interpreter)?.ok_or(anyhow::anyhow!("Semantic analysis error. Should have value"))?;
+                    interpreter.assign_value(target, value);
+
+                    Ok(None)
+                })
+            },
+            SemanticAst::BinaryOperation(left, operator, right, token) => {
+                let (left, operator, right, token) = (self.compile(left), *operator, self.compile(right), token.clone());
+                node(move |interpreter| {
+                    let result = match operator {
+                        // The right side only runs when it can change the result
+                        BinaryOperator::And => truth_operand(&left, interpreter)? && truth_operand(&right, interpreter)?,
+                        BinaryOperator::Or => truth_operand(&left, interpreter)? || truth_operand(&right, interpreter)?,
+                        BinaryOperator::In => {
+                            let item = operand(&left, interpreter)?;
+                            let container = operand(&right, interpreter)?;
+
+                            match contains(&container, &item)? {
+                                Some(found) => found,
+                                None => return Err(interpreter.operator_error(&token, &item, &container))
+                            }
+                        },
+                        BinaryOperator::Equal | BinaryOperator::NotEqual => {
+                            let equal = values_equal(&operand(&left, interpreter)?, &operand(&right, interpreter)?)?;
+
+                            equal == (operator == BinaryOperator::Equal)
+                        },
+                        BinaryOperator::Add | BinaryOperator::Subtract | BinaryOperator::Multiply |
+                        BinaryOperator::Divide | BinaryOperator::Remainder => {
+                            let left = operand(&left, interpreter)?;
+                            let right = operand(&right, interpreter)?;
+
+                            return arithmetic(operator, &left.content, &right.content, &token, interpreter.policy.overflow)?
+                                .ok_or_else(|| interpreter.operator_error(&token, &left, &right))
+                                .map(Some);
+                        },
+                        _ => {
+                            let left = operand(&left, interpreter)?;
+                            let right = operand(&right, interpreter)?;
+                            let ordering = compare_primitives(&left.content, &right.content)
+                                .ok_or_else(|| interpreter.operator_error(&token, &left, &right))?;
+
+                            match operator {
+                                BinaryOperator::Less => ordering.is_lt(),
+                                BinaryOperator::LessEqual => ordering.is_le(),
+                                BinaryOperator::Greater => ordering.is_gt(),
+                                _ => ordering.is_ge(),
+                            }
+                        }
+                    };
+
+                    Ok(Some(Value::new(ValueVariant::Primitive(PrimitiveValue::Bool(result)))))
+                })
+            },
+            SemanticAst::If(condition, body) => {
+                let (condition, body) = (self.compile(condition), self.compile(body));
+                node(move |interpreter| {
+                    if let ValueVariant::Primitive(PrimitiveValue::Bool(true)) = operand(&condition, interpreter)?.content {
+                        body(interpreter)?;
+                    }
+
+                    Ok(None)
+                })
+            },
+            SemanticAst::Return(value) => {
+                let value = value.as_ref().map(|value| self.compile(value));
+                node(move |interpreter| {
+                    let value = match &value {
+                        Some(value) => Some(value(interpreter)?.ok_or(anyhow::anyhow!("Semantic analysis error. Should have value"))?),
+                        None => None
+                    };
+                    interpreter.signal = Some(Signal::Return(value));
+
+                    Ok(None)
+                })
+            },
+            // Spread arguments are only known once they're made, so those calls are walked
+            SemanticAst::FunctionCall(callee, arguments) if !arguments.iter().any(|argument| matches!(**argument, SemanticAst::Spread(_))) => {
+                let callee = self.compile(callee);
+                let arguments: Vec<_> = arguments.iter().map(|argument| self.compile(argument)).collect();
+                let compiler = self.clone();
+                node(move |interpreter| {
+                    let function = match operand(&callee, interpreter)?.content {
+                        ValueVariant::Function(function) => function,
+                        _ => return Err(anyhow::anyhow!("Semantic analysis error. Should have been a function"))
+                    };
+                    let mut values = Vec::with_capacity(arguments.len());
+                    for argument in &arguments {
+                        values.push(operand(argument, interpreter)?);
+                    }
+
+                    match function {
+                        FunctionValue::User(closure) if !closure.definition.is_coroutine && !closure.definition.is_generator => {
+                            compiler.call(interpreter, &closure, values)
+                        },
+                        function => interpreter.call(function, values)
+                    }
+                })
+            },
+            SemanticAst::For(variable, state, iterable, body, scope_id) => {
+                let (variable, state, scope_id) = (*variable, *state, *scope_id);
+                let (iterable, body) = (self.compile(iterable), self.compile(body));
+                node(move |interpreter| {
+                    interpreter.enter_scope(scope_id);
+                    let result = run_for(interpreter, variable, state, &iterable, &body);
+                    interpreter.running_scopes.pop();
+                    result?;
+
+                    Ok(None)
+                })
+            },
+            SemanticAst::DebugPrint(value) => {
+                let value = self.compile(value);
+                node(move |interpreter| {
+                    let text = match value(interpreter)? {
+                        Some(value) => interpreter.show(&value)?,
+                        None => "nothing".to_string()
+                    };
+                    interpreter.output.out(&format!("{}\n", text));
+
+                    Ok(None)
+                })
+            },
+            _ => self.walked(ast)
+        }
+    }
+
+    // Left to the tree-walker, which counts the node itself
+    fn walked(&self, ast: &SemanticAst) -> Code<'a> {
+        let ast = ast.clone();
+        Box::new(move |interpreter| interpreter.interpret(&ast).map(|result| result.value))
+    }
+
+    fn statements(&self, statements: &[SemanticAst]) -> Statements<'a> {
+        statements.iter()
+            .map(|statement| (statement.kind_name(), self.compile(statement)))
+            .collect()
+    }
+
+    // What `Interpreter::call_function` and `call_function_with_frame` do, with the compiled body
+    fn call(&self, interpreter: &mut Interpreter<'a>, function: &Closure, arguments: Vec<Value<'a>>) -> anyhow::Result<Option<Value<'a>>> {
+        let Some(body) = self.body(&function.definition) else {
+            return interpreter.call_function(function, arguments);
+        };
+
+        let definition = &function.definition;
+        let frame = function.call_environment();
+        for (parameter, value) in definition.parameters.iter().zip(arguments) {
+            frame.declare(*parameter, value.uuid);
+            interpreter.value_table.insert(value);
+        }
+
+        interpreter.frames.push(frame);
+        interpreter.enter_scope(definition.scope_id);
+        let result = run_block(interpreter, &body);
+        interpreter.running_scopes.pop();
+        interpreter.frames.pop();
+        result?;
+
+        match interpreter.signal.take() {
+            Some(Signal::Return(value)) => Ok(value),
+            Some(Signal::Yield(..)) => Err(anyhow::anyhow!("{} yields, so it can only run as a coroutine", definition.name)),
+            None if definition.return_id.is_some() => Err(anyhow::anyhow!("{} ended without returning a value", definition.name)),
+            None => Ok(None)
+        }
+    }
+
+    // None once the compiler is gone, and then the function is walked
+    fn body(&self, definition: &Arc<FunctionDefinition>) -> Option<Statements<'a>> {
+        let bodies = self.bodies.upgrade()?;
+        let key = Arc::as_ptr(definition) as usize;
+        if let Some((_, body)) = lock(&bodies).get(&key) {
+            return Some(body.clone());
+        }
+
+        // Compiled without holding the lock, since compiling doesn't run anything
+        let body = self.statements(&definition.body);
+        lock(&bodies).insert(key, (definition.clone(), body.clone()));
+
+        Some(body)
+    }
+}
+
+// What `Interpreter::interpret_statements` does for code that never resumes a coroutine
+fn run_block<'a>(interpreter: &mut Interpreter<'a>, statements: &[(&'static str, Code<'a>)]) -> anyhow::Result<()> {
+    interpreter.deferred.push(Vec::new());
+    let result = run_statements(interpreter, statements);
+    let deferred = interpreter.deferred.pop().unwrap_or_default();
+
+    interpreter.run_deferred(deferred, result)
+}
+
+fn run_statements<'a>(interpreter: &mut Interpreter<'a>, statements: &[(&'static str, Code<'a>)]) -> anyhow::Result<()> {
+    for (_kind, statement) in statements {
+        statement_event!(_kind);
+        interpreter.consume_fuel()?;
+        statement(interpreter)?;
+
+        if interpreter.signal.is_some() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+// What `Interpreter::interpret_for` does for code that never resumes a coroutine
+fn run_for<'a>(interpreter: &mut Interpreter<'a>, variable: uuid::Uuid, state: uuid::Uuid, iterable: &Code<'a>, body: &Code<'a>) -> anyhow::Result<()> {
+    let iterable = operand(iterable, interpreter)?;
+    interpreter.declare_value(state, iterable);
+
+    loop {
+        interpreter.consume_fuel()?;
+        let remaining = interpreter.value_id_of(state)
+            .and_then(|value_id| interpreter.value_table.get(value_id))
+            .cloned()
+            .ok_or(anyhow::anyhow!("Value not found"))?;
+
+        let Some((item, rest)) = interpreter.advance(remaining)? else {
+            return Ok(());
+        };
+        interpreter.declare_value(state, rest);
+        interpreter.declare_value(variable, item);
+
+        body(interpreter)?;
+        // A return leaves the loop
+        if interpreter.signal.is_some() {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClosureCompiler;
+    use crate::exec::interpreter::Interpreter;
+
+    #[test]
+    fn test_closure_compiler() {
+        let programs = [
+            "func fib(n: int) -> int {\n    if n < 2 {\n        return n\n    }\n    return fib(n - 1) + fib(n - 2)\n}\n: fib(15)",
+            "func counter(start: int) -> func() -> int {\n    var n = start\n    return func() -> int {\n        n += 1\n        return n\n    }\n}\nvar next = counter(5)\nnext()\n: next()",
+            "var total = 0\nfor x in [1, 2, 3] {\n    defer print(\"after \" + toText(x))\n    var y = x * 10\n    total += y\n}\n: total",
+            "type Point {\n    x: int\n    y: int\n    func sum(self) -> int {\n        return self.x + self.y\n    }\n}\nfunc twice(p: Point) -> int {\n    return p.sum() * 2\n}\n: twice(Point(1, 2))",
+            "func evens() -> seq<int> {\n    for i in [1, 2, 3] {\n        yield i * 2\n    }\n}\nfunc sum() -> int {\n    var total = 0\n    for e in evens() {\n        total += e\n    }\n    return total\n}\n: sum()",
+            "func describe(n: int) -> string {\n    return match n {\n        0 : \"zero\"\n        _ : \"many\"\n    }\n}\n: describe(0) + describe(2) + \"!\"",
+            "func broken(n: int) -> int {\n    return n / 0\n}\n: 1\nbroken(2)",
+            "func missing(n: int) -> int {\n    if n > 0 {\n        return n\n    }\n}\nmissing(0)",
+        ];
+
+        for program in programs {
+            let mut walker = Interpreter::new();
+            let mut compiled = Interpreter::new();
+            compiled.set_executor(ClosureCompiler::new());
+
+            let (walked_result, walked_out, _) = walker.eval_captured(program.to_string());
+            let (compiled_result, compiled_out, _) = compiled.eval_captured(program.to_string());
+
+            assert_eq!(compiled_out, walked_out, "{}", program);
+            assert_eq!(compiled_result.map_err(|e| e.to_string()).err(), walked_result.map_err(|e| e.to_string()).err(), "{}", program);
+            assert_eq!(compiled.stats().nodes_evaluated, walker.stats().nodes_evaluated, "{}", program);
+        }
+    }
+}
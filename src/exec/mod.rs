@@ -1,2 +1,16 @@
+pub mod cancel;
+pub mod compiled;
+pub mod environment;
+pub mod emit;
+pub mod error;
+pub mod executor;
+pub(crate) mod input;
+pub mod inspect;
 pub mod interpreter;
-pub mod value;
\ No newline at end of file
+pub mod observer;
+pub(crate) mod output;
+pub mod policy;
+pub mod pool;
+pub mod replay;
+pub mod stats;
+pub mod value;
@@ -0,0 +1,61 @@
+use crate::base::semantic_analyzer::SemanticAst;
+
+// Hosts can watch code run, one statement at a time, without changing how it runs. Nothing is
+// worked out for the observer unless there is one, so it costs nothing otherwise.
+
+/// Told about each statement an interpreter finishes running, top-level or nested.
+pub trait Observer: Send {
+    fn statement(&mut self, statement: &StatementRun);
+}
+
+/// A statement that just ran.
+pub struct StatementRun<'s> {
+    pub statement: &'s SemanticAst,
+    /// Its kind, and what it's about when that's a name or an operator, like `Declaration total`
+    pub description: String,
+    /// How many blocks and calls it's inside of. Top-level statements are at 0.
+    pub depth: usize,
+    /// The value it left, shown by its structure and cut short past the format limits
+    pub value: Option<String>,
+    /// Statements left to run, when the execution policy limits them
+    pub fuel: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{Observer, StatementRun};
+    use crate::exec::{interpreter::Interpreter, policy::ExecutionPolicy};
+
+    struct Lines(Arc<Mutex<Vec<String>>>);
+
+    impl Observer for Lines {
+        fn statement(&mut self, statement: &StatementRun) {
+            let value = statement.value.as_deref().unwrap_or("-");
+            let line = format!("{}{} = {} ({:?})", "  ".repeat(statement.depth), statement.description, value, statement.fuel);
+            self.0.lock().unwrap().push(line);
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let lines = Arc::new(Mutex::new(vec![]));
+        let mut interpreter = Interpreter::with_policy(ExecutionPolicy::default().fuel(Some(100)));
+        interpreter.set_observer(Lines(lines.clone()));
+
+        interpreter.eval("var total = 0\nfor i in [1, 2] {\n    total = total + i\n}\ntotal * 10".to_string()).unwrap();
+        assert_eq!(*lines.lock().unwrap(), [
+            "Declaration total = - (Some(99))",
+            "  Assignment total = - (Some(96))",
+            "  Assignment total = - (Some(94))",
+            "For i = - (Some(93))",
+            "BinaryOperation * = 30 (Some(92))",
+        ]);
+
+        lines.lock().unwrap().clear();
+        interpreter.remove_observer();
+        interpreter.eval("total".to_string()).unwrap();
+        assert!(lines.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,51 @@
+use std::io::Write;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+// Where everything odo programs print goes: the process's stdout and stderr, unless
+// `Interpreter::eval_captured` is collecting it.
+
+#[derive(Default)]
+pub(crate) struct Captured {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+// Shared between the interpreter and every native that prints
+#[derive(Clone, Default)]
+pub(crate) struct Output {
+    capture: Arc<Mutex<Option<Captured>>>
+}
+
+impl Output {
+    fn capture_mut(&self) -> MutexGuard<'_, Option<Captured>> {
+        self.capture.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub(crate) fn out(&self, text: &str) {
+        match self.capture_mut().as_mut() {
+            Some(captured) => captured.stdout.push_str(text),
+            None => {
+                let mut stdout = std::io::stdout();
+                let _ = stdout.write_all(text.as_bytes()).and_then(|_| stdout.flush());
+            }
+        }
+    }
+
+    pub(crate) fn err(&self, text: &str) {
+        match self.capture_mut().as_mut() {
+            Some(captured) => captured.stderr.push_str(text),
+            None => {
+                let _ = std::io::stderr().write_all(text.as_bytes());
+            }
+        }
+    }
+
+    // Starts collecting into new buffers. Returns whatever was being collected before, to hand back to `release`.
+    pub(crate) fn capture(&self) -> Option<Captured> {
+        self.capture_mut().replace(Captured::default())
+    }
+
+    pub(crate) fn release(&self, previous: Option<Captured>) -> Captured {
+        std::mem::replace(&mut *self.capture_mut(), previous).unwrap_or_default()
+    }
+}
@@ -0,0 +1,99 @@
+// Calls running inside each other before `max_call_depth` stops them. The stack grows as it's
+// needed, so without a limit runaway recursion would use up the host's memory instead.
+pub const MAX_CALL_DEPTH: usize = 10_000;
+
+/// What a script is allowed to do, fixed when the interpreter is created.
+/// The default is the most restrictive policy.
+#[derive(Debug, Clone)]
+pub struct ExecutionPolicy {
+    // Lets `httpGet` and `httpPost` reach the network. Only has an effect with the `net` feature.
+    pub allow_network: bool,
+    // Maximum number of statements each call from the host (`eval`, `emit`, resuming a coroutine) can run
+    pub fuel: Option<u64>,
+    // What happens when integer arithmetic doesn't fit in an int
+    pub overflow: IntegerOverflow,
+    // Limits on the source code itself, for hosts that run code they don't trust.
+    // Checked while lexing and parsing, before any of it runs. No limits by default.
+    pub max_tokens: Option<usize>,
+    // How deeply expressions and blocks can nest. The parser never allows more than `parser::MAX_NESTING`.
+    pub max_depth: Option<usize>,
+    // Length of string literals, in characters
+    pub max_text_length: Option<usize>,
+    // Function calls running inside each other, counting top-level blocks
+    pub max_call_depth: usize,
+}
+
+impl Default for ExecutionPolicy {
+    fn default() -> Self {
+        ExecutionPolicy {
+            allow_network: false,
+            fuel: None,
+            overflow: IntegerOverflow::default(),
+            max_tokens: None,
+            max_depth: None,
+            max_text_length: None,
+            max_call_depth: MAX_CALL_DEPTH,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum IntegerOverflow {
+    // Stops with a runtime error
+    #[default]
+    Checked,
+    // Wraps around, two's complement
+    Wrapping,
+    // Clamps to the smallest or largest int
+    Saturating,
+}
+
+impl std::str::FromStr for IntegerOverflow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "checked" => Ok(IntegerOverflow::Checked),
+            "wrapping" => Ok(IntegerOverflow::Wrapping),
+            "saturating" => Ok(IntegerOverflow::Saturating),
+            _ => Err(anyhow::anyhow!("Unknown overflow mode {:?}. Expected checked, wrapping or saturating", s))
+        }
+    }
+}
+
+impl ExecutionPolicy {
+    pub fn allow_network(mut self, allow: bool) -> ExecutionPolicy {
+        self.allow_network = allow;
+        self
+    }
+
+    pub fn fuel(mut self, fuel: Option<u64>) -> ExecutionPolicy {
+        self.fuel = fuel;
+        self
+    }
+
+    pub fn overflow(mut self, overflow: IntegerOverflow) -> ExecutionPolicy {
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn max_tokens(mut self, limit: Option<usize>) -> ExecutionPolicy {
+        self.max_tokens = limit;
+        self
+    }
+
+    pub fn max_depth(mut self, limit: Option<usize>) -> ExecutionPolicy {
+        self.max_depth = limit;
+        self
+    }
+
+    pub fn max_text_length(mut self, limit: Option<usize>) -> ExecutionPolicy {
+        self.max_text_length = limit;
+        self
+    }
+
+    pub fn max_call_depth(mut self, limit: usize) -> ExecutionPolicy {
+        self.max_call_depth = limit;
+        self
+    }
+}
@@ -0,0 +1,176 @@
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use super::interpreter::Interpreter;
+
+// Interpreters ready to run code on other threads, for servers that evaluate many independent
+// pieces of code. Building an interpreter registers the standard library and whatever the host
+// adds, so each one is built once and reused.
+//
+// Interpreters can't move between threads (natives only have to be Sync), so each worker thread
+// builds its own and jobs are sent to them. After every job, the interpreter goes back to how it
+// was once built and its garbage is collected, so jobs can't see each other's variables.
+
+type Task = Box<dyn FnOnce(&mut Interpreter<'static>) + Send>;
+
+pub struct InterpreterPool {
+    // None once the pool is dropped, so the workers stop
+    tasks: Option<mpsc::Sender<Task>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+/// The result of a job, once it's done.
+pub struct Pending<T>(mpsc::Receiver<thread::Result<T>>);
+
+impl<T> Pending<T> {
+    /// Blocks until the job is done. Fails if the job panicked.
+    pub fn wait(self) -> anyhow::Result<T> {
+        match self.0.recv() {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(_)) => Err(anyhow::anyhow!("The job panicked")),
+            Err(_) => Err(anyhow::anyhow!("The pool stopped before running the job"))
+        }
+    }
+}
+
+/// What evaluating code in the pool gave, in a form that can leave the worker's thread.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    // The value, like `:` prints it. None when the code has no value.
+    pub value: Option<String>,
+    pub type_name: Option<String>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl InterpreterPool {
+    /// `size` interpreters, each built by `init` on the thread that uses it.
+    /// Fails if any of them can't be built.
+    pub fn new(size: usize, init: impl Fn() -> anyhow::Result<Interpreter<'static>> + Send + Sync + 'static) -> anyhow::Result<InterpreterPool> {
+        let init = Arc::new(init);
+        let (tasks, queue) = mpsc::channel::<Task>();
+        let queue = Arc::new(Mutex::new(queue));
+        let (ready, started) = mpsc::channel();
+
+        let workers = (0..size.max(1)).map(|_| {
+            let (init, queue, ready) = (init.clone(), queue.clone(), ready.clone());
+
+            thread::spawn(move || {
+                let mut interpreter = match init() {
+                    Ok(interpreter) => interpreter,
+                    Err(error) => {
+                        let _ = ready.send(Err(error));
+                        return;
+                    }
+                };
+                interpreter.checkpoint();
+                let _ = ready.send(Ok(()));
+
+                loop {
+                    // Released before the task runs, so the others can take the next one
+                    let task = queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).recv();
+                    let Ok(task) = task else {
+                        return;
+                    };
+
+                    task(&mut interpreter);
+                    interpreter.reset();
+                    interpreter.semantic_analyzer.take_warnings();
+                    interpreter.collect_garbage();
+                }
+            })
+        }).collect();
+
+        let pool = InterpreterPool { tasks: Some(tasks), workers };
+        drop(ready);
+        for _ in 0..pool.workers.len() {
+            started.recv().map_err(|_| anyhow::anyhow!("An interpreter of the pool panicked while being built"))??;
+        }
+
+        Ok(pool)
+    }
+
+    /// `size` interpreters with only the standard library.
+    pub fn with_size(size: usize) -> anyhow::Result<InterpreterPool> {
+        InterpreterPool::new(size, || Ok(Interpreter::new()))
+    }
+
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Runs `job` with the first interpreter that's free.
+    pub fn run<T: Send + 'static>(&self, job: impl FnOnce(&mut Interpreter<'static>) -> T + Send + 'static) -> Pending<T> {
+        let (result, pending) = mpsc::channel();
+        let task: Task = Box::new(move |interpreter| {
+            let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| job(interpreter)));
+            let _ = result.send(outcome);
+        });
+
+        if let Some(tasks) = &self.tasks {
+            // Only fails when every worker is gone, and then `wait` says so
+            let _ = tasks.send(task);
+        }
+
+        Pending(pending)
+    }
+
+    /// Evaluates `code`, capturing what it prints.
+    pub fn eval(&self, code: impl Into<String>) -> Pending<anyhow::Result<Evaluation>> {
+        let code = code.into();
+
+        self.run(move |interpreter| {
+            let (result, stdout, stderr) = interpreter.eval_captured(code);
+            let value = result?.value;
+
+            Ok(Evaluation {
+                value: value.as_ref().map(|value| interpreter.format(value)),
+                type_name: value.as_ref().map(|value| value.type_name(&interpreter.semantic_analyzer)),
+                stdout,
+                stderr,
+            })
+        })
+    }
+}
+
+impl Drop for InterpreterPool {
+    // Waits for the jobs already sent
+    fn drop(&mut self) {
+        self.tasks = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InterpreterPool;
+    use crate::exec::interpreter::Interpreter;
+
+    #[test]
+    fn test_pool() {
+        let pool = InterpreterPool::new(3, || {
+            let mut interpreter = Interpreter::new();
+            interpreter.eval("func double(n: int) -> int {\n    return n * 2\n}".to_string())?;
+            Ok(interpreter)
+        }).unwrap();
+        assert_eq!(pool.size(), 3);
+
+        let jobs: Vec<_> = (0..12).map(|n| pool.eval(format!("var x = double({})\nprint(\"hi\")\nx", n))).collect();
+        for (n, job) in jobs.into_iter().enumerate() {
+            let evaluation = job.wait().unwrap().unwrap();
+            assert_eq!(evaluation.value, Some((n * 2).to_string()));
+            assert_eq!(evaluation.stdout, "hi\n");
+        }
+
+        // Every job starts from what `init` made
+        assert!(pool.eval("x").wait().unwrap().is_err());
+        assert_eq!(pool.run(|interpreter| interpreter.eval("double(4)".to_string()).unwrap().as_int().unwrap()).wait().unwrap(), 8);
+        assert!(pool.run(|_| panic!("broken job")).wait().is_err());
+        assert_eq!(pool.eval("1 + 1").wait().unwrap().unwrap().value.as_deref(), Some("2"));
+
+        let error = InterpreterPool::new(2, || Err(anyhow::anyhow!("no prelude"))).err().unwrap();
+        assert_eq!(error.to_string(), "no prelude");
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+
+/// A value a script got from outside (the clock, randomness, stdin, network responses), in the order it was read.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RecordedInput {
+    pub source: String,
+    pub value: serde_json::Value,
+}
+
+#[derive(Default)]
+enum Mode {
+    #[default]
+    Live,
+    Recording(Vec<RecordedInput>),
+    Replaying(VecDeque<RecordedInput>),
+}
+
+// Shared between the interpreter and every native that reads a nondeterministic input
+#[derive(Clone, Default)]
+pub(crate) struct InputLog {
+    mode: Arc<Mutex<Mode>>
+}
+
+impl InputLog {
+    fn mode(&self) -> std::sync::MutexGuard<'_, Mode> {
+        self.mode.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub(crate) fn record(&self) {
+        *self.mode() = Mode::Recording(vec![]);
+    }
+
+    pub(crate) fn replay(&self, inputs: Vec<RecordedInput>) {
+        *self.mode() = Mode::Replaying(inputs.into());
+    }
+
+    // Goes back to live inputs, returning whatever was recorded
+    pub(crate) fn stop(&self) -> Vec<RecordedInput> {
+        match std::mem::take(&mut *self.mode()) {
+            Mode::Recording(inputs) => inputs,
+            Mode::Live | Mode::Replaying(_) => vec![]
+        }
+    }
+
+    /// Reads an input from `live`, unless replaying, where it comes from the trace instead.
+    pub(crate) fn next<T: Serialize + DeserializeOwned>(&self, source: &str, live: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+        let mut mode = self.mode();
+
+        match &mut *mode {
+            Mode::Live => live(),
+            Mode::Recording(inputs) => {
+                let value = live()?;
+                inputs.push(RecordedInput { source: source.to_string(), value: serde_json::to_value(&value)? });
+
+                Ok(value)
+            },
+            Mode::Replaying(inputs) => {
+                let input = inputs.pop_front()
+                    .ok_or(anyhow::anyhow!("The replay trace has no more inputs, but the script read from {}", source))?;
+
+                if input.source != source {
+                    return Err(anyhow::anyhow!(
+                        "The script diverged from the replay trace: expected a read from {} but got one from {}",
+                        input.source,
+                        source
+                    ));
+                }
+
+                Ok(serde_json::from_value(input.value)?)
+            }
+        }
+    }
+}
+
+pub fn save(path: &Path, inputs: &[RecordedInput]) -> anyhow::Result<()> {
+    let text = serde_json::to_string_pretty(inputs)?;
+
+    std::fs::write(path, text)
+        .with_context(|| format!("Could not write replay trace {}", path.display()))
+}
+
+pub fn load(path: &Path) -> anyhow::Result<Vec<RecordedInput>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Could not read replay trace {}", path.display()))?;
+
+    serde_json::from_str(&text)
+        .with_context(|| format!("Invalid replay trace {}", path.display()))
+}
@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+/// What an interpreter has done since it was created, or since `Interpreter::reset_stats`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    // Nodes interpreted, counting every subexpression
+    pub nodes_evaluated: u64,
+    // Values stored in the value table
+    pub values_allocated: u64,
+    // Literals that came from the value table's cache of small ints and truths, and the ones that had to be made
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    // Method calls that found their method in what the call site remembered, and the ones that looked it up
+    pub call_site_hits: u64,
+    pub call_site_misses: u64,
+    // Symbols in every scope the analyzer still has, right now
+    pub live_symbols: usize,
+    // Most scopes the running code has been nested in at once
+    pub peak_scope_depth: usize,
+    // Calls to `eval` and `run_compiled`, and the time spent in them
+    pub evaluations: u64,
+    pub total_eval_time: Duration,
+    pub longest_eval_time: Duration,
+}
+
+impl Stats {
+    pub(crate) fn record_eval(&mut self, time: Duration) {
+        self.evaluations += 1;
+        self.total_eval_time += time;
+        self.longest_eval_time = self.longest_eval_time.max(time);
+    }
+}
@@ -1,11 +1,28 @@
 use uuid::Uuid;
-use std::{collections::HashMap, fmt::Debug, sync::Arc};
+use std::{any::Any, collections::{BTreeMap, HashMap}, fmt::Debug, ops::RangeInclusive, sync::{Arc, Mutex}};
+use serde::{Serialize, Serializer, Deserialize, Deserializer, de::Visitor};
 
+use crate::base::semantic_analyzer::{SemanticAnalyzer, SymbolId, INT_TYPE, DEC_TYPE, TEXT_TYPE, TRUTH_TYPE, OPAQUE_TYPE, LIST_TYPE, MAP_TYPE, SEQ_TYPE};
+use crate::exec::environment::Closure;
+use crate::exec::interpreter::Coroutine;
 use crate::native::function::NativeFn;
 
+pub mod format;
+pub mod key;
+pub mod numbers;
+pub mod text;
+
+use key::Key;
+use text::Text;
+
 #[derive(Debug)]
 pub struct ValueTable<'a> {
     values: HashMap<Uuid, Value<'a>>,
+    // Values from before the last `freeze`, shared with the forks made then.
+    // Updates go to `values`, which is looked at first.
+    frozen: Arc<HashMap<Uuid, Value<'a>>>,
+    // Values ever inserted, not counting updates
+    allocated: u64,
 }
 
 #[derive(Clone, Debug)]
@@ -21,48 +38,451 @@ impl<'a> Value<'a> {
             uuid: Uuid::new_v4(),
         }
     }
+
+    /// The type symbol of the value at runtime. `Nothing` and functions don't have one.
+    pub fn type_id(&self) -> Option<SymbolId> {
+        match &self.content {
+            ValueVariant::Primitive(PrimitiveValue::Int(_)) => Some(INT_TYPE.symbol_id),
+            ValueVariant::Primitive(PrimitiveValue::Dec(_)) => Some(DEC_TYPE.symbol_id),
+            ValueVariant::Primitive(PrimitiveValue::Text(_)) => Some(TEXT_TYPE.symbol_id),
+            ValueVariant::Primitive(PrimitiveValue::Bool(_)) => Some(TRUTH_TYPE.symbol_id),
+            ValueVariant::Opaque(_) => Some(OPAQUE_TYPE.symbol_id),
+            ValueVariant::List(_) => Some(LIST_TYPE.symbol_id),
+            ValueVariant::Map(_) => Some(MAP_TYPE.symbol_id),
+            ValueVariant::Record(record) => Some(record.type_id),
+            ValueVariant::Seq(_) => Some(SEQ_TYPE.symbol_id),
+            ValueVariant::Nothing | ValueVariant::Function(_) => None
+        }
+    }
+
+    /// Wraps a host object so it can be handed to scripts.
+    pub fn opaque<T: Any + Send + Sync>(object: T) -> Value<'a> {
+        Value::new(ValueVariant::Opaque(Arc::new(object)))
+    }
+
+    pub fn list(items: Vec<Value<'a>>) -> Value<'a> {
+        Value::new(ValueVariant::List(items))
+    }
+
+    pub fn as_list(&self) -> Result<&[Value<'a>], ExtractionError> {
+        match &self.content {
+            ValueVariant::List(items) => Ok(items),
+            other => Err(ExtractionError::UnexpectedVariant("List", other.variant_name()))
+        }
+    }
+
+    pub fn map<K: Into<Key>>(entries: impl IntoIterator<Item = (K, Value<'a>)>) -> Value<'a> {
+        Value::new(ValueVariant::Map(entries.into_iter().map(|(key, value)| (key.into(), value)).collect()))
+    }
+
+    pub fn as_map(&self) -> Result<&BTreeMap<Key, Value<'a>>, ExtractionError> {
+        match &self.content {
+            ValueVariant::Map(entries) => Ok(entries),
+            other => Err(ExtractionError::UnexpectedVariant("Map", other.variant_name()))
+        }
+    }
+
+    /// Gets back the host object behind a handle, checking that it's of the expected type.
+    pub fn as_opaque<T: Any + Send + Sync>(&self) -> Result<Arc<T>, ExtractionError> {
+        match &self.content {
+            ValueVariant::Opaque(object) => object.clone()
+                .downcast::<T>()
+                .map_err(|_| ExtractionError::OpaqueTypeMismatch(std::any::type_name::<T>())),
+            other => Err(ExtractionError::UnexpectedVariant("Opaque", other.variant_name()))
+        }
+    }
+
+    /// The name of the value's type, as it would be written in odo.
+    pub fn type_name(&self, semantic_analyzer: &SemanticAnalyzer) -> String {
+        match &self.content {
+            ValueVariant::Nothing => "nothing".to_string(),
+            ValueVariant::Function(_) => "function".to_string(),
+            ValueVariant::Record(record) => record.type_name.clone(),
+            ValueVariant::Primitive(_) | ValueVariant::Opaque(_) | ValueVariant::List(_) | ValueVariant::Map(_) | ValueVariant::Seq(_) => self.type_id()
+                .and_then(|id| semantic_analyzer.name_of_type(id).ok().flatten())
+                .unwrap_or("<unknown>".to_string())
+        }
+    }
 }
 
+// Lists, maps and records own what's in them, so a value can't contain itself and going
+// through one, to print or compare it, always ends. Seqs are shared, but nothing looks inside them.
 #[derive(Clone, Debug)]
 pub enum ValueVariant<'a> {
     Nothing,
     Primitive(PrimitiveValue),
-    Function(FunctionValue<'a>)
+    Function(FunctionValue<'a>),
+    List(Vec<Value<'a>>),
+    // Sorted by key
+    Map(BTreeMap<Key, Value<'a>>),
+    // A handle to a host object. Scripts can only pass it around, natives downcast it back.
+    Opaque(Arc<dyn Any + Send + Sync>),
+    // A value of a type declared in odo
+    Record(Record<'a>),
+    // What calling a function that returns a seq gives. Its items are made as they're needed.
+    Seq(Sequence)
+}
+
+/// A generator stopped at its last `yield`. Copies of the value share it, so
+/// going through one of them moves all of them forward.
+#[derive(Clone)]
+pub struct Sequence(pub(crate) Arc<Mutex<Coroutine>>);
+
+impl Debug for Sequence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sequence(<suspended function>)")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Record<'a> {
+    pub type_id: SymbolId,
+    // The type can go away with the scope it was declared in, while its values live on
+    pub type_name: String,
+    pub fields: Vec<(String, Value<'a>)>, // In the order they're declared
 }
 
 #[derive(Clone, Debug)]
 pub enum PrimitiveValue {
     Int(i64),
     Dec(f64),
-    Text(String),
+    Text(Text),
     Bool(bool),
 }
 
+#[derive(Debug)]
+pub enum ExtractionError {
+    NoValue,
+    UnexpectedVariant(&'static str, &'static str), // Expected, got
+    OpaqueTypeMismatch(&'static str), // Expected host type
+}
+
+impl ExtractionError {
+    fn description(&self) -> String {
+        match self {
+            ExtractionError::NoValue => "The evaluation didn't produce a value".to_string(),
+            ExtractionError::UnexpectedVariant(expected, got) => {
+                format!("Expected a value of type {} but got {}", expected, got)
+            },
+            ExtractionError::OpaqueTypeMismatch(expected) => {
+                format!("The handle doesn't hold a {}", expected)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ExtractionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl std::error::Error for ExtractionError {}
+
+impl<'a> ValueVariant<'a> {
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            ValueVariant::Nothing => "Nothing",
+            ValueVariant::Primitive(primitive) => primitive.variant_name(),
+            ValueVariant::Function(_) => "Function",
+            ValueVariant::List(_) => "List",
+            ValueVariant::Map(_) => "Map",
+            ValueVariant::Opaque(_) => "Opaque",
+            ValueVariant::Record(_) => "Record",
+            ValueVariant::Seq(_) => "Seq",
+        }
+    }
+}
+
+impl PrimitiveValue {
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            PrimitiveValue::Int(_) => "Int",
+            PrimitiveValue::Dec(_) => "Dec",
+            PrimitiveValue::Text(_) => "Text",
+            PrimitiveValue::Bool(_) => "Bool",
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum FunctionValue<'a> {
     Native(Arc<NativeFn<'a>>),
+    User(Closure),
 }
 
 impl<'a> Debug for FunctionValue<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             FunctionValue::Native(_) => write!(f, "FunctionValue::Native(<native code>)"),
+            FunctionValue::User(closure) => write!(f, "FunctionValue::User({})", closure.definition.name),
         }
     }
 }
 
+impl<'a> Default for ValueTable<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Small ints, both truths and nothing come up often enough, in loop counters and conditions, that
+// every value equal to one of them can share an entry of the table, under a uuid that's always the same
+const SMALL_INTS: RangeInclusive<i64> = -128..=1023;
+const CACHED: u64 = u64::from_be_bytes(*b"odocache");
+
+fn cached_uuid(content: &ValueVariant) -> Option<Uuid> {
+    let index = match content {
+        ValueVariant::Nothing => 0,
+        ValueVariant::Primitive(PrimitiveValue::Bool(b)) => 1 + *b as u64,
+        ValueVariant::Primitive(PrimitiveValue::Int(i)) if SMALL_INTS.contains(i) => 3 + (i - SMALL_INTS.start()) as u64,
+        _ => return None
+    };
+
+    Some(Uuid::from_u64_pair(CACHED, index))
+}
+
 impl<'a> ValueTable<'a> {
     pub fn new() -> ValueTable<'a> {
         ValueTable {
             values: HashMap::new(),
+            frozen: Arc::default(),
+            allocated: 0,
         }
     }
 
     pub fn insert(&mut self, value: Value<'a>) {
-        self.values.insert(value.uuid, value);
+        let uuid = value.uuid;
+        if self.values.insert(uuid, value).is_none() && !self.frozen.contains_key(&uuid) {
+            self.allocated += 1;
+        }
+    }
+
+    pub fn allocated(&self) -> u64 {
+        self.allocated
+    }
+
+    /// The table's shared value for `content`, if it's a small int, a truth or nothing.
+    /// Shared values don't count as allocated, and garbage collection keeps them.
+    pub fn cached(&mut self, content: &ValueVariant<'a>) -> Option<Value<'a>> {
+        let uuid = cached_uuid(content)?;
+        if let Some(value) = self.get(uuid) {
+            return Some(value.clone());
+        }
+
+        let value = Value { content: content.clone(), uuid };
+        self.values.insert(uuid, value.clone());
+        Some(value)
     }
 
     pub fn get(&self, uuid: Uuid) -> Option<&Value<'a>> {
-        self.values.get(&uuid)
+        self.values.get(&uuid).or_else(|| self.frozen.get(&uuid))
+    }
+
+    // Removes the values `keep` rejects, returning how many. Frozen values are only
+    // removed once no fork shares them anymore.
+    pub(crate) fn retain(&mut self, keep: impl Fn(&Uuid) -> bool) -> usize {
+        let keep = |uuid: &Uuid| uuid.as_u64_pair().0 == CACHED || keep(uuid);
+        let before = self.values.len();
+        self.values.retain(|uuid, _| keep(uuid));
+        let mut removed = before - self.values.len();
+
+        if let Some(frozen) = Arc::get_mut(&mut self.frozen) {
+            let before = frozen.len();
+            frozen.retain(|uuid, _| keep(uuid));
+            removed += before - frozen.len();
+        }
+
+        removed
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &Value<'a>> {
+        self.values.values().chain(self.frozen.iter().filter(|(uuid, _)| !self.values.contains_key(uuid)).map(|(_, value)| value))
+    }
+
+    // A table with the same values, sharing them with this one instead of copying them
+    pub(crate) fn fork(&mut self) -> ValueTable<'a> {
+        if !self.values.is_empty() {
+            Arc::make_mut(&mut self.frozen).extend(self.values.drain());
+        }
+
+        ValueTable {
+            values: HashMap::new(),
+            frozen: self.frozen.clone(),
+            allocated: self.allocated,
+        }
+    }
+}
+// Serialization
+// Values map to their natural serde representation: `Nothing` is a unit (`null` in json)
+// primitives are the plain number, string or bool, lists are sequences and maps are maps (objects in json).
+// Functions can't be serialized.
+
+impl Serialize for PrimitiveValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            PrimitiveValue::Int(i) => serializer.serialize_i64(*i),
+            PrimitiveValue::Dec(d) => serializer.serialize_f64(*d),
+            PrimitiveValue::Text(t) => serializer.serialize_str(t),
+            PrimitiveValue::Bool(b) => serializer.serialize_bool(*b),
+        }
+    }
+}
+
+impl<'a> Serialize for ValueVariant<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ValueVariant::Nothing => serializer.serialize_unit(),
+            ValueVariant::Primitive(p) => p.serialize(serializer),
+            ValueVariant::List(items) => serializer.collect_seq(items),
+            ValueVariant::Map(entries) => serializer.collect_map(entries),
+            // Like a map of its fields. It comes back as one.
+            ValueVariant::Record(record) => serializer.collect_map(record.fields.iter().map(|(name, value)| (name, value))),
+            ValueVariant::Function(_) => Err(serde::ser::Error::custom("Native functions can't be serialized")),
+            ValueVariant::Opaque(_) => Err(serde::ser::Error::custom("Host object handles can't be serialized")),
+            ValueVariant::Seq(_) => Err(serde::ser::Error::custom("Sequences can't be serialized")),
+        }
+    }
+}
+
+impl<'a> Serialize for Value<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.content.serialize(serializer)
+    }
+}
+
+struct ValueVariantVisitor;
+
+impl<'de> Visitor<'de> for ValueVariantVisitor {
+    type Value = ValueVariant<'static>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("nothing, an int, a dec, a string, a truth, a list or a map")
+    }
+
+    fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+        Ok(ValueVariant::Nothing)
+    }
+
+    fn visit_none<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+        Ok(ValueVariant::Nothing)
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(ValueVariant::Primitive(PrimitiveValue::Int(v)))
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        let v = i64::try_from(v).map_err(|_| E::custom(format!("{} doesn't fit in an int", v)))?;
+        Ok(ValueVariant::Primitive(PrimitiveValue::Int(v)))
+    }
+
+    fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(ValueVariant::Primitive(PrimitiveValue::Dec(v)))
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(ValueVariant::Primitive(PrimitiveValue::Text(v.into())))
+    }
+
+    fn visit_string<E: serde::de::Error>(self, v: String) -> Result<Self::Value, E> {
+        Ok(ValueVariant::Primitive(PrimitiveValue::Text(v.into())))
     }
-}
\ No newline at end of file
+
+    fn visit_bool<E: serde::de::Error>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(ValueVariant::Primitive(PrimitiveValue::Bool(v)))
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element::<Value<'static>>()? {
+            items.push(item);
+        }
+
+        Ok(ValueVariant::List(items))
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut entries = BTreeMap::new();
+        while let Some((key, value)) = map.next_entry::<String, Value<'static>>()? {
+            entries.insert(Key::Text(key), value);
+        }
+
+        Ok(ValueVariant::Map(entries))
+    }
+}
+
+impl<'de> Deserialize<'de> for PrimitiveValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match deserializer.deserialize_any(ValueVariantVisitor)? {
+            ValueVariant::Primitive(p) => Ok(p),
+            _ => Err(serde::de::Error::custom("Expected a primitive value")),
+        }
+    }
+}
+
+// Deserialized values never hold functions, so they can be moved to any lifetime
+fn deserialized<'a>(variant: ValueVariant<'static>) -> ValueVariant<'a> {
+    match variant {
+        ValueVariant::Nothing => ValueVariant::Nothing,
+        ValueVariant::Primitive(p) => ValueVariant::Primitive(p),
+        ValueVariant::List(items) => ValueVariant::List(
+            items.into_iter()
+                .map(|item| Value { content: deserialized(item.content), uuid: item.uuid })
+                .collect()
+        ),
+        ValueVariant::Map(entries) => ValueVariant::Map(
+            entries.into_iter()
+                .map(|(key, value)| (key, Value { content: deserialized(value.content), uuid: value.uuid }))
+                .collect()
+        ),
+        ValueVariant::Function(_) | ValueVariant::Opaque(_) | ValueVariant::Seq(_) => unreachable!("Host values are never deserialized"),
+        ValueVariant::Record(_) => unreachable!("Records deserialize as maps"),
+    }
+}
+
+impl<'de, 'a> Deserialize<'de> for ValueVariant<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(deserialized(deserializer.deserialize_any(ValueVariantVisitor)?))
+    }
+}
+
+impl<'de, 'a> Deserialize<'de> for Value<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Value::new(ValueVariant::deserialize(deserializer)?))
+    }
+}
+
+impl<'a> Value<'a> {
+    pub fn to_json(&self) -> serde_json::Result<serde_json::Value> {
+        serde_json::to_value(self)
+    }
+
+    pub fn from_json(json: serde_json::Value) -> serde_json::Result<Value<'a>> {
+        serde_json::from_value(json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Value, ValueVariant, PrimitiveValue};
+
+    #[test]
+    fn test_json_round_trip() {
+        let value = Value::new(ValueVariant::Primitive(PrimitiveValue::Text("odo".into())));
+        let json = value.to_json().unwrap();
+
+        assert_eq!(json, serde_json::json!("odo"));
+
+        let back = Value::from_json(serde_json::json!(null)).unwrap();
+        assert!(matches!(back.content, ValueVariant::Nothing));
+
+        let list = Value::from_json(serde_json::json!([1, "two"])).unwrap();
+        assert_eq!(list.as_list().unwrap().len(), 2);
+        assert_eq!(list.to_json().unwrap(), serde_json::json!([1, "two"]));
+
+        let map = Value::from_json(serde_json::json!({"b": 2, "a": [true]})).unwrap();
+        assert_eq!(map.as_map().unwrap().keys().map(|key| key.to_string()).collect::<Vec<_>>(), vec!["\"a\"", "\"b\""]);
+        assert_eq!(map.to_json().unwrap(), serde_json::json!({"a": [true], "b": 2}));
+    }
+}
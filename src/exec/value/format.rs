@@ -0,0 +1,135 @@
+use std::fmt::Write;
+
+use super::{numbers, FunctionValue, PrimitiveValue, Value, ValueVariant};
+
+// How values read when they're shown to people: by `:`, the repl's echo, and anything else
+// that prints them. Big values are cut short, so printing one never floods the terminal.
+//
+// Records and host objects can say how they read themselves (a `toText` method, or what the
+// host binds with `Interpreter::bind_to_text`). That's up to the interpreter, which asks
+// through `custom` and gets None for the ones that don't, shown by their structure instead.
+
+/// How much of a value gets shown. What goes past them reads as `…`, with how much was left out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// Levels of lists, maps and records shown inside each other
+    pub depth: usize,
+    /// Items shown of each list and map, and fields of each record
+    pub width: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { depth: 6, width: 100 }
+    }
+}
+
+pub fn format(value: &Value, limits: Limits) -> String {
+    format_with(value, limits, &mut |_| None)
+}
+
+pub fn format_with<'v>(value: &Value<'v>, limits: Limits, custom: &mut dyn FnMut(&Value<'v>) -> Option<String>) -> String {
+    let mut output = String::new();
+    write_value(&mut output, value, limits, 0, custom);
+    output
+}
+
+fn write_value<'v>(output: &mut String, value: &Value<'v>, limits: Limits, depth: usize, custom: &mut dyn FnMut(&Value<'v>) -> Option<String>) {
+    if matches!(value.content, ValueVariant::Record(_) | ValueVariant::Opaque(_)) {
+        if let Some(text) = custom(value) {
+            output.push_str(&text);
+            return;
+        }
+    }
+
+    match &value.content {
+        ValueVariant::Nothing => output.push_str("nothing"),
+        ValueVariant::Primitive(PrimitiveValue::Int(i)) => { let _ = write!(output, "{}", i); },
+        ValueVariant::Primitive(PrimitiveValue::Dec(d)) => output.push_str(&numbers::dec(*d)),
+        ValueVariant::Primitive(PrimitiveValue::Text(text)) => { let _ = write!(output, "{:?}", text); },
+        ValueVariant::Primitive(PrimitiveValue::Bool(b)) => { let _ = write!(output, "{}", b); },
+        ValueVariant::Function(FunctionValue::Native(_)) => output.push_str("<native function>"),
+        ValueVariant::Function(FunctionValue::User(closure)) => { let _ = write!(output, "<func {}>", closure.definition.name); },
+        ValueVariant::Opaque(_) => output.push_str("<opaque>"),
+        ValueVariant::Seq(_) => output.push_str("<seq>"),
+        ValueVariant::List(items) => {
+            write_items(output, ("[", "]"), items.iter(), items.len(), "items", limits, depth, |output, item, depth| {
+                write_value(output, item, limits, depth, custom);
+            });
+        },
+        ValueVariant::Map(entries) => {
+            write_items(output, ("{", "}"), entries.iter(), entries.len(), "entries", limits, depth, |output, (key, value), depth| {
+                let _ = write!(output, "{}: ", key);
+                write_value(output, value, limits, depth, custom);
+            });
+        },
+        ValueVariant::Record(record) => {
+            output.push_str(&record.type_name);
+            write_items(output, ("(", ")"), record.fields.iter(), record.fields.len(), "fields", limits, depth, |output, (name, value), depth| {
+                let _ = write!(output, "{}: ", name);
+                write_value(output, value, limits, depth, custom);
+            });
+        }
+    }
+}
+
+// `[a, b, … 3 more]`, or `[… 5 items]` when it's nested too deep to show what's in it
+#[allow(clippy::too_many_arguments)]
+fn write_items<T>(
+    output: &mut String,
+    (open, close): (&str, &str),
+    items: impl Iterator<Item = T>,
+    count: usize,
+    noun: &str,
+    limits: Limits,
+    depth: usize,
+    mut write_item: impl FnMut(&mut String, T, usize),
+) {
+    output.push_str(open);
+
+    if count > 0 && depth >= limits.depth {
+        let _ = write!(output, "… {} {}", count, noun);
+    } else {
+        for (index, item) in items.take(limits.width).enumerate() {
+            if index > 0 {
+                output.push_str(", ");
+            }
+            write_item(output, item, depth + 1);
+        }
+
+        if count > limits.width {
+            let _ = write!(output, "{}… {} more", if limits.width > 0 { ", " } else { "" }, count - limits.width);
+        }
+    }
+
+    output.push_str(close);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{format, Limits};
+    use crate::exec::value::{PrimitiveValue, Value, ValueVariant};
+
+    fn int<'a>(i: i64) -> Value<'a> {
+        Value::new(ValueVariant::Primitive(PrimitiveValue::Int(i)))
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = Limits { depth: 2, width: 3 };
+
+        let short = Value::list(vec![int(1), Value::new(ValueVariant::Primitive(PrimitiveValue::Dec(2.0)))]);
+        assert_eq!(format(&short, limits), "[1, 2.0]");
+
+        let long = Value::list((1..=10).map(int).collect());
+        assert_eq!(format(&long, limits), "[1, 2, 3, … 7 more]");
+
+        let nested = Value::list(vec![Value::list(vec![Value::list(vec![int(1), int(2)]), Value::list(vec![])])]);
+        assert_eq!(format(&nested, limits), "[[[… 2 items], []]]");
+
+        let map = Value::map([("a", int(1)), ("b", Value::map(BTreeMap::<String, _>::new()))]);
+        assert_eq!(format(&map, limits), "{\"a\": 1, \"b\": {}}");
+    }
+}
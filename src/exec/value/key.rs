@@ -0,0 +1,141 @@
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+
+use super::{PrimitiveValue, Value, ValueVariant};
+
+// What maps are keyed by. Only values whose equality can't surprise can be keys: ints, strings,
+// truths, and lists of those, which stand in for tuples. Decs can't, since NaN isn't equal to
+// itself and 0.1 + 0.2 isn't 0.3, and neither can nothing, maps, records, functions, seqs or
+// handles to host objects.
+
+/// A map key. Two keys are equal, and hash the same, when the values they were made from are
+/// equal. Maps keep their keys sorted: truths first, then ints, strings and lists.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Key {
+    Bool(bool),
+    Int(i64),
+    Text(String),
+    List(Vec<Key>),
+}
+
+impl Key {
+    pub fn to_value<'a>(&self) -> Value<'a> {
+        let content = match self {
+            Key::Bool(b) => ValueVariant::Primitive(PrimitiveValue::Bool(*b)),
+            Key::Int(i) => ValueVariant::Primitive(PrimitiveValue::Int(*i)),
+            Key::Text(text) => ValueVariant::Primitive(PrimitiveValue::Text(text.as_str().into())),
+            Key::List(items) => ValueVariant::List(items.iter().map(Key::to_value).collect()),
+        };
+
+        Value::new(content)
+    }
+}
+
+impl TryFrom<&Value<'_>> for Key {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &Value) -> anyhow::Result<Key> {
+        let kind = match &value.content {
+            ValueVariant::Primitive(PrimitiveValue::Bool(b)) => return Ok(Key::Bool(*b)),
+            ValueVariant::Primitive(PrimitiveValue::Int(i)) => return Ok(Key::Int(*i)),
+            ValueVariant::Primitive(PrimitiveValue::Text(text)) => return Ok(Key::Text(text.to_string())),
+            ValueVariant::List(items) => return items.iter().map(Key::try_from).collect::<anyhow::Result<_>>().map(Key::List),
+            ValueVariant::Primitive(PrimitiveValue::Dec(_)) => "A dec",
+            ValueVariant::Nothing => "Nothing",
+            ValueVariant::Map(_) => "A map",
+            ValueVariant::Record(record) => return Err(unhashable(&format!("A {}", record.type_name))),
+            ValueVariant::Function(_) => "A function",
+            ValueVariant::Seq(_) => "A seq",
+            ValueVariant::Opaque(_) => "A host object",
+        };
+
+        Err(unhashable(kind))
+    }
+}
+
+fn unhashable(kind: &str) -> anyhow::Error {
+    anyhow::anyhow!("{} can't be a map key. Keys are ints, strings, truths, or lists of them", kind)
+}
+
+impl From<&str> for Key {
+    fn from(text: &str) -> Key {
+        Key::Text(text.to_string())
+    }
+}
+
+impl From<String> for Key {
+    fn from(text: String) -> Key {
+        Key::Text(text)
+    }
+}
+
+impl From<i64> for Key {
+    fn from(i: i64) -> Key {
+        Key::Int(i)
+    }
+}
+
+impl From<bool> for Key {
+    fn from(b: bool) -> Key {
+        Key::Bool(b)
+    }
+}
+
+// Like the value it was made from is shown
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Bool(b) => write!(f, "{}", b),
+            Key::Int(i) => write!(f, "{}", i),
+            Key::Text(text) => write!(f, "{:?}", text),
+            Key::List(items) => {
+                write!(f, "[")?;
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+// Formats like json only take string keys, and turn ints and truths into strings themselves
+impl Serialize for Key {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Key::Bool(b) => serializer.serialize_bool(*b),
+            Key::Int(i) => serializer.serialize_i64(*i),
+            Key::Text(text) => serializer.serialize_str(text),
+            Key::List(items) => serializer.collect_seq(items),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Key;
+    use crate::exec::value::{PrimitiveValue, Value, ValueVariant};
+
+    #[test]
+    fn test_keys() {
+        let int = |i| Value::new(ValueVariant::Primitive(PrimitiveValue::Int(i)));
+        let pair = Value::list(vec![int(1), Value::new(ValueVariant::Primitive(PrimitiveValue::Text("a".into())))]);
+
+        let key = Key::try_from(&pair).unwrap();
+        assert_eq!(key, Key::List(vec![Key::Int(1), Key::from("a")]));
+        assert_eq!(key.to_string(), "[1, \"a\"]");
+        assert_eq!(Key::try_from(&key.to_value()).unwrap(), key);
+
+        let dec = Value::list(vec![Value::new(ValueVariant::Primitive(PrimitiveValue::Dec(0.5)))]);
+        assert_eq!(Key::try_from(&dec).unwrap_err().to_string(), "A dec can't be a map key. Keys are ints, strings, truths, or lists of them");
+
+        let map = Value::map([(Key::Int(2), int(20)), (Key::from(true), int(0)), (Key::Int(1), int(10))]);
+        assert_eq!(serde_json::to_string(&map).unwrap(), "{\"true\":0,\"1\":10,\"2\":20}");
+        assert!(serde_json::to_string(&Value::map([(key, int(0))])).is_err());
+        assert_eq!(map.as_map().unwrap().keys().cloned().collect::<Vec<_>>(), vec![Key::Bool(true), Key::Int(1), Key::Int(2)]);
+    }
+}
@@ -0,0 +1,150 @@
+// Every way numbers become text, so they read the same wherever they're shown.
+
+/// The shortest text that reads back as the same dec. It always has a `.` or an exponent,
+/// so it can't be taken for an int.
+pub fn dec(d: f64) -> String {
+    format!("{:?}", d)
+}
+
+/// `d` rounded to `places` digits after the point.
+pub fn fixed(d: f64, places: usize) -> String {
+    format!("{:.*}", places, d)
+}
+
+/// `i` in lowercase hexadecimal, with a `-` in front when it's negative.
+pub fn hex(i: i64) -> String {
+    match i < 0 {
+        true => format!("-{:x}", i.unsigned_abs()),
+        false => format!("{:x}", i)
+    }
+}
+
+/// `i` with a `,` between each group of three digits.
+pub fn int_with_separators(i: i64) -> String {
+    grouped(&i.to_string())
+}
+
+/// `d` with a `,` between each group of three digits before the point. Never with an exponent,
+/// which would get separators in the wrong place.
+pub fn dec_with_separators(d: f64) -> String {
+    grouped(&d.to_string())
+}
+
+fn grouped(number: &str) -> String {
+    let (sign, unsigned) = match number.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", number)
+    };
+    let digits = unsigned.find(|c: char| !c.is_ascii_digit()).unwrap_or(unsigned.len());
+    let (whole, rest) = unsigned.split_at(digits);
+
+    let mut output = String::from(sign);
+    for (index, digit) in whole.chars().enumerate() {
+        if index > 0 && (whole.len() - index) % 3 == 0 {
+            output.push(',');
+        }
+        output.push(digit);
+    }
+    output.push_str(rest);
+
+    output
+}
+
+/// Why some text isn't a number.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    // The character where it stops being a number, counting characters from 0
+    Unexpected(usize, char),
+    // It ended where a digit should have been
+    Incomplete,
+    TooLarge,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ParseError::Unexpected(position, c) => write!(f, "unexpected {:?} at position {}", c, position),
+            ParseError::Incomplete => write!(f, "it ends before a digit"),
+            ParseError::TooLarge => write!(f, "it's too large"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A whole number, like `-1_000`: an optional sign, then digits with `_` between any of them.
+/// Only ASCII digits count, whatever the locale.
+pub fn parse_int<T: std::str::FromStr>(text: &str) -> Result<T, ParseError> {
+    normalized(text, false)?.parse().map_err(|_| ParseError::TooLarge)
+}
+
+/// A whole number, or one with a fraction like `12.345_6`.
+pub fn parse_dec(text: &str) -> Result<f64, ParseError> {
+    normalized(text, true)?.parse().map_err(|_| ParseError::TooLarge)
+}
+
+// The number without its `_`s, once it's checked to be one
+fn normalized(text: &str, fraction: bool) -> Result<String, ParseError> {
+    let mut chars = text.chars().enumerate().peekable();
+    let mut output = String::new();
+
+    if let Some((_, sign)) = chars.next_if(|(_, c)| *c == '-' || *c == '+') {
+        output.push(sign);
+    }
+
+    let mut point_allowed = fraction;
+    loop {
+        // Every part has at least a digit, and `_` only goes between two of them
+        match chars.next() {
+            Some((_, c)) if c.is_ascii_digit() => output.push(c),
+            Some((position, c)) => return Err(ParseError::Unexpected(position, c)),
+            None => return Err(ParseError::Incomplete)
+        }
+
+        let mut at_point = false;
+        while let Some((position, c)) = chars.next() {
+            match c {
+                _ if c.is_ascii_digit() => output.push(c),
+                '_' if chars.peek().is_some_and(|(_, next)| next.is_ascii_digit()) => {},
+                '.' if point_allowed => {
+                    at_point = true;
+                    break;
+                },
+                _ => return Err(ParseError::Unexpected(position, c))
+            }
+        }
+
+        if !at_point {
+            return Ok(output);
+        }
+        output.push('.');
+        point_allowed = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dec, grouped, parse_dec, parse_int, ParseError};
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(dec(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(dec(2.0), "2.0");
+        assert_eq!((0.1 + 0.2f64).to_string().parse::<f64>().unwrap(), dec(0.1 + 0.2).parse::<f64>().unwrap());
+
+        assert_eq!(grouped("1234567"), "1,234,567");
+        assert_eq!(grouped("-123456.789"), "-123,456.789");
+        assert_eq!(grouped("999"), "999");
+
+        assert_eq!(parse_int::<i64>("-1_000_000"), Ok(-1000000));
+        assert_eq!(parse_int::<i64>("12x4"), Err(ParseError::Unexpected(2, 'x')));
+        assert_eq!(parse_int::<i64>("1__0"), Err(ParseError::Unexpected(1, '_')));
+        assert_eq!(parse_int::<i64>("١٢"), Err(ParseError::Unexpected(0, '١')));
+        assert_eq!(parse_int::<i64>("99999999999999999999"), Err(ParseError::TooLarge));
+        assert_eq!(parse_int::<i64>("-"), Err(ParseError::Incomplete));
+        assert_eq!(parse_dec("12.345_6"), Ok(12.3456));
+        assert_eq!(parse_dec("3."), Err(ParseError::Incomplete));
+        assert_eq!(parse_dec("3.1.4"), Err(ParseError::Unexpected(3, '.')));
+        assert_eq!(parse_dec("1,5"), Err(ParseError::Unexpected(1, ',')));
+    }
+}
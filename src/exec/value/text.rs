@@ -0,0 +1,256 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use serde::{Serialize, Serializer};
+
+// The strings odo values hold. Values get cloned on every variable read and argument passed, so
+// cloning a string only counts a reference. Joining long strings doesn't copy them either: the
+// result points at both sides (a rope), and becomes one string the first time it's read. That
+// way building a string piece by piece in a loop takes linear time, not quadratic.
+
+// Joins shorter than this are copied right away, since then copying is cheaper than pointing
+const FLAT_LIMIT: usize = 256;
+
+/// An immutable string that's cheap to clone and to join.
+#[derive(Clone)]
+pub struct Text(Arc<Repr>);
+
+enum Repr {
+    Flat(Box<str>),
+    Joined(Rope),
+}
+
+struct Rope {
+    len: usize,
+    // Filled the first time it's read
+    flat: OnceLock<Box<str>>,
+    // Let go of once it's flat, so a long chain of joins doesn't keep every step alive
+    parts: Mutex<Option<(Text, Text)>>,
+}
+
+impl Text {
+    pub fn new(text: impl Into<Box<str>>) -> Text {
+        Text(Arc::new(Repr::Flat(text.into())))
+    }
+
+    /// `self` followed by `other`.
+    pub fn concat(&self, other: &Text) -> Text {
+        if other.is_empty() {
+            return self.clone();
+        }
+        if self.is_empty() {
+            return other.clone();
+        }
+
+        let len = self.len() + other.len();
+        if len < FLAT_LIMIT {
+            let mut joined = String::with_capacity(len);
+            joined.push_str(self);
+            joined.push_str(other);
+            return Text::new(joined);
+        }
+
+        let parts = Mutex::new(Some((self.clone(), other.clone())));
+        Text(Arc::new(Repr::Joined(Rope { len, flat: OnceLock::new(), parts })))
+    }
+
+    /// In bytes. Doesn't make joined text flat.
+    pub fn len(&self) -> usize {
+        match &*self.0 {
+            Repr::Flat(text) => text.len(),
+            Repr::Joined(rope) => rope.len
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn as_str(&self) -> &str {
+        match &*self.0 {
+            Repr::Flat(text) => text,
+            Repr::Joined(rope) => rope.flat.get_or_init(|| {
+                let flat = self.flatten(rope.len);
+                lock(&rope.parts).take();
+                flat
+            })
+        }
+    }
+
+    // Without recursion, since joins in a loop make ropes as deep as the loop is long
+    fn flatten(&self, len: usize) -> Box<str> {
+        let mut flat = String::with_capacity(len);
+        let mut pending = vec![self.clone()];
+
+        while let Some(text) = pending.pop() {
+            let rope = match &*text.0 {
+                Repr::Flat(part) => {
+                    flat.push_str(part);
+                    continue;
+                },
+                Repr::Joined(rope) => rope
+            };
+
+            if let Some(part) = rope.flat.get() {
+                flat.push_str(part);
+                continue;
+            }
+
+            let parts = lock(&rope.parts).clone();
+            match parts {
+                Some((left, right)) => {
+                    pending.push(right);
+                    pending.push(left);
+                },
+                // Another thread is making it flat, so this waits for it
+                None => flat.push_str(text.as_str())
+            }
+        }
+
+        flat.into_boxed_str()
+    }
+}
+
+fn lock(parts: &Mutex<Option<(Text, Text)>>) -> std::sync::MutexGuard<'_, Option<(Text, Text)>> {
+    parts.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl Drop for Rope {
+    // Also without recursion. Parts something else still uses are left alone.
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        let take_parts = |rope: &mut Rope, pending: &mut Vec<Text>| {
+            if let Some((left, right)) = rope.parts.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner()).take() {
+                pending.push(left);
+                pending.push(right);
+            }
+        };
+
+        take_parts(self, &mut pending);
+        while let Some(text) = pending.pop() {
+            if let Some(Repr::Joined(mut rope)) = Arc::into_inner(text.0) {
+                take_parts(&mut rope, &mut pending);
+            }
+        }
+    }
+}
+
+impl Deref for Text {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Default for Text {
+    fn default() -> Text {
+        Text::new("")
+    }
+}
+
+impl From<String> for Text {
+    fn from(text: String) -> Text {
+        Text::new(text)
+    }
+}
+
+impl From<&str> for Text {
+    fn from(text: &str) -> Text {
+        Text::new(text)
+    }
+}
+
+impl From<Text> for String {
+    fn from(text: Text) -> String {
+        text.as_str().to_string()
+    }
+}
+
+impl PartialEq for Text {
+    fn eq(&self, other: &Text) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || (self.len() == other.len() && self.as_str() == other.as_str())
+    }
+}
+
+impl Eq for Text {}
+
+impl PartialEq<str> for Text {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Text {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialOrd for Text {
+    fn partial_cmp(&self, other: &Text) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Text {
+    fn cmp(&self, other: &Text) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Hash for Text {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl fmt::Display for Text {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Debug for Text {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Serialize for Text {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Text, FLAT_LIMIT};
+
+    #[test]
+    fn test_text() {
+        let short = Text::from("odo").concat(&Text::from("!"));
+        assert_eq!(short, "odo!");
+
+        let piece = Text::from("x".repeat(FLAT_LIMIT));
+        let mut built = Text::default();
+        for _ in 0..4 {
+            built = built.concat(&piece).concat(&Text::from("-"));
+        }
+        assert_eq!(built.len(), 4 * (FLAT_LIMIT + 1));
+        let copy = built.clone();
+        assert_eq!(built.as_str(), format!("{}-", "x".repeat(FLAT_LIMIT)).repeat(4));
+        assert_eq!(copy, built);
+
+        // Neither reading nor dropping ropes this deep overflows the stack
+        let deep = || (0..200_000).fold(piece.clone(), |deep, _| deep.concat(&Text::from("y")));
+        let read = deep();
+        assert_eq!(read.len(), FLAT_LIMIT + 200_000);
+        assert!(read.starts_with("xx") && read.ends_with("yy"));
+        drop(read);
+        drop(deep());
+    }
+}
@@ -0,0 +1,417 @@
+use std::{net::{TcpListener, TcpStream}, path::PathBuf, sync::{mpsc, Arc, Mutex}, thread, time::{SystemTime, UNIX_EPOCH}};
+
+use anyhow::Context;
+use odo::{base::diagnostics::Diagnostic, exec::{cancel::CancellationToken, interpreter::Interpreter}};
+use ring::hmac;
+use serde::Deserialize;
+use serde_json::{json, Value as Json};
+use uuid::Uuid;
+
+use crate::sessions::{self, Journal};
+
+mod zmq;
+use zmq::SocketType;
+
+// `odo kernel`: a Jupyter kernel, so notebooks can run odo. Every cell is evaluated in the same
+// session, one at a time and all or nothing, like repl lines. It speaks version 5.3 of the
+// Jupyter messaging protocol, and only what a notebook needs of it.
+
+const PROTOCOL_VERSION: &str = "5.3";
+// Separates the routing frames from the message
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+// Where Jupyter says to listen, and the key to sign messages with
+#[derive(Deserialize)]
+struct ConnectionInfo {
+    transport: String,
+    ip: String,
+    shell_port: u16,
+    iopub_port: u16,
+    stdin_port: u16,
+    control_port: u16,
+    hb_port: u16,
+    key: String,
+    signature_scheme: String,
+}
+
+/// Registers the kernel with Jupyter, in the user's data directory.
+pub fn install() -> anyhow::Result<()> {
+    let data = match std::env::var("JUPYTER_DATA_DIR") {
+        Ok(data) => PathBuf::from(data),
+        Err(_) => PathBuf::from(std::env::var("HOME").context("HOME isn't set")?).join(".local").join("share").join("jupyter"),
+    };
+    let directory = data.join("kernels").join("odo");
+    std::fs::create_dir_all(&directory)?;
+
+    let executable = std::env::current_exe()?;
+    let spec = json!({
+        "argv": [executable, "kernel", "--connection-file", "{connection_file}"],
+        "display_name": "odo",
+        "language": "odo",
+    });
+    std::fs::write(directory.join("kernel.json"), serde_json::to_string_pretty(&spec)?)?;
+    println!("Installed the odo kernel in {}", directory.display());
+
+    Ok(())
+}
+
+/// Serves the sockets in the connection file until a client asks the kernel to shut down.
+/// With a `session` name, cells continue that saved session and are saved to it.
+pub fn run(mut interpreter: Interpreter, connection_file: &str, session: Option<&str>) -> anyhow::Result<()> {
+    let info: ConnectionInfo = serde_json::from_str(&std::fs::read_to_string(connection_file)
+        .with_context(|| format!("Could not read connection file {}", connection_file))?)?;
+
+    if info.transport != "tcp" {
+        anyhow::bail!("The kernel only listens over tcp, not {}", info.transport);
+    }
+    let key = match info.key.as_str() {
+        "" => None,
+        key if info.signature_scheme == "hmac-sha256" => Some(hmac::Key::new(hmac::HMAC_SHA256, key.as_bytes())),
+        _ => anyhow::bail!("The kernel only signs messages with hmac-sha256, not {}", info.signature_scheme),
+    };
+
+    let bind = |port: u16| TcpListener::bind((info.ip.as_str(), port))
+        .with_context(|| format!("Could not listen on {}:{}", info.ip, port));
+
+    let (sender, requests) = mpsc::channel();
+    serve_requests(bind(info.shell_port)?, sender.clone(), None);
+    // Interrupts come in while a cell runs, so they can't wait their turn
+    let interrupts = Interrupts { key: key.clone(), cancellation: interpreter.cancellation_token() };
+    serve_requests(bind(info.control_port)?, sender, Some(interrupts));
+    // Nothing asks for input, but clients connect to it all the same
+    serve_requests(bind(info.stdin_port)?, mpsc::channel().0, None);
+    heartbeat(bind(info.hb_port)?);
+    let subscribers = publish(bind(info.iopub_port)?);
+
+    let journal = match session {
+        Some(name) => {
+            let directory = sessions::directory().context("Sessions are saved in the config directory, and HOME isn't set")?;
+            let journal = Journal::open(&directory, name)?;
+            journal.replay(&mut interpreter)?;
+            Some(journal)
+        },
+        None => None
+    };
+
+    let mut kernel = Kernel { interpreter, journal, key, session: Uuid::new_v4().to_string(), subscribers, execution_count: 0 };
+
+    for (message, connection) in requests {
+        let Some(request) = parse(kernel.key.as_ref(), message) else {
+            continue;
+        };
+
+        if !kernel.handle(request, &connection)? {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+// A client's connection to the shell or control socket, to answer it on
+type Connection = Arc<Mutex<TcpStream>>;
+
+// Each client gets a thread reading its requests. They all go to the kernel through one
+// channel, so cells run one at a time.
+fn serve_requests(listener: TcpListener, requests: mpsc::Sender<(zmq::Message, Connection)>, interrupts: Option<Interrupts>) {
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let (requests, interrupts) = (requests.clone(), interrupts.clone());
+            thread::spawn(move || {
+                let Ok(writer) = zmq::handshake(&mut stream, SocketType::Router).and_then(|_| stream.try_clone()) else {
+                    return;
+                };
+                let connection = Arc::new(Mutex::new(writer));
+
+                while let Ok(message) = zmq::receive(&mut stream) {
+                    // Stopped right away, and answered in order with everything else
+                    if let Some(interrupts) = &interrupts {
+                        let request = parse(interrupts.key.as_ref(), message.clone());
+                        if request.is_some_and(|request| request.msg_type() == "interrupt_request") {
+                            interrupts.cancellation.cancel();
+                        }
+                    }
+
+                    if requests.send((message, connection.clone())).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+}
+
+#[derive(Clone)]
+struct Interrupts {
+    key: Option<hmac::Key>,
+    cancellation: CancellationToken,
+}
+
+// Answers every ping with itself, even while a cell runs, so clients know the kernel is alive
+fn heartbeat(listener: TcpListener) {
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            thread::spawn(move || {
+                if zmq::handshake(&mut stream, SocketType::Rep).is_err() {
+                    return;
+                }
+
+                while let Ok(message) = zmq::receive(&mut stream) {
+                    if zmq::send(&mut stream, &message).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+}
+
+// Everyone connected to iopub gets every message, whatever they subscribed to
+fn publish(listener: TcpListener) -> Arc<Mutex<Vec<TcpStream>>> {
+    let subscribers = Arc::new(Mutex::new(Vec::new()));
+
+    let accepted = subscribers.clone();
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let Ok(writer) = zmq::handshake(&mut stream, SocketType::Pub).and_then(|_| stream.try_clone()) else {
+                continue;
+            };
+            accepted.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(writer);
+
+            // Subscriptions come in as messages. Reading them keeps the connection from filling up.
+            thread::spawn(move || while zmq::receive(&mut stream).is_ok() {});
+        }
+    });
+
+    subscribers
+}
+
+struct Request {
+    // The routing frames, sent back with the reply
+    identities: Vec<Vec<u8>>,
+    header: Json,
+    content: Json,
+}
+
+impl Request {
+    fn msg_type(&self) -> &str {
+        self.header["msg_type"].as_str().unwrap_or_default()
+    }
+}
+
+// Messages that don't have the frames they should, or aren't signed with the key, are dropped
+fn parse(key: Option<&hmac::Key>, mut message: zmq::Message) -> Option<Request> {
+    let delimiter = message.iter().position(|frame| frame == DELIMITER)?;
+    let frames = message.split_off(delimiter + 1);
+    let [signature, header, parent, metadata, content, ..] = frames.as_slice() else {
+        return None;
+    };
+
+    if let Some(key) = key {
+        let signed = [header, parent, metadata, content].map(|frame| frame.as_slice()).concat();
+        hmac::verify(key, &signed, &decode_hex(signature)?).ok()?;
+    }
+
+    message.pop();
+    Some(Request {
+        identities: message,
+        header: serde_json::from_slice(header).ok()?,
+        content: serde_json::from_slice(content).ok()?,
+    })
+}
+
+struct Kernel<'a> {
+    interpreter: Interpreter<'a>,
+    key: Option<hmac::Key>,
+    session: String,
+    subscribers: Arc<Mutex<Vec<TcpStream>>>,
+    execution_count: u64,
+    journal: Option<Journal>,
+}
+
+impl<'a> Kernel<'a> {
+    // Returns false once the kernel should stop
+    fn handle(&mut self, request: Request, connection: &Connection) -> anyhow::Result<bool> {
+        self.publish("status", &request.header, json!({ "execution_state": "busy" }));
+
+        let reply = match request.msg_type() {
+            "kernel_info_request" => json!({
+                "status": "ok",
+                "protocol_version": PROTOCOL_VERSION,
+                "implementation": "odo",
+                "implementation_version": env!("CARGO_PKG_VERSION"),
+                "language_info": {
+                    "name": "odo",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "mimetype": "text/x-odo",
+                    "file_extension": ".odo",
+                },
+                "banner": "odo",
+                "help_links": [],
+            }),
+            "execute_request" => self.execute(&request),
+            "inspect_request" => self.inspect(&request),
+            "is_complete_request" => json!({ "status": "complete" }),
+            "complete_request" => {
+                let cursor = request.content["cursor_pos"].clone();
+                json!({ "status": "ok", "matches": [], "cursor_start": cursor, "cursor_end": cursor, "metadata": {} })
+            },
+            "comm_info_request" => json!({ "status": "ok", "comms": {} }),
+            "history_request" => json!({ "status": "ok", "history": [] }),
+            // The running cell was already stopped, when this was read
+            "interrupt_request" => json!({ "status": "ok" }),
+            "shutdown_request" => json!({ "status": "ok", "restart": request.content["restart"] }),
+            other => {
+                eprintln!("odo kernel: ignoring a {} message", other);
+                Json::Null
+            }
+        };
+
+        if !reply.is_null() {
+            let msg_type = request.msg_type().replace("_request", "_reply");
+            let mut message = request.identities.clone();
+            message.extend(self.message(&msg_type, &request.header, reply));
+
+            let mut stream = connection.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            zmq::send(&mut *stream, &message)?;
+        }
+
+        self.publish("status", &request.header, json!({ "execution_state": "idle" }));
+
+        Ok(request.msg_type() != "shutdown_request")
+    }
+
+    fn execute(&mut self, request: &Request) -> Json {
+        let code = request.content["code"].as_str().unwrap_or_default().to_string();
+        let silent = request.content["silent"].as_bool().unwrap_or(false);
+
+        if !silent {
+            self.execution_count += 1;
+            self.publish("execute_input", &request.header, json!({ "code": code, "execution_count": self.execution_count }));
+        }
+
+        let journal = &mut self.journal;
+        let (result, stdout, mut stderr) = self.interpreter.capture_output(|interpreter| match journal {
+            Some(journal) => journal.run(interpreter, code),
+            None => interpreter.eval_atomic(code)
+        });
+        for warning in self.interpreter.semantic_analyzer.take_warnings() {
+            stderr.push_str(&format!("{}\n", warning));
+        }
+
+        if !silent {
+            for (name, text) in [("stdout", stdout), ("stderr", stderr)] {
+                if !text.is_empty() {
+                    self.publish("stream", &request.header, json!({ "name": name, "text": text }));
+                }
+            }
+        }
+
+        match result {
+            Ok(result) => {
+                if let Some(value) = result.value.filter(|_| !silent) {
+                    let data = json!({ "text/plain": self.interpreter.format(&value) });
+                    self.publish("execute_result", &request.header, json!({ "execution_count": self.execution_count, "data": data, "metadata": {} }));
+                }
+
+                json!({ "status": "ok", "execution_count": self.execution_count, "payload": [], "user_expressions": {} })
+            },
+            Err(error) => {
+                // Where it happened, for frontends that can point at it
+                let diagnostic = Diagnostic::from_error(&error, None);
+                let error = json!({
+                    "ename": diagnostic.code,
+                    "evalue": error.to_string(),
+                    "traceback": [error.to_string()],
+                    "diagnostic": diagnostic,
+                });
+                if !silent {
+                    self.publish("error", &request.header, error.clone());
+                }
+
+                let mut reply = json!({ "status": "error", "execution_count": self.execution_count });
+                reply.as_object_mut().expect("The reply is an object").extend(error.as_object().cloned().unwrap_or_default());
+                reply
+            }
+        }
+    }
+
+    // The type of what's under the cursor, like the repl's `:type`
+    fn inspect(&mut self, request: &Request) -> Json {
+        let code = request.content["code"].as_str().unwrap_or_default();
+        // Jupyter counts the cursor in characters
+        let cursor = request.content["cursor_pos"].as_u64().unwrap_or_default() as usize;
+        let offset = code.char_indices().nth(cursor).map_or(code.len(), |(offset, _)| offset);
+        let before = code[..offset].char_indices().next_back().map(|(offset, _)| offset);
+
+        let info = self.interpreter.type_at(code, offset)
+            .or_else(|| before.and_then(|before| self.interpreter.type_at(code, before)));
+
+        match info {
+            Some(info) => json!({ "status": "ok", "found": true, "data": { "text/plain": info.type_name }, "metadata": {} }),
+            None => json!({ "status": "ok", "found": false, "data": {}, "metadata": {} }),
+        }
+    }
+
+    fn message(&self, msg_type: &str, parent: &Json, content: Json) -> zmq::Message {
+        let header = json!({
+            "msg_id": Uuid::new_v4().to_string(),
+            "session": self.session,
+            "username": "odo",
+            "date": now(),
+            "msg_type": msg_type,
+            "version": PROTOCOL_VERSION,
+        });
+
+        let frames = [header, parent.clone(), json!({}), content].map(|part| part.to_string().into_bytes());
+        let signature = match &self.key {
+            Some(key) => encode_hex(hmac::sign(key, &frames.concat()).as_ref()),
+            None => String::new(),
+        };
+
+        [DELIMITER.to_vec(), signature.into_bytes()].into_iter().chain(frames).collect()
+    }
+
+    fn publish(&self, msg_type: &str, parent: &Json, content: Json) {
+        let mut message = vec![msg_type.as_bytes().to_vec()];
+        message.extend(self.message(msg_type, parent, content));
+
+        // Subscribers that went away are dropped
+        self.subscribers.lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .retain_mut(|stream| zmq::send(stream, &message).is_ok());
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(text: &[u8]) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(text).ok()?;
+    if text.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..text.len()).step_by(2).map(|start| u8::from_str_radix(text.get(start..start + 2)?, 16).ok()).collect()
+}
+
+// ISO 8601 in UTC, like 2024-01-31T12:00:00.000000Z
+fn now() -> String {
+    let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (days, seconds) = ((elapsed.as_secs() / 86400) as i64, elapsed.as_secs() % 86400);
+
+    // Days since the epoch to a date, from Howard Hinnant's civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60, elapsed.subsec_micros())
+}
@@ -0,0 +1,172 @@
+use std::io::{self, Read, Write};
+
+// Just enough of ZeroMQ for a Jupyter kernel: ZMTP 3.0 over TCP with the NULL mechanism.
+// Every connection is handled on its own, so a ROUTER answers on the connection a request
+// came in on instead of routing by identity, and a PUB sends everything to every subscriber.
+
+#[derive(Clone, Copy)]
+pub enum SocketType {
+    Router,
+    Pub,
+    Rep,
+    #[cfg(test)]
+    Req,
+}
+
+impl SocketType {
+    fn name(&self) -> &'static str {
+        match self {
+            SocketType::Router => "ROUTER",
+            SocketType::Pub => "PUB",
+            SocketType::Rep => "REP",
+            #[cfg(test)]
+            SocketType::Req => "REQ",
+        }
+    }
+}
+
+/// The frames of a message, in order.
+pub type Message = Vec<Vec<u8>>;
+
+const MORE: u8 = 0x01;
+const LONG: u8 = 0x02;
+const COMMAND: u8 = 0x04;
+
+// Kernel messages are small. Anything bigger is a broken or hostile peer.
+const MAX_FRAME: u64 = 64 * 1024 * 1024;
+
+/// Exchanges greetings and READY commands with the peer, before any message.
+pub fn handshake(stream: &mut (impl Read + Write), socket_type: SocketType) -> io::Result<()> {
+    let mut greeting = [0u8; 64];
+    greeting[0] = 0xFF;
+    greeting[9] = 0x7F;
+    greeting[10] = 3;
+    greeting[12..16].copy_from_slice(b"NULL");
+    stream.write_all(&greeting)?;
+
+    let mut peer = [0u8; 64];
+    stream.read_exact(&mut peer)?;
+    if peer[0] != 0xFF || peer[9] != 0x7F || peer[10] < 3 {
+        return Err(invalid("The peer doesn't speak ZMTP 3"));
+    }
+    if &peer[12..16] != b"NULL" {
+        return Err(invalid("Only the NULL security mechanism is supported"));
+    }
+
+    let mut ready = b"\x05READY".to_vec();
+    property(&mut ready, "Socket-Type", socket_type.name().as_bytes());
+    write_frame(stream, &ready, COMMAND)?;
+
+    loop {
+        let (flags, body) = read_frame(stream)?;
+        if flags & COMMAND != 0 && body.starts_with(b"\x05READY") {
+            return Ok(());
+        }
+    }
+}
+
+pub fn send(stream: &mut impl Write, message: &[Vec<u8>]) -> io::Result<()> {
+    // All at once, so frames from different threads can't end up mixed
+    let mut buffer = Vec::new();
+    for (index, frame) in message.iter().enumerate() {
+        let more = if index + 1 < message.len() { MORE } else { 0 };
+        write_frame(&mut buffer, frame, more)?;
+    }
+
+    stream.write_all(&buffer)?;
+    stream.flush()
+}
+
+/// The next message from the peer. Commands between messages are skipped.
+pub fn receive(stream: &mut impl Read) -> io::Result<Message> {
+    let mut message = Vec::new();
+    loop {
+        let (flags, body) = read_frame(stream)?;
+        if flags & COMMAND != 0 {
+            continue;
+        }
+
+        message.push(body);
+        if flags & MORE == 0 {
+            return Ok(message);
+        }
+    }
+}
+
+// name-length name value-length value
+fn property(command: &mut Vec<u8>, name: &str, value: &[u8]) {
+    command.push(name.len() as u8);
+    command.extend_from_slice(name.as_bytes());
+    command.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    command.extend_from_slice(value);
+}
+
+fn write_frame(stream: &mut impl Write, body: &[u8], flags: u8) -> io::Result<()> {
+    if body.len() > u8::MAX as usize {
+        stream.write_all(&[flags | LONG])?;
+        stream.write_all(&(body.len() as u64).to_be_bytes())?;
+    } else {
+        stream.write_all(&[flags, body.len() as u8])?;
+    }
+
+    stream.write_all(body)
+}
+
+fn read_frame(stream: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut flags = [0u8];
+    stream.read_exact(&mut flags)?;
+    let flags = flags[0];
+
+    let size = if flags & LONG != 0 {
+        let mut size = [0u8; 8];
+        stream.read_exact(&mut size)?;
+        u64::from_be_bytes(size)
+    } else {
+        let mut size = [0u8];
+        stream.read_exact(&mut size)?;
+        size[0] as u64
+    };
+
+    if size > MAX_FRAME {
+        return Err(invalid("The peer sent a frame that's too big"));
+    }
+
+    let mut body = vec![0; size as usize];
+    stream.read_exact(&mut body)?;
+
+    Ok((flags, body))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{TcpListener, TcpStream};
+
+    use super::{handshake, receive, send, SocketType};
+
+    #[test]
+    fn test_round_trip() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let echo = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            handshake(&mut stream, SocketType::Rep).unwrap();
+            let message = receive(&mut stream).unwrap();
+            send(&mut stream, &message).unwrap();
+        });
+
+        let mut client = TcpStream::connect(address).unwrap();
+        handshake(&mut client, SocketType::Req).unwrap();
+
+        // Long frames take 8 bytes for their size
+        let message = vec![vec![], b"ping".to_vec(), vec![7; 300]];
+        send(&mut client, &message).unwrap();
+        assert_eq!(receive(&mut client).unwrap(), message);
+
+        echo.join().unwrap();
+    }
+}
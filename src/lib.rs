@@ -1,3 +1,7 @@
+#[macro_use]
+mod trace;
+
 pub mod base;
+pub mod compile;
 pub mod exec;
-pub mod native;
\ No newline at end of file
+pub mod native;
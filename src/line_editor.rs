@@ -0,0 +1,244 @@
+// Editing needs a terminal in raw mode, which is only done on unix for now
+#![cfg_attr(not(unix), allow(dead_code))]
+
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+
+// Reads the repl's lines. On a terminal, the arrows move through the line and through the
+// history, so past lines can be run again. Anywhere else, like when input is piped in, lines
+// are read as they come.
+
+/// The next line, with its newline like `read_line` leaves it. None at the end of input.
+pub fn read_line(prompt: &str, history: &VecDeque<String>) -> anyhow::Result<Option<String>> {
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+
+    #[cfg(unix)]
+    if let Some(_raw) = terminal::RawMode::enter() {
+        return Editor::new(prompt, history).run();
+    }
+
+    let mut line = String::new();
+    if std::io::stdin().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(line))
+}
+
+#[cfg(unix)]
+mod terminal {
+    // Keys arrive as they're pressed and aren't echoed, while it's alive. Ctrl-C still
+    // sends its signal, so the repl handles it as it always does.
+    pub struct RawMode(libc::termios);
+
+    impl RawMode {
+        pub fn enter() -> Option<RawMode> {
+            unsafe {
+                if libc::isatty(libc::STDIN_FILENO) == 0 {
+                    return None;
+                }
+
+                let mut original: libc::termios = std::mem::zeroed();
+                if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                    return None;
+                }
+
+                let mut raw = original;
+                raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+                raw.c_cc[libc::VMIN] = 1;
+                raw.c_cc[libc::VTIME] = 0;
+                if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                    return None;
+                }
+
+                Some(RawMode(original))
+            }
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0) };
+        }
+    }
+}
+
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    // Ctrl-D
+    EndOfInput,
+    Other,
+}
+
+struct Editor<'h> {
+    prompt: &'h str,
+    history: &'h VecDeque<String>,
+    line: Vec<char>,
+    cursor: usize,
+    // Which history line is shown, counting back from the newest. None for the line being typed.
+    recalled: Option<usize>,
+    // What was being typed before going through the history
+    typed: Vec<char>,
+}
+
+impl<'h> Editor<'h> {
+    fn new(prompt: &'h str, history: &'h VecDeque<String>) -> Editor<'h> {
+        Editor { prompt, history, line: vec![], cursor: 0, recalled: None, typed: vec![] }
+    }
+
+    fn run(mut self) -> anyhow::Result<Option<String>> {
+        loop {
+            match read_key()? {
+                Key::Char(c) => {
+                    self.line.insert(self.cursor, c);
+                    self.cursor += 1;
+                },
+                Key::Enter => {
+                    println!();
+                    let mut line: String = self.line.into_iter().collect();
+                    line.push('\n');
+                    return Ok(Some(line));
+                },
+                Key::EndOfInput if self.line.is_empty() => {
+                    println!();
+                    return Ok(None);
+                },
+                Key::Backspace if self.cursor > 0 => {
+                    self.cursor -= 1;
+                    self.line.remove(self.cursor);
+                },
+                Key::Delete if self.cursor < self.line.len() => {
+                    self.line.remove(self.cursor);
+                },
+                Key::Left => self.cursor = self.cursor.saturating_sub(1),
+                Key::Right => self.cursor = (self.cursor + 1).min(self.line.len()),
+                Key::Home => self.cursor = 0,
+                Key::End => self.cursor = self.line.len(),
+                Key::Up => self.recall(self.recalled.map_or(0, |back| back + 1)),
+                Key::Down => match self.recalled {
+                    Some(0) => {
+                        self.recalled = None;
+                        self.line = std::mem::take(&mut self.typed);
+                        self.cursor = self.line.len();
+                    },
+                    Some(back) => self.recall(back - 1),
+                    None => {},
+                },
+                _ => continue
+            }
+
+            self.redraw()?;
+        }
+    }
+
+    fn recall(&mut self, back: usize) {
+        let Some(line) = self.history.len().checked_sub(back + 1).and_then(|index| self.history.get(index)) else {
+            return;
+        };
+
+        if self.recalled.is_none() {
+            self.typed = std::mem::take(&mut self.line);
+        }
+
+        self.recalled = Some(back);
+        self.line = line.chars().collect();
+        self.cursor = self.line.len();
+    }
+
+    // The whole line again, with the cursor put back where it is in the line
+    fn redraw(&self) -> anyhow::Result<()> {
+        let line: String = self.line.iter().collect();
+        let mut output = format!("\r{}{}\x1b[K", self.prompt, line);
+        if self.cursor < self.line.len() {
+            output.push_str(&format!("\x1b[{}D", self.line.len() - self.cursor));
+        }
+
+        print!("{}", output);
+        std::io::stdout().flush()?;
+        Ok(())
+    }
+}
+
+fn read_byte() -> anyhow::Result<Option<u8>> {
+    let mut byte = [0];
+    match std::io::stdin().read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0]))
+    }
+}
+
+fn read_key() -> anyhow::Result<Key> {
+    let Some(byte) = read_byte()? else {
+        return Ok(Key::EndOfInput);
+    };
+
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        0x7f | 0x08 => Key::Backspace,
+        0x04 => Key::EndOfInput,
+        0x1b => read_escape()?,
+        byte if byte < 0x20 => Key::Other,
+        byte if byte < 0x80 => Key::Char(byte as char),
+        first => {
+            // The rest of a character that takes more than one byte
+            let length = match first {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                _ => 4,
+            };
+            let mut bytes = vec![first];
+            for _ in 1..length {
+                bytes.extend(read_byte()?);
+            }
+
+            match std::str::from_utf8(&bytes).ok().and_then(|text| text.chars().next()) {
+                Some(c) => Key::Char(c),
+                None => Key::Other
+            }
+        }
+    };
+
+    Ok(key)
+}
+
+// What's after an escape: `[` and a letter for the arrows, or numbers ending in `~`
+fn read_escape() -> anyhow::Result<Key> {
+    if !matches!(read_byte()?, Some(b'[') | Some(b'O')) {
+        return Ok(Key::Other);
+    }
+
+    let mut number = String::new();
+    loop {
+        let key = match read_byte()? {
+            Some(b'A') => Key::Up,
+            Some(b'B') => Key::Down,
+            Some(b'C') => Key::Right,
+            Some(b'D') => Key::Left,
+            Some(b'H') => Key::Home,
+            Some(b'F') => Key::End,
+            Some(digit) if digit.is_ascii_digit() => {
+                number.push(digit as char);
+                continue;
+            },
+            Some(b'~') => match number.as_str() {
+                "1" | "7" => Key::Home,
+                "4" | "8" => Key::End,
+                "3" => Key::Delete,
+                _ => Key::Other
+            },
+            _ => Key::Other
+        };
+
+        return Ok(key);
+    }
+}
@@ -1,39 +1,559 @@
+use anyhow::Context;
 use clap::Parser;
-use anyhow;
+use odo::base::{bundle::{self, Bundle, Recording}, diagnostics::{self, Diagnostic}, lints::Lint, modules::FileLoader, syntax};
+use odo::exec::{compiled::CompiledProgram, emit, inspect, value::format::Limits, interpreter::Interpreter, policy::{ExecutionPolicy, IntegerOverflow}, replay};
+use std::{path::{Path, PathBuf}, process::ExitCode};
+
+mod config;
+use config::{Config, ColorScheme};
+#[cfg(feature = "kernel")]
+mod kernel;
+mod line_editor;
+mod manifest;
+use manifest::Manifest;
+mod serve;
+mod sessions;
 
 #[derive(Parser)]
 #[command(author, version)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     source_file: Option<String>,
 
     // About
     #[clap(short, long)]
     about: bool,
+
+    // These override the values in the config file
+    #[clap(long, global = true)]
+    prompt: Option<String>,
+
+    #[clap(long, global = true)]
+    fuel: Option<u64>,
+
+    #[clap(long, global = true)]
+    history_size: Option<usize>,
+
+    #[clap(long, global = true)]
+    no_color: bool,
+
+    // Starts the repl without the logo
+    #[clap(short, long, global = true)]
+    quiet: bool,
+
+    // Doesn't print the value of each expression the repl evaluates
+    #[clap(long, global = true)]
+    no_echo_results: bool,
+
+    // checked, wrapping or saturating
+    #[clap(long, global = true, default_value = "checked")]
+    overflow: IntegerOverflow,
+
+    // Programs with warnings still exit with 0, if they run fine
+    #[clap(long, global = true)]
+    no_fail_on_warnings: bool,
+
+    // Stricter rules: no mixing ints and decs, no hiding names with parameters,
+    // no `any` in function signatures, and warnings are errors
+    #[clap(long, global = true)]
+    strict: bool,
+
+    // Turns off a kind of warning, like dead-code. Can be given more than once.
+    #[clap(long, global = true, value_name = "LINT", value_parser = |name: &str| Lint::from_name(name))]
+    allow: Vec<Lint>,
+
+    // Defines a feature, so declarations marked @cfg(FEATURE) are there. Can be given more than once.
+    #[clap(long, global = true, value_name = "FEATURE")]
+    define: Vec<String>,
+
+    // How errors and warnings are printed: human, or json for editors and CI (one object per line, on stderr)
+    #[clap(long, global = true, default_value = "human")]
+    error_format: ErrorFormat,
+
+    // Saves the inputs the script reads (time, random, stdin) to a trace file
+    #[clap(long, global = true, value_name = "TRACE")]
+    record: Option<String>,
+
+    // Runs the script with the inputs from a recorded trace
+    #[clap(long, global = true, value_name = "TRACE", conflicts_with = "record")]
+    replay: Option<String>,
+
+    // Prints the time spent in each phase and every statement that runs
+    #[cfg(feature = "trace")]
+    #[clap(long, global = true)]
+    trace: bool,
+
+    // Lets scripts use httpGet and httpPost
+    #[cfg(feature = "net")]
+    #[clap(long, global = true)]
+    allow_net: bool,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq)]
+enum ErrorFormat {
+    Human,
+    Json,
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Cli::parse();
+#[derive(clap::Subcommand)]
+enum Command {
+    // Prints an editor syntax definition, generated from the lexer's keywords and operators
+    Syntax {
+        // tmlanguage or tree-sitter
+        #[clap(long)]
+        emit: syntax::Format,
+    },
+    // Analyzes a file and saves it, so running it skips lexing, parsing and analysis
+    Build {
+        source_file: String,
+        // Defaults to the source file with an .odoc extension
+        #[clap(short, long)]
+        output: Option<String>,
+    },
+    // Runs a source file, a compiled .odoc file or an .odob bundle. Without one, runs the entry of
+    // the project's odo.toml.
+    Run {
+        file: Option<String>,
+    },
+    // Packs a source file and every module it imports into one .odob file that odo run can run.
+    // Without a file, bundles the entry of the project's odo.toml.
+    Bundle {
+        source_file: Option<String>,
+        // Defaults to the source file with an .odob extension
+        #[clap(short, long)]
+        output: Option<String>,
+    },
+    // Prints the analyzed program: its scopes and symbols, and the tree that runs
+    Inspect {
+        source_file: String,
+    },
+    // Prints the program in another language. Experimental: only part of odo has a translation
+    Emit {
+        source_file: String,
+        // js
+        #[clap(long)]
+        target: emit::Target,
+    },
+    // Starts the repl, like running odo without a file
+    Repl {
+        // Files evaluated into the session before the first prompt, in order
+        #[clap(long, value_name = "FILE")]
+        preload: Vec<String>,
+    },
+    // Evaluates code for editors and web frontends, one JSON request per line. Listens on
+    // localhost, or on a unix socket.
+    Serve {
+        #[clap(long)]
+        port: Option<u16>,
+        #[cfg(unix)]
+        #[clap(long, value_name = "PATH", conflicts_with = "port")]
+        socket: Option<String>,
+        // Where named sessions are saved. Defaults to the sessions folder in the config directory.
+        #[clap(long, value_name = "DIR")]
+        sessions: Option<String>,
+    },
+    // Runs as a Jupyter kernel, with the sockets in the connection file Jupyter starts it with
+    #[cfg(feature = "kernel")]
+    Kernel {
+        #[clap(long)]
+        connection_file: Option<String>,
+        // Registers the kernel with Jupyter, so notebooks can pick odo
+        #[clap(long)]
+        install: bool,
+        // Continues a saved session, and saves the cells to it
+        #[clap(long, value_name = "NAME")]
+        session: Option<String>,
+    },
+}
+
+// Exit codes, so shell scripts and CI can tell failures apart. 0 is success.
+// The program failed while running, or something else went wrong
+const EXIT_RUNTIME_ERROR: u8 = 1;
+// The program couldn't be lexed, parsed or analyzed, so it never ran.
+// Also for programs with warnings, unless --no-fail-on-warnings is given.
+const EXIT_COMPILE_ERROR: u8 = 2;
+// The command line was wrong
+const EXIT_USAGE_ERROR: u8 = 3;
+
+fn main() -> ExitCode {
+    let args = match Cli::try_parse() {
+        Ok(args) => args,
+        Err(error) => {
+            let _ = error.print();
+            // --help and --version are "errors" too, printed to stdout
+            return if error.use_stderr() { ExitCode::from(EXIT_USAGE_ERROR) } else { ExitCode::SUCCESS };
+        }
+    };
+    let format = args.error_format;
+
+    match run(args) {
+        Ok(code) => code,
+        Err(error) => {
+            match format {
+                ErrorFormat::Human => eprintln!("Error: {:?}", error),
+                ErrorFormat::Json => eprintln!("{}", Diagnostic::from_error(&error, None).to_json()),
+            }
+
+            ExitCode::from(exit_code(&error))
+        }
+    }
+}
 
+fn exit_code(error: &anyhow::Error) -> u8 {
+    if diagnostics::is_compile_error(error) {
+        EXIT_COMPILE_ERROR
+    } else {
+        EXIT_RUNTIME_ERROR
+    }
+}
+
+fn run(args: Cli) -> anyhow::Result<ExitCode> {
     if args.about {
         repl::print_logo();
-        return Ok(());
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let project = match &args.command {
+        Some(Command::Run { file: None }) | Some(Command::Bundle { source_file: None, .. }) => Some(Manifest::find(&std::env::current_dir()?)?),
+        _ => None
+    };
+
+    let source_file = match &args.command {
+        Some(Command::Syntax { emit }) => {
+            print!("{}", syntax::emit(*emit));
+            return Ok(ExitCode::SUCCESS);
+        },
+        Some(Command::Run { file }) | Some(Command::Bundle { source_file: file, .. }) => file.clone()
+            .or_else(|| project.as_ref().map(|project| project.entry().display().to_string())),
+        _ => args.source_file.clone()
+    };
+
+    #[cfg(feature = "trace")]
+    if args.trace {
+        tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+            .with_writer(std::io::stderr)
+            .init();
+    }
+
+    let mut config = Config::load()?;
+    if let Some(prompt) = args.prompt.clone() {
+        config.prompt = prompt;
     }
+    if let Some(fuel) = args.fuel {
+        config.fuel = Some(fuel);
+    }
+    if let Some(history_size) = args.history_size {
+        config.history_size = history_size;
+    }
+    if args.no_color {
+        config.colors = ColorScheme::None;
+    }
+
+    let policy = ExecutionPolicy::default()
+        .fuel(config.fuel)
+        .overflow(args.overflow);
+    #[cfg(feature = "net")]
+    let policy = policy.allow_network(args.allow_net);
+
+    let format = args.error_format;
+
+    #[cfg(feature = "kernel")]
+    if let Some(Command::Kernel { connection_file, install, session: name }) = &args.command {
+        if *install {
+            kernel::install()?;
+        }
+
+        if let Some(connection_file) = connection_file {
+            let mut interpreter = session(policy, &config)?;
+            checks(&mut interpreter, &args);
+            kernel::run(interpreter, connection_file, name.as_deref())?;
+        } else if !*install {
+            anyhow::bail!("odo kernel needs --connection-file or --install");
+        }
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Build { source_file, output }) = &args.command {
+        let code = report(std::fs::read_to_string(source_file)
+            .with_context(|| format!("Could not read source file {}", source_file)), format, source_file)?;
+
+        // Compiled against the same prelude and modules it will run with
+        let mut interpreter = session(policy, &config)?;
+        checks(&mut interpreter, &args);
+        imports_from(&mut interpreter, source_file, None);
+
+        let output = match output {
+            Some(output) => output.into(),
+            None => Path::new(source_file).with_extension("odoc"),
+        };
+
+        report(interpreter.compile(code), format, source_file)?.save(&output)?;
+    } else if let Some(Command::Bundle { output, .. }) = &args.command {
+        let source_file = source_file.unwrap_or_default();
+        let code = report(std::fs::read_to_string(&source_file)
+            .with_context(|| format!("Could not read source file {}", source_file)), format, &source_file)?;
+
+        // Analyzing the program finds every module it imports
+        let mut interpreter = session(policy, &config)?;
+        checks(&mut interpreter, &args);
+        let loader = Recording::new(module_loader(&source_file, project.as_ref()));
+        interpreter.set_module_loader(loader.clone());
+        interpreter.set_main_module(main_module(&source_file));
+        report(interpreter.compile(code.clone()), format, &source_file)?;
+
+        let output = match output {
+            Some(output) => output.into(),
+            None => Path::new(&source_file).with_extension(bundle::EXTENSION),
+        };
+
+        loader.bundle(&main_module(&source_file), code)?.save(&output)?;
+    } else if let Some(Command::Inspect { source_file }) = &args.command {
+        let code = report(std::fs::read_to_string(source_file)
+            .with_context(|| format!("Could not read source file {}", source_file)), format, source_file)?;
+
+        let mut interpreter = session(policy, &config)?;
+        checks(&mut interpreter, &args);
+        imports_from(&mut interpreter, source_file, None);
+
+        let program = report(interpreter.compile(code), format, source_file)?;
+        print!("{}", inspect::inspect(&program));
+    } else if let Some(Command::Emit { source_file, target }) = &args.command {
+        let code = report(std::fs::read_to_string(source_file)
+            .with_context(|| format!("Could not read source file {}", source_file)), format, source_file)?;
+
+        let mut interpreter = session(policy, &config)?;
+        checks(&mut interpreter, &args);
+        imports_from(&mut interpreter, source_file, None);
+
+        let program = report(interpreter.compile(code), format, source_file)?;
+        print!("{}", report(emit::emit(&program, *target), format, source_file)?);
+    } else if let Some(Command::Serve { port, sessions, .. }) = &args.command {
+        let listener = match port {
+            Some(port) => serve::Listener::Tcp(std::net::TcpListener::bind(("127.0.0.1", *port))
+                .with_context(|| format!("Could not listen on port {}", port))?),
+            None => listen_socket(&args)?,
+        };
+
+        let sessions = sessions.as_ref().map(std::path::PathBuf::from).or_else(sessions::directory);
 
-    if let Some(input_path) = args.source_file {
-        // Execute the file
-        todo!("Implement file execution with scoping and modularity");
+        serve::run(listener, &|| {
+            let mut interpreter = session(policy.clone(), &config)?;
+            checks(&mut interpreter, &args);
+            Ok(interpreter)
+        }, sessions.as_deref())?;
+    } else if let Some(input_path) = source_file {
+        let mut interpreter = session(policy, &config)?;
+        checks(&mut interpreter, &args);
+        imports_from(&mut interpreter, &input_path, project.as_ref());
+
+        if let Some(trace) = &args.replay {
+            interpreter.replay_inputs(replay::load(Path::new(trace))?);
+        }
+
+        if args.record.is_some() {
+            interpreter.record_inputs();
+        }
+
+        let result = if input_path.ends_with(".odoc") {
+            interpreter.run_compiled(report(CompiledProgram::load(Path::new(&input_path)), format, &input_path)?)
+        } else if input_path.ends_with(".odob") {
+            // Its modules come from the bundle, not from the files around it
+            let bundle = report(Bundle::load(Path::new(&input_path)), format, &input_path)?;
+            let code = bundle.entry_source()?.to_string();
+            interpreter.set_main_module(&bundle.entry);
+            interpreter.set_module_loader(bundle);
+
+            interpreter.eval(code)
+        } else {
+            let code = report(std::fs::read_to_string(&input_path)
+                .with_context(|| format!("Could not read source file {}", input_path)), format, &input_path)?;
+
+            interpreter.eval(code)
+        };
+
+        let warnings = interpreter.semantic_analyzer.take_warnings();
+        for warning in &warnings {
+            match format {
+                ErrorFormat::Human => eprintln!("{}", config.colors.warning(&warning.to_string())),
+                ErrorFormat::Json => eprintln!("{}", Diagnostic::from_warning(warning, Some(&input_path)).to_json()),
+            }
+        }
+
+        // Failing runs are the ones worth replaying, so the trace is saved either way
+        if let Some(trace) = &args.record {
+            replay::save(Path::new(trace), &interpreter.stop_recording())?;
+        }
+
+        report(result, format, &input_path)?;
+
+        if !warnings.is_empty() && !args.no_fail_on_warnings {
+            return Ok(ExitCode::from(EXIT_COMPILE_ERROR));
+        }
     } else {
+        let preload = match args.command {
+            Some(Command::Repl { preload }) => preload,
+            _ => vec![]
+        };
+
+        let options = repl::Options {
+            preload,
+            banner: !args.quiet,
+            echo_results: !args.no_echo_results,
+            allowed: args.allow,
+            defined: args.define,
+            strict: args.strict,
+        };
+
         // Execute the repl
-        repl::repl()?;
+        repl::repl(policy, &config, &options)?;
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+// Errors are printed by returning them from main, unless tools asked for json
+fn report<T>(result: anyhow::Result<T>, format: ErrorFormat, file: &str) -> anyhow::Result<T> {
+    match (result, format) {
+        (Err(error), ErrorFormat::Json) => {
+            eprintln!("{}", Diagnostic::from_error(&error, Some(file)).to_json());
+            std::process::exit(exit_code(&error).into());
+        },
+        (result, _) => result
+    }
+}
+
+// `odo serve --socket PATH`
+#[cfg(unix)]
+fn listen_socket(args: &Cli) -> anyhow::Result<serve::Listener> {
+    match &args.command {
+        Some(Command::Serve { socket: Some(path), .. }) => Ok(serve::Listener::Unix(std::os::unix::net::UnixListener::bind(path)
+            .with_context(|| format!("Could not listen on {}", path))?)),
+        _ => anyhow::bail!("odo serve needs --port or --socket"),
     }
+}
+
+#[cfg(not(unix))]
+fn listen_socket(_args: &Cli) -> anyhow::Result<serve::Listener> {
+    anyhow::bail!("odo serve needs --port")
+}
 
+// What the analyzer checks in the user's code. The prelude and modules are loaded before, without them.
+fn checks(interpreter: &mut Interpreter, args: &Cli) {
+    interpreter.set_strict(args.strict);
+    for lint in &args.allow {
+        interpreter.semantic_analyzer.allow(*lint);
+    }
+    for feature in &args.define {
+        interpreter.semantic_analyzer.define(feature);
+    }
+}
 
-    Ok(())
+// The file being run imports modules next to it, like the modules it imports do, and then from the
+// project's source directories
+fn imports_from(interpreter: &mut Interpreter, source_file: &str, project: Option<&Manifest>) {
+    interpreter.set_module_loader(module_loader(source_file, project));
+    interpreter.set_main_module(main_module(source_file));
+}
+
+fn module_loader(source_file: &str, project: Option<&Manifest>) -> FileLoader {
+    let directory = Path::new(source_file).parent().map(Path::to_path_buf).unwrap_or_default();
+    let roots = std::iter::once(directory)
+        .chain(project.map(Manifest::source_roots).unwrap_or_default())
+        .collect();
+
+    FileLoader::new(roots)
+}
+
+// The same path the loader gives modules, so importing the file back is noticed
+fn main_module(source_file: &str) -> PathBuf {
+    let path = Path::new(source_file);
+    path.canonicalize().unwrap_or(path.to_path_buf())
+}
+
+// An interpreter with the settings from the config file, before anything is loaded into it
+fn configured<'a>(policy: ExecutionPolicy, config: &Config) -> Interpreter<'a> {
+    let mut interpreter = Interpreter::with_policy(policy);
+    interpreter.set_tab_width(config.tab_width);
+    interpreter.set_format_limits(Limits { depth: config.print_depth, width: config.print_width });
+
+    interpreter
+}
+
+// An interpreter with the prelude and the configured modules loaded
+fn session<'a>(policy: ExecutionPolicy, config: &Config) -> anyhow::Result<Interpreter<'a>> {
+    let mut interpreter = configured(policy, config);
+    prelude::load(&mut interpreter)?;
+    prelude::load_modules(&mut interpreter, config)?;
+
+    Ok(interpreter)
+}
+
+mod prelude {
+    use anyhow::Context;
+    use odo::exec::interpreter::Interpreter;
+    use std::path::{Path, PathBuf};
+
+    use crate::config::{self, Config};
+
+    // `ODO_PRELUDE` takes precedence over the file in the user's config directory
+    fn path() -> Option<PathBuf> {
+        if let Ok(path) = std::env::var("ODO_PRELUDE") {
+            return Some(PathBuf::from(path));
+        }
+
+        let path = config::directory()?.join("prelude.odo");
+
+        if path.exists() {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    pub fn load(interpreter: &mut Interpreter) -> anyhow::Result<()> {
+        match path() {
+            Some(path) => load_file(interpreter, &path, "prelude"),
+            None => Ok(())
+        }
+    }
+
+    // The modules listed in the config are loaded just like the prelude
+    pub fn load_modules(interpreter: &mut Interpreter, config: &Config) -> anyhow::Result<()> {
+        for path in config.module_paths() {
+            load_file(interpreter, &path, "module")?;
+        }
+
+        Ok(())
+    }
+
+    // Files given to `odo repl --preload`, evaluated just like the prelude
+    pub fn load_files(interpreter: &mut Interpreter, paths: &[String]) -> anyhow::Result<()> {
+        for path in paths {
+            load_file(interpreter, Path::new(path), "file")?;
+        }
+
+        Ok(())
+    }
+
+    fn load_file(interpreter: &mut Interpreter, path: &Path, kind: &str) -> anyhow::Result<()> {
+        let code = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read {} {}", kind, path.display()))?;
+
+        interpreter.set_prelude(code)
+            .with_context(|| format!("Error in {} {}", kind, path.display()))
+    }
 }
 
 mod repl {
-    use odo::{exec::interpreter::Interpreter, native::function::NativeFunctionBindable};
-    use std::io::Write;
+    use odo::{base::{lints::Lint, modules::FileLoader}, exec::{inspect, interpreter::{Interpreter, Variable}, observer::{Observer, StatementRun}, policy::ExecutionPolicy, value::format::{self, Limits}}, native::function::NativeFunctionBindable};
+    use std::{collections::VecDeque, path::PathBuf};
+
+    use crate::{config::{self, ColorScheme, Config}, line_editor};
 
     pub fn print_logo() {
         let logo = format!(
@@ -52,44 +572,403 @@ mod repl {
                    {}
           Luis Gonzalez (louis1001)
                  2019-2023
-    "#, 
+    "#,
             env!("CARGO_PKG_VERSION"));
 
         println!("{}", logo);
     }
 
-    pub fn repl() -> anyhow::Result<()> {
+    // Previous inputs, saved to a file between sessions
+    struct History {
+        path: Option<PathBuf>,
+        lines: VecDeque<String>,
+        size: usize,
+    }
+
+    impl History {
+        fn load(size: usize) -> History {
+            let path = config::directory().map(|dir| dir.join("history"));
+
+            let lines = path.as_ref()
+                .and_then(|path| std::fs::read_to_string(path).ok())
+                .map(|text| text.lines().map(str::to_string).collect())
+                .unwrap_or_default();
+
+            let mut history = History { path, lines, size };
+            history.truncate();
+            history
+        }
+
+        fn truncate(&mut self) {
+            while self.lines.len() > self.size {
+                self.lines.pop_front();
+            }
+        }
+
+        fn push(&mut self, line: &str) {
+            if !line.is_empty() {
+                self.lines.push_back(line.to_string());
+                self.truncate();
+            }
+        }
+
+        fn save(&self) -> anyhow::Result<()> {
+            let path = match &self.path {
+                Some(path) if self.size > 0 => path,
+                _ => return Ok(())
+            };
+
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+
+            let text: String = self.lines.iter().map(|line| format!("{}\n", line)).collect();
+            std::fs::write(path, text)?;
+
+            Ok(())
+        }
+    }
+
+    // Lines that start with `:` and the name of a command are for the repl, not code to evaluate.
+    // Anything else that starts with `:`, like `: x`, is still printed.
+    enum Command {
+        // Reads everything until Ctrl-D, then evaluates it as one unit
+        Paste,
+        // Takes back what the last line declared and assigned
+        Undo,
+        // The tree the code parses to, without running it
+        Ast(String),
+        // The tokens the code is made of
+        Tokens(String),
+        // The scopes names are looked up in, and what's in them
+        Scope,
+        // Prints each statement as it runs, or stops printing them
+        Trace(String),
+        // The variables there are, with their values
+        Vars,
+    }
+
+    impl Command {
+        fn parse(line: &str) -> Option<Command> {
+            let line = line.trim();
+            let (name, code) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let code = code.trim().to_string();
+
+            match (name, code.is_empty()) {
+                (":paste", true) => Some(Command::Paste),
+                (":undo", true) => Some(Command::Undo),
+                (":scope", true) => Some(Command::Scope),
+                (":vars", true) => Some(Command::Vars),
+                (":ast", _) => Some(Command::Ast(code)),
+                (":tokens", _) => Some(Command::Tokens(code)),
+                (":trace", _) => Some(Command::Trace(code)),
+                _ => None
+            }
+        }
+    }
+
+    // `:trace on`. Each statement as it finishes, nested under the ones it's in, with the value it
+    // left and the fuel left after it
+    struct Tracer(ColorScheme);
+
+    impl Observer for Tracer {
+        fn statement(&mut self, statement: &StatementRun) {
+            let mut line = format!("{}{}", "  ".repeat(statement.depth), statement.description);
+            if let Some(value) = &statement.value {
+                line.push_str(&format!(" => {}", value));
+            }
+            if let Some(fuel) = statement.fuel {
+                line.push_str(&format!("  [fuel {}]", fuel));
+            }
+
+            println!("{}", self.0.dim(&line));
+        }
+    }
+
+    // How much of each value `:vars` shows. Past these, values are cut short like `:` cuts them.
+    const PREVIEW_LIMITS: Limits = Limits { depth: 2, width: 5 };
+    const PREVIEW_LENGTH: usize = 40;
+
+    // `:vars`. One row per variable, with the columns lined up
+    fn variables_table(variables: &[Variable], colors: ColorScheme) -> String {
+        if variables.is_empty() {
+            return "No variables yet\n".to_string();
+        }
+
+        let rows: Vec<[String; 4]> = variables.iter()
+            .map(|variable| {
+                let mut preview = format::format(&variable.value, PREVIEW_LIMITS);
+                if preview.chars().count() > PREVIEW_LENGTH {
+                    preview = preview.chars().take(PREVIEW_LENGTH - 1).chain(std::iter::once('…')).collect();
+                }
+
+                [variable.name.clone(), variable.type_name.clone(), preview, variable.scope.clone()]
+            })
+            .collect();
+
+        let header = ["name", "type", "value", "scope"].map(str::to_string);
+        let mut widths = header.clone().map(|title| title.chars().count());
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        // Padded before it's colored, so the escape codes don't count as width
+        let line = |row: &[String; 4], paint: &dyn Fn(usize, &str) -> String| {
+            let cells: Vec<_> = row.iter().zip(widths).enumerate()
+                .map(|(column, (cell, width))| paint(column, &format!("{:<width$}", cell, width = width)))
+                .collect();
+            format!("{}\n", cells.join("  ").trim_end())
+        };
+
+        let mut table = line(&header, &|_, text| colors.header(text));
+        for row in &rows {
+            table.push_str(&line(row, &|column, text| match column {
+                3 => colors.dim(text),
+                _ => text.to_string()
+            }));
+        }
+
+        table
+    }
+
+    // Pasted programs can have empty lines, so only the end of input finishes a paste
+    fn read_paste(history: &mut History) -> anyhow::Result<String> {
+        let mut code = String::new();
+        loop {
+            let mut line = String::new();
+            if std::io::stdin().read_line(&mut line)? == 0 {
+                break;
+            }
+
+            history.push(line.trim());
+            code.push_str(&line);
+        }
+
+        Ok(code)
+    }
+
+    fn evaluate(interpreter: &mut Interpreter, code: String, config: &Config, options: &Options) {
+        // A line that fails leaves the session as it was before it, even when it's interrupted
+        let result = interrupt::while_running(|| interpreter.eval_atomic(code));
+
+        for warning in interpreter.semantic_analyzer.take_warnings() {
+            println!("{}", config.colors.warning(&warning.to_string()));
+        }
+
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                println!("{}", config.colors.error(&e.to_string()));
+                return;
+            }
+        };
+
+        if let Some(value) = result.value.filter(|_| options.echo_results) {
+            println!("{}", interpreter.format(&value));
+        }
+    }
+
+    // Ctrl-C stops the line that's running instead of the repl. At the prompt, it still quits.
+    #[cfg(unix)]
+    mod interrupt {
+        use odo::exec::cancel::CancellationToken;
+        use std::sync::{atomic::{AtomicBool, Ordering}, OnceLock};
+
+        static TOKEN: OnceLock<CancellationToken> = OnceLock::new();
+        static RUNNING: AtomicBool = AtomicBool::new(false);
+
+        // Only atomics, which is all a signal handler can safely touch
+        extern "C" fn handle(_signal: libc::c_int) {
+            match TOKEN.get() {
+                Some(token) if RUNNING.load(Ordering::SeqCst) => token.cancel(),
+                // 128 + SIGINT, like the default handler
+                _ => unsafe { libc::_exit(130) }
+            }
+        }
+
+        pub fn install(token: CancellationToken) {
+            if TOKEN.set(token).is_ok() {
+                let handler: extern "C" fn(libc::c_int) = handle;
+                unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) };
+            }
+        }
+
+        pub fn while_running<T>(f: impl FnOnce() -> T) -> T {
+            RUNNING.store(true, Ordering::SeqCst);
+            let result = f();
+            RUNNING.store(false, Ordering::SeqCst);
+            result
+        }
+    }
+
+    #[cfg(not(unix))]
+    mod interrupt {
+        use odo::exec::cancel::CancellationToken;
+
+        pub fn install(_token: CancellationToken) {}
+
+        pub fn while_running<T>(f: impl FnOnce() -> T) -> T {
+            f()
+        }
+    }
+
+    // How a repl session starts and what it prints. The prompt is in the config.
+    pub struct Options {
+        // Files evaluated into the session before the first prompt
+        pub preload: Vec<String>,
+        // The logo, before anything else
+        pub banner: bool,
+        // The value of each expression that has one
+        pub echo_results: bool,
+        // Lints that don't give warnings
+        pub allowed: Vec<Lint>,
+        // Features for @cfg
+        pub defined: Vec<String>,
+        // Whether the analyzer uses its stricter rules
+        pub strict: bool,
+    }
+
+    pub fn repl(policy: ExecutionPolicy, config: &Config, options: &Options) -> anyhow::Result<()> {
+        if options.banner {
+            print_logo();
+        }
+
         // It keeps context through the repl, so it's just one for all loops.
-        let mut interpreter = Interpreter::new();
+        let mut interpreter = super::configured(policy, config);
 
         interpreter.bind_void_function("hello", |_| {
             println!("Hello, world!");
         })?;
 
+        if let Err(e) = super::prelude::load(&mut interpreter) {
+            println!("{}", config.colors.error(&format!("{:#}", e)));
+        }
+
+        if let Err(e) = super::prelude::load_modules(&mut interpreter, config) {
+            println!("{}", config.colors.error(&format!("{:#}", e)));
+        }
+
+        interrupt::install(interpreter.cancellation_token());
+
+        // Like `checks`, after the prelude and modules
+        interpreter.set_strict(options.strict);
+        for lint in &options.allowed {
+            interpreter.semantic_analyzer.allow(*lint);
+        }
+        for feature in &options.defined {
+            interpreter.semantic_analyzer.define(feature);
+        }
+        interpreter.set_module_loader(FileLoader::new(vec![PathBuf::from(".")]));
+
+        if let Err(e) = super::prelude::load_files(&mut interpreter, &options.preload) {
+            println!("{}", config.colors.error(&format!("{:#}", e)));
+        }
+
+        let mut history = History::load(config.history_size);
+
         loop {
-            print!("> ");
-            let mut input = String::new();
-    
-            std::io::stdout().flush()?;
-            std::io::stdin().read_line(&mut input)?;
+            // Up and down go through the history
+            let Some(input) = line_editor::read_line(&config.colors.prompt(&config.prompt), &history.lines)? else {
+                // End of input
+                break;
+            };
 
-            if input == "exit" {
+            history.push(input.trim());
+
+            if input.trim() == "exit" {
                 break;
             }
-    
-            let result = match interpreter.eval(input) {
-                Ok(result) => result,
-                Err(e) => {
-                    println!("{}", e);
+
+            let code = match Command::parse(&input) {
+                Some(Command::Paste) => {
+                    println!("Paste mode: Ctrl-D evaluates everything pasted at once");
+                    read_paste(&mut history)?
+                },
+                Some(Command::Undo) => {
+                    if !interpreter.undo() {
+                        println!("Nothing to undo");
+                    }
                     continue;
-                }
+                },
+                Some(Command::Ast(code)) => {
+                    match interpreter.parse(&code) {
+                        Ok(statements) => print!("{}", inspect::syntax_tree(&statements)),
+                        Err(e) => println!("{}", config.colors.error(&e.to_string())),
+                    }
+                    continue;
+                },
+                Some(Command::Scope) => {
+                    match interpreter.scope_chain() {
+                        Ok(tree) => print!("{}", tree),
+                        Err(e) => println!("{}", config.colors.error(&e.to_string())),
+                    }
+                    continue;
+                },
+                Some(Command::Vars) => {
+                    match interpreter.variables() {
+                        Ok(variables) => print!("{}", variables_table(&variables, config.colors)),
+                        Err(e) => println!("{}", config.colors.error(&e.to_string())),
+                    }
+                    continue;
+                },
+                Some(Command::Trace(setting)) => {
+                    match setting.as_str() {
+                        "on" => interpreter.set_observer(Tracer(config.colors)),
+                        "off" => interpreter.remove_observer(),
+                        _ => println!("Use :trace on or :trace off"),
+                    }
+                    continue;
+                },
+                Some(Command::Tokens(code)) => {
+                    match interpreter.tokenize(&code) {
+                        Ok(tokens) => print!("{}", inspect::tokens(&tokens)),
+                        Err(e) => println!("{}", config.colors.error(&e.to_string())),
+                    }
+                    continue;
+                },
+                None => input
             };
 
-            if let Some(value) = result.value {
-                println!("{:#?}", value.content);
-            }
+            evaluate(&mut interpreter, code, config, options);
         }
 
-        Ok(())
+        history.save()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use odo::exec::policy::ExecutionPolicy;
+
+    use clap::Parser;
+
+    use crate::config::Config;
+
+    #[test]
+    fn test_flags_after_subcommand() {
+        let args = super::Cli::try_parse_from(["odo", "run", "f.odo", "--no-fail-on-warnings", "--record", "trace.json", "--fuel", "5", "--overflow", "wrapping"]).unwrap();
+        assert!(args.no_fail_on_warnings);
+        assert_eq!(args.record.as_deref(), Some("trace.json"));
+        assert_eq!(args.fuel, Some(5));
+        assert_eq!(args.overflow, super::IntegerOverflow::Wrapping);
+
+        let args = super::Cli::try_parse_from(["odo", "repl", "--replay", "trace.json", "--history-size", "3", "--no-color"]).unwrap();
+        assert_eq!(args.replay.as_deref(), Some("trace.json"));
+        assert_eq!(args.history_size, Some(3));
+        assert!(args.no_color);
+
+        assert!(super::Cli::try_parse_from(["odo", "run", "f.odo", "--record", "a", "--replay", "b"]).is_err());
+    }
+
+    #[test]
+    fn test_configured_print_limits() {
+        let config = Config { print_depth: 1, print_width: 2, ..Config::default() };
+        let mut interpreter = super::configured(ExecutionPolicy::default(), &config);
+
+        let (result, stdout, _) = interpreter.eval_captured(": [1, 2, 3]\n: [[1, 2]]".to_string());
+        result.unwrap();
+        assert_eq!(stdout, "[1, 2, … 1 more]\n[[… 2 items]]\n");
+    }
+}
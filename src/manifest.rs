@@ -0,0 +1,102 @@
+use anyhow::Context;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+// `odo.toml`, at the root of a project:
+//
+//     [package]
+//     entry = "src/main.odo"
+//     sources = ["src", "lib"]
+//
+// `odo run` without a file runs the entry, and modules are looked for in the source directories.
+// Paths are relative to the manifest.
+
+pub const FILE_NAME: &str = "odo.toml";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub package: Package,
+    // The directory the manifest is in
+    #[serde(skip)]
+    pub root: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Package {
+    #[serde(default = "default_entry")]
+    pub entry: PathBuf,
+    // Where imports are resolved, after the directory of the module importing them.
+    // The manifest's directory when there are none.
+    #[serde(default)]
+    pub sources: Vec<PathBuf>,
+}
+
+fn default_entry() -> PathBuf {
+    PathBuf::from("main.odo")
+}
+
+impl Manifest {
+    // The manifest in `directory` or the closest of its parents
+    pub fn find(directory: &Path) -> anyhow::Result<Manifest> {
+        let path = directory.ancestors()
+            .map(|ancestor| ancestor.join(FILE_NAME))
+            .find(|path| path.is_file())
+            .ok_or_else(|| anyhow::anyhow!("No {} in {} or its parents. Give odo run a file to run, or create one", FILE_NAME, directory.display()))?;
+
+        Manifest::from_file(&path)
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<Manifest> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read manifest {}", path.display()))?;
+
+        let mut manifest: Manifest = toml::from_str(&text)
+            .with_context(|| format!("Invalid manifest {}", path.display()))?;
+        manifest.root = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        Ok(manifest)
+    }
+
+    pub fn entry(&self) -> PathBuf {
+        self.root.join(&self.package.entry)
+    }
+
+    pub fn source_roots(&self) -> Vec<PathBuf> {
+        if self.package.sources.is_empty() {
+            return vec![self.root.clone()];
+        }
+
+        self.package.sources.iter()
+            .map(|source| self.root.join(source))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Manifest;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_manifest() {
+        let mut manifest: Manifest = toml::from_str(r#"
+            [package]
+            entry = "src/main.odo"
+            sources = ["src", "lib"]
+        "#).unwrap();
+        manifest.root = PathBuf::from("project");
+
+        assert_eq!(manifest.entry(), PathBuf::from("project/src/main.odo"));
+        assert_eq!(manifest.source_roots(), [PathBuf::from("project/src"), PathBuf::from("project/lib")]);
+
+        // Everything has a default
+        let mut manifest: Manifest = toml::from_str("[package]").unwrap();
+        manifest.root = PathBuf::from("project");
+        assert_eq!(manifest.entry(), PathBuf::from("project/main.odo"));
+        assert_eq!(manifest.source_roots(), [PathBuf::from("project")]);
+
+        assert!(toml::from_str::<Manifest>("[package]\nsource = [\"src\"]").is_err());
+    }
+}
@@ -1,57 +1,172 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
 
-use crate::base::semantic_analyzer::{FunctionTypeSymbol, Symbol, SymbolVariant, NativeFunctionSymbol};
+use crate::base::semantic_analyzer::{Symbol, SymbolVariant, NativeFunctionSymbol};
 use crate::exec::interpreter::Interpreter;
-use crate::exec::value::{Value, ValueVariant, FunctionValue};
+use crate::exec::value::{Value, ValueVariant, PrimitiveValue, FunctionValue};
 
-pub type NativeFn<'a> = dyn Fn(Vec<Value<'a>>) -> Option<Value<'a>> + Sync + 'a;
+// Natives return nothing (`None`) or a value, and can fail with a runtime error
+pub type NativeFn<'a> = dyn Fn(Vec<Value<'a>>) -> anyhow::Result<Option<Value<'a>>> + Sync + 'a;
+
+// The text a native was given at `index`. The analyzer already checked the type, so anything
+// else is a bug in how the native was bound.
+pub(crate) fn text_argument<'v>(args: &'v [Value], index: usize) -> anyhow::Result<&'v str> {
+    match args.get(index).map(|value| &value.content) {
+        Some(ValueVariant::Primitive(PrimitiveValue::Text(text))) => Ok(text),
+        _ => Err(anyhow::anyhow!("Semantic analysis error. Argument {} should be a string", index))
+    }
+}
 
 pub trait NativeFunctionBindable<'obj> {
     // Has to be able to be a closure, and the closure has to be able to be called.
-    fn bind_void_function<'a, F>(&mut self, name: &str, f: F) -> anyhow::Result<()> where F: Fn(Vec<Value>) -> () + Sync + 'obj;
+    fn bind_void_function<'a, F>(&mut self, name: &str, f: F) -> anyhow::Result<()> where F: Fn(Vec<Value>) + Sync + 'obj;
+
+    // Types are referenced by name (`int`, `string`, `any`, `list<int>`, `<int:string>`, ...). A `None` return type means the function returns nothing.
+    // Single capital letters are type parameters: `first(list<T>) -> T` returns an int when given a `list<int>`.
+    fn bind_function<F>(&mut self, name: &str, argument_types: &[&str], return_type: Option<&str>, f: F) -> anyhow::Result<()>
+        where F: Fn(Vec<Value<'obj>>) -> anyhow::Result<Option<Value<'obj>>> + Sync + 'obj;
+
+    // For natives that keep state between calls. The closure is only ever called by one thread at a time.
+    fn bind_mut_function<F>(&mut self, name: &str, argument_types: &[&str], return_type: Option<&str>, f: F) -> anyhow::Result<()>
+        where F: FnMut(Vec<Value<'obj>>) -> anyhow::Result<Option<Value<'obj>>> + Send + 'obj;
+
+    // Groups natives under a namespace, so they're called as `name.function(...)` from odo.
+    fn register_module<F>(&mut self, name: &str, build: F) -> anyhow::Result<()>
+        where F: FnOnce(&mut NativeModule<'_, 'obj>) -> anyhow::Result<()>;
 }
 
-impl<'inter> NativeFunctionBindable<'inter> for Interpreter<'inter> {
-    fn bind_void_function<F>(&mut self, name: &str, f: F) -> anyhow::Result<()> where F: Fn(Vec<Value>) -> () + Sync + 'inter, {
-        // Construct the type of the function.
-        let function_type_name = FunctionTypeSymbol::construct_type_name(
-            None,
-            vec![],
-            &self.semantic_analyzer
-        )?;
-
-        let function_type = Symbol::new(
-            function_type_name,
-            SymbolVariant::FunctionType(FunctionTypeSymbol::new(None, vec![]))
-        );
-
-        let function_symbol = Symbol::new(
-            name.to_string(),
-            SymbolVariant::NativeFunction(NativeFunctionSymbol::new(function_type.symbol_id))
-        );
-
-        // Insert the type into the global scope
-        {
-            let global_scope = self.semantic_analyzer.global_scope_mut().expect("There's always a global scope");
-            global_scope.insert(function_type.clone());
-        }
+pub struct NativeModule<'m, 'inter> {
+    interpreter: &'m mut Interpreter<'inter>,
+    table_id: Uuid
+}
 
-        // Insert the symbol into the current scope.
-        {
-            let current_scope = self.semantic_analyzer.current_scope_mut().expect("There's always a scope");
-            current_scope.insert(function_symbol.clone());
-        }
+impl<'m, 'inter> NativeModule<'m, 'inter> {
+    pub fn function<F>(&mut self, name: &str, argument_types: &[&str], return_type: Option<&str>, f: F) -> anyhow::Result<()>
+        where F: Fn(Vec<Value<'inter>>) -> anyhow::Result<Option<Value<'inter>>> + Sync + 'inter
+    {
+        self.interpreter.bind_function_in_scope(self.table_id, name, argument_types, return_type, f)
+    }
+
+    pub fn mut_function<F>(&mut self, name: &str, argument_types: &[&str], return_type: Option<&str>, f: F) -> anyhow::Result<()>
+        where F: FnMut(Vec<Value<'inter>>) -> anyhow::Result<Option<Value<'inter>>> + Send + 'inter
+    {
+        self.function(name, argument_types, return_type, synchronized(f))
+    }
 
-        let native_fn = move |args: Vec<Value>| {
+    pub fn void_function<F>(&mut self, name: &str, f: F) -> anyhow::Result<()> where F: Fn(Vec<Value>) + Sync + 'inter {
+        self.function(name, &[], None, move |args| {
             f(args);
-            None
+            Ok(None)
+        })
+    }
+
+    // Modules can be nested: `outer.inner.function(...)`
+    pub fn module<F>(&mut self, name: &str, build: F) -> anyhow::Result<()>
+        where F: FnOnce(&mut NativeModule<'_, 'inter>) -> anyhow::Result<()>
+    {
+        let table_id = self.interpreter.semantic_analyzer.declare_namespace(self.table_id, name)?;
+
+        build(&mut NativeModule { interpreter: self.interpreter, table_id })
+    }
+}
+
+impl<'inter> NativeFunctionBindable<'inter> for Interpreter<'inter> {
+    fn bind_void_function<F>(&mut self, name: &str, f: F) -> anyhow::Result<()> where F: Fn(Vec<Value>) + Sync + 'inter, {
+        self.bind_function(name, &[], None, move |args| {
+            f(args);
+            Ok(None)
+        })
+    }
+
+    fn bind_function<F>(&mut self, name: &str, argument_types: &[&str], return_type: Option<&str>, f: F) -> anyhow::Result<()>
+        where F: Fn(Vec<Value<'inter>>) -> anyhow::Result<Option<Value<'inter>>> + Sync + 'inter
+    {
+        let scope_id = self.semantic_analyzer.current_scope_id;
+
+        self.bind_function_in_scope(scope_id, name, argument_types, return_type, f)
+    }
+
+    fn bind_mut_function<F>(&mut self, name: &str, argument_types: &[&str], return_type: Option<&str>, f: F) -> anyhow::Result<()>
+        where F: FnMut(Vec<Value<'inter>>) -> anyhow::Result<Option<Value<'inter>>> + Send + 'inter
+    {
+        self.bind_function(name, argument_types, return_type, synchronized(f))
+    }
+
+    fn register_module<F>(&mut self, name: &str, build: F) -> anyhow::Result<()>
+        where F: FnOnce(&mut NativeModule<'_, 'inter>) -> anyhow::Result<()>
+    {
+        let scope_id = self.semantic_analyzer.current_scope_id;
+        let table_id = self.semantic_analyzer.declare_namespace(scope_id, name)?;
+
+        build(&mut NativeModule { interpreter: self, table_id })
+    }
+}
+
+// Turns a stateful closure into one that can be shared like any other native
+fn synchronized<'a, F>(f: F) -> impl Fn(Vec<Value<'a>>) -> anyhow::Result<Option<Value<'a>>> + Sync + 'a
+    where F: FnMut(Vec<Value<'a>>) -> anyhow::Result<Option<Value<'a>>> + Send + 'a
+{
+    let f = Mutex::new(f);
+
+    move |args| {
+        // A panicking native shouldn't make the function unusable for the rest of the session
+        let mut f = f.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        f(args)
+    }
+}
+
+impl<'inter> Interpreter<'inter> {
+    fn bind_function_in_scope<F>(&mut self, scope_id: Uuid, name: &str, argument_types: &[&str], return_type: Option<&str>, f: F) -> anyhow::Result<()>
+        where F: Fn(Vec<Value<'inter>>) -> anyhow::Result<Option<Value<'inter>>> + Sync + 'inter
+    {
+        let argument_ids = argument_types.iter()
+            .map(|type_name| self.semantic_analyzer.native_type_id(type_name))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let return_id = match return_type {
+            Some(type_name) => Some(self.semantic_analyzer.native_type_id(type_name)?),
+            None => None
         };
 
-        let value = Value::new(ValueVariant::Function(FunctionValue::Native(Arc::new(native_fn))));
+        let function_type_id = self.semantic_analyzer.function_type_id(return_id, argument_ids)?;
+
+        let variant = SymbolVariant::NativeFunction(NativeFunctionSymbol::new(function_type_id));
+        let mut function_symbol = match self.semantic_analyzer.qualified_name(scope_id, name)? {
+            Some(qualified) => Symbol::stable(name.to_string(), variant, &format!("native {}", qualified)),
+            None => Symbol::new(name.to_string(), variant)
+        };
+
+        // Binding a name again replaces the native. Code analyzed before still calls it by its id.
+        let previous = self.semantic_analyzer.take_native(scope_id, name)?;
+        if let Some(previous) = &previous {
+            function_symbol.symbol_id = previous.symbol_id;
+        }
+
+        // Insert the symbol into the requested scope.
+        self.semantic_analyzer.scope_mut(scope_id)?
+            .insert(function_symbol.clone());
+
+        let value = Value::new(ValueVariant::Function(FunctionValue::Native(Arc::new(f))));
         self.value_table.insert(value.clone());
 
         self.bind_symbol_to_value(function_symbol.symbol_id, value.uuid);
 
+        if let Some(previous) = previous {
+            self.semantic_analyzer.forget_function_type(previous.value_type_id()?)?;
+        }
+
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Removes the native bound as `name`, with `bind_function` or in a module (`math.sqrt`),
+    /// so hosts can take back or swap what they bound. Code that calls it fails from then on.
+    /// Its value is freed by the next `collect_garbage`.
+    pub fn unbind_function(&mut self, name: &str) -> anyhow::Result<()> {
+        let scope_id = self.semantic_analyzer.current_scope_id;
+        let symbol = self.semantic_analyzer.take_native(scope_id, name)?
+            .ok_or_else(|| anyhow::anyhow!("There's no native called {}", name))?;
+
+        self.unbind_symbol(symbol.symbol_id);
+        self.semantic_analyzer.forget_function_type(symbol.value_type_id()?)
+    }
+}
@@ -1 +1,6 @@
-pub mod function;
\ No newline at end of file
+pub mod function;
+pub mod stdlib;
+#[cfg(feature = "regex")]
+pub mod patterns;
+#[cfg(feature = "net")]
+pub mod net;
@@ -0,0 +1,76 @@
+use crate::exec::interpreter::Interpreter;
+use crate::exec::value::{Value, ValueVariant, PrimitiveValue};
+use crate::native::function::{NativeFunctionBindable, text_argument};
+
+fn response_text(response: Result<ureq::Response, ureq::Error>, url: &str) -> anyhow::Result<String> {
+    let body = response
+        .map_err(|e| anyhow::anyhow!("Request to {} failed: {}", url, e))?
+        .into_string()?;
+
+    Ok(body)
+}
+
+fn text<'a>(body: String) -> anyhow::Result<Option<Value<'a>>> {
+    Ok(Some(Value::new(ValueVariant::Primitive(PrimitiveValue::Text(body.into())))))
+}
+
+// httpGet(url) -> string
+// httpPost(url, body) -> string
+// They're always bound, so scripts analyze the same way under any policy, and fail when called if the network isn't allowed.
+// Responses go through the interpreter's input log, so replaying a run doesn't send its requests again.
+pub fn register(interpreter: &mut Interpreter) -> anyhow::Result<()> {
+    let allowed = interpreter.policy.allow_network;
+    let check_allowed = move || {
+        if allowed {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Network access is disabled by the execution policy"))
+        }
+    };
+
+    let inputs = interpreter.inputs.clone();
+    interpreter.bind_function("httpGet", &["string"], Some("string"), move |args| {
+        check_allowed()?;
+        let url = text_argument(&args, 0)?;
+
+        text(inputs.next("httpGet", || response_text(ureq::get(url).call(), url))?)
+    })?;
+
+    let inputs = interpreter.inputs.clone();
+    interpreter.bind_function("httpPost", &["string", "string"], Some("string"), move |args| {
+        check_allowed()?;
+        let url = text_argument(&args, 0)?;
+        let body = text_argument(&args, 1)?;
+
+        text(inputs.next("httpPost", || response_text(ureq::post(url).send_string(body), url))?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::exec::interpreter::Interpreter;
+
+    #[test]
+    fn test_network_denied_by_default() {
+        let mut interpreter = Interpreter::new();
+
+        match interpreter.eval(r#"httpGet("http://localhost")"#.to_string()) {
+            Err(e) => assert!(e.to_string().contains("disabled")),
+            Ok(_) => panic!("The default policy shouldn't allow network access")
+        }
+    }
+
+    #[test]
+    fn test_replayed_responses() {
+        use crate::exec::{policy::ExecutionPolicy, replay::RecordedInput};
+
+        // Nothing listens there, so only the replayed response can answer
+        let mut interpreter = Interpreter::with_policy(ExecutionPolicy::default().allow_network(true));
+        let response = RecordedInput { source: "httpPost".to_string(), value: serde_json::json!("sent") };
+        interpreter.replay_inputs(vec![response]);
+
+        let result = interpreter.eval(r#"httpPost("http://127.0.0.1:1", "body")"#.to_string()).unwrap();
+        assert_eq!(result.as_text().unwrap(), "sent");
+        assert!(interpreter.eval(r#"httpGet("http://127.0.0.1:1")"#.to_string()).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use regex::Regex;
+
+use crate::exec::interpreter::Interpreter;
+use crate::exec::value::{Value, ValueVariant, PrimitiveValue};
+use crate::native::function::{NativeFunctionBindable, text_argument};
+
+// Scripts tend to use the same few patterns in a loop, so compiling them once is worth it
+const CACHE_LIMIT: usize = 64;
+
+#[derive(Default, Clone)]
+pub struct PatternCache {
+    patterns: Arc<Mutex<HashMap<String, Regex>>>
+}
+
+impl PatternCache {
+    pub fn get(&self, pattern: &str) -> anyhow::Result<Regex> {
+        let mut patterns = self.patterns.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if let Some(regex) = patterns.get(pattern) {
+            return Ok(regex.clone());
+        }
+
+        let regex = Regex::new(pattern)
+            .map_err(|e| anyhow::anyhow!("Invalid pattern {:?}: {}", pattern, e))?;
+
+        if patterns.len() >= CACHE_LIMIT {
+            patterns.clear();
+        }
+        patterns.insert(pattern.to_string(), regex.clone());
+
+        Ok(regex)
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn text<'a>(text: String) -> Value<'a> {
+    Value::new(ValueVariant::Primitive(PrimitiveValue::Text(text.into())))
+}
+
+// matches(text, pattern) -> truth
+// findAll(text, pattern) -> list
+// replaceRegex(text, pattern, replacement) -> string
+pub fn register(interpreter: &mut Interpreter) -> anyhow::Result<()> {
+    let cache = interpreter.pattern_cache.clone();
+    interpreter.bind_function("matches", &["string", "string"], Some("truth"), move |args| {
+        let regex = cache.get(text_argument(&args, 1)?)?;
+        let found = regex.is_match(text_argument(&args, 0)?);
+
+        Ok(Some(Value::new(ValueVariant::Primitive(PrimitiveValue::Bool(found)))))
+    })?;
+
+    let cache = interpreter.pattern_cache.clone();
+    interpreter.bind_function("findAll", &["string", "string"], Some("list"), move |args| {
+        let regex = cache.get(text_argument(&args, 1)?)?;
+        let found = regex.find_iter(text_argument(&args, 0)?)
+            .map(|m| text(m.as_str().to_string()))
+            .collect();
+
+        Ok(Some(Value::list(found)))
+    })?;
+
+    let cache = interpreter.pattern_cache.clone();
+    interpreter.bind_function("replaceRegex", &["string", "string", "string"], Some("string"), move |args| {
+        let regex = cache.get(text_argument(&args, 1)?)?;
+        // `$1`, `$name` in the replacement refer to capture groups
+        let replaced = regex.replace_all(text_argument(&args, 0)?, text_argument(&args, 2)?)
+            .into_owned();
+
+        Ok(Some(text(replaced)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::exec::interpreter::Interpreter;
+
+    #[test]
+    fn test_pattern_natives() {
+        let mut interpreter = Interpreter::new();
+
+        let result = interpreter.eval(r#"matches("error: disk full", "^error")"#.to_string()).unwrap();
+        assert!(result.as_bool().unwrap());
+
+        let result = interpreter.eval(r#"findAll("a1 b22 c333", "[0-9]+")"#.to_string()).unwrap();
+        let found: Vec<_> = result.as_list().unwrap().iter()
+            .map(|value| value.to_json().unwrap())
+            .collect();
+        assert_eq!(found, vec!["1", "22", "333"]);
+
+        let result = interpreter.eval(r#"replaceRegex("2023-12-01", "([0-9]+)-([0-9]+)-([0-9]+)", "$3/$2/$1")"#.to_string()).unwrap();
+        assert_eq!(result.as_text().unwrap(), "01/12/2023");
+
+        // Every distinct pattern is compiled once
+        assert_eq!(interpreter.pattern_cache.len(), 3);
+        interpreter.eval(r#"matches("again", "^error")"#.to_string()).unwrap();
+        assert_eq!(interpreter.pattern_cache.len(), 3);
+
+        assert!(interpreter.eval(r#"matches("text", "(")"#.to_string()).is_err());
+    }
+}
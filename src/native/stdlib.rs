@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::exec::interpreter::{EventHandlers, Interpreter};
+use crate::exec::value::{numbers, text::Text, Value, ValueVariant, PrimitiveValue, FunctionValue};
+use crate::native::function::{NativeFunctionBindable, text_argument};
+
+/// Parts of the standard library, for hosts that only want some of it, like sandboxes
+/// that start from nothing (`Interpreter::with_stdlib`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Std {
+    /// typeOf
+    Types,
+    /// on, for the events the host emits
+    Events,
+    /// random, time, readLine and readInt
+    Input,
+    /// print and printError
+    Output,
+    /// append, first and take
+    Lists,
+    /// parseInt, parseDec, toFixed, toHex and withSeparators
+    Numbers,
+    /// matches, findAll and replaceRegex
+    #[cfg(feature = "regex")]
+    Patterns,
+    /// httpGet and httpPost
+    #[cfg(feature = "net")]
+    Net,
+}
+
+impl Std {
+    /// What interpreters start with unless the host chooses.
+    pub const ALL: &'static [Std] = &[
+        Std::Types,
+        Std::Events,
+        Std::Input,
+        Std::Output,
+        Std::Lists,
+        Std::Numbers,
+        #[cfg(feature = "regex")]
+        Std::Patterns,
+        #[cfg(feature = "net")]
+        Std::Net,
+    ];
+}
+
+// Natives every interpreter starts with.
+pub fn register(interpreter: &mut Interpreter) -> anyhow::Result<()> {
+    register_modules(interpreter, Std::ALL)
+}
+
+pub fn register_modules(interpreter: &mut Interpreter, modules: &[Std]) -> anyhow::Result<()> {
+    for module in modules {
+        match module {
+            Std::Types => register_type_of(interpreter)?,
+            Std::Events => register_on(interpreter)?,
+            Std::Input => register_inputs(interpreter)?,
+            Std::Output => register_output(interpreter)?,
+            Std::Lists => {
+                register_lists(interpreter)?;
+                register_sequences(interpreter)?;
+            },
+            Std::Numbers => register_numbers(interpreter)?,
+            #[cfg(feature = "regex")]
+            Std::Patterns => crate::native::patterns::register(interpreter)?,
+            #[cfg(feature = "net")]
+            Std::Net => crate::native::net::register(interpreter)?,
+        }
+    }
+
+    Ok(())
+}
+
+// typeOf(value) -> string
+fn register_type_of(interpreter: &mut Interpreter) -> anyhow::Result<()> {
+    // Natives can't reach the semantic analyzer, so the names are resolved when binding.
+    let type_names: HashMap<_, _> = [
+        Value::new(ValueVariant::Nothing),
+        Value::new(ValueVariant::Primitive(PrimitiveValue::Int(0))),
+        Value::new(ValueVariant::Primitive(PrimitiveValue::Dec(0.0))),
+        Value::new(ValueVariant::Primitive(PrimitiveValue::Text(Text::default()))),
+        Value::new(ValueVariant::Primitive(PrimitiveValue::Bool(false))),
+        Value::opaque(()),
+        Value::list(vec![]),
+        Value::map::<String>([]),
+    ].iter()
+        .map(|value| (value.type_id(), value.type_name(&interpreter.semantic_analyzer)))
+        .collect();
+
+    interpreter.bind_function("typeOf", &["any"], Some("string"), move |args| {
+        let value = args.first().ok_or(anyhow::anyhow!("Semantic analysis error. Should have one argument"))?;
+
+        let name = match value.content {
+            ValueVariant::Function(_) => "function".to_string(),
+            ValueVariant::Record(ref record) => record.type_name.clone(),
+            ValueVariant::Seq(_) => "seq".to_string(),
+            _ => type_names[&value.type_id()].clone()
+        };
+
+        Ok(Some(Value::new(ValueVariant::Primitive(PrimitiveValue::Text(name.into())))))
+    })
+}
+
+// on(event: string, handler: function). The host triggers the handlers with `Interpreter::emit`.
+fn register_on(interpreter: &mut Interpreter) -> anyhow::Result<()> {
+    let handlers = interpreter.event_handlers.clone();
+
+    interpreter.bind_function("on", &["string", "any"], None, on(handlers))
+}
+
+// What `on` does, registering into `handlers`. Forks bind their own.
+pub(crate) fn on<'a>(handlers: EventHandlers) -> impl Fn(Vec<Value<'a>>) -> anyhow::Result<Option<Value<'a>>> + Sync + 'a {
+    move |args| {
+        let event = match &args[0].content {
+            ValueVariant::Primitive(PrimitiveValue::Text(event)) => event.to_string(),
+            _ => return Err(anyhow::anyhow!("Semantic analysis error. Event should be a string"))
+        };
+
+        let handler = match &args[1].content {
+            ValueVariant::Function(FunctionValue::User(handler)) => handler.clone(),
+            _ => return Err(anyhow::anyhow!("Event handlers must be functions declared in odo"))
+        };
+
+        handlers.lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .entry(event)
+            .or_default()
+            .push(handler);
+
+        Ok(None)
+    }
+}
+
+// random() -> dec, between 0 and 1
+// time() -> dec, seconds since the unix epoch
+// readLine() -> string, a line of input without the line break. Empty at the end of input.
+// readInt() -> int, a line of input holding a whole number. Fails on anything else.
+// Input is stdin, unless the host set a reader with `Interpreter::set_input`.
+// They all go through the interpreter's input log, so a recorded run can be replayed exactly.
+fn register_inputs(interpreter: &mut Interpreter) -> anyhow::Result<()> {
+    let inputs = interpreter.inputs.clone();
+    // xorshift64*, seeded from the clock. Good enough for scripts, and doesn't need a dependency.
+    let mut state = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos() as u64 | 1;
+    interpreter.bind_mut_function("random", &[], Some("dec"), move |_| {
+        let value = inputs.next("random", || {
+            state ^= state >> 12;
+            state ^= state << 25;
+            state ^= state >> 27;
+            let bits = state.wrapping_mul(0x2545F4914F6CDD1D) >> 11;
+
+            Ok(bits as f64 / (1u64 << 53) as f64)
+        })?;
+
+        Ok(Some(Value::new(ValueVariant::Primitive(PrimitiveValue::Dec(value)))))
+    })?;
+
+    let inputs = interpreter.inputs.clone();
+    interpreter.bind_function("time", &[], Some("dec"), move |_| {
+        let value = inputs.next("time", || Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64()))?;
+
+        Ok(Some(Value::new(ValueVariant::Primitive(PrimitiveValue::Dec(value)))))
+    })?;
+
+    let (inputs, input) = (interpreter.inputs.clone(), interpreter.input.clone());
+    interpreter.bind_function("readLine", &[], Some("string"), move |_| {
+        let line = inputs.next("readLine", || Ok(input.read_line()?.unwrap_or_default()))?;
+
+        Ok(Some(Value::new(ValueVariant::Primitive(PrimitiveValue::Text(line.into())))))
+    })?;
+
+    let (inputs, input) = (interpreter.inputs.clone(), interpreter.input.clone());
+    interpreter.bind_function("readInt", &[], Some("int"), move |_| {
+        let value = inputs.next("readInt", || {
+            let line = input.read_line()?
+                .ok_or(anyhow::anyhow!("readInt reached the end of the input"))?;
+
+            numbers::parse_int(line.trim())
+                .map_err(|e| anyhow::anyhow!("readInt expected a whole number, but read `{}`: {}", line, e))
+        })?;
+
+        Ok(Some(Value::new(ValueVariant::Primitive(PrimitiveValue::Int(value)))))
+    })
+}
+
+// print(text: string) and printError(text: string), each followed by a line break.
+// They go through the interpreter's output, so hosts can capture them.
+fn register_output(interpreter: &mut Interpreter) -> anyhow::Result<()> {
+    let output = interpreter.output.clone();
+    interpreter.bind_function("print", &["string"], None, move |args| {
+        output.out(&format!("{}\n", text_argument(&args, 0)?));
+        Ok(None)
+    })?;
+
+    let output = interpreter.output.clone();
+    interpreter.bind_function("printError", &["string"], None, move |args| {
+        output.err(&format!("{}\n", text_argument(&args, 0)?));
+        Ok(None)
+    })
+}
+
+// append(items: list<T>, item: T) -> list<T>, a copy of the list with the item at the end
+// first(items: list<T>) -> T. Fails on an empty list.
+fn register_lists(interpreter: &mut Interpreter) -> anyhow::Result<()> {
+    interpreter.bind_function("append", &["list<T>", "T"], Some("list<T>"), |mut args| {
+        let item = args.pop().ok_or(anyhow::anyhow!("Semantic analysis error. Should have two arguments"))?;
+        let mut items = list_argument(&args[0])?.to_vec();
+        items.push(item);
+
+        Ok(Some(Value::list(items)))
+    })?;
+
+    interpreter.bind_function("first", &["list<T>"], Some("T"), |args| {
+        let first = list_argument(&args[0])?.first()
+            .ok_or(anyhow::anyhow!("first can't take the first item of an empty list"))?;
+
+        Ok(Some(first.clone()))
+    })
+}
+
+// toFixed(x: dec, places: int) -> string, rounded to that many digits after the point
+// toHex(n: int) -> string, in lowercase hexadecimal
+// withSeparators(x: int or dec) -> string, with a `,` between each group of three digits
+// parseInt(text: string) -> int and parseDec(text: string) -> dec, written like literals, with `_` between
+// digits allowed. They fail on anything else, saying where.
+fn register_numbers(interpreter: &mut Interpreter) -> anyhow::Result<()> {
+    interpreter.bind_function("parseInt", &["string"], Some("int"), |args| {
+        let text = text_argument(&args, 0)?;
+        let value = numbers::parse_int(text).map_err(|e| anyhow::anyhow!("parseInt can't read {:?}: {}", text, e))?;
+
+        Ok(Some(Value::new(ValueVariant::Primitive(PrimitiveValue::Int(value)))))
+    })?;
+
+    interpreter.bind_function("parseDec", &["string"], Some("dec"), |args| {
+        let text = text_argument(&args, 0)?;
+        let value = numbers::parse_dec(text).map_err(|e| anyhow::anyhow!("parseDec can't read {:?}: {}", text, e))?;
+
+        Ok(Some(Value::new(ValueVariant::Primitive(PrimitiveValue::Dec(value)))))
+    })?;
+
+    interpreter.bind_function("toFixed", &["dec", "int"], Some("string"), |args| {
+        let (x, places) = match (&args[0].content, &args[1].content) {
+            (ValueVariant::Primitive(PrimitiveValue::Dec(x)), ValueVariant::Primitive(PrimitiveValue::Int(places))) => (*x, *places),
+            _ => return Err(anyhow::anyhow!("Semantic analysis error. Should be a dec and an int"))
+        };
+
+        if !(0..=MAX_PLACES).contains(&places) {
+            return Err(anyhow::anyhow!("toFixed takes from 0 to {} places, not {}", MAX_PLACES, places));
+        }
+
+        Ok(Some(Value::new(ValueVariant::Primitive(PrimitiveValue::Text(numbers::fixed(x, places as usize).into())))))
+    })?;
+
+    interpreter.bind_function("toHex", &["int"], Some("string"), |args| {
+        match &args[0].content {
+            ValueVariant::Primitive(PrimitiveValue::Int(n)) => Ok(Some(Value::new(ValueVariant::Primitive(PrimitiveValue::Text(numbers::hex(*n).into()))))),
+            _ => Err(anyhow::anyhow!("Semantic analysis error. Should be an int"))
+        }
+    })?;
+
+    interpreter.bind_function("withSeparators", &["any"], Some("string"), |args| {
+        let text = match &args[0].content {
+            ValueVariant::Primitive(PrimitiveValue::Int(i)) => numbers::int_with_separators(*i),
+            ValueVariant::Primitive(PrimitiveValue::Dec(d)) => numbers::dec_with_separators(*d),
+            _ => return Err(anyhow::anyhow!("withSeparators takes an int or a dec"))
+        };
+
+        Ok(Some(Value::new(ValueVariant::Primitive(PrimitiveValue::Text(text.into())))))
+    })
+}
+
+// More digits than a dec can hold
+const MAX_PLACES: i64 = 20;
+
+// take(count, items) -> list. Going through a seq runs odo code, which natives can't do, so it's written in odo.
+const SEQUENCES: &str = "
+func take(count: int, items: seq) -> list {
+    var taken = []
+    if count <= 0 {
+        return taken
+    }
+
+    var left = count
+    for item in items {
+        taken = append(taken, item)
+        left = left - 1
+        if left == 0 {
+            return taken
+        }
+    }
+
+    return taken
+}
+";
+
+fn register_sequences(interpreter: &mut Interpreter) -> anyhow::Result<()> {
+    // The limits the host sets are for scripts, not for the standard library
+    let policy = std::mem::take(&mut interpreter.policy);
+    let result = interpreter.set_prelude(SEQUENCES.to_string());
+    interpreter.policy = policy;
+
+    result
+}
+
+fn list_argument<'v, 'a>(value: &'v Value<'a>) -> anyhow::Result<&'v [Value<'a>]> {
+    match &value.content {
+        ValueVariant::List(items) => Ok(items),
+        _ => Err(anyhow::anyhow!("Semantic analysis error. Should be a list"))
+    }
+}
+
@@ -0,0 +1,329 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::{mpsc, Mutex};
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::thread;
+
+use odo::{base::diagnostics::Diagnostic, exec::interpreter::Interpreter};
+use serde::Deserialize;
+use serde_json::{json, Value as Json};
+
+use crate::sessions::Journal;
+
+// `odo serve`: a long-lived odo process for editors and web frontends. Each line a client sends
+// is a JSON request, and each gets a JSON line back:
+//
+//   {"id": 1, "code": "var x = 2\nx * 3"}
+//   {"id": 1, "ok": true, "value": "6", "type": "int", "stdout": "", "stderr": "", "diagnostics": []}
+//
+// When the code fails, `ok` is false and the error is the first of the diagnostics. Every
+// connection has an interpreter of its own, so clients can't see each other's variables, and
+// each request is all or nothing, like a repl line. Scripts run under the server's policy,
+// and can't read the server's stdin.
+//
+// Requests with an `op` control the connection's session instead:
+//   {"op": "attach", "session": "notes"}  continues the saved session, and saves to it from then on
+//   {"op": "reset"}                       forgets everything evaluated, in the saved session too
+//   {"op": "gc"}                          frees the values nothing uses anymore
+//   {"op": "cancel"}                      stops the code running for the connection, which fails
+//                                         with a Cancelled error. It's answered right away.
+
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+type Session<'s> = dyn Fn() -> anyhow::Result<Interpreter<'static>> + Sync + 's;
+
+/// Answers every connection on its own thread, with an interpreter from `session`.
+/// Named sessions are saved in `sessions`.
+pub fn run(listener: Listener, session: &Session<'_>, sessions: Option<&Path>) -> anyhow::Result<()> {
+    thread::scope(|scope| {
+        match listener {
+            Listener::Tcp(listener) => {
+                for stream in listener.incoming() {
+                    let stream = stream?;
+                    let reader = BufReader::new(stream.try_clone()?);
+                    scope.spawn(move || connection(reader, stream, session, sessions));
+                }
+            },
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                for stream in listener.incoming() {
+                    let stream = stream?;
+                    let reader = BufReader::new(stream.try_clone()?);
+                    scope.spawn(move || connection(reader, stream, session, sessions));
+                }
+            }
+        }
+
+        Ok(())
+    })
+}
+
+// Until the client hangs up. Problems with a connection only end that connection.
+fn connection(reader: impl BufRead + Send, mut writer: impl Write + Send, session: &Session<'_>, sessions: Option<&Path>) {
+    let interpreter = match session() {
+        Ok(interpreter) => interpreter,
+        Err(error) => {
+            let _ = writeln!(writer, "{}", failure(Json::Null, &error, String::new(), String::new()));
+            return;
+        }
+    };
+    let mut connection = Connection { interpreter, journal: None, sessions };
+    connection.interpreter.set_input(Box::new(io::empty()));
+    // What `reset` goes back to
+    connection.interpreter.checkpoint();
+
+    let writer = Mutex::new(writer);
+    let cancellation = connection.interpreter.cancellation_token();
+    let (lines, requests) = mpsc::channel();
+
+    thread::scope(|scope| {
+        // Cancels can't wait for the code they cancel, so lines are read while it runs
+        let writer = &writer;
+        scope.spawn(move || {
+            for line in reader.lines() {
+                let Ok(line) = line else {
+                    return;
+                };
+
+                match serde_json::from_str::<Request>(&line) {
+                    Ok(Request { op: Op::Cancel, id, .. }) => {
+                        cancellation.cancel();
+                        if respond_line(writer, &json!({ "id": id, "ok": true })).is_err() {
+                            return;
+                        }
+                    },
+                    _ if line.trim().is_empty() => {},
+                    _ => {
+                        if lines.send(line).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+
+        for line in requests {
+            if respond_line(writer, &connection.respond(&line)).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+fn respond_line(writer: &Mutex<impl Write>, response: &Json) -> io::Result<()> {
+    let mut writer = writer.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    writeln!(writer, "{}", response)?;
+    writer.flush()
+}
+
+#[derive(Deserialize)]
+struct Request {
+    // Anything the client wants back with the response, to match them up
+    #[serde(default)]
+    id: Json,
+    #[serde(default)]
+    op: Op,
+    #[serde(default)]
+    code: String,
+    session: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+enum Op {
+    #[default]
+    Eval,
+    Attach,
+    Reset,
+    Gc,
+    Cancel,
+}
+
+struct Connection<'s> {
+    interpreter: Interpreter<'static>,
+    // The named session the connection saves to, once it attaches to one
+    journal: Option<Journal>,
+    sessions: Option<&'s Path>,
+}
+
+impl<'s> Connection<'s> {
+    // The response to one line from a client
+    fn respond(&mut self, line: &str) -> Json {
+        let request: Request = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(error) => {
+                let error = anyhow::anyhow!("Invalid request: {}", error);
+                return failure(Json::Null, &error, String::new(), String::new());
+            }
+        };
+
+        let result = match request.op {
+            Op::Eval => return self.eval(request.id, request.code),
+            Op::Attach => self.attach(request.session.as_deref()),
+            Op::Reset => self.reset(),
+            Op::Gc => Ok(json!({ "collected": self.interpreter.collect_garbage() })),
+            // Answered when it's read, so by now there's nothing running to cancel
+            Op::Cancel => Ok(json!({})),
+        };
+
+        match result {
+            Ok(mut response) => {
+                response["id"] = request.id;
+                response["ok"] = json!(true);
+                response
+            },
+            Err(error) => failure(request.id, &error, String::new(), String::new())
+        }
+    }
+
+    fn eval(&mut self, id: Json, code: String) -> Json {
+        let interpreter = &mut self.interpreter;
+        let journal = &mut self.journal;
+        let (result, stdout, stderr) = interpreter.capture_output(|interpreter| match journal {
+            Some(journal) => journal.run(interpreter, code),
+            None => interpreter.eval_atomic(code)
+        });
+
+        let warnings: Vec<Diagnostic> = interpreter.semantic_analyzer.take_warnings().iter()
+            .map(|warning| Diagnostic::from_warning(warning, None))
+            .collect();
+
+        match result {
+            Ok(result) => {
+                let (value, type_name) = match &result.value {
+                    Some(value) => (json!(interpreter.format(value)), json!(value.type_name(&interpreter.semantic_analyzer))),
+                    None => (Json::Null, Json::Null),
+                };
+
+                json!({
+                    "id": id,
+                    "ok": true,
+                    "value": value,
+                    "type": type_name,
+                    "stdout": stdout,
+                    "stderr": stderr,
+                    "diagnostics": warnings,
+                })
+            },
+            Err(error) => {
+                let mut response = failure(id, &error, stdout, stderr);
+                if let Some(diagnostics) = response["diagnostics"].as_array_mut() {
+                    diagnostics.extend(warnings.iter().map(|warning| json!(warning)));
+                }
+                response
+            }
+        }
+    }
+
+    // Starts over from the saved session, leaving behind what the connection evaluated before
+    fn attach(&mut self, name: Option<&str>) -> anyhow::Result<Json> {
+        let name = name.ok_or_else(|| anyhow::anyhow!("attach needs the name of a session"))?;
+        let directory = self.sessions.ok_or_else(|| anyhow::anyhow!("This server can't save sessions"))?;
+
+        // Closed first, so attaching again to the same one works
+        self.journal = None;
+        self.interpreter.reset();
+
+        let journal = Journal::open(directory, name)?;
+        if let Err(error) = journal.replay(&mut self.interpreter) {
+            self.interpreter.reset();
+            return Err(error);
+        }
+        self.interpreter.collect_garbage();
+
+        let restored = journal.len();
+        self.journal = Some(journal);
+
+        Ok(json!({ "session": name, "restored": restored }))
+    }
+
+    fn reset(&mut self) -> anyhow::Result<Json> {
+        self.interpreter.reset();
+        if let Some(journal) = &mut self.journal {
+            journal.clear()?;
+        }
+
+        Ok(json!({ "collected": self.interpreter.collect_garbage() }))
+    }
+}
+
+fn failure(id: Json, error: &anyhow::Error, stdout: String, stderr: String) -> Json {
+    json!({
+        "id": id,
+        "ok": false,
+        "stdout": stdout,
+        "stderr": stderr,
+        "diagnostics": [Diagnostic::from_error(error, None)],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use odo::exec::interpreter::Interpreter;
+
+    use super::Connection;
+
+    #[test]
+    fn test_respond() {
+        let mut connection = Connection { interpreter: Interpreter::new(), journal: None, sessions: None };
+
+        let response = connection.respond(r#"{"id": 1, "code": "var x = 2\nprint(\"hi\")\nx * 3"}"#);
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["ok"], true);
+        assert_eq!(response["value"], "6");
+        assert_eq!(response["type"], "int");
+        assert_eq!(response["stdout"], "hi\n");
+
+        // Failed requests leave nothing behind
+        let response = connection.respond(r#"{"id": "a", "code": "var y = 1\ny + z"}"#);
+        assert_eq!(response["ok"], false);
+        assert_eq!(response["diagnostics"][0]["code"], "unknown-name");
+        assert_eq!(connection.respond(r#"{"code": "y"}"#)["ok"], false);
+
+        let response = connection.respond("not json");
+        assert_eq!(response["id"], serde_json::Value::Null);
+        assert!(response["diagnostics"][0]["message"].as_str().unwrap().starts_with("Invalid request"));
+    }
+
+    #[test]
+    fn test_sessions() {
+        let directory = std::env::temp_dir().join(format!("odo-sessions-{}", uuid::Uuid::new_v4()));
+        let connect = || {
+            let mut interpreter = Interpreter::new();
+            interpreter.checkpoint();
+            Connection { interpreter, journal: None, sessions: Some(&directory) }
+        };
+
+        let mut first = connect();
+        first.respond(r#"{"code": "var before = 1"}"#);
+        assert_eq!(first.respond(r#"{"op": "attach", "session": "notes"}"#)["restored"], 0);
+        assert_eq!(first.respond(r#"{"code": "before"}"#)["ok"], false);
+        first.respond(r#"{"code": "var seed = random()\nvar names = [\"a\"]"}"#);
+        let seed = first.respond(r#"{"code": "seed"}"#)["value"].clone();
+
+        // One connection at a time
+        let mut second = connect();
+        assert_eq!(second.respond(r#"{"op": "attach", "session": "notes"}"#)["ok"], false);
+        drop(first);
+
+        // The same inputs, so the same values
+        assert_eq!(second.respond(r#"{"op": "attach", "session": "notes"}"#)["restored"], 2);
+        assert_eq!(second.respond(r#"{"code": "seed"}"#)["value"], seed);
+        assert_eq!(second.respond(r#"{"op": "gc"}"#)["ok"], true);
+        assert_eq!(second.respond(r#"{"code": "names"}"#)["value"], "[\"a\"]");
+
+        assert_eq!(second.respond(r#"{"op": "reset"}"#)["ok"], true);
+        assert_eq!(second.respond(r#"{"code": "seed"}"#)["ok"], false);
+        drop(second);
+        assert_eq!(connect().respond(r#"{"op": "attach", "session": "notes"}"#)["restored"], 0);
+
+        assert_eq!(connect().respond(r#"{"op": "attach", "session": "../notes"}"#)["ok"], false);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
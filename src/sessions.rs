@@ -0,0 +1,113 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::Context;
+use odo::exec::{interpreter::{ExecutionResult, Interpreter}, replay::RecordedInput};
+use serde::{Deserialize, Serialize};
+
+// Named sessions for `odo serve` and `odo kernel`, kept between connections. Values can't be
+// saved as they are, since functions hold on to the environments they run in, so a session is
+// saved as the code that ran in it, with the inputs it read. Opening it runs that code again,
+// with the same clock, randomness, stdin and network responses, so requests aren't sent twice.
+
+// Sessions open in this process. Two connections writing to one journal would lose code.
+static OPEN: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    code: String,
+    inputs: Vec<RecordedInput>,
+}
+
+pub struct Journal {
+    name: String,
+    path: PathBuf,
+    entries: Vec<Entry>,
+}
+
+/// Where sessions are saved, unless the command line says otherwise.
+pub fn directory() -> Option<PathBuf> {
+    Some(crate::config::directory()?.join("sessions"))
+}
+
+impl Journal {
+    /// The session called `name` in `directory`, empty when it's new.
+    pub fn open(directory: &Path, name: &str) -> anyhow::Result<Journal> {
+        let valid = !name.is_empty() && name.len() <= 64
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            anyhow::bail!("Session names are letters, digits, - and _, not {:?}", name);
+        }
+
+        let path = directory.join(name).with_extension("json");
+        let entries = match path.exists() {
+            true => serde_json::from_str(&std::fs::read_to_string(&path)?)
+                .with_context(|| format!("Could not read session {}", path.display()))?,
+            false => vec![]
+        };
+
+        if !OPEN.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(name.to_string()) {
+            anyhow::bail!("Session {} is already open", name);
+        }
+
+        Ok(Journal { name: name.to_string(), path, entries })
+    }
+
+    /// How many pieces of code the session has.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Runs the session's code again, into `interpreter`.
+    pub fn replay(&self, interpreter: &mut Interpreter) -> anyhow::Result<()> {
+        for entry in &self.entries {
+            interpreter.replay_inputs(entry.inputs.clone());
+            let (result, _, _) = interpreter.capture_output(|interpreter| interpreter.eval(entry.code.clone()));
+            interpreter.stop_recording();
+
+            result.with_context(|| format!("Could not restore session {}", self.name))?;
+        }
+        interpreter.semantic_analyzer.take_warnings();
+
+        Ok(())
+    }
+
+    /// Like `eval_atomic`, saving the code to the session when it succeeds.
+    pub fn run<'a>(&mut self, interpreter: &mut Interpreter<'a>, code: String) -> anyhow::Result<ExecutionResult<'a>> {
+        interpreter.record_inputs();
+        let result = interpreter.eval_atomic(code.clone());
+        let inputs = interpreter.stop_recording();
+
+        if result.is_ok() {
+            self.entries.push(Entry { code, inputs });
+            self.save()?;
+        }
+
+        result
+    }
+
+    /// Forgets all the session's code.
+    pub fn clear(&mut self) -> anyhow::Result<()> {
+        self.entries.clear();
+        self.save()
+    }
+
+    // Written next to the old one and moved over it, so a crash can't leave half a session
+    fn save(&self) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let temporary = self.path.with_extension("json.tmp");
+        std::fs::write(&temporary, serde_json::to_string(&self.entries)?)?;
+        std::fs::rename(&temporary, &self.path)
+            .with_context(|| format!("Could not save session {}", self.path.display()))
+    }
+}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        OPEN.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&self.name);
+    }
+}
@@ -0,0 +1,30 @@
+// Instrumentation for the `trace` feature. Without it, these expand to nothing.
+
+// Enters a span for a phase of the pipeline, timed until the returned guard is dropped
+#[cfg(feature = "trace")]
+macro_rules! phase_span {
+    ($name:literal) => {
+        tracing::debug_span!($name).entered()
+    };
+}
+
+#[cfg(not(feature = "trace"))]
+macro_rules! phase_span {
+    ($name:literal) => {
+        ()
+    };
+}
+
+#[cfg(feature = "trace")]
+macro_rules! statement_event {
+    ($kind:expr) => {
+        tracing::trace!(statement = $kind)
+    };
+}
+
+#[cfg(not(feature = "trace"))]
+macro_rules! statement_event {
+    ($kind:expr) => {
+        ()
+    };
+}